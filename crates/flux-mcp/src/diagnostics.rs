@@ -1,10 +1,34 @@
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
+    io,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use globset::{Glob, GlobSetBuilder};
+use regex::Regex;
+use rmcp::schemars::{self, JsonSchema};
+
+use crate::{
+    flux_runner::{VerificationReport, VerificationSummary},
+    lsp,
+    source::{self, ItemRange},
+};
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, JsonSchema)]
 pub struct DiagnosticTarget {
     pub name: String,
     pub kind: Option<Vec<String>>,
+    /// The root source file of the target, e.g. `/repo/src/lib.rs`
+    #[serde(default)]
+    pub src_path: Option<String>,
+    /// The edition the target is compiled with, e.g. `2021`
+    #[serde(default)]
+    pub edition: Option<String>,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, JsonSchema)]
 pub struct DiagnosticSpan {
     pub file_name: String,
     pub line_start: i64,
@@ -13,95 +37,2905 @@ pub struct DiagnosticSpan {
     pub column_end: i64,
     #[serde(default)]
     pub is_primary: bool,
+    /// Present when the span comes from a macro expansion
+    #[serde(default)]
+    pub expansion: Option<Box<DiagnosticSpanExpansion>>,
+    /// What the span points at, e.g. "expected `int`, found ..."
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// UTF-8 byte offset of the start of the span, only computed on request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub byte_start: Option<usize>,
+    /// UTF-8 byte offset of the end of the span, only computed on request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub byte_end: Option<usize>,
+    /// The source text the span currently covers, only read on request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, JsonSchema)]
+pub struct DiagnosticSpanExpansion {
+    /// The span of the macro invocation
+    pub span: DiagnosticSpan,
+    pub macro_decl_name: String,
+    /// The span of the macro definition, if known
+    pub def_site_span: Option<DiagnosticSpan>,
+}
+
+/// The severity of a diagnostic, ordered from least to most severe so that "at least a warning"
+/// is `level >= Level::Warning`.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    /// A level this version doesn't know about, kept as is in [`DiagnosticMessage::level`]
+    #[default]
+    Unknown,
+    Help,
+    Note,
+    FailureNote,
+    Warning,
+    Error,
+    /// An internal compiler error
+    Ice,
+}
+
+impl FromStr for Level {
+    type Err = std::convert::Infallible;
+
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        Ok(match level {
+            "error" => Level::Error,
+            "warning" => Level::Warning,
+            "note" => Level::Note,
+            "help" => Level::Help,
+            "failure-note" => Level::FailureNote,
+            "error: internal compiler error" => Level::Ice,
+            _ => Level::Unknown,
+        })
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, JsonSchema)]
 pub struct DiagnosticMessage {
     pub level: String,
+    /// `level`, parsed
+    #[serde(default)]
+    pub parsed_level: Level,
     pub message: String,
     pub code: Option<String>,
+    /// The long-form explanation of `code`, when rustc has one and it was asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_explanation: Option<String>,
     pub rendered: Option<String>,
     pub spans: Vec<DiagnosticSpan>,
+    /// The notes and help attached to the message, e.g. `note: this is the condition that
+    /// cannot be proved`, each with its own spans
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<DiagnosticMessage>,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, JsonSchema)]
 pub struct Diagnostic {
     pub message: DiagnosticMessage,
     pub package_id: Option<String>,
     pub target: Option<DiagnosticTarget>,
+    /// The innermost function, `impl` or type definition containing the primary span
+    #[serde(default)]
+    pub enclosing_item: Option<String>,
+    /// See [`synthetic_code`]
+    #[serde(default)]
+    pub synthetic_code: String,
+    /// Values violating the failed refinement, when flux found some
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counterexample: Option<Counterexample>,
+    /// See [`fingerprint`]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub fingerprint: String,
+}
+
+/// A concrete assignment to the variables of a failed refinement that makes it false.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, JsonSchema)]
+pub struct Counterexample {
+    /// Each variable and its value as flux printed them, e.g. `("n", "-1")`
+    pub assignments: Vec<(String, String)>,
+}
+
+impl DiagnosticSpan {
+    fn start(&self) -> (i64, i64) {
+        (self.line_start, self.column_start)
+    }
+
+    fn end(&self) -> (i64, i64) {
+        (self.line_end, self.column_end)
+    }
+
+    /// Whether the two spans are in the same file and their ranges overlap or touch.
+    fn touches(&self, other: &DiagnosticSpan) -> bool {
+        self.file_name == other.file_name
+            && self.start() <= other.end()
+            && other.start() <= self.end()
+    }
+}
+
+fn parse_span(span: &serde_json::Value) -> Option<DiagnosticSpan> {
+    let file_name = span.get("file_name")?.as_str()?.to_string();
+    let line_start = span.get("line_start")?.as_i64().unwrap_or(0);
+    let column_start = span.get("column_start")?.as_i64().unwrap_or(0);
+    let line_end = span.get("line_end")?.as_i64().unwrap_or(0);
+    let column_end = span.get("column_end")?.as_i64().unwrap_or(0);
+    let is_primary = span.get("is_primary")?.as_bool().unwrap_or(true);
+    let expansion = span
+        .get("expansion")
+        .and_then(parse_expansion)
+        .map(Box::new);
+    let label = span
+        .get("label")
+        .and_then(|label| label.as_str())
+        .map(|label| label.to_string());
+    Some(DiagnosticSpan {
+        file_name,
+        line_start,
+        column_start,
+        line_end,
+        column_end,
+        is_primary,
+        expansion,
+        label,
+        byte_start: None,
+        byte_end: None,
+        text: None,
+    })
+}
+
+fn parse_expansion(expansion: &serde_json::Value) -> Option<DiagnosticSpanExpansion> {
+    let span = parse_span(expansion.get("span")?)?;
+    let macro_decl_name = expansion
+        .get("macro_decl_name")
+        .and_then(|name| name.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let def_site_span = expansion.get("def_site_span").and_then(parse_span);
+    Some(DiagnosticSpanExpansion { span, macro_decl_name, def_site_span })
 }
 
 fn parse_spans(spans: &serde_json::Value) -> Option<Vec<DiagnosticSpan>> {
     let mut res = Vec::new();
     for span in spans.as_array()? {
-        let file_name = span.get("file_name")?.as_str()?.to_string();
-        let line_start = span.get("line_start")?.as_i64().unwrap_or(0);
-        let column_start = span.get("column_start")?.as_i64().unwrap_or(0);
-        let line_end = span.get("line_end")?.as_i64().unwrap_or(0);
-        let column_end = span.get("column_end")?.as_i64().unwrap_or(0);
-        let is_primary = span.get("is_primary")?.as_bool().unwrap_or(true);
-        res.push(DiagnosticSpan {
-            file_name,
-            line_start,
-            column_start,
-            line_end,
-            column_end,
-            is_primary,
-        })
+        res.push(parse_span(span)?);
     }
     Some(res)
 }
 
-pub(crate) fn parse_message(message: &serde_json::Value) -> Option<DiagnosticMessage> {
+/// Follows the expansion chain of a span coming from a macro back to the outermost invocation,
+/// which is the code the user actually wrote. The primary flag of the original span is kept.
+pub(crate) fn user_facing_span(span: &DiagnosticSpan) -> DiagnosticSpan {
+    let mut current = span;
+    while let Some(expansion) = &current.expansion {
+        current = &expansion.span;
+    }
+    DiagnosticSpan { is_primary: span.is_primary, expansion: None, ..current.clone() }
+}
+
+pub fn parse_message(message: &serde_json::Value) -> Option<DiagnosticMessage> {
     let level = message.get("level")?.as_str()?.to_string();
-    let code = message
-        .get("code")
-        .and_then(|code| code.as_str().map(|code| code.to_string()));
+    let Ok(parsed_level) = level.parse();
+    // rustc emits `{"code": ..., "explanation": ...}`, but plain strings are accepted too.
+    let (code, code_explanation) = match message.get("code") {
+        Some(serde_json::Value::String(code)) => (Some(code.clone()), None),
+        Some(code) => {
+            let field = |name| code.get(name)?.as_str().map(str::to_string);
+            (field("code"), field("explanation"))
+        }
+        None => (None, None),
+    };
     let rendered = message
         .get("rendered")
         .and_then(|rendered| rendered.as_str().map(|rendered| rendered.to_string()));
     let spans = message.get("spans").and_then(parse_spans).unwrap_or(vec![]);
+    let children = message
+        .get("children")
+        .and_then(serde_json::Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(parse_message)
+        .collect();
     let message = message.get("message")?.as_str()?.to_string();
-    Some(DiagnosticMessage { level, message, code, rendered, spans })
+    Some(DiagnosticMessage {
+        level,
+        parsed_level,
+        message,
+        code,
+        code_explanation,
+        rendered,
+        spans,
+        children,
+    })
+}
+
+/// Reads the counterexample attached to a flux message, if it has one. It is taken from a
+/// `counterexample` field, either an object from variables to values or a list of
+/// `[variable, value]` pairs, or else from a child note like `counterexample: n = -1, m = 0`.
+pub fn parse_counterexample(message: &serde_json::Value) -> Option<Counterexample> {
+    let value_text = |value: &serde_json::Value| {
+        match value {
+            serde_json::Value::String(value) => value.clone(),
+            value => value.to_string(),
+        }
+    };
+    let assignments: Vec<(String, String)> = match message.get("counterexample") {
+        Some(serde_json::Value::Object(fields)) => {
+            fields
+                .iter()
+                .map(|(name, value)| (name.clone(), value_text(value)))
+                .collect()
+        }
+        Some(serde_json::Value::Array(pairs)) => {
+            pairs
+                .iter()
+                .filter_map(|pair| {
+                    match pair.as_array()?.as_slice() {
+                        [name, value] => Some((name.as_str()?.to_string(), value_text(value))),
+                        _ => None,
+                    }
+                })
+                .collect()
+        }
+        _ => {
+            message
+                .get("children")
+                .and_then(serde_json::Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|child| {
+                    child
+                        .get("message")?
+                        .as_str()?
+                        .strip_prefix("counterexample:")
+                })
+                .flat_map(|note| note.split(','))
+                .filter_map(|assignment| {
+                    let (name, value) = assignment.split_once('=')?;
+                    Some((name.trim().to_string(), value.trim().to_string()))
+                })
+                .collect()
+        }
+    };
+    (!assignments.is_empty()).then_some(Counterexample { assignments })
 }
 
-pub(crate) fn parse_target(target: &serde_json::Value) -> Option<DiagnosticTarget> {
+pub fn parse_target(target: &serde_json::Value) -> Option<DiagnosticTarget> {
     let name = target.get("name")?.as_str()?.to_string();
+    let field = |name| target.get(name)?.as_str().map(str::to_string);
+    let (src_path, edition) = (field("src_path"), field("edition"));
     let kind = target.get("kind");
     if let Some(kind) = kind {
         let mut kinds = Vec::new();
         for k in kind.as_array()? {
-            kinds.push(k.as_str()?.to_string())
+            kinds.push(k.as_str()?.to_string());
         }
-        Some(DiagnosticTarget { name, kind: Some(kinds) })
+        Some(DiagnosticTarget { name, kind: Some(kinds), src_path, edition })
     } else {
-        Some(DiagnosticTarget { name, kind: None })
+        Some(DiagnosticTarget { name, kind: None, src_path, edition })
     }
 }
 
-pub(crate) fn retain_only_syntax_errors(diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
-    let flux_error_markers: &[&str] = &[
-        "error jumping to join point",
-        "assignment might be unsafe",
-        "call to function that may panic",
-        "refinement type error",
-        "possible division by zero",
-        "possible reminder with a divisor of zero",
-        "assertion might fail",
-        "parameter inference error at function call",
-        "type invariant may not hold (when place is folded)",
-        "cannot prove this code safe",
-        "arithmetic operation may overflow",
-        "arithmetic operation may underflow",
-        "unsupported type in function call",
-        "invariant cannot be proven",
-        "associated refinement"
+/// The kinds of errors flux reports for failed refinement checks, recognized by their message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FluxErrorKind {
+    JoinPoint,
+    UnsafeAssignment,
+    MayPanic,
+    RefinementType,
+    DivisionByZero,
+    RemainderByZero,
+    Assert,
+    ParamInference,
+    FoldedInvariant,
+    CannotProve,
+    Overflow,
+    Underflow,
+    UnsupportedCall,
+    Invariant,
+    AssocRefinement,
+}
+
+impl FluxErrorKind {
+    const MARKERS: &[(&str, FluxErrorKind)] = &[
+        ("error jumping to join point", FluxErrorKind::JoinPoint),
+        ("assignment might be unsafe", FluxErrorKind::UnsafeAssignment),
+        ("call to function that may panic", FluxErrorKind::MayPanic),
+        ("refinement type error", FluxErrorKind::RefinementType),
+        ("possible division by zero", FluxErrorKind::DivisionByZero),
+        ("possible reminder with a divisor of zero", FluxErrorKind::RemainderByZero),
+        ("assertion might fail", FluxErrorKind::Assert),
+        ("parameter inference error at function call", FluxErrorKind::ParamInference),
+        ("type invariant may not hold (when place is folded)", FluxErrorKind::FoldedInvariant),
+        ("cannot prove this code safe", FluxErrorKind::CannotProve),
+        ("arithmetic operation may overflow", FluxErrorKind::Overflow),
+        ("arithmetic operation may underflow", FluxErrorKind::Underflow),
+        ("unsupported type in function call", FluxErrorKind::UnsupportedCall),
+        ("invariant cannot be proven", FluxErrorKind::Invariant),
+        ("associated refinement", FluxErrorKind::AssocRefinement),
     ];
+
+    /// The kind of a diagnostic message, if it is a flux refinement error.
+    pub(crate) fn of(message: &str) -> Option<FluxErrorKind> {
+        Self::MARKERS
+            .iter()
+            .find(|(marker, _)| message.contains(marker))
+            .map(|(_, kind)| *kind)
+    }
+
+    /// A stable code for the kind, e.g. `FLUX-OVERFLOW`.
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            FluxErrorKind::JoinPoint => "FLUX-JOIN-POINT",
+            FluxErrorKind::UnsafeAssignment => "FLUX-ASSIGN",
+            FluxErrorKind::MayPanic => "FLUX-PANIC",
+            FluxErrorKind::RefinementType => "FLUX-REFINEMENT",
+            FluxErrorKind::DivisionByZero => "FLUX-DIV-ZERO",
+            FluxErrorKind::RemainderByZero => "FLUX-REM-ZERO",
+            FluxErrorKind::Assert => "FLUX-ASSERT",
+            FluxErrorKind::ParamInference => "FLUX-PARAM-INFERENCE",
+            FluxErrorKind::FoldedInvariant => "FLUX-FOLD-INVARIANT",
+            FluxErrorKind::CannotProve => "FLUX-CANNOT-PROVE",
+            FluxErrorKind::Overflow => "FLUX-OVERFLOW",
+            FluxErrorKind::Underflow => "FLUX-UNDERFLOW",
+            FluxErrorKind::UnsupportedCall => "FLUX-UNSUPPORTED-CALL",
+            FluxErrorKind::Invariant => "FLUX-INVARIANT",
+            FluxErrorKind::AssocRefinement => "FLUX-ASSOC-REFINEMENT",
+        }
+    }
+}
+
+/// The diagnostic's own code if it has one, otherwise a code derived from its
+/// [`FluxErrorKind`], and `UNCLASSIFIED` for anything else.
+pub(crate) fn synthetic_code(diagnostic: &Diagnostic) -> String {
+    let message = &diagnostic.message;
+    match (&message.code, FluxErrorKind::of(&message.message)) {
+        (Some(code), _) => code.clone(),
+        (None, Some(kind)) => kind.code().to_string(),
+        (None, None) => "UNCLASSIFIED".to_string(),
+    }
+}
+
+pub(crate) fn retain_only_syntax_errors(diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    retain_non_refinement_errors(diagnostics, false)
+}
+
+/// Like [`retain_only_syntax_errors`], but also keeps warnings. Flux reports its own checks at
+/// `error` level, so warnings are the rustc lints it passes through, e.g. unused items (which
+/// includes functions whose specs are never exercised) or unused attributes.
+pub(crate) fn with_warnings(diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    retain_non_refinement_errors(diagnostics, true)
+}
+
+/// The complement of [`retain_only_syntax_errors`]: keeps only the flux refinement failures,
+/// whose messages have a [`FluxErrorKind`] marker.
+pub(crate) fn retain_only_flux_errors(diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    diagnostics
+        .into_iter()
+        .filter(|diag| FluxErrorKind::of(&diag.message.message).is_some())
+        .collect()
+}
+
+/// One flux refinement failure, pointed at by its primary span.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FluxFailure {
+    /// The primary span, traced back to user code
+    pub span: Option<DiagnosticSpan>,
+    /// The [synthetic code](synthetic_code), e.g. `FLUX-OVERFLOW`
+    pub code: String,
+    pub message: String,
+}
+
+/// The flux refinement failures whose primary spans start on the same line.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FailureSite {
+    /// `None` for the failures without a primary span, which are listed last
+    pub file_name: Option<String>,
+    pub line: Option<i64>,
+    /// In column order
+    pub failures: Vec<FluxFailure>,
+}
+
+/// Groups the flux refinement failures among `diagnostics` by the file and line their primary
+/// span starts on, sorted by file and line.
+pub(crate) fn failure_sites(diagnostics: Vec<Diagnostic>) -> Vec<FailureSite> {
+    let mut sites: BTreeMap<(bool, Option<String>, Option<i64>), Vec<FluxFailure>> =
+        BTreeMap::new();
+    for diagnostic in retain_only_flux_errors(diagnostics) {
+        let span = diagnostic.message.primary_span().map(user_facing_span);
+        let key = span.as_ref().map_or((true, None, None), |span| {
+            (false, Some(span.file_name.clone()), Some(span.line_start))
+        });
+        sites.entry(key).or_default().push(FluxFailure {
+            span,
+            code: synthetic_code(&diagnostic),
+            message: diagnostic.message.message,
+        });
+    }
+    sites
+        .into_iter()
+        .map(|((_, file_name, line), mut failures)| {
+            failures.sort_by_key(|failure| failure.span.as_ref().map(|span| span.column_start));
+            FailureSite { file_name, line, failures }
+        })
+        .collect()
+}
+
+fn retain_non_refinement_errors(
+    diagnostics: Vec<Diagnostic>,
+    include_warnings: bool,
+) -> Vec<Diagnostic> {
     diagnostics
         .into_iter()
         .filter(|diag| {
-            diag.message.level.as_str() == "error"
-                && !flux_error_markers.iter().any(|marker| diag.message.message.contains(marker))
+            let level = diag.message.parsed_level;
+            (level == Level::Error || (include_warnings && level == Level::Warning))
+                && FluxErrorKind::of(&diag.message.message).is_none()
+        })
+        .collect()
+}
+
+/// Coalesces spans in the same file whose ranges overlap or touch. Only spans with the same
+/// `is_primary` flag are merged, and the result keeps the order of first occurrence.
+pub(crate) fn merge_spans(spans: Vec<DiagnosticSpan>) -> Vec<DiagnosticSpan> {
+    let mut res: Vec<Option<DiagnosticSpan>> = Vec::new();
+    for mut span in spans {
+        // Growing a span can make it touch spans it didn't before, so keep absorbing until
+        // nothing changes. The merged span takes the place of the earliest span it absorbed.
+        let mut slot: Option<usize> = None;
+        while let Some(i) = res.iter().position(|other| {
+            other
+                .as_ref()
+                .is_some_and(|other| other.is_primary == span.is_primary && other.touches(&span))
+        }) {
+            let other = res[i].take().unwrap();
+            if other.start() < span.start() {
+                span.line_start = other.line_start;
+                span.column_start = other.column_start;
+            }
+            if other.end() > span.end() {
+                span.line_end = other.line_end;
+                span.column_end = other.column_end;
+            }
+            slot = Some(slot.map_or(i, |slot| slot.min(i)));
+        }
+        match slot {
+            Some(i) => res[i] = Some(span),
+            None => res.push(Some(span)),
+        }
+    }
+    res.into_iter().flatten().collect()
+}
+
+impl DiagnosticMessage {
+    /// The first primary span, if any.
+    pub fn primary_span(&self) -> Option<&DiagnosticSpan> {
+        self.spans.iter().find(|span| span.is_primary)
+    }
+
+    /// A one line rendering for when there is no rendered text, with the location and label of
+    /// the primary span, e.g. `src/lib.rs:3:5: error: refinement type error: a precondition
+    /// cannot be proved`.
+    pub fn render_short(&self) -> String {
+        let mut res = String::new();
+        if let Some(span) = self.primary_span() {
+            res.push_str(&format!(
+                "{}:{}:{}: ",
+                span.file_name, span.line_start, span.column_start
+            ));
+        }
+        res.push_str(&format!("{}: {}", self.level, self.message));
+        if let Some(label) = self.primary_span().and_then(|span| span.label.as_deref()) {
+            res.push_str(&format!(": {label}"));
+        }
+        res
+    }
+}
+
+/// The items of every source file looked at so far, so that each file is parsed at most once
+/// per run.
+#[derive(Default)]
+pub(crate) struct ItemCache {
+    files: HashMap<PathBuf, Vec<ItemRange>>,
+}
+
+/// Finds the innermost item whose range contains `span`, after following macro expansions back
+/// to user code. File names are resolved against `repo_path`, the directory flux ran in.
+pub(crate) fn enclosing_item(
+    span: &DiagnosticSpan,
+    repo_path: &Path,
+    cache: &mut ItemCache,
+) -> Option<String> {
+    enclosing_item_range(span, repo_path, cache).map(|(_, item)| item.name)
+}
+
+/// Like [`enclosing_item`], but returns the file the item is in and the lines it spans too.
+pub(crate) fn enclosing_item_range(
+    span: &DiagnosticSpan,
+    repo_path: &Path,
+    cache: &mut ItemCache,
+) -> Option<(PathBuf, ItemRange)> {
+    let span = user_facing_span(span);
+    let path = lsp::resolve_path(repo_path, &span.file_name);
+    let items = cache
+        .files
+        .entry(path.clone())
+        .or_insert_with_key(|path| source::item_ranges(path));
+    let line = usize::try_from(span.line_start).ok()?;
+    let item = items
+        .iter()
+        .filter(|item| item.start_line <= line && line <= item.end_line)
+        .min_by_key(|item| item.end_line - item.start_line)?;
+    Some((path, item.clone()))
+}
+
+/// The most `impl` block source [`enclosing_impl`] returns before cutting it short.
+pub const MAX_IMPL_CONTEXT_BYTES: usize = 32 * 1024;
+
+/// The source of the innermost `impl` block containing `span`, after following macro
+/// expansions back to user code, so that a failing method comes with the generics, bounds and
+/// sibling specs it is checked against. Blocks over [`MAX_IMPL_CONTEXT_BYTES`] are cut at a line
+/// break and end with a `// ...` marker.
+pub(crate) fn enclosing_impl(span: &DiagnosticSpan, repo_path: &Path) -> Option<String> {
+    let span = user_facing_span(span);
+    let path = lsp::resolve_path(repo_path, &span.file_name);
+    let mut text = source::enclosing_impl_text(&path, usize::try_from(span.line_start).ok()?)?;
+    if text.len() > MAX_IMPL_CONTEXT_BYTES {
+        let end = text[..text.floor_char_boundary(MAX_IMPL_CONTEXT_BYTES)]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        text.truncate(end);
+        text.push_str("// ... the rest of the impl block is cut\n");
+    }
+    Some(text)
+}
+
+/// The goals flux gave up on because their SMT query timed out, named by their enclosing item
+/// or, outside of one, by the location of their primary span.
+pub(crate) fn timed_out_goals(diagnostics: &[Diagnostic]) -> Vec<String> {
+    diagnostics
+        .iter()
+        .filter(|diagnostic| {
+            let message = &diagnostic.message;
+            [Some(&message.message), message.rendered.as_ref()]
+                .into_iter()
+                .flatten()
+                .any(|text| {
+                    let text = text.to_lowercase();
+                    text.contains("timed out") || text.contains("timeout")
+                })
+        })
+        .filter_map(|diagnostic| {
+            diagnostic.enclosing_item.clone().or_else(|| {
+                let span = diagnostic.message.primary_span()?;
+                Some(format!("{}:{}:{}", span.file_name, span.line_start, span.column_start))
+            })
+        })
+        .collect()
+}
+
+/// Sets `enclosing_item` on every diagnostic with a primary span.
+pub(crate) fn tag_enclosing_items(diagnostics: &mut [Diagnostic], repo_path: &Path) {
+    let mut cache = ItemCache::default();
+    for diagnostic in diagnostics {
+        let item = diagnostic
+            .message
+            .primary_span()
+            .and_then(|span| enclosing_item(span, repo_path, &mut cache));
+        diagnostic.enclosing_item = item;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, JsonSchema)]
+pub enum SortOrder {
+    /// By file, then line, then column of the primary span
+    #[serde(rename = "location")]
+    ByLocation,
+    /// Errors first, then warnings, then everything else
+    #[serde(rename = "level")]
+    ByLevel,
+    /// Flux errors of the same kind next to each other, then the diagnostics of no flux kind
+    #[serde(rename = "kind")]
+    ByKind,
+}
+
+/// Puts `diagnostics` in an order that doesn't depend on how cargo scheduled the build: by the
+/// file, line and column of the primary span, then by message, with diagnostics without a
+/// location last. Strings are compared bytewise, so the order is the same under any locale.
+pub(crate) fn sort_canonically(diagnostics: &mut [Diagnostic]) {
+    diagnostics.sort_by_cached_key(|diagnostic| {
+        let message = &diagnostic.message;
+        let location = message
+            .primary_span()
+            .map_or((true, String::new(), 0, 0), |span| {
+                (false, span.file_name.clone(), span.line_start, span.column_start)
+            });
+        (location, message.message.clone(), message.level.clone(), message.rendered.clone())
+    });
+}
+
+/// Sorts `diagnostics` in place. The sort is stable, so ties keep the canonical order.
+pub(crate) fn sort(diagnostics: &mut [Diagnostic], order: SortOrder) {
+    match order {
+        SortOrder::ByLocation => {
+            diagnostics.sort_by_cached_key(|diagnostic| {
+                // Diagnostics without a location go last.
+                diagnostic
+                    .message
+                    .primary_span()
+                    .map_or((true, String::new(), 0, 0), |span| {
+                        let span = user_facing_span(span);
+                        (false, span.file_name, span.line_start, span.column_start)
+                    })
+            });
+        }
+        SortOrder::ByLevel => {
+            diagnostics.sort_by_key(|diagnostic| {
+                // Errors and ICEs are equally urgent.
+                std::cmp::Reverse(diagnostic.message.parsed_level.min(Level::Error))
+            });
+        }
+        SortOrder::ByKind => {
+            diagnostics.sort_by_cached_key(|diagnostic| {
+                let kind = FluxErrorKind::of(&diagnostic.message.message);
+                (kind.is_none(), kind)
+            });
+        }
+    }
+}
+
+/// Converts a 1-based line and 1-based char column, as reported by rustc, into a UTF-8 byte
+/// offset into `contents`. A tab counts as a single column. Positions past the end of a line or
+/// of the file are clamped to it.
+pub(crate) fn column_to_byte_offset(contents: &str, line: usize, column: usize) -> usize {
+    let mut offset = 0;
+    for (i, text) in contents.split_inclusive('\n').enumerate() {
+        if i + 1 == line {
+            let text = text.trim_end_matches(['\n', '\r']);
+            let within = text
+                .char_indices()
+                .nth(column.saturating_sub(1))
+                .map_or(text.len(), |(byte, _)| byte);
+            return offset + within;
+        }
+        offset += text.len();
+    }
+    offset
+}
+
+/// Fills in `byte_start` and `byte_end` for the spans of every diagnostic, reading each file
+/// (resolved against `repo_path`) once. Spans in files that can't be read are left untouched.
+pub(crate) fn add_byte_offsets(diagnostics: &mut [Diagnostic], repo_path: &Path) {
+    let mut files: HashMap<PathBuf, Option<String>> = HashMap::new();
+    for span in diagnostics
+        .iter_mut()
+        .flat_map(|diagnostic| &mut diagnostic.message.spans)
+    {
+        let path = lsp::resolve_path(repo_path, &span.file_name);
+        let Some(contents) = files
+            .entry(path)
+            .or_insert_with_key(|path| std::fs::read_to_string(path).ok())
+        else {
+            continue;
+        };
+        let offset = |line: i64, column: i64| {
+            column_to_byte_offset(contents, line.max(0) as usize, column.max(0) as usize)
+        };
+        span.byte_start = Some(offset(span.line_start, span.column_start));
+        span.byte_end = Some(offset(span.line_end, span.column_end));
+    }
+}
+
+/// The text `span` covers in its file, resolved against `repo_path`, from its start up to but
+/// not including its end column, line breaks included for a multi-line span. Returns `None` if
+/// the file can't be read or the span doesn't fit in it, e.g. because the file was edited since.
+pub fn span_text(span: &DiagnosticSpan, repo_path: &Path) -> Option<String> {
+    let contents = std::fs::read_to_string(lsp::resolve_path(repo_path, &span.file_name)).ok()?;
+    span_text_in(&contents, span)
+}
+
+fn span_text_in(contents: &str, span: &DiagnosticSpan) -> Option<String> {
+    // Unlike `column_to_byte_offset`, positions past the end of their line don't fit.
+    let offset = |line: i64, column: i64| -> Option<usize> {
+        let index = usize::try_from(line.checked_sub(1)?).ok()?;
+        let column = usize::try_from(column.checked_sub(1)?).ok()?;
+        let mut offset = 0;
+        let text = contents
+            .split_inclusive('\n')
+            .inspect(|text| offset += text.len())
+            .nth(index)?;
+        let start = offset - text.len();
+        let text = text.trim_end_matches(['\n', '\r']);
+        let within = match text.char_indices().nth(column) {
+            Some((byte, _)) => byte,
+            None if text.chars().count() == column => text.len(),
+            None => return None,
+        };
+        Some(start + within)
+    };
+    let start = offset(span.line_start, span.column_start)?;
+    let end = offset(span.line_end, span.column_end)?;
+    contents.get(start..end).map(str::to_string)
+}
+
+/// Fills in `text` for the spans of every diagnostic, reading each file (resolved against
+/// `repo_path`) once. Spans that don't fit in their file are left without text.
+pub(crate) fn add_span_texts(diagnostics: &mut [Diagnostic], repo_path: &Path) {
+    let mut files: HashMap<PathBuf, Option<String>> = HashMap::new();
+    for span in diagnostics
+        .iter_mut()
+        .flat_map(|diagnostic| &mut diagnostic.message.spans)
+    {
+        let path = lsp::resolve_path(repo_path, &span.file_name);
+        let contents = files
+            .entry(path)
+            .or_insert_with_key(|path| std::fs::read_to_string(path).ok());
+        span.text = contents
+            .as_deref()
+            .and_then(|contents| span_text_in(contents, span));
+    }
+}
+
+/// An identity for `diagnostic` that stays the same across runs and edits elsewhere in its
+/// file: a hash of its code, its message, the file of its user-facing primary span and the
+/// source text of the lines that span covers, with whitespace collapsed. The line number only
+/// replaces the text when the file can't be read. File names are resolved against `repo_path`.
+pub fn fingerprint(diagnostic: &Diagnostic, repo_path: &Path) -> String {
+    let contents = diagnostic.message.primary_span().and_then(|span| {
+        std::fs::read_to_string(lsp::resolve_path(repo_path, &span.file_name)).ok()
+    });
+    fingerprint_in(diagnostic, contents.as_deref())
+}
+
+fn fingerprint_in(diagnostic: &Diagnostic, contents: Option<&str>) -> String {
+    let span = diagnostic.message.primary_span().map(user_facing_span);
+    let (file_name, context) = match &span {
+        Some(span) => {
+            let lines = contents.map(|contents| {
+                contents
+                    .lines()
+                    .skip(usize::try_from(span.line_start - 1).unwrap_or(0))
+                    .take(usize::try_from(span.line_end - span.line_start + 1).unwrap_or(1))
+                    .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+                    .collect::<Vec<_>>()
+            });
+            let context = match lines {
+                Some(lines) if !lines.is_empty() => lines.join("\n"),
+                _ => format!("line {}", span.line_start),
+            };
+            (span.file_name.as_str(), context)
+        }
+        None => ("", String::new()),
+    };
+    let hash = source::hash_parts(&[
+        synthetic_code(diagnostic).as_bytes(),
+        diagnostic.message.message.as_bytes(),
+        file_name.as_bytes(),
+        context.as_bytes(),
+    ]);
+    format!("{hash:016x}")
+}
+
+/// Fills in the [`fingerprint`] of every diagnostic, reading each file once.
+pub(crate) fn add_fingerprints(diagnostics: &mut [Diagnostic], repo_path: &Path) {
+    let mut files: HashMap<PathBuf, Option<String>> = HashMap::new();
+    for diagnostic in diagnostics {
+        let contents = diagnostic.message.primary_span().and_then(|span| {
+            files
+                .entry(lsp::resolve_path(repo_path, &span.file_name))
+                .or_insert_with_key(|path| std::fs::read_to_string(path).ok())
+                .as_deref()
+        });
+        diagnostic.fingerprint = fingerprint_in(diagnostic, contents);
+    }
+}
+
+/// The diagnostics whose [`fingerprint`] isn't among `known`. A fingerprint known once only
+/// accounts for one diagnostic, so a second copy of a known diagnostic counts as new.
+pub(crate) fn introduced_since(diagnostics: &[Diagnostic], known: &[String]) -> Vec<Diagnostic> {
+    let mut remaining: HashMap<&str, usize> = HashMap::new();
+    for fingerprint in known {
+        *remaining.entry(fingerprint).or_insert(0) += 1;
+    }
+    diagnostics
+        .iter()
+        .filter(|diagnostic| {
+            match remaining.get_mut(diagnostic.fingerprint.as_str()) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    false
+                }
+                _ => true,
+            }
+        })
+        .cloned()
+        .collect()
+}
+
+/// Whether `span`, once traced back to user code, points outside of `repo_path`, e.g. into a
+/// registry dependency under `~/.cargo/registry` or the standard library.
+pub(crate) fn is_external(span: &DiagnosticSpan, repo_path: &Path) -> bool {
+    let span = user_facing_span(span);
+    let root = repo_path
+        .canonicalize()
+        .unwrap_or_else(|_| repo_path.to_path_buf());
+    !lexical_path(&root, &span.file_name).starts_with(&root)
+}
+
+/// Resolves `file_name` against `root`, removing `..` by hand since files that no longer exist
+/// can't be canonicalized.
+fn lexical_path(root: &Path, file_name: &str) -> PathBuf {
+    without_parent_dirs(&lsp::resolve_path(root, file_name))
+}
+
+/// `path` with every `..` removed along with the component before it, without touching the
+/// file system.
+pub(crate) fn without_parent_dirs(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Shifts the lines and columns of every span, including those of macro expansions, from the
+/// 1-based coordinates flux reports to `base`, which is 0 or 1. The rendered text keeps rustc's
+/// 1-based locations either way.
+pub(crate) fn rebase_coordinates(diagnostics: &mut [Diagnostic], base: u8) {
+    let shift = i64::from(base) - 1;
+    if shift == 0 {
+        return;
+    }
+    for span in diagnostics.iter_mut().flat_map(|diagnostic| {
+        let message = &mut diagnostic.message;
+        let children = message
+            .children
+            .iter_mut()
+            .flat_map(|child| &mut child.spans);
+        message.spans.iter_mut().chain(children)
+    }) {
+        rebase_span(span, shift);
+    }
+}
+
+fn rebase_span(span: &mut DiagnosticSpan, shift: i64) {
+    span.line_start += shift;
+    span.column_start += shift;
+    span.line_end += shift;
+    span.column_end += shift;
+    if let Some(expansion) = &mut span.expansion {
+        rebase_span(&mut expansion.span, shift);
+        if let Some(def_site) = &mut expansion.def_site_span {
+            rebase_span(def_site, shift);
+        }
+    }
+}
+
+/// Rewrites every span path, which flux reports relative to `root`, to be relative to
+/// `repo_path`, or absolute if it lies outside of it. The locations in the rendered text that
+/// point at a rewritten span, i.e. `--> file:line:col` and `::: file:line:col`, are rewritten to
+/// match, leaving the rest of the text alone.
+pub(crate) fn normalize_paths(diagnostics: &mut [Diagnostic], root: &Path, repo_path: &Path) {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let repo_path = repo_path
+        .canonicalize()
+        .unwrap_or_else(|_| repo_path.to_path_buf());
+    for diagnostic in diagnostics {
+        let message = &mut diagnostic.message;
+        let mut locations = Vec::new();
+        let children = message
+            .children
+            .iter_mut()
+            .flat_map(|child| &mut child.spans);
+        for span in message.spans.iter_mut().chain(children) {
+            normalize_span(span, &root, &repo_path, &mut locations);
+        }
+        let Some(rendered) = &mut message.rendered else { continue };
+        for (old, new) in locations {
+            for arrow in ["--> ", "::: "] {
+                *rendered = rendered.replace(&format!("{arrow}{old}"), &format!("{arrow}{new}"));
+            }
+        }
+    }
+}
+
+/// Normalizes `span` and the spans of its macro expansion, collecting the old and new
+/// `file:line:col` of every span whose path changed.
+fn normalize_span(
+    span: &mut DiagnosticSpan,
+    root: &Path,
+    repo_path: &Path,
+    locations: &mut Vec<(String, String)>,
+) {
+    let path = lexical_path(root, &span.file_name);
+    let file_name = match path.strip_prefix(repo_path) {
+        Ok(relative) => relative.to_string_lossy().into_owned(),
+        Err(_) => path.to_string_lossy().into_owned(),
+    };
+    if file_name != span.file_name {
+        let location =
+            |file_name: &str| format!("{file_name}:{}:{}", span.line_start, span.column_start);
+        locations.push((location(&span.file_name), location(&file_name)));
+        span.file_name = file_name;
+    }
+    if let Some(expansion) = &mut span.expansion {
+        normalize_span(&mut expansion.span, root, repo_path, locations);
+        if let Some(def_site) = &mut expansion.def_site_span {
+            normalize_span(def_site, root, repo_path, locations);
+        }
+    }
+}
+
+/// Drops the diagnostics whose primary span [is external](is_external) to `repo_path`.
+/// Diagnostics without a primary span are kept.
+pub(crate) fn retain_local(diagnostics: &mut Vec<Diagnostic>, repo_path: &Path) {
+    diagnostics.retain(|diagnostic| {
+        diagnostic
+            .message
+            .primary_span()
+            .is_none_or(|span| !is_external(span, repo_path))
+    });
+}
+
+/// Drops the copies of a library's diagnostics reported again for another of its targets, e.g.
+/// when the library is also compiled as a test harness. Diagnostics identical except for their
+/// `target` are only kept for the `lib` target, and only if one of them is for it.
+pub(crate) fn prefer_lib_target(diagnostics: &mut Vec<Diagnostic>) {
+    let is_lib = |diagnostic: &Diagnostic| {
+        diagnostic
+            .target
+            .as_ref()
+            .and_then(|target| target.kind.as_ref())
+            .is_some_and(|kind| kind.iter().any(|kind| kind == "lib"))
+    };
+    let without_target = |diagnostic: &Diagnostic| {
+        serde_json::to_string(&Diagnostic { target: None, ..diagnostic.clone() }).unwrap()
+    };
+    let in_lib: HashSet<String> = diagnostics
+        .iter()
+        .filter(|diagnostic| is_lib(diagnostic))
+        .map(without_target)
+        .collect();
+    diagnostics
+        .retain(|diagnostic| is_lib(diagnostic) || !in_lib.contains(&without_target(diagnostic)));
+}
+
+/// Drops the diagnostics whose primary span is in a file matching one of `patterns`. Paths are
+/// matched relative to `repo_path`, the directory flux ran in.
+pub(crate) fn exclude_by_glob(
+    diagnostics: &mut Vec<Diagnostic>,
+    patterns: &[String],
+    repo_path: &Path,
+) -> Result<(), globset::Error> {
+    if patterns.is_empty() {
+        return Ok(());
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    let globs = builder.build()?;
+    let root = repo_path
+        .canonicalize()
+        .unwrap_or_else(|_| repo_path.to_path_buf());
+    diagnostics.retain(|diagnostic| {
+        let Some(span) = diagnostic.message.primary_span() else { return true };
+        let span = user_facing_span(span);
+        let path = lsp::resolve_path(&root, &span.file_name);
+        let relative = path
+            .strip_prefix(&root)
+            .unwrap_or(Path::new(&span.file_name));
+        !globs.is_match(relative)
+    });
+    Ok(())
+}
+
+/// Keeps the diagnostics whose message or rendered text matches `re`.
+pub(crate) fn filter_by_regex(diagnostics: &mut Vec<Diagnostic>, re: &Regex) {
+    diagnostics.retain(|diagnostic| {
+        re.is_match(&diagnostic.message.message)
+            || diagnostic
+                .message
+                .rendered
+                .as_deref()
+                .is_some_and(|rendered| re.is_match(rendered))
+    });
+}
+
+/// The package name in a cargo package id, which is either `foo 0.1.0 (path+file:///...)` or a
+/// package id spec like `path+file:///.../foo#0.1.0` or `registry+https://...#foo@0.1.0`. Ids
+/// are kept as the JSON flux printed, so surrounding quotes are dropped.
+pub(crate) fn package_name(package_id: &str) -> &str {
+    let id = package_id.trim_matches('"');
+    match id.rsplit_once('#') {
+        Some((_, fragment)) if fragment.contains('@') => fragment.split('@').next().unwrap_or(id),
+        Some((url, _)) => url.rsplit('/').next().unwrap_or(id),
+        None => id.split_whitespace().next().unwrap_or(id),
+    }
+}
+
+/// Groups diagnostics by the [name](package_name) of their package, with the diagnostics
+/// without a package id under `<unknown>`.
+pub(crate) fn group_by_package(diagnostics: Vec<Diagnostic>) -> BTreeMap<String, Vec<Diagnostic>> {
+    let mut groups: BTreeMap<String, Vec<Diagnostic>> = BTreeMap::new();
+    for diagnostic in diagnostics {
+        let name = diagnostic
+            .package_id
+            .as_deref()
+            .map_or("<unknown>", package_name)
+            .to_string();
+        groups.entry(name).or_default().push(diagnostic);
+    }
+    groups
+}
+
+/// Serializes the diagnostics as newline-delimited JSON, one object per line. Newlines inside
+/// the diagnostics are escaped, so every line is a whole diagnostic.
+pub(crate) fn to_ndjson(diagnostics: &[Diagnostic]) -> String {
+    let mut res = String::new();
+    for diagnostic in diagnostics {
+        res.push_str(&serde_json::to_string(diagnostic).unwrap());
+        res.push('\n');
+    }
+    res
+}
+
+/// Strips a diagnostic down to its level, message, code and primary span, dropping the
+/// secondary spans, the children, the rendered text and the code's explanation. The primary
+/// span keeps its label.
+pub(crate) fn minimize(diagnostic: &mut Diagnostic) {
+    diagnostic.counterexample = None;
+    let message = &mut diagnostic.message;
+    message.rendered = None;
+    message.code_explanation = None;
+    message.children = vec![];
+    let primary = message.spans.iter().position(|span| span.is_primary);
+    message.spans = match primary {
+        Some(i) => vec![message.spans.swap_remove(i)],
+        None => vec![],
+    };
+}
+
+/// What [`fit_to_budget`] dropped to make a report fit.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct BudgetTruncation {
+    /// Always true, so that clients can tell the report was cut down
+    pub truncated: bool,
+    /// How many diagnostics lost their rendered text
+    pub dropped_rendered: usize,
+    /// How many secondary spans were dropped
+    pub dropped_spans: usize,
+    /// How many diagnostics were dropped, least severe first
+    pub dropped_diagnostics: usize,
+}
+
+/// Cuts the diagnostics of `report` down until they serialize to at most `max_chars`
+/// characters as a JSON array, dropping the least important content first: the rendered text,
+/// then the secondary spans, then whole diagnostics from the least severe. Each step goes from
+/// the last diagnostic backwards and stops as soon as the report fits. Returns what was dropped,
+/// or `None` if the report already fit.
+pub(crate) fn fit_to_budget(
+    report: &mut VerificationReport,
+    max_chars: usize,
+) -> Option<BudgetTruncation> {
+    let size = |diagnostic: &Diagnostic| {
+        serde_json::to_string(diagnostic).map_or(0, |text| text.chars().count())
+    };
+    let diagnostics = &mut report.diagnostics;
+    let mut sizes: Vec<usize> = diagnostics.iter().map(size).collect();
+    // The brackets and the commas between the diagnostics count too.
+    let fits = |sizes: &[usize]| {
+        sizes.iter().sum::<usize>() + sizes.len().saturating_sub(1) + 2 <= max_chars
+    };
+    let mut dropped = BudgetTruncation { truncated: true, ..Default::default() };
+    for i in (0..diagnostics.len()).rev() {
+        if fits(&sizes) {
+            break;
+        }
+        if diagnostics[i].message.rendered.take().is_some() {
+            dropped.dropped_rendered += 1;
+            sizes[i] = size(&diagnostics[i]);
+        }
+    }
+    for i in (0..diagnostics.len()).rev() {
+        if fits(&sizes) {
+            break;
+        }
+        let spans = &mut diagnostics[i].message.spans;
+        let before = spans.len();
+        spans.retain(|span| span.is_primary);
+        if spans.len() < before {
+            dropped.dropped_spans += before - spans.len();
+            sizes[i] = size(&diagnostics[i]);
+        }
+    }
+    while !fits(&sizes) {
+        let Some(i) = (0..diagnostics.len())
+            .min_by_key(|&i| (diagnostics[i].message.parsed_level, std::cmp::Reverse(i)))
+        else {
+            break;
+        };
+        diagnostics.remove(i);
+        sizes.remove(i);
+        dropped.dropped_diagnostics += 1;
+    }
+    (dropped != BudgetTruncation { truncated: true, ..Default::default() }).then_some(dropped)
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&apos;"),
+            '\n' => res.push_str("&#10;"),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// Renders the report in the Checkstyle XML format, with one `<file>` element per file holding
+/// the diagnostics whose primary span is in that file. Diagnostics without a primary span are
+/// omitted since Checkstyle errors must be attached to a file.
+pub(crate) fn to_checkstyle(report: &VerificationReport) -> String {
+    let mut files: BTreeMap<String, Vec<(&DiagnosticMessage, DiagnosticSpan)>> = BTreeMap::new();
+    for diagnostic in &report.diagnostics {
+        let Some(span) = diagnostic.message.primary_span() else { continue };
+        let span = user_facing_span(span);
+        files
+            .entry(span.file_name.clone())
+            .or_default()
+            .push((&diagnostic.message, span));
+    }
+    let mut res = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    res.push_str("<checkstyle version=\"4.3\">\n");
+    for (file_name, errors) in files {
+        writeln!(res, "  <file name=\"{}\">", escape_xml(&file_name)).unwrap();
+        for (message, span) in errors {
+            let severity = match message.level.as_str() {
+                "error" => "error",
+                "warning" => "warning",
+                _ => "info",
+            };
+            let source = message.code.as_deref().unwrap_or("flux");
+            writeln!(
+                res,
+                "    <error line=\"{}\" column=\"{}\" severity=\"{severity}\" message=\"{}\" source=\"{}\"/>",
+                span.line_start,
+                span.column_start,
+                escape_xml(&message.message),
+                escape_xml(source),
+            )
+            .unwrap();
+        }
+        res.push_str("  </file>\n");
+    }
+    res.push_str("</checkstyle>\n");
+    res
+}
+
+/// Whether terminal output should be colored, which it is unless `NO_COLOR` is set to anything
+/// but the empty string.
+pub(crate) fn color_enabled() -> bool {
+    std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+/// Renders the report for a human at a terminal, like rustc does: each diagnostic's level and
+/// message, the location of its user-facing primary span, and the first line of source it
+/// covers with carets under the span and its label. Paths are resolved against `root`. With
+/// `color`, levels and gutters are colored with ANSI escapes.
+pub(crate) fn render_terminal(report: &VerificationReport, root: &Path, color: bool) -> String {
+    let mut sources = HashMap::new();
+    let mut res = String::new();
+    for diagnostic in &report.diagnostics {
+        render_diagnostic(&mut res, &diagnostic.message, root, color, &mut sources);
+        res.push('\n');
+    }
+    res
+}
+
+/// Reconstructs a rustc-style rendering of a diagnostic from its structured fields and the
+/// source under `repo_path`, for messages flux printed without a `rendered` form. A primary span
+/// covering several lines is underlined on its first.
+pub(crate) fn render_from_spans(diagnostic: &Diagnostic, repo_path: &Path) -> String {
+    let mut res = String::new();
+    render_diagnostic(&mut res, &diagnostic.message, repo_path, false, &mut HashMap::new());
+    res
+}
+
+/// Renders a diagnostic like [`render_from_spans`], followed by each of its notes and help
+/// beneath it as rustc lays them out: a child without a span as a `= note: ...` line, one with
+/// a primary span with its own location and snippet.
+pub(crate) fn render_with_children(diagnostic: &Diagnostic, repo_path: &Path) -> String {
+    let mut sources = HashMap::new();
+    let mut res = render_from_spans(diagnostic, repo_path);
+    let gutter = diagnostic
+        .message
+        .primary_span()
+        .map_or(0, |span| user_facing_span(span).line_start.to_string().len());
+    for child in &diagnostic.message.children {
+        if child.primary_span().is_some() {
+            render_diagnostic(&mut res, child, repo_path, false, &mut sources);
+        } else {
+            writeln!(res, "{} = {}: {}", " ".repeat(gutter), child.level, child.message).unwrap();
+        }
+    }
+    res
+}
+
+/// Renders the diagnostics as rustc's `--error-format=human` stream: each diagnostic's
+/// `rendered` text, reconstructed with [`render_from_spans`] where flux printed none, followed by
+/// rustc's closing count of errors and warnings.
+pub(crate) fn render_human(diagnostics: &[Diagnostic], repo_path: &Path) -> String {
+    let mut sources = HashMap::new();
+    let mut res = String::new();
+    for diagnostic in diagnostics {
+        match &diagnostic.message.rendered {
+            Some(rendered) => {
+                res.push_str(rendered.trim_end());
+                res.push('\n');
+            }
+            None => {
+                render_diagnostic(&mut res, &diagnostic.message, repo_path, false, &mut sources);
+            }
+        }
+        res.push('\n');
+    }
+    let count = |level| {
+        diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.message.parsed_level == level)
+            .count()
+    };
+    let plural = |n: usize| if n == 1 { "" } else { "s" };
+    let (errors, warnings) = (count(Level::Error), count(Level::Warning));
+    let warnings_emitted = format!("{warnings} warning{} emitted", plural(warnings));
+    match (errors, warnings) {
+        (0, 0) => {}
+        (0, _) => res.push_str(&format!("warning: {warnings_emitted}\n\n")),
+        (_, 0) => {
+            res.push_str(&format!(
+                "error: aborting due to {errors} previous error{}\n\n",
+                plural(errors)
+            ));
+        }
+        _ => {
+            res.push_str(&format!(
+                "error: aborting due to {errors} previous error{}; {warnings_emitted}\n\n",
+                plural(errors)
+            ));
+        }
+    }
+    res
+}
+
+/// Appends the rendering of [`render_terminal`] for one diagnostic to `res`, reading source
+/// files through the `sources` cache.
+fn render_diagnostic(
+    res: &mut String,
+    message: &DiagnosticMessage,
+    root: &Path,
+    color: bool,
+    sources: &mut HashMap<String, Option<String>>,
+) {
+    let paint = |style: &str, text: &str| {
+        if color { format!("\x1b[{style}m{text}\x1b[0m") } else { text.to_string() }
+    };
+    let style = match message.parsed_level {
+        Level::Error | Level::Ice => "1;31",
+        Level::Warning => "1;33",
+        Level::Note | Level::FailureNote => "1;32",
+        Level::Help => "1;36",
+        Level::Unknown => "1",
+    };
+    writeln!(res, "{}: {}", paint(style, &message.level), paint("1", &message.message)).unwrap();
+    let Some(span) = message.primary_span() else { return };
+    let span = user_facing_span(span);
+    let line_no = span.line_start.to_string();
+    let gutter = " ".repeat(line_no.len());
+    writeln!(
+        res,
+        "{gutter}{} {}:{}:{}",
+        paint("1;34", "-->"),
+        span.file_name,
+        span.line_start,
+        span.column_start
+    )
+    .unwrap();
+    let contents = sources
+        .entry(span.file_name.clone())
+        .or_insert_with(|| std::fs::read_to_string(lsp::resolve_path(root, &span.file_name)).ok());
+    let line = contents.as_deref().and_then(|contents| {
+        contents
+            .lines()
+            .nth(usize::try_from(span.line_start).ok()?.checked_sub(1)?)
+    });
+    if let Some(line) = line {
+        let width = line.chars().count();
+        let start = usize::try_from(span.column_start - 1)
+            .unwrap_or(0)
+            .min(width);
+        let end = if span.line_end == span.line_start {
+            usize::try_from(span.column_end - 1).unwrap_or(0).min(width)
+        } else {
+            width
+        };
+        let mut carets = " ".repeat(start) + &"^".repeat(end.saturating_sub(start).max(1));
+        if let Some(label) = &span.label {
+            carets = format!("{carets} {label}");
+        }
+        let bar = paint("1;34", "|");
+        writeln!(res, "{gutter} {bar}").unwrap();
+        writeln!(res, "{} {bar} {line}", paint("1;34", &line_no)).unwrap();
+        writeln!(res, "{gutter} {bar} {}", paint(style, &carets)).unwrap();
+    }
+}
+
+/// Renders the report in GitLab's Code Quality format: an array of issues, each located at the
+/// user-facing primary span of a diagnostic. Diagnostics without a primary span are omitted.
+pub(crate) fn to_gitlab_codequality(report: &VerificationReport) -> serde_json::Value {
+    let mut issues = Vec::new();
+    for diagnostic in &report.diagnostics {
+        let message = &diagnostic.message;
+        let Some(span) = message.primary_span() else { continue };
+        let span = user_facing_span(span);
+        let severity = match message.level.as_str() {
+            "error" => "major",
+            "warning" => "minor",
+            _ => "info",
+        };
+        let fingerprint = source::hash_parts(&[
+            message.message.as_bytes(),
+            span.file_name.as_bytes(),
+            &span.line_start.to_le_bytes(),
+        ]);
+        issues.push(serde_json::json!({
+            "description": message.message,
+            "check_name": message.code.as_deref().unwrap_or("flux"),
+            "fingerprint": format!("{fingerprint:016x}"),
+            "severity": severity,
+            "location": {
+                "path": span.file_name,
+                "lines": { "begin": span.line_start },
+            },
+        }));
+    }
+    serde_json::Value::Array(issues)
+}
+
+/// Renders the report as tab-separated values with a header row and the columns `level`,
+/// `code`, `file`, `line`, `col` and `message`, one row per user-facing primary span in canonical
+/// order. A diagnostic without a primary span gets one row with an empty location. Backslashes,
+/// tabs and line breaks in the cells are escaped, so every row is one line.
+pub(crate) fn to_tsv(report: &VerificationReport) -> String {
+    let escape = |cell: &str| {
+        cell.replace('\\', "\\\\")
+            .replace('\t', "\\t")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r")
+    };
+    let mut diagnostics = report.diagnostics.clone();
+    sort_canonically(&mut diagnostics);
+    let mut res = String::from("level\tcode\tfile\tline\tcol\tmessage\n");
+    for diagnostic in &diagnostics {
+        let message = &diagnostic.message;
+        let mut locations: Vec<_> = message
+            .spans
+            .iter()
+            .filter(|span| span.is_primary)
+            .map(|span| {
+                let span = user_facing_span(span);
+                (
+                    escape(&span.file_name),
+                    span.line_start.to_string(),
+                    span.column_start.to_string(),
+                )
+            })
+            .collect();
+        if locations.is_empty() {
+            locations.push(Default::default());
+        }
+        for (file, line, col) in locations {
+            let code = message.code.as_deref().unwrap_or_default();
+            writeln!(
+                res,
+                "{}\t{}\t{file}\t{line}\t{col}\t{}",
+                escape(&message.level),
+                escape(code),
+                escape(&message.message)
+            )
+            .unwrap();
+        }
+    }
+    res
+}
+
+/// Renders the report as one Markdown document for a pull request comment: a heading with the
+/// outcome, a table counting the errors and warnings of each file, then a collapsible
+/// `<details>` section per file with each diagnostic's message, a link to its location and its
+/// rendering in a fenced block. Paths are relative to `repo_path`, so that the links resolve
+/// from the repository's root. Diagnostics without a primary span come last, under `Other`.
+pub(crate) fn to_markdown(report: &VerificationReport, repo_path: &Path) -> String {
+    let mut diagnostics = report.diagnostics.clone();
+    sort_canonically(&mut diagnostics);
+    // Keyed first on whether there is no file, so that those come last.
+    let mut files: BTreeMap<(bool, Option<String>), Vec<&Diagnostic>> = BTreeMap::new();
+    for diagnostic in &diagnostics {
+        let file = diagnostic.message.primary_span().map(|span| {
+            let file_name = &user_facing_span(span).file_name;
+            source::display_path(repo_path, Path::new(file_name))
+        });
+        files
+            .entry((file.is_none(), file))
+            .or_default()
+            .push(diagnostic);
+    }
+    let count = |diagnostics: &[&Diagnostic], level| {
+        diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.message.parsed_level == level)
+            .count()
+    };
+
+    let outcome = if report.success { "passed" } else { "failed" };
+    let mut res = format!("## Flux verification {outcome}\n\n");
+    if files.is_empty() {
+        res.push_str("No diagnostics.\n");
+        return res;
+    }
+    res.push_str("| File | Errors | Warnings |\n| --- | ---: | ---: |\n");
+    for ((_, file), diagnostics) in &files {
+        let file = file
+            .as_deref()
+            .map_or("Other".to_string(), |file| format!("`{file}`"));
+        let (errors, warnings) =
+            (count(diagnostics, Level::Error), count(diagnostics, Level::Warning));
+        writeln!(res, "| {file} | {errors} | {warnings} |").unwrap();
+    }
+    let all: Vec<&Diagnostic> = diagnostics.iter().collect();
+    writeln!(
+        res,
+        "| **Total** | **{}** | **{}** |",
+        count(&all, Level::Error),
+        count(&all, Level::Warning)
+    )
+    .unwrap();
+
+    for ((_, file), diagnostics) in &files {
+        let title = file
+            .as_deref()
+            .map_or("Other".to_string(), |file| format!("<code>{}</code>", escape_xml(file)));
+        let plural = if diagnostics.len() == 1 { "" } else { "s" };
+        writeln!(
+            res,
+            "\n<details>\n<summary>{title} ({} diagnostic{plural})</summary>\n",
+            diagnostics.len()
+        )
+        .unwrap();
+        for diagnostic in diagnostics {
+            let message = &diagnostic.message;
+            let code = message
+                .code
+                .as_deref()
+                .map(|code| format!("[{code}]"))
+                .unwrap_or_default();
+            write!(res, "**{}{code}**: {}", message.level, message.message).unwrap();
+            if let (Some(file), Some(span)) = (file, message.primary_span()) {
+                let line = user_facing_span(span).line_start;
+                write!(res, " at [{file}:{line}]({file}#L{line})").unwrap();
+            }
+            let rendered = match &message.rendered {
+                Some(rendered) => rendered.trim_end().to_string(),
+                None => {
+                    render_with_children(diagnostic, repo_path)
+                        .trim_end()
+                        .to_string()
+                }
+            };
+            // The fence must be longer than any run of backticks in the rendering.
+            let longest_run = rendered
+                .split(|c| c != '`')
+                .map(str::len)
+                .max()
+                .unwrap_or_default();
+            let fence = "`".repeat((longest_run + 1).max(3));
+            writeln!(res, "\n\n{fence}text\n{rendered}\n{fence}\n").unwrap();
+        }
+        res.push_str("</details>\n");
+    }
+    res
+}
+
+/// A one-line status for scripts to branch on, in exactly this format:
+///
+/// ```text
+/// FLUX_RESULT errors=<n> warnings=<n> success=<true|false>
+/// ```
+///
+/// The fields always come in this order, separated by single spaces, with no trailing text.
+/// `success` is the run's result, so it's false when flux crashed even with no errors.
+pub(crate) fn status_line(report: &VerificationReport) -> String {
+    let summary = VerificationSummary::of(report.success, &report.diagnostics);
+    format!(
+        "FLUX_RESULT errors={} warnings={} success={}",
+        summary.errors, summary.warnings, summary.success
+    )
+}
+
+/// Where a diagnostic's primary span starts, for stepping through errors in an editor.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Location {
+    pub file_name: String,
+    pub line: i64,
+    pub column: i64,
+    pub level: String,
+}
+
+/// The primary span locations of the report, traced back to user code and sorted by file,
+/// line and column.
+pub(crate) fn locations(report: &VerificationReport) -> Vec<Location> {
+    let mut locations: Vec<_> = report
+        .diagnostics
+        .iter()
+        .filter_map(|diagnostic| {
+            let span = user_facing_span(diagnostic.message.primary_span()?);
+            Some(Location {
+                file_name: span.file_name,
+                line: span.line_start,
+                column: span.column_start,
+                level: diagnostic.message.level.clone(),
+            })
         })
+        .collect();
+    locations
+        .sort_by(|a, b| (&a.file_name, a.line, a.column).cmp(&(&b.file_name, b.line, b.column)));
+    locations
+}
+
+/// What is wrong with a primary span, see [`validate_spans`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SpanIssue {
+    /// The file doesn't exist under the repository, e.g. a generated or virtual file
+    MissingFile,
+    /// A line is before the first or past the last of the file's `lines`
+    LineOutOfRange { lines: usize },
+    /// A column is past the end of its line, which is `width` characters long
+    ColumnOutOfRange { line: i64, width: usize },
+    /// The span ends before it starts
+    Inverted,
+}
+
+/// A primary span that doesn't point at a location in the repository.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SpanProblem {
+    /// The message of the diagnostic the span belongs to
+    pub message: String,
+    pub file_name: String,
+    pub line_start: i64,
+    pub column_start: i64,
+    pub line_end: i64,
+    pub column_end: i64,
+    #[serde(flatten)]
+    pub issue: SpanIssue,
+}
+
+/// Checks that every primary span of the report, as flux reported it, points inside an
+/// existing file under `repo_path`: its lines within the file's and its 1-based columns within
+/// their lines, the end column being one past the last character. Catches flux pointing into
+/// generated files and coordinates going wrong in parsing, which clients would otherwise only
+/// see as files or lines they can't open.
+pub(crate) fn validate_spans(report: &VerificationReport, repo_path: &Path) -> Vec<SpanProblem> {
+    let mut sources: HashMap<String, Option<String>> = HashMap::new();
+    let mut problems = vec![];
+    for diagnostic in &report.diagnostics {
+        for span in diagnostic
+            .message
+            .spans
+            .iter()
+            .filter(|span| span.is_primary)
+        {
+            let contents = sources.entry(span.file_name.clone()).or_insert_with(|| {
+                std::fs::read_to_string(lsp::resolve_path(repo_path, &span.file_name)).ok()
+            });
+            let issue = match contents {
+                None => Some(SpanIssue::MissingFile),
+                Some(contents) => span_issue(span, contents),
+            };
+            if let Some(issue) = issue {
+                problems.push(SpanProblem {
+                    message: diagnostic.message.message.clone(),
+                    file_name: span.file_name.clone(),
+                    line_start: span.line_start,
+                    column_start: span.column_start,
+                    line_end: span.line_end,
+                    column_end: span.column_end,
+                    issue,
+                });
+            }
+        }
+    }
+    problems
+}
+
+fn span_issue(span: &DiagnosticSpan, contents: &str) -> Option<SpanIssue> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let in_range =
+        |line: i64| usize::try_from(line).is_ok_and(|line| 1 <= line && line <= lines.len());
+    if !in_range(span.line_start) || !in_range(span.line_end) {
+        return Some(SpanIssue::LineOutOfRange { lines: lines.len() });
+    }
+    if (span.line_end, span.column_end) < (span.line_start, span.column_start) {
+        return Some(SpanIssue::Inverted);
+    }
+    for (line, column) in [(span.line_start, span.column_start), (span.line_end, span.column_end)] {
+        let width = lines[line as usize - 1].chars().count();
+        if column < 1 || column as usize > width + 1 {
+            return Some(SpanIssue::ColumnOutOfRange { line, width });
+        }
+    }
+    None
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ReportDiff {
+    /// Diagnostics present before but not after
+    pub resolved: Vec<Diagnostic>,
+    /// Diagnostics present after but not before
+    pub introduced: Vec<Diagnostic>,
+    /// Diagnostics present in both reports
+    pub unchanged: Vec<Diagnostic>,
+}
+
+/// A file, line and column.
+type Position<'a> = (&'a str, i64, i64);
+
+/// The identity used to match diagnostics across runs.
+fn diff_key(diagnostic: &Diagnostic) -> (&str, Option<&str>, Option<Position<'_>>) {
+    let span = diagnostic
+        .message
+        .primary_span()
+        .map(|span| (span.file_name.as_str(), span.line_start, span.column_start));
+    (&diagnostic.message.message, diagnostic.message.code.as_deref(), span)
+}
+
+/// Compares two reports, matching diagnostics on their message, code and primary span. Repeated
+/// diagnostics are matched one-to-one.
+pub(crate) fn diff(before: &VerificationReport, after: &VerificationReport) -> ReportDiff {
+    let mut unmatched: Vec<Option<&Diagnostic>> = before.diagnostics.iter().map(Some).collect();
+    let mut introduced = Vec::new();
+    let mut unchanged = Vec::new();
+    for diagnostic in &after.diagnostics {
+        let key = diff_key(diagnostic);
+        let matched = unmatched
+            .iter_mut()
+            .find(|before| before.is_some_and(|before| diff_key(before) == key));
+        match matched {
+            Some(before) => {
+                before.take();
+                unchanged.push(diagnostic.clone());
+            }
+            None => introduced.push(diagnostic.clone()),
+        }
+    }
+    let resolved = unmatched.into_iter().flatten().cloned().collect();
+    ReportDiff { resolved, introduced, unchanged }
+}
+
+/// Identifies a diagnostic in a baseline by its [`fingerprint`], so that edits elsewhere in its
+/// file don't invalidate the baseline. The code, file and message are kept for whoever reviews
+/// the baseline, but don't take part in matching.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DiagnosticFingerprint {
+    pub fingerprint: String,
+    pub code: String,
+    pub file_name: String,
+    pub message: String,
+}
+
+impl DiagnosticFingerprint {
+    pub(crate) fn of(diagnostic: &Diagnostic) -> Self {
+        let file_name = diagnostic
+            .message
+            .primary_span()
+            .map(user_facing_span)
+            .map_or_else(String::new, |span| span.file_name);
+        DiagnosticFingerprint {
+            fingerprint: diagnostic.fingerprint.clone(),
+            code: synthetic_code(diagnostic),
+            file_name,
+            message: diagnostic.message.message.clone(),
+        }
+    }
+}
+
+/// The fingerprints of every diagnostic in the report, to be suppressed by future runs. The
+/// diagnostics are expected to come with their [`fingerprint`], see [`add_fingerprints`].
+pub(crate) fn generate_baseline(report: &VerificationReport) -> Vec<DiagnosticFingerprint> {
+    report
+        .diagnostics
+        .iter()
+        .map(DiagnosticFingerprint::of)
         .collect()
 }
+
+/// Removes the diagnostics whose [`fingerprint`] matches `baseline`, one-to-one, and recomputes `success` from the
+/// remaining errors, internal compiler errors included. A failed run only becomes a success when
+/// the errors it reported were all suppressed, so a run that failed without reporting any error,
+/// e.g. because flux crashed, still fails.
+pub(crate) fn apply_baseline(
+    mut report: VerificationReport,
+    baseline: &[DiagnosticFingerprint],
+) -> VerificationReport {
+    let mut suppressed: HashMap<&str, usize> = HashMap::new();
+    for known in baseline {
+        *suppressed.entry(&known.fingerprint).or_insert(0) += 1;
+    }
+    let mut suppressed_errors = false;
+    report.diagnostics.retain(|diagnostic| {
+        match suppressed.get_mut(diagnostic.fingerprint.as_str()) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                suppressed_errors |= diagnostic.message.parsed_level >= Level::Error;
+                false
+            }
+            _ => true,
+        }
+    });
+    let errors = report
+        .diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.message.parsed_level >= Level::Error);
+    report.success = !errors && (report.success || suppressed_errors);
+    report
+}
+
+/// Whether the report counts as a success once errors whose [`synthetic_code`] is in `allowed`
+/// are let through. Those diagnostics stay in the report, and internal compiler errors count as
+/// errors. A run that failed without reporting any error, e.g. because flux crashed, still fails.
+pub(crate) fn success_ignoring(report: &VerificationReport, allowed: &[String]) -> bool {
+    let mut errors = report
+        .diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.message.parsed_level >= Level::Error)
+        .peekable();
+    if errors.peek().is_none() {
+        return report.success;
+    }
+    errors.all(|diagnostic| allowed.contains(&synthetic_code(diagnostic)))
+}
+
+/// Whether `report` passes when every warning counts as a failure, whatever flux's exit
+/// status.
+pub(crate) fn success_with_deny_warnings(report: &VerificationReport) -> bool {
+    report.success
+        && !report
+            .diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.message.parsed_level == Level::Warning)
+}
+
+pub(crate) fn save_baseline(path: &Path, baseline: &[DiagnosticFingerprint]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(baseline)?)
+}
+
+pub(crate) fn load_baseline(path: &Path) -> io::Result<Vec<DiagnosticFingerprint>> {
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(
+        file_name: &str,
+        start: (i64, i64),
+        end: (i64, i64),
+        is_primary: bool,
+    ) -> DiagnosticSpan {
+        DiagnosticSpan {
+            file_name: file_name.to_string(),
+            line_start: start.0,
+            column_start: start.1,
+            line_end: end.0,
+            column_end: end.1,
+            is_primary,
+            expansion: None,
+            label: None,
+            byte_start: None,
+            byte_end: None,
+            text: None,
+        }
+    }
+
+    fn diagnostic(level: &str, message: &str, spans: Vec<DiagnosticSpan>) -> Diagnostic {
+        Diagnostic {
+            message: DiagnosticMessage {
+                level: level.to_string(),
+                parsed_level: level.parse().unwrap(),
+                message: message.to_string(),
+                code: None,
+                code_explanation: None,
+                rendered: None,
+                spans,
+                children: vec![],
+            },
+            package_id: None,
+            target: None,
+            enclosing_item: None,
+            counterexample: None,
+            synthetic_code: String::new(),
+            fingerprint: String::new(),
+        }
+    }
+
+    #[test]
+    fn sorting_by_kind_groups_flux_errors_of_a_kind() {
+        let mut diagnostics = vec![
+            diagnostic("error", "arithmetic operation may overflow", vec![]),
+            diagnostic("warning", "unused variable: `x`", vec![]),
+            diagnostic("error", "refinement type error", vec![span("a.rs", (1, 1), (1, 2), true)]),
+            diagnostic("error", "arithmetic operation may overflow", vec![]),
+            diagnostic("error", "refinement type error", vec![span("b.rs", (1, 1), (1, 2), true)]),
+        ];
+        sort(&mut diagnostics, SortOrder::ByKind);
+        let sorted: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let file = diagnostic
+                    .message
+                    .primary_span()
+                    .map(|span| span.file_name.as_str());
+                (diagnostic.message.message.as_str(), file)
+            })
+            .collect();
+        assert_eq!(
+            sorted,
+            [
+                ("refinement type error", Some("a.rs")),
+                ("refinement type error", Some("b.rs")),
+                ("arithmetic operation may overflow", None),
+                ("arithmetic operation may overflow", None),
+                ("unused variable: `x`", None),
+            ]
+        );
+        let order: SortOrder = serde_json::from_str("\"kind\"").unwrap();
+        assert_eq!(order, SortOrder::ByKind);
+    }
+
+    #[test]
+    fn merge_spans_coalesces_touching_spans() {
+        let merged = merge_spans(vec![
+            span("a.rs", (1, 1), (1, 5), true),
+            span("a.rs", (3, 1), (3, 4), true),
+            span("a.rs", (1, 5), (2, 3), true),
+            span("b.rs", (1, 1), (1, 5), true),
+            span("a.rs", (1, 2), (1, 3), false),
+        ]);
+        let ranges: Vec<_> = merged
+            .iter()
+            .map(|span| (span.file_name.as_str(), span.start(), span.end(), span.is_primary))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                ("a.rs", (1, 1), (2, 3), true),
+                ("a.rs", (3, 1), (3, 4), true),
+                ("b.rs", (1, 1), (1, 5), true),
+                ("a.rs", (1, 2), (1, 3), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn user_facing_span_follows_expansions() {
+        let span = parse_span(&serde_json::json!({
+            "file_name": "/rustc/library/core/src/macros/mod.rs",
+            "line_start": 10, "column_start": 1, "line_end": 12, "column_end": 2,
+            "is_primary": true,
+            "expansion": {
+                "macro_decl_name": "inner!",
+                "span": {
+                    "file_name": "src/macros.rs",
+                    "line_start": 4, "column_start": 9, "line_end": 4, "column_end": 20,
+                    "is_primary": false,
+                    "expansion": {
+                        "macro_decl_name": "outer!",
+                        "span": {
+                            "file_name": "src/lib.rs",
+                            "line_start": 7, "column_start": 5, "line_end": 7, "column_end": 15,
+                            "is_primary": false
+                        }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let user = user_facing_span(&span);
+        assert_eq!(user.file_name, "src/lib.rs");
+        assert_eq!((user.line_start, user.column_start), (7, 5));
+        assert!(user.is_primary);
+    }
+
+    #[test]
+    fn locations_are_primary_spans_sorted_and_traced_through_expansions() {
+        let mut expanded = span("/rustc/library/core/src/macros/mod.rs", (10, 1), (12, 2), true);
+        expanded.expansion = Some(Box::new(DiagnosticSpanExpansion {
+            span: span("src/lib.rs", (2, 5), (2, 15), false),
+            macro_decl_name: "assert!".to_string(),
+            def_site_span: None,
+        }));
+        let report = VerificationReport {
+            diagnostics: vec![
+                diagnostic("error", "b", vec![span("src/b.rs", (1, 1), (1, 2), true)]),
+                diagnostic("warning", "expanded", vec![expanded]),
+                diagnostic(
+                    "error",
+                    "a",
+                    vec![
+                        span("src/a.rs", (9, 1), (9, 2), false),
+                        span("src/lib.rs", (2, 1), (2, 4), true),
+                    ],
+                ),
+                diagnostic(
+                    "error",
+                    "no primary span",
+                    vec![span("src/a.rs", (1, 1), (1, 2), false)],
+                ),
+            ],
+            ..Default::default()
+        };
+        let locations: Vec<_> = locations(&report)
+            .into_iter()
+            .map(|location| (location.file_name, location.line, location.column, location.level))
+            .collect();
+        assert_eq!(
+            locations,
+            [
+                ("src/b.rs".to_string(), 1, 1, "error".to_string()),
+                ("src/lib.rs".to_string(), 2, 1, "error".to_string()),
+                ("src/lib.rs".to_string(), 2, 5, "warning".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn enclosing_impls_are_the_innermost_and_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        let contents = "fn free() {}\n\
+                        #[flux::refined_by(n: int)]\n\
+                        impl<T: Copy> Outer<T> where T: Default {\n    \
+                        fn f() {\n        \
+                        impl Inner {\n            \
+                        fn g() {}\n        \
+                        }\n    \
+                        }\n\
+                        }\n";
+        std::fs::write(dir.path().join("src/lib.rs"), contents).unwrap();
+        let at = |line| enclosing_impl(&span("src/lib.rs", (line, 1), (line, 2), true), dir.path());
+
+        assert_eq!(at(1), None);
+        let outer = at(4).unwrap();
+        assert!(outer.starts_with("#[flux::refined_by(n: int)]\nimpl<T: Copy> Outer<T> where"));
+        assert!(outer.ends_with("    }\n}"));
+        assert_eq!(at(6).unwrap(), "impl Inner {\n            fn g() {}\n        }");
+
+        let methods = "    fn f() {}\n".repeat(MAX_IMPL_CONTEXT_BYTES / 10);
+        std::fs::write(dir.path().join("src/lib.rs"), format!("impl A {{\n{methods}}}\n")).unwrap();
+        let capped = at(2).unwrap();
+        assert!(capped.len() <= MAX_IMPL_CONTEXT_BYTES + 64);
+        assert!(capped.ends_with("    fn f() {}\n// ... the rest of the impl block is cut\n"));
+    }
+
+    #[test]
+    fn enclosing_item_is_the_innermost_item() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        let source = "struct Foo;\n\nimpl Foo {\n    fn bar(&self) {\n        let x = 1;\n    }\n}\n\nfn baz() {}\n";
+        std::fs::write(dir.path().join("src/lib.rs"), source).unwrap();
+
+        let mut cache = ItemCache::default();
+        let mut enclosing = |line| {
+            enclosing_item(&span("src/lib.rs", (line, 1), (line, 2), true), dir.path(), &mut cache)
+        };
+        assert_eq!(enclosing(5).as_deref(), Some("Foo::bar"));
+        assert_eq!(enclosing(7).as_deref(), Some("impl Foo"));
+        assert_eq!(enclosing(9).as_deref(), Some("baz"));
+        assert_eq!(enclosing(8), None);
+        assert_eq!(cache.files.len(), 1);
+    }
+
+    #[test]
+    fn columns_are_converted_to_byte_offsets() {
+        let contents = "fn f() {}\r\n\tlet é = \"∀x\";\n";
+        assert_eq!(column_to_byte_offset(contents, 1, 1), 0);
+        assert_eq!(column_to_byte_offset(contents, 1, 4), 3);
+        // Line 2 starts after the `\r\n`, its tab counts as one column.
+        assert_eq!(column_to_byte_offset(contents, 2, 1), 11);
+        assert_eq!(column_to_byte_offset(contents, 2, 6), 16);
+        assert_eq!(column_to_byte_offset(contents, 2, 7), 18);
+        assert_eq!(column_to_byte_offset(contents, 2, 12), 25);
+        assert_eq!(column_to_byte_offset(contents, 2, 100), 28);
+        assert_eq!(column_to_byte_offset(contents, 5, 1), contents.len());
+    }
+
+    #[test]
+    fn primary_labels_are_parsed_and_rendered() {
+        let message = serde_json::json!({
+            "level": "error",
+            "message": "refinement type error",
+            "code": null,
+            "rendered": null,
+            "spans": [
+                {"file_name": "src/lib.rs", "line_start": 1, "column_start": 1, "line_end": 1,
+                 "column_end": 4, "is_primary": false, "label": "inside this call"},
+                {"file_name": "src/lib.rs", "line_start": 3, "column_start": 5, "line_end": 3,
+                 "column_end": 9, "is_primary": true, "label": "a precondition cannot be proved"},
+            ],
+        });
+        let message = parse_message(&message).unwrap();
+        assert_eq!(message.spans[0].label.as_deref(), Some("inside this call"));
+        assert_eq!(
+            message.render_short(),
+            "src/lib.rs:3:5: error: refinement type error: a precondition cannot be proved"
+        );
+    }
+
+    #[test]
+    fn targets_keep_their_source_path_and_edition() {
+        let target = serde_json::json!({
+            "name": "foo",
+            "kind": ["lib"],
+            "src_path": "/repo/src/lib.rs",
+            "edition": "2021",
+        });
+        let parsed = parse_target(&target).unwrap();
+        assert_eq!(parsed.src_path.as_deref(), Some("/repo/src/lib.rs"));
+        assert_eq!(parsed.edition.as_deref(), Some("2021"));
+
+        let old: DiagnosticTarget = serde_json::from_str(r#"{"name":"foo","kind":null}"#).unwrap();
+        assert_eq!((old.src_path, old.edition), (None, None));
+    }
+
+    #[test]
+    fn counterexamples_are_read_from_fields_and_notes() {
+        let expected = Counterexample {
+            assignments: vec![
+                ("m".to_string(), "0".to_string()),
+                ("n".to_string(), "-1".to_string()),
+            ],
+        };
+        let object = serde_json::json!({"counterexample": {"m": 0, "n": "-1"}});
+        assert_eq!(parse_counterexample(&object).as_ref(), Some(&expected));
+        let pairs = serde_json::json!({"counterexample": [["m", "0"], ["n", -1], ["bad"]]});
+        assert_eq!(parse_counterexample(&pairs).as_ref(), Some(&expected));
+        let note = serde_json::json!({"children": [
+            {"message": "this is the condition that cannot be proved"},
+            {"message": "counterexample: m = 0, n = -1"},
+        ]});
+        assert_eq!(parse_counterexample(&note), Some(expected));
+        let none = serde_json::json!({"children": [], "counterexample": {}});
+        assert_eq!(parse_counterexample(&none), None);
+    }
+
+    #[test]
+    fn codes_are_parsed_from_strings_and_objects() {
+        let message = |code| {
+            serde_json::json!({
+                "level": "error",
+                "message": "mismatched types",
+                "code": code,
+                "rendered": null,
+                "spans": [],
+            })
+        };
+        let parsed = parse_message(&message(serde_json::json!("E0308"))).unwrap();
+        assert_eq!(parsed.code.as_deref(), Some("E0308"));
+        assert_eq!(parsed.code_explanation, None);
+
+        let object =
+            serde_json::json!({"code": "E0308", "explanation": "Expected type did not match"});
+        let parsed = parse_message(&message(object)).unwrap();
+        assert_eq!(parsed.code.as_deref(), Some("E0308"));
+        assert_eq!(parsed.code_explanation.as_deref(), Some("Expected type did not match"));
+
+        let parsed = parse_message(&message(serde_json::Value::Null)).unwrap();
+        assert_eq!((parsed.code, parsed.code_explanation), (None, None));
+    }
+
+    #[test]
+    fn ndjson_has_one_diagnostic_per_line() {
+        let diagnostic = |line| {
+            let mut diagnostic = diagnostic(
+                "error",
+                "refinement type error",
+                vec![span("src/lib.rs", (line, 5), (line, 9), true)],
+            );
+            diagnostic.message.rendered =
+                Some("error: refinement type error\n --> src/lib.rs\n".to_string());
+            diagnostic
+        };
+        let ndjson = to_ndjson(&[diagnostic(3), diagnostic(7)]);
+        let lines: Vec<Diagnostic> = ndjson
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].message.spans[0].line_start, 7);
+        assert!(ndjson.ends_with('\n'));
+    }
+
+    #[test]
+    fn terminal_rendering_points_at_the_primary_span() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "fn f() {\n    let x = g(1);\n}\n").unwrap();
+        let mut primary = span("lib.rs", (2, 13), (2, 17), true);
+        primary.label = Some("a precondition cannot be proved".to_string());
+        let report = VerificationReport {
+            diagnostics: vec![diagnostic(
+                "error",
+                "refinement type error",
+                vec![span("lib.rs", (1, 1), (3, 2), false), primary],
+            )],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render_terminal(&report, dir.path(), false),
+            "error: refinement type error\n \
+             --> lib.rs:2:13\n  \
+             |\n\
+             2 |     let x = g(1);\n  \
+             |             ^^^^ a precondition cannot be proved\n\n"
+        );
+        let colored = render_terminal(&report, dir.path(), true);
+        assert!(colored.starts_with("\x1b[1;31merror\x1b[0m: "));
+    }
+
+    #[test]
+    fn multi_line_primary_spans_are_underlined_on_their_first_line() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "fn f() {\n    g(1,\n      2);\n}\n").unwrap();
+        let diagnostic = diagnostic(
+            "error",
+            "refinement type error",
+            vec![span("lib.rs", (2, 5), (3, 9), true)],
+        );
+
+        assert_eq!(
+            render_from_spans(&diagnostic, dir.path()),
+            "error: refinement type error\n \
+             --> lib.rs:2:5\n  \
+             |\n\
+             2 |     g(1,\n  \
+             |     ^^^^\n"
+        );
+    }
+
+    #[test]
+    fn human_output_prefers_flux_renderings_and_ends_like_rustc() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "fn f() {\n    g(1);\n}\n").unwrap();
+        let diagnostic = |rendered: Option<&str>| {
+            let mut diagnostic = diagnostic(
+                "error",
+                "refinement type error",
+                vec![span("lib.rs", (2, 5), (2, 9), true)],
+            );
+            diagnostic.message.rendered = rendered.map(str::to_string);
+            diagnostic
+        };
+        let diagnostics = [diagnostic(Some("error: from flux\n\n")), diagnostic(None)];
+
+        assert_eq!(
+            render_human(&diagnostics, dir.path()),
+            "error: from flux\n\n\
+             error: refinement type error\n \
+             --> lib.rs:2:5\n  \
+             |\n\
+             2 |     g(1);\n  \
+             |     ^^^^\n\n\
+             error: aborting due to 2 previous errors\n\n"
+        );
+        assert_eq!(render_human(&[], dir.path()), "");
+    }
+
+    #[test]
+    fn rendered_locations_follow_normalized_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("crates/foo/src")).unwrap();
+        let mut diagnostic = diagnostic(
+            "error",
+            "refinement type error",
+            vec![span("crates/foo/src/lib.rs", (3, 5), (3, 9), true)],
+        );
+        diagnostic.message.rendered = Some(
+            "error: refinement type error\n --> crates/foo/src/lib.rs:3:5\n  |\n3 | \
+                     // see crates/foo/src/lib.rs:3:5\n"
+                .to_string(),
+        );
+
+        let mut diagnostics = [diagnostic];
+        normalize_paths(&mut diagnostics, root, &root.join("crates/foo"));
+        let message = &diagnostics[0].message;
+        assert_eq!(message.spans[0].file_name, "src/lib.rs");
+        assert_eq!(
+            message.rendered.as_deref().unwrap(),
+            "error: refinement type error\n --> src/lib.rs:3:5\n  |\n3 | \
+             // see crates/foo/src/lib.rs:3:5\n"
+        );
+    }
+
+    #[test]
+    fn package_names_are_parsed_from_both_id_formats() {
+        assert_eq!(package_name("\"foo 0.1.0 (path+file:///work/foo)\""), "foo");
+        assert_eq!(package_name("\"path+file:///work/crates/foo#0.1.0\""), "foo");
+        assert_eq!(package_name("path+file:///work/crates/foo-dir#foo@0.1.0"), "foo");
+        assert_eq!(
+            package_name("registry+https://github.com/rust-lang/crates.io-index#serde@1.0.0"),
+            "serde"
+        );
+    }
+
+    #[test]
+    fn levels_are_ordered_by_severity() {
+        let level = |level: &str| level.parse::<Level>().unwrap();
+        assert!(level("error") > level("warning"));
+        assert!(level("warning") > level("note"));
+        assert!(level("error: internal compiler error") > level("error"));
+        assert_eq!(level("fatal"), Level::Unknown);
+        assert!(level("fatal") < level("help"));
+    }
+
+    #[test]
+    fn registry_spans_are_external() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry =
+            "/home/user/.cargo/registry/src/index.crates.io-6f17d22bba15001f/foo-0.1.0/src/lib.rs";
+        assert!(!is_external(&span("src/lib.rs", (1, 1), (1, 2), true), dir.path()));
+        assert!(is_external(&span(registry, (1, 1), (1, 2), true), dir.path()));
+        assert!(is_external(&span("../other/src/lib.rs", (1, 1), (1, 2), true), dir.path()));
+    }
+
+    #[test]
+    fn excluded_globs_match_repo_relative_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let diagnostic = |file_name: &str| {
+            diagnostic(
+                "error",
+                "refinement type error",
+                vec![span(file_name, (1, 1), (1, 2), true)],
+            )
+        };
+        let absolute = dir.path().join("src/generated/b.rs");
+        let mut diagnostics = vec![
+            diagnostic("src/lib.rs"),
+            diagnostic("src/generated/a.rs"),
+            diagnostic(absolute.to_str().unwrap()),
+        ];
+        exclude_by_glob(&mut diagnostics, &["src/generated/**".to_string()], dir.path()).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message.spans[0].file_name, "src/lib.rs");
+
+        assert!(exclude_by_glob(&mut diagnostics, &["src/[".to_string()], dir.path()).is_err());
+    }
+
+    #[test]
+    fn failure_sites_keep_only_flux_errors_grouped_by_line() {
+        let diagnostic = |message: &str, spans| diagnostic("error", message, spans);
+        let diagnostics = vec![
+            diagnostic("mismatched types", vec![span("src/lib.rs", (1, 5), (1, 9), true)]),
+            diagnostic(
+                "arithmetic operation may overflow",
+                vec![span("src/lib.rs", (7, 13), (7, 18), true)],
+            ),
+            diagnostic(
+                "refinement type error",
+                vec![
+                    span("src/lib.rs", (2, 1), (2, 4), false),
+                    span("src/lib.rs", (7, 5), (7, 9), true),
+                ],
+            ),
+            diagnostic("assertion might fail", vec![span("src/a.rs", (3, 5), (3, 20), true)]),
+            diagnostic("refinement type error", vec![]),
+        ];
+
+        let sites = failure_sites(diagnostics);
+        let keys: Vec<_> = sites
+            .iter()
+            .map(|site| (site.file_name.as_deref(), site.line, site.failures.len()))
+            .collect();
+        assert_eq!(
+            keys,
+            [(Some("src/a.rs"), Some(3), 1), (Some("src/lib.rs"), Some(7), 2), (None, None, 1)]
+        );
+        let codes: Vec<_> = sites[1]
+            .failures
+            .iter()
+            .map(|failure| failure.code.as_str())
+            .collect();
+        assert_eq!(codes, ["FLUX-REFINEMENT", "FLUX-OVERFLOW"]);
+        let span = sites[1].failures[0].span.as_ref().unwrap();
+        assert_eq!((span.line_start, span.column_start, span.column_end), (7, 5, 9));
+        assert!(span.is_primary);
+        assert!(sites[2].failures[0].span.is_none());
+    }
+
+    #[test]
+    fn span_texts_are_cut_from_the_current_source() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "fn f(x: i32) {\n    x + 1\n}\n").unwrap();
+        let text = |start, end| span_text(&span("lib.rs", start, end, true), dir.path());
+
+        assert_eq!(text((1, 9), (1, 12)).as_deref(), Some("i32"));
+        assert_eq!(text((1, 14), (3, 2)).as_deref(), Some("{\n    x + 1\n}"));
+        assert_eq!(text((2, 5), (2, 10)).as_deref(), Some("x + 1"));
+        assert_eq!(text((2, 5), (2, 12)), None);
+        assert_eq!(text((3, 2), (3, 2)).as_deref(), Some(""));
+        assert_eq!(text((4, 1), (4, 2)), None);
+        assert_eq!(text((2, 9), (2, 5)), None);
+        assert_eq!(span_text(&span("missing.rs", (1, 1), (1, 2), true), dir.path()), None);
+    }
+
+    #[test]
+    fn children_are_rendered_beneath_their_parent() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "fn f(x: i32) -> i32 {\n    x - 1\n}\n").unwrap();
+        let message = serde_json::json!({
+            "level": "error",
+            "message": "refinement type error",
+            "spans": [span("lib.rs", (2, 5), (2, 10), true)],
+            "children": [
+                {"level": "note", "message": "this is the condition that cannot be proved",
+                 "spans": [span("lib.rs", (1, 17), (1, 20), true)], "children": []},
+                {"level": "help", "message": "add a precondition", "spans": [], "children": []},
+            ],
+        });
+        let mut diagnostic = diagnostic("error", "", vec![]);
+        diagnostic.message = parse_message(&message).unwrap();
+        assert_eq!(diagnostic.message.children.len(), 2);
+
+        assert_eq!(
+            render_with_children(&diagnostic, dir.path()),
+            "error: refinement type error\n \
+             --> lib.rs:2:5\n  |\n2 |     x - 1\n  |     ^^^^^\n\
+             note: this is the condition that cannot be proved\n \
+             --> lib.rs:1:17\n  |\n1 | fn f(x: i32) -> i32 {\n  |                 ^^^\n  \
+             = help: add a precondition\n"
+        );
+    }
+
+    #[test]
+    fn spans_outside_the_sources_are_problems() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "fn f() {\n    g(1);\n}\n").unwrap();
+        let diagnostic = |spans| diagnostic("error", "refinement type error", spans);
+        let report = VerificationReport {
+            diagnostics: vec![
+                diagnostic(vec![
+                    span("lib.rs", (2, 5), (2, 10), true),
+                    span("lib.rs", (9, 1), (9, 2), false),
+                ]),
+                diagnostic(vec![span("lib.rs", (4, 1), (4, 2), true)]),
+                diagnostic(vec![span("lib.rs", (2, 5), (2, 11), true)]),
+                diagnostic(vec![span("lib.rs", (2, 5), (1, 1), true)]),
+                diagnostic(vec![span("<generated>.rs", (1, 1), (1, 2), true)]),
+            ],
+            ..Default::default()
+        };
+
+        let issues: Vec<_> = validate_spans(&report, dir.path())
+            .into_iter()
+            .map(|problem| problem.issue)
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                SpanIssue::LineOutOfRange { lines: 3 },
+                SpanIssue::ColumnOutOfRange { line: 2, width: 9 },
+                SpanIssue::Inverted,
+                SpanIssue::MissingFile,
+            ]
+        );
+    }
+
+    #[test]
+    fn markdown_reports_group_files_into_collapsible_sections() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/lib.rs"), "fn f() {\n    g(1);\n}\n").unwrap();
+        let diagnostic = |level: &str, spans, rendered: Option<&str>| {
+            let mut diagnostic = diagnostic(level, "refinement type error", spans);
+            diagnostic.message.rendered = rendered.map(str::to_string);
+            diagnostic
+        };
+        let report = VerificationReport {
+            success: false,
+            diagnostics: vec![
+                diagnostic("error", vec![span("src/lib.rs", (2, 5), (2, 9), true)], None),
+                diagnostic("warning", vec![], Some("warning: has ``` fences\n")),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            to_markdown(&report, dir.path()),
+            "## Flux verification failed\n\n\
+             | File | Errors | Warnings |\n| --- | ---: | ---: |\n\
+             | `src/lib.rs` | 1 | 0 |\n\
+             | Other | 0 | 1 |\n\
+             | **Total** | **1** | **1** |\n\
+             \n<details>\n<summary><code>src/lib.rs</code> (1 diagnostic)</summary>\n\n\
+             **error**: refinement type error at [src/lib.rs:2](src/lib.rs#L2)\n\n\
+             ```text\n\
+             error: refinement type error\n \
+             --> src/lib.rs:2:5\n  \
+             |\n\
+             2 |     g(1);\n  \
+             |     ^^^^\n\
+             ```\n\n\
+             </details>\n\
+             \n<details>\n<summary>Other (1 diagnostic)</summary>\n\n\
+             **warning**: refinement type error\n\n\
+             ````text\nwarning: has ``` fences\n````\n\n\
+             </details>\n"
+        );
+        let passed = VerificationReport { success: true, ..Default::default() };
+        assert_eq!(
+            to_markdown(&passed, dir.path()),
+            "## Flux verification passed\n\nNo diagnostics.\n"
+        );
+    }
+
+    #[test]
+    fn checkstyle_reports_group_errors_by_file() {
+        let mut coded =
+            diagnostic("error", "a < b & c", vec![span("src/b.rs", (4, 2), (4, 3), true)]);
+        coded.message.code = Some("E0308".to_string());
+        let report = VerificationReport {
+            diagnostics: vec![
+                coded,
+                diagnostic("warning", "unused", vec![span("src/a.rs", (7, 5), (7, 6), true)]),
+                diagnostic("note", "see \"here\"", vec![span("src/b.rs", (1, 1), (1, 2), true)]),
+                diagnostic("error", "no location", vec![]),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            to_checkstyle(&report),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <checkstyle version=\"4.3\">\n  \
+             <file name=\"src/a.rs\">\n    \
+             <error line=\"7\" column=\"5\" severity=\"warning\" message=\"unused\" source=\"flux\"/>\n  \
+             </file>\n  \
+             <file name=\"src/b.rs\">\n    \
+             <error line=\"4\" column=\"2\" severity=\"error\" message=\"a &lt; b &amp; c\" source=\"E0308\"/>\n    \
+             <error line=\"1\" column=\"1\" severity=\"info\" message=\"see &quot;here&quot;\" source=\"flux\"/>\n  \
+             </file>\n\
+             </checkstyle>\n"
+        );
+    }
+
+    #[test]
+    fn gitlab_issues_are_located_at_user_code_with_stable_fingerprints() {
+        let mut expanded = span("/rustc/library/core/src/macros/mod.rs", (10, 1), (12, 2), true);
+        expanded.expansion = Some(Box::new(DiagnosticSpanExpansion {
+            span: span("src/lib.rs", (2, 5), (2, 15), false),
+            macro_decl_name: "assert!".to_string(),
+            def_site_span: None,
+        }));
+        let report = VerificationReport {
+            diagnostics: vec![
+                diagnostic("error", "assertion might fail", vec![expanded]),
+                diagnostic("warning", "unused", vec![span("src/lib.rs", (7, 5), (7, 6), true)]),
+                diagnostic("error", "no location", vec![]),
+            ],
+            ..Default::default()
+        };
+
+        let issues = to_gitlab_codequality(&report);
+        let issues = issues.as_array().unwrap();
+        assert_eq!(issues.len(), 2);
+        assert_eq!(
+            issues[0]["location"],
+            serde_json::json!({
+                "path": "src/lib.rs",
+                "lines": { "begin": 2 },
+            })
+        );
+        assert_eq!(issues[0]["severity"], "major");
+        assert_eq!(issues[0]["check_name"], "flux");
+        assert_eq!(issues[1]["severity"], "minor");
+        assert_ne!(issues[0]["fingerprint"], issues[1]["fingerprint"]);
+        assert_eq!(to_gitlab_codequality(&report)[0]["fingerprint"], issues[0]["fingerprint"]);
+    }
+
+    #[test]
+    fn report_diffs_match_repeated_diagnostics_one_to_one() {
+        let at = |line| vec![span("src/lib.rs", (line, 5), (line, 9), true)];
+        let before = VerificationReport {
+            diagnostics: vec![
+                diagnostic("error", "refinement type error", at(3)),
+                diagnostic("error", "refinement type error", at(3)),
+                diagnostic("error", "assertion might fail", at(8)),
+            ],
+            ..Default::default()
+        };
+        let after = VerificationReport {
+            diagnostics: vec![
+                diagnostic("error", "refinement type error", at(3)),
+                diagnostic("error", "assertion might fail", at(9)),
+            ],
+            ..Default::default()
+        };
+
+        let diff = diff(&before, &after);
+        let lines = |diagnostics: &[Diagnostic]| -> Vec<_> {
+            diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.message.primary_span().unwrap().line_start)
+                .collect()
+        };
+        assert_eq!(lines(&diff.unchanged), [3]);
+        assert_eq!(lines(&diff.resolved), [3, 8]);
+        assert_eq!(lines(&diff.introduced), [9]);
+    }
+
+    #[test]
+    fn minimal_diagnostics_keep_only_the_first_primary_span() {
+        let mut full = diagnostic(
+            "error",
+            "refinement type error",
+            vec![
+                span("src/lib.rs", (1, 1), (1, 2), false),
+                span("src/lib.rs", (3, 5), (3, 9), true),
+                span("src/lib.rs", (8, 1), (8, 2), true),
+            ],
+        );
+        full.message.rendered = Some("error: refinement type error".to_string());
+        full.message.code_explanation = Some("explained".to_string());
+        full.message.children = vec![diagnostic("note", "a note", vec![]).message];
+
+        minimize(&mut full);
+        let message = &full.message;
+        assert_eq!(
+            (message.rendered.as_deref(), message.code_explanation.as_deref()),
+            (None, None)
+        );
+        assert!(message.children.is_empty());
+        assert_eq!(message.spans.len(), 1);
+        assert_eq!((message.spans[0].line_start, message.spans[0].column_start), (3, 5));
+
+        let mut unlocated =
+            diagnostic("error", "no location", vec![span("a.rs", (1, 1), (1, 2), false)]);
+        minimize(&mut unlocated);
+        assert!(unlocated.message.spans.is_empty());
+    }
+
+    #[test]
+    fn regex_filters_match_the_message_or_the_rendered_text() {
+        let mut rendered = diagnostic("error", "assertion might fail", vec![]);
+        rendered.message.rendered =
+            Some("error: assertion might fail\n  --> src/seq.rs".to_string());
+        let mut diagnostics = vec![
+            diagnostic("error", "refinement type error in seq::push", vec![]),
+            rendered,
+            diagnostic("error", "refinement type error in vec::push", vec![]),
+        ];
+
+        filter_by_regex(&mut diagnostics, &Regex::new(r"seq").unwrap());
+        let messages: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message.message.as_str())
+            .collect();
+        assert_eq!(messages, ["refinement type error in seq::push", "assertion might fail"]);
+    }
+
+    #[test]
+    fn tsv_reports_have_a_row_per_primary_span() {
+        let diagnostic = |message: &str, spans| {
+            let mut diagnostic = diagnostic("error", message, spans);
+            diagnostic.message.code = Some("FLUX-REFINE".to_string());
+            diagnostic
+        };
+        let report = VerificationReport {
+            diagnostics: vec![
+                diagnostic(
+                    "two\tparts",
+                    vec![
+                        span("src/b.rs", (4, 2), (4, 3), true),
+                        span("src/b.rs", (9, 1), (9, 2), false),
+                        span("src/a.rs", (7, 5), (7, 6), true),
+                    ],
+                ),
+                diagnostic("no location\nat all", vec![]),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            to_tsv(&report),
+            "level\tcode\tfile\tline\tcol\tmessage\n\
+             error\tFLUX-REFINE\tsrc/b.rs\t4\t2\ttwo\\tparts\n\
+             error\tFLUX-REFINE\tsrc/a.rs\t7\t5\ttwo\\tparts\n\
+             error\tFLUX-REFINE\t\t\t\tno location\\nat all\n"
+        );
+    }
+
+    #[test]
+    fn lib_targets_win_over_their_test_harness_copies() {
+        let diagnostic = |line, kind: &str| {
+            let mut diagnostic = diagnostic(
+                "error",
+                "refinement type error",
+                vec![span("src/lib.rs", (line, 1), (line, 2), true)],
+            );
+            diagnostic.target = Some(DiagnosticTarget {
+                name: "foo".to_string(),
+                kind: Some(vec![kind.to_string()]),
+                src_path: None,
+                edition: None,
+            });
+            diagnostic
+        };
+        let mut diagnostics = vec![
+            diagnostic(1, "test"),
+            diagnostic(1, "lib"),
+            diagnostic(2, "test"),
+            diagnostic(3, "bin"),
+            diagnostic(3, "test"),
+        ];
+        prefer_lib_target(&mut diagnostics);
+        let kept: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let kind = &diagnostic.target.as_ref().unwrap().kind.as_ref().unwrap()[0];
+                (diagnostic.message.spans[0].line_start, kind.as_str())
+            })
+            .collect();
+        assert_eq!(kept, [(1, "lib"), (2, "test"), (3, "bin"), (3, "test")]);
+    }
+
+    #[test]
+    fn baselines_suppress_known_diagnostics_one_to_one() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        let source = "fn f(x: i32) -> i32 {\n    let y = x;\n    x + 1\n}\n\n\
+                      fn g(x: i32) -> i32 {\n    x * 2\n}\n";
+        std::fs::write(dir.path().join("src/lib.rs"), source).unwrap();
+        let report = |success, lines: &[i64]| {
+            let mut diagnostics: Vec<_> = lines
+                .iter()
+                .map(|&line| {
+                    diagnostic(
+                        "error",
+                        "arithmetic operation may overflow",
+                        vec![span("src/lib.rs", (line, 5), (line, 10), true)],
+                    )
+                })
+                .collect();
+            add_fingerprints(&mut diagnostics, dir.path());
+            VerificationReport { success, diagnostics, ..Default::default() }
+        };
+        let known = report(false, &[3, 7]);
+        let baseline = generate_baseline(&known);
+        assert_eq!(baseline[0].code, "FLUX-OVERFLOW");
+
+        let path = dir.path().join("baseline.json");
+        save_baseline(&path, &baseline).unwrap();
+        let baseline = load_baseline(&path).unwrap();
+
+        assert!(apply_baseline(known.clone(), &baseline).success);
+        let remaining = apply_baseline(report(false, &[3, 3, 7]), &baseline);
+        assert!(!remaining.success);
+        assert_eq!(remaining.diagnostics.len(), 1);
+
+        // Lines inserted above the known diagnostics don't bring them back.
+        std::fs::write(dir.path().join("src/lib.rs"), format!("use std::ops::Add;\n\n{source}"))
+            .unwrap();
+        let moved = apply_baseline(report(false, &[5, 9]), &baseline);
+        assert!(moved.success);
+        assert!(moved.diagnostics.is_empty());
+
+        let crashed = VerificationReport { success: false, ..Default::default() };
+        assert!(!apply_baseline(crashed, &baseline).success);
+        let mut with_ice = report(false, &[5]);
+        with_ice.diagnostics.push(diagnostic(
+            "error: internal compiler error",
+            "unexpected panic",
+            vec![],
+        ));
+        assert!(!apply_baseline(with_ice, &baseline).success);
+    }
+
+    #[test]
+    fn allowed_codes_dont_fail_the_run() {
+        let diagnostic = |message: &str, code: Option<&str>| {
+            let mut diagnostic = diagnostic("error", message, vec![]);
+            diagnostic.message.code = code.map(str::to_string);
+            diagnostic
+        };
+        let report = VerificationReport {
+            diagnostics: vec![
+                diagnostic("arithmetic operation may overflow", None),
+                diagnostic("mismatched types", Some("E0308")),
+            ],
+            ..Default::default()
+        };
+        let allowed = ["FLUX-OVERFLOW".to_string(), "E0308".to_string()];
+        assert!(success_ignoring(&report, &allowed));
+        assert!(!success_ignoring(&report, &allowed[..1]));
+        let crashed = VerificationReport { success: false, ..Default::default() };
+        assert!(!success_ignoring(&crashed, &allowed));
+
+        let mut with_ice = report.clone();
+        with_ice.diagnostics.push(self::diagnostic(
+            "error: internal compiler error",
+            "unexpected panic",
+            vec![],
+        ));
+        assert!(!success_ignoring(&with_ice, &allowed));
+    }
+
+    #[test]
+    fn fingerprints_follow_the_source_line_rather_than_its_number() {
+        let dir = tempfile::tempdir().unwrap();
+        let diagnostic = |line: i64| {
+            diagnostic(
+                "error",
+                "refinement type error",
+                vec![span("lib.rs", (line, 5), (line, 9), true)],
+            )
+        };
+        std::fs::write(dir.path().join("lib.rs"), "fn f() {\n    g(x)\n}\n").unwrap();
+        let before = fingerprint(&diagnostic(2), dir.path());
+        assert_eq!(before.len(), 16);
+
+        std::fs::write(dir.path().join("lib.rs"), "// moved\nfn f() {\n      g(x)\n}\n").unwrap();
+        assert_eq!(fingerprint(&diagnostic(3), dir.path()), before);
+        let mut diagnostics = [diagnostic(3), diagnostic(2)];
+        add_fingerprints(&mut diagnostics, dir.path());
+        assert_eq!(diagnostics[0].fingerprint, before);
+        assert_ne!(diagnostics[1].fingerprint, before);
+
+        std::fs::write(dir.path().join("lib.rs"), "// moved\nfn f() {\n    g(y)\n}\n").unwrap();
+        assert_ne!(fingerprint(&diagnostic(3), dir.path()), before);
+    }
+
+    #[test]
+    fn only_diagnostics_beyond_the_known_fingerprints_are_new() {
+        let diagnostic = |fingerprint: &str| {
+            let mut diagnostic = diagnostic("warning", fingerprint, vec![]);
+            diagnostic.fingerprint = fingerprint.to_string();
+            diagnostic
+        };
+        let diagnostics = [diagnostic("a"), diagnostic("b"), diagnostic("a"), diagnostic("c")];
+        let known = ["a".to_string(), "c".to_string(), "d".to_string()];
+        let new: Vec<_> = introduced_since(&diagnostics, &known)
+            .into_iter()
+            .map(|diagnostic| diagnostic.fingerprint)
+            .collect();
+        assert_eq!(new, ["b", "a"]);
+    }
+
+    #[test]
+    fn denied_warnings_fail_the_run() {
+        let warning = diagnostic("warning", "unused variable", vec![]);
+        let mut note = warning.clone();
+        (note.message.level, note.message.parsed_level) = ("note".to_string(), Level::Note);
+        let clean =
+            VerificationReport { success: true, diagnostics: vec![note], ..Default::default() };
+        assert!(success_with_deny_warnings(&clean));
+        let warned =
+            VerificationReport { success: true, diagnostics: vec![warning], ..Default::default() };
+        assert!(!success_with_deny_warnings(&warned));
+        let failed = VerificationReport { success: false, ..Default::default() };
+        assert!(!success_with_deny_warnings(&failed));
+    }
+
+    #[test]
+    fn status_lines_count_errors_and_warnings() {
+        let diagnostic = |level: &str| diagnostic(level, "refinement type error", vec![]);
+        let report = VerificationReport {
+            diagnostics: vec![
+                diagnostic("error"),
+                diagnostic("warning"),
+                diagnostic("error"),
+                diagnostic("note"),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(status_line(&report), "FLUX_RESULT errors=2 warnings=1 success=false");
+    }
+
+    #[test]
+    fn oversized_reports_drop_the_least_important_content_first() {
+        let diagnostic = |level: &str| {
+            let mut diagnostic = diagnostic(
+                level,
+                "refinement type error",
+                vec![
+                    span("src/lib.rs", (3, 5), (3, 9), true),
+                    span("src/lib.rs", (1, 1), (1, 4), false),
+                ],
+            );
+            diagnostic.message.rendered = Some("error: refinement type error\n".repeat(8));
+            diagnostic
+        };
+        let full = VerificationReport {
+            diagnostics: vec![diagnostic("error"), diagnostic("warning"), diagnostic("error")],
+            ..Default::default()
+        };
+        let size = |report: &VerificationReport| {
+            serde_json::to_string(&report.diagnostics)
+                .unwrap()
+                .chars()
+                .count()
+        };
+        let mut report = full.clone();
+        assert_eq!(fit_to_budget(&mut report, size(&full)), None);
+
+        // Dropping the rendered text of the last diagnostic is enough.
+        let budget = size(&full) - 10;
+        let dropped = fit_to_budget(&mut report, budget).unwrap();
+        assert_eq!((dropped.dropped_rendered, dropped.dropped_spans), (1, 0));
+        assert!(report.diagnostics[0].message.rendered.is_some());
+        assert!(size(&report) <= budget);
+
+        let mut minimal = full.clone();
+        minimal.diagnostics.iter_mut().for_each(minimize);
+        let budget = size(&minimal) - 1;
+        let mut report = full.clone();
+        let dropped = fit_to_budget(&mut report, budget).unwrap();
+        assert_eq!(
+            dropped,
+            BudgetTruncation {
+                truncated: true,
+                dropped_rendered: 3,
+                dropped_spans: 3,
+                dropped_diagnostics: 1,
+            }
+        );
+        let levels: Vec<_> = report
+            .diagnostics
+            .iter()
+            .map(|d| d.message.level.as_str())
+            .collect();
+        assert_eq!(levels, ["error", "error"]);
+        assert!(size(&report) <= budget);
+        let clean = VerificationReport { success: true, ..Default::default() };
+        assert_eq!(status_line(&clean), "FLUX_RESULT errors=0 warnings=0 success=true");
+    }
+}
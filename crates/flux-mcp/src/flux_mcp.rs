@@ -1,27 +1,81 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
+use regex::Regex;
 use rmcp::{
-    ErrorData as McpErrorData, ServerHandler,
+    ErrorData as McpErrorData, Peer, RoleServer, ServerHandler,
     handler::server::{tool::ToolRouter, wrapper::Parameters},
-    model::{CallToolResult, Content, ServerCapabilities, ServerInfo},
+    model::{
+        CallToolResult, Content, LoggingLevel, LoggingMessageNotificationParam, ServerCapabilities,
+        ServerInfo,
+    },
     tool, tool_handler, tool_router,
 };
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, OnceCell};
 
 use crate::{
     diagnostics,
-    flux_runner::{FluxRunner, VerificationReport, VerifyRepositoryArgs, VerifyPackageArgs, GetLemmaArgs},
+    flux_runner::{
+        self, CalleesArgs, CheckLemmaSyntaxArgs, CheckTrustBudgetArgs, ChildTracker,
+        DEFAULT_BUNDLE_CHARS, DEFAULT_MINIMIZE_ATTEMPTS, DEFAULT_SLOW_GOALS, DEFAULT_SUGGESTIONS,
+        DebugVerifyArgs, DiffReportsArgs, ExtractSigsArgs, FluxRunner, FluxRunnerError,
+        FluxVersion, FormatSigArgs, GetConstraintsArgs, GetLemmaArgs, GetLemmaLocationsArgs,
+        GetLemmasCachedArgs, GoalContextBundleArgs, GoalStatusArgs, ItemRangeArgs, Lemma,
+        LemmaBodyDiffArgs, LemmaDefsArgs, LemmaDiffArgs, LemmaGraphArgs, LemmaLocation,
+        LemmasAboutArgs, LemmasForSortArgs, MinimizeLemmasArgs, OutputFormat, PollVerificationArgs,
+        RefinedByArgs, RepoPathArgs, ResolvePackageArgs, RunOptions, SlowGoalsArgs,
+        SpecCoverageArgs, SpecEditImpactArgs, StartWatchArgs, SuggestPreconditionArgs,
+        SuggestTrustedAnnotationArgs, TryLemmaArgs, ValidateReportArgs, VerificationReport,
+        VerificationSummary, VerifyAgainstBaselineArgs, VerifyAndFmtArgs, VerifyAndSuggestArgs,
+        VerifyDeltaArgs, VerifyFixturesArgs, VerifyFunctionArgs, VerifyGitArgs, VerifyManyArgs,
+        VerifyOwningPackageArgs, VerifyPackageArgs, VerifyPackageTreeArgs, VerifyPatchArgs,
+        VerifyRepositoryArgs, VerifySourceArgs, VerifyStartArgs, VerifyWithExtraLemmasArgs,
+    },
+    jobs::{JobStatus, Jobs},
+    repo_config::RepoConfig,
+    sigfmt,
+    watch::{self, Watch},
 };
 
+/// Told along with a report whose diagnostics were cut short at `max_output_bytes`.
+const TRUNCATED_NOTE: &str = "Flux was stopped for printing too much output, only the diagnostics before the limit are included";
+
 pub struct FluxMcp {
-    runner: Arc<Mutex<FluxRunner>>,
+    runner: Arc<FluxRunner>,
+    /// The flux processes in flight, reaped on shutdown.
+    children: ChildTracker,
+    /// Active watches, keyed by canonical repository path.
+    watches: Mutex<HashMap<PathBuf, Watch>>,
+    /// Verifications started with `verify_start`, and the latest finished ones.
+    jobs: Mutex<Jobs>,
+    /// The result of the first `flux_version` call, reused for the lifetime of the server.
+    version: OnceCell<FluxVersion>,
+    /// The result of the first `supported_flags` call, reused for the lifetime of the server.
+    supported_flags: OnceCell<Vec<String>>,
+    /// The diagnostic fingerprints of the last successful `regressions_only` run of each
+    /// repository, keyed by canonical repository path.
+    green_baselines: Mutex<HashMap<PathBuf, Vec<String>>>,
     tool_router: ToolRouter<Self>,
 }
 
 #[tool_router]
 impl FluxMcp {
     pub fn new() -> Self {
-        Self { runner: Arc::new(Mutex::new(FluxRunner::new())), tool_router: Self::tool_router() }
+        let runner = FluxRunner::new();
+        Self {
+            children: runner.children(),
+            runner: Arc::new(runner),
+            watches: Mutex::new(HashMap::new()),
+            jobs: Mutex::new(Jobs::default()),
+            version: OnceCell::new(),
+            supported_flags: OnceCell::new(),
+            green_baselines: Mutex::new(HashMap::new()),
+            tool_router: Self::tool_router(),
+        }
     }
 
     #[tool(description = "Run Flux verification on a repository and return results")]
@@ -29,91 +83,977 @@ impl FluxMcp {
         &self,
         Parameters(args): Parameters<VerifyRepositoryArgs>,
     ) -> Result<CallToolResult, McpErrorData> {
-        let runner = self.runner.lock().await;
-        let result = runner.verify_repository(&args.repo_path).await;
+        let runner = &self.runner;
+        if args.raw {
+            return match runner.verify_repository_raw(&args.repo_path).await {
+                Ok((success, messages)) => {
+                    let levels = messages
+                        .iter()
+                        .filter_map(|message| message.get("message")?.get("level")?.as_str());
+                    let summary = VerificationSummary::new(success, levels);
+                    Ok(CallToolResult::success(vec![
+                        Content::text(serde_json::to_string(&summary).unwrap()),
+                        Content::text(serde_json::Value::Array(messages).to_string()),
+                    ]))
+                }
+                Err(err) => Err(runner_error("Verification failed", err)),
+            };
+        }
+        if args.dry_run {
+            let command = runner
+                .dry_run(&args.repo_path, args.collect_stats, Self::overrides(&args))
+                .map_err(|err| runner_error("Dry run failed", err))?;
+            return Ok(CallToolResult::success(vec![Content::text(command)]));
+        }
+        let message_regex = args
+            .message_regex
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|err| McpErrorData::invalid_params(format!("Invalid regex {err}"), None))?;
+        let coordinate_base = coordinate_base(args.coordinate_base)?;
+        let result = Self::verify_scoped(runner, &args).await;
         match result {
-            Ok(report) => {
-                let result_text = if report.success {
-                    "Verification Succeeded".to_string()
-                } else {
-                    "Verification Failed".to_string()
+            Ok(mut report) => {
+                if let Some(allowed) = &args.allowed_codes {
+                    report.success = diagnostics::success_ignoring(&report, allowed);
+                }
+                if args.deny_warnings {
+                    report.success = diagnostics::success_with_deny_warnings(&report);
+                }
+                if let Some(re) = &message_regex {
+                    diagnostics::filter_by_regex(&mut report.diagnostics, re);
+                }
+                if args.prefer_lib_target {
+                    diagnostics::prefer_lib_target(&mut report.diagnostics);
+                }
+                if !args.include_explanations {
+                    for diagnostic in &mut report.diagnostics {
+                        diagnostic.message.code_explanation = None;
+                    }
+                }
+                if args.merge_spans {
+                    for diagnostic in &mut report.diagnostics {
+                        let spans = std::mem::take(&mut diagnostic.message.spans);
+                        diagnostic.message.spans = diagnostics::merge_spans(spans);
+                    }
+                }
+                if let Some(order) = args.sort {
+                    diagnostics::sort(&mut report.diagnostics, order);
+                }
+                if args.minimal {
+                    report
+                        .diagnostics
+                        .iter_mut()
+                        .for_each(diagnostics::minimize);
+                }
+                if args.byte_offsets {
+                    let root = report.resolved_root.as_deref().unwrap_or(&args.repo_path);
+                    diagnostics::add_byte_offsets(&mut report.diagnostics, Path::new(root));
+                }
+                if args.span_text {
+                    let root = report.resolved_root.as_deref().unwrap_or(&args.repo_path);
+                    diagnostics::add_span_texts(&mut report.diagnostics, Path::new(root));
+                }
+                if args.normalize_paths {
+                    let root = report.resolved_root.as_deref().unwrap_or(&args.repo_path);
+                    diagnostics::normalize_paths(
+                        &mut report.diagnostics,
+                        Path::new(root),
+                        Path::new(&args.repo_path),
+                    );
+                }
+                let summary = VerificationSummary::of(report.success, &report.diagnostics);
+                let mut diagnostic_text = vec![];
+                if args.status_line {
+                    diagnostic_text.push(Content::text(diagnostics::status_line(&report)));
+                }
+                diagnostic_text.push(Content::text(serde_json::to_string(&summary).unwrap()));
+                if let Some(max_chars) = args.max_chars
+                    && let Some(dropped) = diagnostics::fit_to_budget(&mut report, max_chars)
+                {
+                    diagnostic_text.push(Content::text(serde_json::to_string(&dropped).unwrap()));
+                }
+                if report.truncated {
+                    diagnostic_text.push(Content::text(TRUNCATED_NOTE));
+                }
+                if let Some(ice) = &report.ice {
+                    diagnostic_text.push(Content::text(format!(
+                        "Flux crashed with an internal compiler error, a flux bug to report rather than a proof failure:\n{ice}"
+                    )));
+                }
+                if let Some(warning) = &report.toolchain_warning {
+                    diagnostic_text.push(Content::text(warning.clone()));
+                }
+                if !report.skipped_packages.is_empty() {
+                    diagnostic_text.push(Content::text(format!(
+                        "Not verified because a package they depend on failed to build: {}",
+                        report.skipped_packages.join(", ")
+                    )));
+                }
+                if let Some(queued_ms) = report.queued_ms {
+                    diagnostic_text.push(Content::text(format!(
+                        "Queued for {queued_ms}ms behind other verifications before flux started"
+                    )));
+                }
+                // Rendering reads the source, so it goes by the spans as flux reported them.
+                let mut rebased = report.diagnostics.clone();
+                diagnostics::rebase_coordinates(&mut rebased, coordinate_base);
+                if args.format == OutputFormat::Ndjson {
+                    diagnostic_text.push(Content::text(diagnostics::to_ndjson(&rebased)));
+                }
+                // Normalized paths are relative to `repo_path` rather than to the cargo root.
+                let root = match &report.resolved_root {
+                    Some(root) if !args.normalize_paths => root,
+                    _ => &args.repo_path,
                 };
-                let mut diagnostic_text: Vec<_> = report
-                    .diagnostics
-                    .iter()
-                    .map(|diagnostic| Content::text(serde_json::to_string(diagnostic).unwrap()))
-                    .collect();
-                diagnostic_text.push(Content::text(result_text));
+                for (diagnostic, shown) in report.diagnostics.iter().zip(&rebased) {
+                    if matches!(args.format, OutputFormat::Json | OutputFormat::Both) {
+                        diagnostic_text.push(Content::text(serde_json::to_string(shown).unwrap()));
+                    }
+                    if matches!(args.format, OutputFormat::Rendered | OutputFormat::Both) {
+                        let rendered = match &diagnostic.message.rendered {
+                            Some(rendered) => rendered.clone(),
+                            None => diagnostics::render_with_children(diagnostic, Path::new(root)),
+                        };
+                        diagnostic_text.push(Content::text(rendered));
+                    }
+                }
+                if let Some(stats) = &report.solver_stats {
+                    diagnostic_text.push(Content::text(serde_json::to_string(stats).unwrap()));
+                }
+                if args.debug_parse
+                    && let Some(stats) = &report.parse_stats
+                {
+                    diagnostic_text.push(Content::text(
+                        serde_json::json!({ "parse_stats": stats }).to_string(),
+                    ));
+                }
                 Ok(CallToolResult::success(diagnostic_text))
             }
-            Err(err) => {
-                Err(McpErrorData::invalid_request(format!("Verification failed {err}"), None))
-            }
+            Err(err) => Err(runner_error("Verification failed", err)),
         }
     }
 
-    #[tool(description = "Run Flux verification on a set of packages in a repository and return results")]
+    #[tool(
+        description = "Run Flux verification on a set of packages in a repository and return results"
+    )]
     async fn verify_packages(
         &self,
         Parameters(args): Parameters<VerifyPackageArgs>,
     ) -> Result<CallToolResult, McpErrorData> {
-        let runner = self.runner.lock().await;
+        let runner = &self.runner;
         let slice: Vec<&str> = args.packages.iter().map(|s| s.as_str()).collect();
         let package_arg: &[&str] = slice.as_slice();
-        let result = runner.verify_package(&args.repo_path, Some(package_arg)).await;
+        let timeout = args.timeout_secs.map(Duration::from_secs);
+        let result = runner
+            .verify_package_with_timeout(&args.repo_path, Some(package_arg), timeout)
+            .await;
         match result {
             Ok(report) => {
-                let result_text = if report.success {
-                    "Verification Succeeded".to_string()
-                } else {
-                    "Verification Failed".to_string()
-                };
-                let mut diagnostic_text: Vec<_> = report
-                    .diagnostics
+                let summary = VerificationSummary::of(report.success, &report.diagnostics);
+                let mut diagnostic_text =
+                    vec![Content::text(serde_json::to_string(&summary).unwrap())];
+                diagnostic_text.extend(
+                    report.diagnostics.iter().map(|diagnostic| {
+                        Content::text(serde_json::to_string(diagnostic).unwrap())
+                    }),
+                );
+                if report.truncated {
+                    diagnostic_text.push(Content::text(TRUNCATED_NOTE));
+                }
+                Ok(CallToolResult::success(diagnostic_text))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification on a package and on every workspace package that depends on it, directly or not, since a spec change can break them. The first item after the summary lists the packages verified; registry dependencies are never included"
+    )]
+    async fn verify_package_tree(
+        &self,
+        Parameters(args): Parameters<VerifyPackageTreeArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner
+            .verify_package_tree(&args.repo_path, &args.package)
+            .await
+        {
+            Ok((packages, report)) => {
+                let summary = VerificationSummary::of(report.success, &report.diagnostics);
+                let mut diagnostic_text = vec![
+                    Content::text(serde_json::to_string(&summary).unwrap()),
+                    Content::text(serde_json::json!({ "packages": packages }).to_string()),
+                ];
+                diagnostic_text.extend(
+                    report.diagnostics.iter().map(|diagnostic| {
+                        Content::text(serde_json::to_string(diagnostic).unwrap())
+                    }),
+                );
+                if report.truncated {
+                    diagnostic_text.push(Content::text(TRUNCATED_NOTE));
+                }
+                Ok(CallToolResult::success(diagnostic_text))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification on just the workspace package owning a file, found with `cargo metadata`. The quickest check after editing a single file. The first item after the summary names the package verified"
+    )]
+    async fn verify_owning_package(
+        &self,
+        Parameters(args): Parameters<VerifyOwningPackageArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner
+            .verify_owning_package(&args.repo_path, &args.file_path)
+            .await
+        {
+            Ok((package, report)) => {
+                let summary = VerificationSummary::of(report.success, &report.diagnostics);
+                let mut diagnostic_text = vec![
+                    Content::text(serde_json::to_string(&summary).unwrap()),
+                    Content::text(serde_json::json!({ "package": package }).to_string()),
+                ];
+                diagnostic_text.extend(
+                    report.diagnostics.iter().map(|diagnostic| {
+                        Content::text(serde_json::to_string(diagnostic).unwrap())
+                    }),
+                );
+                if report.truncated {
+                    diagnostic_text.push(Content::text(TRUNCATED_NOTE));
+                }
+                Ok(CallToolResult::success(diagnostic_text))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification on a repository with its constraint and checker trace dumps enabled, for debugging why the solver can't prove a goal. The dumps are written to `out_log` and only its path is returned with the usual report, since they are far too large for a response"
+    )]
+    async fn debug_verify(
+        &self,
+        Parameters(args): Parameters<DebugVerifyArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.debug_verify(&args.repo_path, &args.out_log).await {
+            Ok((log_path, report)) => {
+                let summary = VerificationSummary::of(report.success, &report.diagnostics);
+                let mut diagnostic_text = vec![
+                    Content::text(serde_json::to_string(&summary).unwrap()),
+                    Content::text(serde_json::json!({ "log_path": log_path }).to_string()),
+                ];
+                diagnostic_text.extend(
+                    report.diagnostics.iter().map(|diagnostic| {
+                        Content::text(serde_json::to_string(diagnostic).unwrap())
+                    }),
+                );
+                if report.truncated {
+                    diagnostic_text.push(Content::text(TRUNCATED_NOTE));
+                }
+                Ok(CallToolResult::success(diagnostic_text))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Find the slowest goals of verifying a repository, slowest first, to profile proof performance. Goals are functions when flux times them, otherwise whole packages verified one at a time"
+    )]
+    async fn slow_goals(
+        &self,
+        Parameters(args): Parameters<SlowGoalsArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let limit = args.limit.unwrap_or(DEFAULT_SLOW_GOALS);
+        match runner.slow_goals(&args.repo_path, limit).await {
+            Ok(slow) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&slow).unwrap(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Resolve a workspace package given by name to its package id, or a `package_id` from a diagnostic to its name, along with its version and manifest path"
+    )]
+    async fn resolve_package(
+        &self,
+        Parameters(args): Parameters<ResolvePackageArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.resolve_package(&args.repo_path, &args.package).await {
+            Ok(package) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&package).unwrap(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Resolving the package failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Verify each package separately and return the time spent on each, slowest first"
+    )]
+    async fn verify_timings(
+        &self,
+        Parameters(args): Parameters<VerifyPackageArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let packages: Vec<&str> = args.packages.iter().map(|s| s.as_str()).collect();
+        let timeout = args.timeout_secs.map(Duration::from_secs);
+        let result = runner
+            .verify_per_package(&args.repo_path, &packages, timeout)
+            .await;
+        match result {
+            Ok(mut report) => {
+                report
+                    .package_timings
+                    .sort_by(|a, b| b.duration_ms.cmp(&a.duration_ms));
+                let mut timings_text: Vec<_> = report
+                    .package_timings
                     .iter()
-                    .map(|diagnostic| Content::text(serde_json::to_string(diagnostic).unwrap()))
+                    .map(|timing| Content::text(serde_json::to_string(timing).unwrap()))
                     .collect();
-                diagnostic_text.push(Content::text(result_text));
+                timings_text.push(Content::text(format!(
+                    "Total duration: {} ms",
+                    report.total_duration_ms
+                )));
+                Ok(CallToolResult::success(timings_text))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification on a single function and return only its diagnostics"
+    )]
+    async fn verify_function(
+        &self,
+        Parameters(args): Parameters<VerifyFunctionArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let result = runner
+            .verify_function(&args.repo_path, &args.package, &args.function_path)
+            .await;
+        match result {
+            Ok(report) => {
+                let summary = VerificationSummary::of(report.success, &report.diagnostics);
+                let mut diagnostic_text =
+                    vec![Content::text(serde_json::to_string(&summary).unwrap())];
+                diagnostic_text.extend(
+                    report.diagnostics.iter().map(|diagnostic| {
+                        Content::text(serde_json::to_string(diagnostic).unwrap())
+                    }),
+                );
+                if report.truncated {
+                    diagnostic_text.push(Content::text(TRUNCATED_NOTE));
+                }
                 Ok(CallToolResult::success(diagnostic_text))
             }
-            Err(err) => {
-                Err(McpErrorData::invalid_request(format!("Verification failed {err}"), None))
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification on a standalone flux-annotated source file, given inline. Diagnostics point into `source.rs`, with line numbers matching the given source"
+    )]
+    async fn verify_source(
+        &self,
+        Parameters(args): Parameters<VerifySourceArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let result = self.runner.verify_source(&args.source).await;
+        match result {
+            Ok(report) => {
+                let summary = VerificationSummary::of(report.success, &report.diagnostics);
+                let mut diagnostic_text =
+                    vec![Content::text(serde_json::to_string(&summary).unwrap())];
+                diagnostic_text.extend(
+                    report.diagnostics.iter().map(|diagnostic| {
+                        Content::text(serde_json::to_string(diagnostic).unwrap())
+                    }),
+                );
+                if report.truncated {
+                    diagnostic_text.push(Content::text(TRUNCATED_NOTE));
+                }
+                Ok(CallToolResult::success(diagnostic_text))
             }
+            Err(err) => Err(runner_error("Verification failed", err)),
         }
     }
 
-    #[tool(description = "Get only the syntax errors from Flux verification")]
-    async fn get_syntax_errors(
+    #[tool(
+        description = "Check that a proposed lemma parses and its spec is well-formed before writing it to disk, without running the solver. Give the definitions it refers to as `context`. Only syntax and spec errors are returned, pointing into `source.rs` with the lemma's own line numbers"
+    )]
+    async fn check_lemma_syntax(
+        &self,
+        Parameters(args): Parameters<CheckLemmaSyntaxArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let result = self
+            .runner
+            .check_lemma_syntax(&args.lemma, args.context.as_deref())
+            .await;
+        match result {
+            Ok(report) => {
+                let summary =
+                    VerificationSummary::of(report.diagnostics.is_empty(), &report.diagnostics);
+                let mut diagnostic_text =
+                    vec![Content::text(serde_json::to_string(&summary).unwrap())];
+                diagnostic_text.extend(
+                    report.diagnostics.iter().map(|diagnostic| {
+                        Content::text(serde_json::to_string(diagnostic).unwrap())
+                    }),
+                );
+                Ok(CallToolResult::success(diagnostic_text))
+            }
+            Err(err) => Err(runner_error("Syntax check failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Pretty-print a flux signature in canonical form, with consistent spacing, each `requires` and `ensures` clause on its own line, and the refinement parameters it binds listed. Use it to compare signatures or to tidy one before writing it"
+    )]
+    async fn format_sig(
+        &self,
+        Parameters(args): Parameters<FormatSigArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        match sigfmt::format_sig(&args.sig_text) {
+            Some(formatted) => Ok(CallToolResult::success(vec![Content::text(formatted)])),
+            None => {
+                Err(McpErrorData::invalid_params(
+                    "The signature doesn't tokenize, check its delimiters",
+                    None,
+                ))
+            }
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification on a repository and return a Checkstyle XML report"
+    )]
+    async fn get_checkstyle_report(
+        &self,
+        Parameters(args): Parameters<VerifyRepositoryArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let result = Self::verify_scoped(runner, &args).await;
+        match result {
+            Ok(report) => {
+                Ok(CallToolResult::success(vec![Content::text(diagnostics::to_checkstyle(
+                    &report,
+                ))]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification on a repository and return a report for reading in a terminal, rendered like rustc with the source line under each diagnostic. Colored with ANSI escapes unless `NO_COLOR` is set"
+    )]
+    async fn get_terminal_report(
+        &self,
+        Parameters(args): Parameters<VerifyRepositoryArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let result = Self::verify_scoped(runner, &args).await;
+        match result {
+            Ok(report) => {
+                let root = report.resolved_root.as_deref().unwrap_or(&args.repo_path);
+                let text = diagnostics::render_terminal(
+                    &report,
+                    Path::new(root),
+                    diagnostics::color_enabled(),
+                );
+                Ok(CallToolResult::success(vec![Content::text(text)]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification and return only the location and level of each diagnostic, sorted by file and line"
+    )]
+    async fn get_error_locations(
+        &self,
+        Parameters(args): Parameters<VerifyRepositoryArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let result = Self::verify_scoped(runner, &args).await;
+        match result {
+            Ok(report) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&diagnostics::locations(&report)).unwrap(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification on a workspace and return the diagnostics grouped by package name, with those without a package under `<unknown>`"
+    )]
+    async fn verify_repository_by_package(
+        &self,
+        Parameters(args): Parameters<VerifyRepositoryArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let result = Self::verify_scoped(runner, &args).await;
+        match result {
+            Ok(report) => {
+                let summary = VerificationSummary::of(report.success, &report.diagnostics);
+                let mut content = vec![Content::text(serde_json::to_string(&summary).unwrap())];
+                if !report.skipped_packages.is_empty() {
+                    content.push(Content::text(
+                        serde_json::json!({ "skipped_packages": report.skipped_packages })
+                            .to_string(),
+                    ));
+                }
+                let groups = diagnostics::group_by_package(report.diagnostics);
+                content.push(Content::text(serde_json::to_string(&groups).unwrap()));
+                Ok(CallToolResult::success(content))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification on a repository and return the diagnostics as tab-separated values with the columns level, code, file, line, col and message, one row per primary span in a stable order, for spreadsheets and grep"
+    )]
+    async fn get_tsv_report(
+        &self,
+        Parameters(args): Parameters<VerifyRepositoryArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let result = Self::verify_scoped(runner, &args).await;
+        match result {
+            Ok(report) => {
+                Ok(CallToolResult::success(vec![Content::text(diagnostics::to_tsv(&report))]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification on a repository and return a Markdown report to post as a pull request comment: a table of error and warning counts per file, then a collapsible section per file with each diagnostic rendered in a code block and linked by its repository-relative path"
+    )]
+    async fn get_markdown_report(
+        &self,
+        Parameters(args): Parameters<VerifyRepositoryArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let result = Self::verify_scoped(runner, &args).await;
+        match result {
+            Ok(report) => {
+                let root = report.resolved_root.as_deref().unwrap_or(&args.repo_path);
+                let text = diagnostics::to_markdown(&report, Path::new(root));
+                Ok(CallToolResult::success(vec![Content::text(text)]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification on a repository and return a GitLab Code Quality report"
+    )]
+    async fn get_gitlab_report(
         &self,
         Parameters(args): Parameters<VerifyRepositoryArgs>,
     ) -> Result<CallToolResult, McpErrorData> {
-        let runner = self.runner.lock().await;
-        let result = runner.verify_repository(&args.repo_path).await;
+        let runner = &self.runner;
+        let result = Self::verify_scoped(runner, &args).await;
+        match result {
+            Ok(report) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    diagnostics::to_gitlab_codequality(&report).to_string(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Verify every fixture under `tests/tests/pos` and `tests/tests/neg` and report, per fixture, the expected and actual outcome. Mismatches are pos fixtures that now fail or neg fixtures that now pass"
+    )]
+    async fn verify_corpus(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.verify_corpus(&args.repo_path).await {
+            Ok(corpus) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&corpus).unwrap(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Verify several independent repositories in one call, concurrently, and report each one's result and timing along with whether all of them passed. A repository that can't be verified gets an `error` without stopping the others"
+    )]
+    async fn verify_many(
+        &self,
+        Parameters(args): Parameters<VerifyManyArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let result = self.runner.verify_many(&args.repo_paths).await;
+        Ok(CallToolResult::success(vec![Content::text(serde_json::to_string(&result).unwrap())]))
+    }
+
+    #[tool(
+        description = "Verify the pos or neg test fixtures of a repository, checking that pos fixtures verify and neg fixtures fail"
+    )]
+    async fn verify_fixtures(
+        &self,
+        Parameters(args): Parameters<VerifyFixturesArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let result = runner
+            .verify_fixtures(&args.repo_path, args.expectation)
+            .await;
         match result {
-            Ok(VerificationReport { diagnostics, .. }) => {
-                let syntax_errors = diagnostics::retain_only_syntax_errors(diagnostics);
-                let result_text = format!("Found {} syntax errors", syntax_errors.len());
-                let mut diagnostic_text: Vec<_> = syntax_errors
+            Ok(results) => {
+                let failed = results.iter().filter(|result| !result.passed).count();
+                let summary =
+                    format!("{} of {} fixtures passed", results.len() - failed, results.len());
+                let mut results_text: Vec<_> = results
                     .iter()
-                    .map(|diagnostic| Content::text(serde_json::to_string(diagnostic).unwrap()))
+                    .map(|result| Content::text(serde_json::to_string(result).unwrap()))
                     .collect();
-                diagnostic_text.push(Content::text(result_text));
+                results_text.push(Content::text(summary));
+                Ok(CallToolResult::success(results_text))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification, ignoring the known diagnostics recorded in a baseline file. Pass `update` to record the current diagnostics as the baseline. Errors with one of the `allowed_codes` are still reported among the new diagnostics but don't fail the run"
+    )]
+    async fn verify_against_baseline(
+        &self,
+        Parameters(args): Parameters<VerifyAgainstBaselineArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let verify = VerifyRepositoryArgs {
+            repo_path: args.repo_path.clone(),
+            include_external: args.include_external,
+            ..Default::default()
+        };
+        let report = Self::verify_scoped(runner, &verify)
+            .await
+            .map_err(|err| runner_error("Verification failed", err))?;
+        let baseline_path = Path::new(&args.baseline_path);
+        if args.update {
+            let baseline = diagnostics::generate_baseline(&report);
+            diagnostics::save_baseline(baseline_path, &baseline).map_err(|err| {
+                McpErrorData::internal_error(format!("Failed to save baseline {err}"), None)
+            })?;
+            return Ok(CallToolResult::success(vec![Content::text(format!(
+                "Recorded {} diagnostics in {}",
+                baseline.len(),
+                baseline_path.display()
+            ))]));
+        }
+        let baseline = diagnostics::load_baseline(baseline_path).map_err(|err| {
+            McpErrorData::invalid_params(format!("Failed to load baseline {err}"), None)
+        })?;
+        let mut report = diagnostics::apply_baseline(report, &baseline);
+        if let Some(allowed) = &args.allowed_codes {
+            report.success = diagnostics::success_ignoring(&report, allowed);
+        }
+        let summary = VerificationSummary::of(report.success, &report.diagnostics);
+        let mut diagnostic_text = vec![Content::text(serde_json::to_string(&summary).unwrap())];
+        diagnostic_text.extend(
+            report
+                .diagnostics
+                .iter()
+                .map(|diagnostic| Content::text(serde_json::to_string(diagnostic).unwrap())),
+        );
+        Ok(CallToolResult::success(diagnostic_text))
+    }
+
+    #[tool(
+        description = "Compare two verification runs, given as repositories or previously returned reports, and list the resolved, introduced and unchanged diagnostics"
+    )]
+    async fn diff_reports(
+        &self,
+        Parameters(args): Parameters<DiffReportsArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let before = self
+            .report_from(args.before_report.as_deref(), args.before_repo_path.as_deref())
+            .await?;
+        let after = self
+            .report_from(args.after_report.as_deref(), args.after_repo_path.as_deref())
+            .await?;
+        let diff = diagnostics::diff(&before, &after);
+        let summary = format!(
+            "{} resolved, {} introduced, {} unchanged",
+            diff.resolved.len(),
+            diff.introduced.len(),
+            diff.unchanged.len()
+        );
+        Ok(CallToolResult::success(vec![
+            Content::text(serde_json::to_string(&diff).unwrap()),
+            Content::text(summary),
+        ]))
+    }
+
+    #[tool(
+        description = "Compare two sets of lemmas, each dumped from a repository or read from a saved snapshot, to catch lemmas lost or moved while upgrading flux or refactoring. Lemmas are matched by name and reported as `added`, `removed` or `moved` to a different location"
+    )]
+    async fn lemma_diff(
+        &self,
+        Parameters(args): Parameters<LemmaDiffArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let before = self
+            .lemmas_from(args.before_snapshot.as_deref(), args.before_repo_path.as_deref())
+            .await?;
+        let after = self
+            .lemmas_from(args.after_snapshot.as_deref(), args.after_repo_path.as_deref())
+            .await?;
+        let diff = flux_runner::diff_lemmas(&before, &after);
+        let summary = format!(
+            "{} added, {} removed, {} moved, {} unchanged",
+            diff.added.len(),
+            diff.removed.len(),
+            diff.moved.len(),
+            diff.unchanged
+        );
+        Ok(CallToolResult::success(vec![
+            Content::text(serde_json::to_string(&diff).unwrap()),
+            Content::text(summary),
+        ]))
+    }
+
+    #[tool(description = "Get only the syntax errors from Flux verification")]
+    async fn get_syntax_errors(
+        &self,
+        Parameters(args): Parameters<VerifyRepositoryArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let result = Self::verify_scoped(runner, &args).await;
+        match result {
+            Ok(VerificationReport { success, diagnostics, .. }) => {
+                let syntax_errors = if args.include_warnings {
+                    diagnostics::with_warnings(diagnostics)
+                } else {
+                    diagnostics::retain_only_syntax_errors(diagnostics)
+                };
+                let summary = VerificationSummary::of(success, &syntax_errors);
+                let mut diagnostic_text =
+                    vec![Content::text(serde_json::to_string(&summary).unwrap())];
+                diagnostic_text.extend(
+                    syntax_errors.iter().map(|diagnostic| {
+                        Content::text(serde_json::to_string(diagnostic).unwrap())
+                    }),
+                );
                 Ok(CallToolResult::success(diagnostic_text))
             }
-            Err(err) => {
-                Err(McpErrorData::invalid_request(format!("Verification failed {err}"), None))
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Get only the Flux refinement failures from verification, the complement of `get_syntax_errors`. Failures whose primary spans start on the same line are grouped into one entry with the file and line, and each failure carries its primary span, code and message"
+    )]
+    async fn get_verification_errors(
+        &self,
+        Parameters(args): Parameters<VerifyRepositoryArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let result = Self::verify_scoped(runner, &args).await;
+        match result {
+            Ok(VerificationReport { success, diagnostics, .. }) => {
+                let flux_errors = diagnostics::retain_only_flux_errors(diagnostics);
+                let summary = VerificationSummary::of(success, &flux_errors);
+                let sites = diagnostics::failure_sites(flux_errors);
+                Ok(CallToolResult::success(vec![
+                    Content::text(serde_json::to_string(&summary).unwrap()),
+                    Content::text(serde_json::to_string(&sites).unwrap()),
+                ]))
             }
+            Err(err) => Err(runner_error("Verification failed", err)),
         }
     }
 
-    #[tool(description = "Get a list of available lemmas that can be used to help the solver with verification")]
+    #[tool(
+        description = "Get a list of available lemmas that can be used to help the solver with verification. With `stream`, each lemma is also sent as a logging notification as soon as flux prints it, to start showing lemmas before a long dump finishes"
+    )]
     async fn get_lemmas(
         &self,
         Parameters(args): Parameters<GetLemmaArgs>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let coordinate_base = coordinate_base(args.coordinate_base)?;
+        let packages: Option<Vec<&str>> = args
+            .packages
+            .as_ref()
+            .map(|packages| packages.iter().map(String::as_str).collect());
+        let result = if args.stream {
+            let (sink, stream) = runner.lemma_channel();
+            let forwarder = tokio::spawn(async move {
+                let mut stream = stream;
+                while let Some(mut lemma) = stream.recv().await {
+                    lemma.rebase_coordinates(coordinate_base);
+                    let notification = LoggingMessageNotificationParam {
+                        level: LoggingLevel::Info,
+                        logger: Some("flux-lemmas".to_string()),
+                        data: serde_json::to_value(&lemma).unwrap(),
+                    };
+                    if let Err(err) = peer.notify_logging_message(notification).await {
+                        tracing::warn!("Stopping lemma notifications: {err}");
+                        break;
+                    }
+                }
+            });
+            let result = runner
+                .get_lemmas_streaming(&args.repo_path, packages.as_deref(), sink)
+                .await;
+            let _ = forwarder.await;
+            result
+        } else {
+            runner
+                .get_lemmas(&args.repo_path, packages.as_deref())
+                .await
+        };
+        match result {
+            Ok(mut lemmas) => {
+                if args.trusted_lemmas {
+                    lemmas.retain(|lemma| lemma.trusted);
+                }
+                let lemmas_text: Vec<_> = lemmas
+                    .iter_mut()
+                    .map(|lemma| {
+                        lemma.rebase_coordinates(coordinate_base);
+                        Content::text(serde_json::to_string(lemma).unwrap())
+                    })
+                    .collect();
+                Ok(CallToolResult::success(lemmas_text))
+            }
+            Err(err) => Err(runner_error("Failed to fetch lemmas", err)),
+        }
+    }
+
+    #[tool(
+        description = "Get the available lemmas from a snapshot on disk, re-dumping them only when the contents of the Rust sources changed since it was taken. The first item is the `fingerprint` of the sources the lemmas come from"
+    )]
+    async fn get_lemmas_cached(
+        &self,
+        Parameters(args): Parameters<GetLemmasCachedArgs>,
     ) -> Result<CallToolResult, McpErrorData> {
-        let runner = self.runner.lock().await;
-        let result = runner.get_lemmas(&args.repo_path).await;
+        let snapshot = lemma_snapshot_path(&args.repo_path, args.snapshot_path);
+        let runner = &self.runner;
+        let result = runner.get_lemmas_cached(&args.repo_path, &snapshot).await;
         match result {
+            Ok(snapshot) => {
+                let fingerprint = serde_json::json!({ "fingerprint": snapshot.fingerprint });
+                let mut lemmas_text = vec![Content::text(fingerprint.to_string())];
+                lemmas_text.extend(
+                    snapshot
+                        .lemmas
+                        .iter()
+                        .map(|lemma| Content::text(serde_json::to_string(lemma).unwrap())),
+                );
+                Ok(CallToolResult::success(lemmas_text))
+            }
+            Err(err) => Err(runner_error("Failed to fetch lemmas", err)),
+        }
+    }
+
+    #[tool(
+        description = "Find lemmas declared in several modules under the same bare name, which makes naming one ambiguous. Use the qualified name, or rename one, before instantiating such a lemma"
+    )]
+    async fn lemma_conflicts(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.detect_lemma_conflicts(&args.repo_path).await {
+            Ok(conflicts) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&conflicts).unwrap(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Failed to fetch lemmas", err)),
+        }
+    }
+
+    #[tool(
+        description = "List the available lemmas with a one-line preview of each, the trimmed source of its first line, for showing a lemma picker without fetching whole definitions"
+    )]
+    async fn lemma_previews(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.lemma_previews(&args.repo_path).await {
+            Ok(previews) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&previews).unwrap(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Failed to fetch lemmas", err)),
+        }
+    }
+
+    #[tool(description = "Count the available lemmas in each file, without their definitions")]
+    async fn lemma_index(
+        &self,
+        Parameters(args): Parameters<GetLemmaArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let packages: Option<Vec<&str>> = args
+            .packages
+            .as_ref()
+            .map(|packages| packages.iter().map(String::as_str).collect());
+        match runner
+            .lemma_index(&args.repo_path, packages.as_deref())
+            .await
+        {
+            Ok(index) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&index).unwrap(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Failed to fetch lemmas", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification and list the functions with a flux signature that passed, i.e. have no error inside them and aren't trusted. The list is empty when an error can't be attributed to a function, since then nothing is known to have passed"
+    )]
+    async fn verified_items(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.verified_items(&args.repo_path).await {
+            Ok(items) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::json!({ "verified": items }).to_string(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "List the available lemmas whose `requires` or `ensures` clauses mention a function symbol, e.g. `head`, to narrow the candidates when the failing goal is known to involve it"
+    )]
+    async fn lemmas_about(
+        &self,
+        Parameters(args): Parameters<LemmasAboutArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.lemmas_about(&args.repo_path, &args.symbol).await {
             Ok(lemmas) => {
                 let lemmas_text: Vec<_> = lemmas
                     .iter()
@@ -121,20 +1061,1300 @@ impl FluxMcp {
                     .collect();
                 Ok(CallToolResult::success(lemmas_text))
             }
-            Err(err) => {
-                Err(McpErrorData::invalid_request(format!("Failed to fetch lemmas {err}"), None))
+            Err(err) => Err(runner_error("Failed to fetch lemmas", err)),
+        }
+    }
+
+    #[tool(
+        description = "List the available lemmas stated over a sort, e.g. `ISeq`: those whose signature names it or mentions a `defs!` function or a type refined by it. When a failing goal involves a value of that sort, these are the relevant candidates"
+    )]
+    async fn lemmas_for_sort(
+        &self,
+        Parameters(args): Parameters<LemmasForSortArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner
+            .lemmas_for_sort(&args.repo_path, &args.sort_name)
+            .await
+        {
+            Ok(lemmas) => {
+                let lemmas_text: Vec<_> = lemmas
+                    .iter()
+                    .map(|lemma| Content::text(serde_json::to_string(lemma).unwrap()))
+                    .collect();
+                Ok(CallToolResult::success(lemmas_text))
             }
+            Err(err) => Err(runner_error("Failed to fetch lemmas", err)),
         }
     }
-}
 
-#[tool_handler]
-impl ServerHandler for FluxMcp {
-    fn get_info(&self) -> ServerInfo {
-        ServerInfo {
-            instructions: Some("This server exposes Flux verification tools".to_string()),
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
-            ..Default::default()
+    #[tool(
+        description = "Count how often each available lemma is used in the repository's sources. Lemmas used 0 times are candidates for removal"
+    )]
+    async fn lemma_usage(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.lemma_usage(&args.repo_path).await {
+            Ok(usage) => {
+                let usage_text: Vec<_> = usage
+                    .iter()
+                    .map(|(lemma, uses)| {
+                        Content::text(
+                            serde_json::json!({ "lemma": lemma, "uses": uses }).to_string(),
+                        )
+                    })
+                    .collect();
+                Ok(CallToolResult::success(usage_text))
+            }
+            Err(err) => Err(runner_error("Failed to fetch lemmas", err)),
+        }
+    }
+
+    #[tool(
+        description = "Get the graph of how lemmas build on each other, where an edge from A to B means A's body or signature refers to B. Optionally also rendered as Graphviz DOT"
+    )]
+    async fn lemma_graph(
+        &self,
+        Parameters(args): Parameters<LemmaGraphArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.lemma_graph(&args.repo_path).await {
+            Ok(graph) => {
+                let mut graph_text = vec![Content::text(serde_json::to_string(&graph).unwrap())];
+                if args.dot {
+                    graph_text.push(Content::text(graph.to_dot()));
+                }
+                Ok(CallToolResult::success(graph_text))
+            }
+            Err(err) => Err(runner_error("Failed to fetch lemmas", err)),
+        }
+    }
+
+    #[tool(
+        description = "Verify a repository with constraint dumps enabled and return the constraints flux generated for each function, after simplification and before they are sent to the solver. Optionally only for functions with a given name or path"
+    )]
+    async fn get_constraints(
+        &self,
+        Parameters(args): Parameters<GetConstraintsArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner
+            .get_constraints(&args.repo_path, args.function.as_deref())
+            .await
+        {
+            Ok(dumps) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&dumps).unwrap(),
+                )]))
+            }
+            Err(err @ FluxRunnerError::UnsupportedOption(_)) => {
+                Err(McpErrorData::internal_error(
+                    format!("Constraint dumps are unsupported by this flux version: {err}"),
+                    None,
+                ))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Get the location of every available lemma as a `file://` URI and 0-based range"
+    )]
+    async fn get_lemma_locations(
+        &self,
+        Parameters(args): Parameters<GetLemmaLocationsArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let packages: Option<Vec<&str>> = args
+            .packages
+            .as_ref()
+            .map(|packages| packages.iter().map(String::as_str).collect());
+        let result = runner
+            .get_lemmas(&args.repo_path, packages.as_deref())
+            .await;
+        match result {
+            Ok(lemmas) => {
+                let locations_text: Vec<_> = lemmas
+                    .iter()
+                    .map(|lemma| {
+                        let location = LemmaLocation {
+                            name: lemma.name.clone(),
+                            location: lemma.to_location(&args.repo_path, args.position_encoding),
+                        };
+                        Content::text(serde_json::to_string(&location).unwrap())
+                    })
+                    .collect();
+                Ok(CallToolResult::success(locations_text))
+            }
+            Err(err) => Err(runner_error("Failed to fetch lemmas", err)),
+        }
+    }
+
+    #[tool(
+        description = "Get the sorts and uninterpreted functions declared in `flux_rs::defs!` blocks"
+    )]
+    async fn get_definitions(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let definitions = runner.get_definitions(&args.repo_path).await;
+        let definitions_text: Vec<_> = definitions
+            .iter()
+            .map(|def| Content::text(serde_json::to_string(def).unwrap()))
+            .collect();
+        Ok(CallToolResult::success(definitions_text))
+    }
+
+    #[tool(
+        description = "List the opaque sorts and the uninterpreted functions, with their parameters and return sorts, declared in `flux_rs::defs!` blocks, grouped into `sorts` and `funcs`"
+    )]
+    async fn list_sorts_and_funcs(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let grouped = runner.list_sorts_and_funcs(&args.repo_path).await;
+        Ok(CallToolResult::success(vec![Content::text(serde_json::to_string(&grouped).unwrap())]))
+    }
+
+    #[tool(
+        description = "Try out a new flux signature for a function without touching the repository: a scratch copy is verified before and after the edit. Reports whether the function verifies with each signature, which other items the edit broke or fixed, and the diagnostics that changed"
+    )]
+    async fn spec_edit_impact(
+        &self,
+        Parameters(args): Parameters<SpecEditImpactArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner
+            .spec_edit_impact(&args.repo_path, &args.item, &args.new_sig)
+            .await
+        {
+            Ok(impact) => {
+                let summary = format!(
+                    "`{}` {} before and {} after the edit, {} other items broke and {} were fixed",
+                    impact.item,
+                    if impact.passed_before { "verified" } else { "failed" },
+                    if impact.passes_after { "verifies" } else { "fails" },
+                    impact.broken.len(),
+                    impact.fixed.len(),
+                );
+                Ok(CallToolResult::success(vec![
+                    Content::text(serde_json::to_string(&impact).unwrap()),
+                    Content::text(summary),
+                ]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Get the flux signature of every function, method and trait item in a file that has one, with the function's name and the line of the `sig` attribute"
+    )]
+    async fn extract_sigs(
+        &self,
+        Parameters(args): Parameters<ExtractSigsArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.extract_sigs(&args.repo_path, &args.file_path).await {
+            Ok(sigs) => {
+                let sigs_text: Vec<_> = sigs
+                    .iter()
+                    .map(|sig| Content::text(serde_json::to_string(sig).unwrap()))
+                    .collect();
+                Ok(CallToolResult::success(sigs_text))
+            }
+            Err(err) => Err(runner_error("Failed to extract signatures", err)),
+        }
+    }
+
+    #[tool(
+        description = "Try a batch of lemma instantiations in one run: insert every call, each before a line of its file, into a scratch copy of the repository and verify it. Reports whether verification passes, whether each call was accepted (calls that don't parse or point past the end of their file are left out), the errors flux reports at each call, and the diagnostics that remain. The repository itself is never touched"
+    )]
+    async fn verify_with_extra_lemmas(
+        &self,
+        Parameters(args): Parameters<VerifyWithExtraLemmasArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner
+            .verify_with_extra_lemmas(&args.repo_path, &args.calls)
+            .await
+        {
+            Ok(result) => {
+                let accepted = result.calls.iter().filter(|call| call.accepted).count();
+                let summary = format!(
+                    "Verification {} with {accepted} of {} calls inserted, {} diagnostics remain",
+                    if result.success { "passed" } else { "failed" },
+                    result.calls.len(),
+                    result.remaining.len(),
+                );
+                Ok(CallToolResult::success(vec![
+                    Content::text(serde_json::to_string(&result).unwrap()),
+                    Content::text(summary),
+                ]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Try one lemma instantiation: insert the call `name(args);` at a line and column inside a failing function, in a scratch copy of the repository, and compare the Flux refinement errors with and without it. The outcome is `helped` when the enclosing item has fewer errors and none are new, `no_change` when it has as many, and `new_errors` when the call itself fails, e.g. on the lemma's precondition, or errors appear that weren't there before. The name must be one `get_lemmas` lists. The repository itself is never touched"
+    )]
+    async fn try_lemma(
+        &self,
+        Parameters(args): Parameters<TryLemmaArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner
+            .try_lemma(
+                &args.repo_path,
+                &args.file_path,
+                args.line,
+                args.column,
+                &args.name,
+                &args.args,
+            )
+            .await
+        {
+            Ok(result) => {
+                let summary = format!(
+                    "`{}` in {}: {} refinement errors before, {} after, {} at the call, {} new",
+                    result.call,
+                    result.item,
+                    result.errors_before,
+                    result.errors_after,
+                    result.call_errors.len(),
+                    result.introduced.len(),
+                );
+                Ok(CallToolResult::success(vec![
+                    Content::text(serde_json::to_string(&result).unwrap()),
+                    Content::text(summary),
+                ]))
+            }
+            Err(err) => Err(runner_error("Trying the lemma failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification and return only the errors, each with the `counterexample` flux found for it, if any: the values of the variables that make the failed refinement false. After the summary, the number of errors that have one is given as `with_counterexample`"
+    )]
+    async fn verify_with_counterexamples(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.verify_repository(&args.repo_path, false).await {
+            Ok(report) => {
+                let errors: Vec<_> = report
+                    .diagnostics
+                    .iter()
+                    .filter(|diagnostic| {
+                        diagnostic.message.parsed_level == diagnostics::Level::Error
+                    })
+                    .collect();
+                let with_counterexample = errors
+                    .iter()
+                    .filter(|diagnostic| diagnostic.counterexample.is_some())
+                    .count();
+                let summary = VerificationSummary::of(report.success, &report.diagnostics);
+                let mut diagnostic_text = vec![
+                    Content::text(serde_json::to_string(&summary).unwrap()),
+                    Content::text(
+                        serde_json::json!({ "with_counterexample": with_counterexample })
+                            .to_string(),
+                    ),
+                ];
+                diagnostic_text.extend(
+                    errors.iter().map(|diagnostic| {
+                        Content::text(serde_json::to_string(diagnostic).unwrap())
+                    }),
+                );
+                Ok(CallToolResult::success(diagnostic_text))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Verify the repository and return the diagnostics as a single rustc `--error-format=human` text stream, ending with rustc's error count, for tools and scripts that process rustc's plain output"
+    )]
+    async fn verify_human(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.verify_repository(&args.repo_path, false).await {
+            Ok(report) => {
+                let root = report.resolved_root.as_deref().unwrap_or(&args.repo_path);
+                let text = diagnostics::render_human(&report.diagnostics, Path::new(root));
+                Ok(CallToolResult::success(vec![Content::text(text)]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Verify the repository and return each error together with the source of the `impl` block it is in, as `impl_context`, with the block's generics, where-clauses and sibling methods. Use it for errors inside methods, where the single function is too little context"
+    )]
+    async fn verify_with_impl_context(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.verify_repository(&args.repo_path, false).await {
+            Ok(report) => {
+                let root = report.resolved_root.as_deref().unwrap_or(&args.repo_path);
+                let summary = VerificationSummary::of(report.success, &report.diagnostics);
+                let mut diagnostic_text =
+                    vec![Content::text(serde_json::to_string(&summary).unwrap())];
+                for diagnostic in &report.diagnostics {
+                    if diagnostic.message.parsed_level != diagnostics::Level::Error {
+                        continue;
+                    }
+                    let impl_context = diagnostic
+                        .message
+                        .primary_span()
+                        .and_then(|span| diagnostics::enclosing_impl(span, Path::new(root)));
+                    diagnostic_text.push(Content::text(
+                        serde_json::json!({ "diagnostic": diagnostic, "impl_context": impl_context })
+                            .to_string(),
+                    ));
+                }
+                Ok(CallToolResult::success(diagnostic_text))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "ESCAPE HATCH, NOT A FIX: return the edit marking the item a failing diagnostic is in as `#[flux::trusted]`, which silences the error by no longer verifying the item. Lists what becomes unverified. Only use it when no lemma or spec change can make the check pass"
+    )]
+    async fn suggest_trusted_annotation(
+        &self,
+        Parameters(args): Parameters<SuggestTrustedAnnotationArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner
+            .suggest_trusted_annotation(&args.repo_path, &args.diagnostic)
+            .await
+        {
+            Ok(annotation) => {
+                Ok(CallToolResult::success(vec![
+                    Content::text(annotation.warning.clone()),
+                    Content::text(serde_json::to_string(&annotation).unwrap()),
+                ]))
+            }
+            Err(err) => Err(runner_error("Failed to suggest an annotation", err)),
+        }
+    }
+
+    #[tool(
+        description = "Given a diagnostic at a call whose precondition flux couldn't prove, propose `requires` clauses for the caller, found by rewriting the callee's `requires` in terms of the caller's refinement parameters. HEURISTIC SUGGESTIONS, NOT GUARANTEES: each has a confidence of high, medium or low and should be checked by verifying again"
+    )]
+    async fn suggest_precondition(
+        &self,
+        Parameters(args): Parameters<SuggestPreconditionArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner
+            .suggest_precondition(&args.repo_path, &args.diagnostic)
+            .await
+        {
+            Ok(suggestions) => {
+                Ok(CallToolResult::success(vec![
+                    Content::text(suggestions.warning.clone()),
+                    Content::text(serde_json::to_string(&suggestions).unwrap()),
+                ]))
+            }
+            Err(err) => Err(runner_error("Failed to suggest a precondition", err)),
+        }
+    }
+
+    #[tool(
+        description = "Apply a unified diff to a scratch copy of the repository with `git apply` and run Flux verification on the result, without touching the repository. After the summary, the hunks that failed to apply and were left out are listed as `rejected`"
+    )]
+    async fn verify_patch(
+        &self,
+        Parameters(args): Parameters<VerifyPatchArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.verify_patch(&args.repo_path, &args.patch).await {
+            Ok((report, rejected)) => {
+                let summary = VerificationSummary::of(report.success, &report.diagnostics);
+                let mut diagnostic_text = vec![
+                    Content::text(serde_json::to_string(&summary).unwrap()),
+                    Content::text(serde_json::json!({ "rejected": rejected }).to_string()),
+                ];
+                diagnostic_text.extend(
+                    report.diagnostics.iter().map(|diagnostic| {
+                        Content::text(serde_json::to_string(diagnostic).unwrap())
+                    }),
+                );
+                if report.truncated {
+                    diagnostic_text.push(Content::text(TRUNCATED_NOTE));
+                }
+                Ok(CallToolResult::success(diagnostic_text))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification on a repository, then `cargo fmt` on a scratch copy of it, and return the report along with the formatted contents of the given files, or of every file formatting changed. A formatting failure is reported as `error` without affecting the report. The repository is only changed with `write: true`, which writes the formatted files back"
+    )]
+    async fn verify_and_fmt(
+        &self,
+        Parameters(args): Parameters<VerifyAndFmtArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner
+            .verify_and_fmt(&args.repo_path, args.files.as_deref(), args.write)
+            .await
+        {
+            Ok((report, fmt)) => {
+                let summary = VerificationSummary::of(report.success, &report.diagnostics);
+                let mut diagnostic_text = vec![
+                    Content::text(serde_json::to_string(&summary).unwrap()),
+                    Content::text(serde_json::to_string(&fmt).unwrap()),
+                ];
+                diagnostic_text.extend(
+                    report.diagnostics.iter().map(|diagnostic| {
+                        Content::text(serde_json::to_string(diagnostic).unwrap())
+                    }),
+                );
+                if report.truncated {
+                    diagnostic_text.push(Content::text(TRUNCATED_NOTE));
+                }
+                Ok(CallToolResult::success(diagnostic_text))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Shallow-clone a remote git repository at the given branch, tag or commit (`ref`, defaulting to the default branch) into a temporary directory and run Flux verification on it. Only https, http, ssh and git URLs are accepted, and the clone is bounded in time and size. The report records the commit verified"
+    )]
+    async fn verify_git(
+        &self,
+        Parameters(args): Parameters<VerifyGitArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner
+            .verify_git(&args.repo_url, args.git_ref.as_deref())
+            .await
+        {
+            Ok(report) => {
+                let summary = VerificationSummary::of(report.success, &report.diagnostics);
+                let mut diagnostic_text = vec![
+                    Content::text(serde_json::to_string(&summary).unwrap()),
+                    Content::text(serde_json::json!({ "commit": report.commit }).to_string()),
+                ];
+                diagnostic_text.extend(
+                    report.diagnostics.iter().map(|diagnostic| {
+                        Content::text(serde_json::to_string(diagnostic).unwrap())
+                    }),
+                );
+                if report.truncated {
+                    diagnostic_text.push(Content::text(TRUNCATED_NOTE));
+                }
+                Ok(CallToolResult::success(diagnostic_text))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Find a minimal subset of candidate lemma calls that still makes verification pass, so no unneeded instantiations are left in the code. Calls are inserted into scratch copies like `verify_with_extra_lemmas` does, and each one is dropped in turn if verification passes without it. Runs at most `max_attempts` (default 32) verifications and reports whether they ran out"
+    )]
+    async fn minimize_lemmas(
+        &self,
+        Parameters(args): Parameters<MinimizeLemmasArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let max_attempts = args.max_attempts.unwrap_or(DEFAULT_MINIMIZE_ATTEMPTS);
+        match runner
+            .minimize_lemmas(&args.repo_path, &args.calls, max_attempts)
+            .await
+        {
+            Ok(minimized) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&minimized).unwrap(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "List the items flux takes on faith instead of checking, those marked `#[flux::trusted]` or `#[flux::opaque]`, to audit how much of the repository is actually verified"
+    )]
+    async fn list_trusted(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let items = runner.list_trusted(&args.repo_path).await;
+        let items_text: Vec<_> = items
+            .iter()
+            .map(|item| Content::text(serde_json::to_string(item).unwrap()))
+            .collect();
+        Ok(CallToolResult::success(items_text))
+    }
+
+    #[tool(
+        description = "Report how many of the public functions of a workspace package, or of the whole repository if no package is given, have a `#[flux::sig]`, as `total_fns`, `specced_fns`, `trusted_fns` and their `ratio`, to track the progress of adopting flux"
+    )]
+    async fn spec_coverage(
+        &self,
+        Parameters(args): Parameters<SpecCoverageArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner
+            .spec_coverage(&args.repo_path, args.package.as_deref())
+            .await
+        {
+            Ok(coverage) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&coverage).unwrap(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Measuring coverage failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "List the functions a function calls, to look up their specs when proving a goal in it. Calls are found syntactically: paths as written, with only the name for method calls. `depth` follows the callees defined in the repository transitively, up to 5 levels"
+    )]
+    async fn callees(
+        &self,
+        Parameters(args): Parameters<CalleesArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let depth = args.depth.unwrap_or(1);
+        match runner.callees(&args.repo_path, &args.item, depth).await {
+            Some(callees) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&callees).unwrap(),
+                )]))
+            }
+            None => {
+                Err(McpErrorData::invalid_params(
+                    format!("No function {} with a body", args.item),
+                    None,
+                ))
+            }
+        }
+    }
+
+    #[tool(
+        description = "Find the file and line range of a named item: a function, type, trait, constant or module, or a method or associated item of an impl or trait. `item_path` is a path such as `crate::seq::RSeq::push` resolved from the crate root, or a suffix of one like `RSeq::push`, which picks the shortest matching item"
+    )]
+    async fn item_range(
+        &self,
+        Parameters(args): Parameters<ItemRangeArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.item_range(&args.repo_path, &args.item_path).await {
+            Some(range) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&range).unwrap(),
+                )]))
+            }
+            None => {
+                Err(McpErrorData::invalid_params(
+                    format!("No item {} in the repository", args.item_path),
+                    None,
+                ))
+            }
+        }
+    }
+
+    #[tool(
+        description = "Get the refinement index of a struct or enum from its `#[flux::refined_by(...)]` attribute: each index field with its sort, e.g. `elems: ISeq`, which is what the type's refinements and the lemmas about it talk about"
+    )]
+    async fn refined_by(
+        &self,
+        Parameters(args): Parameters<RefinedByArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.refined_by(&args.repo_path, &args.type_name).await {
+            Some(refined_by) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&refined_by).unwrap(),
+                )]))
+            }
+            None => {
+                Err(McpErrorData::invalid_params(
+                    format!("No type named {} with a `refined_by` attribute", args.type_name),
+                    None,
+                ))
+            }
+        }
+    }
+
+    #[tool(
+        description = "List the calls to the `assume` and `assert` flux intrinsics, with the fact each one states. Assumed facts are not proven, so together with `list_trusted` this gives the trust surface of the repository"
+    )]
+    async fn list_assumptions(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let sites = runner.list_assumptions(&args.repo_path).await;
+        let sites_text: Vec<_> = sites
+            .iter()
+            .map(|site| Content::text(serde_json::to_string(site).unwrap()))
+            .collect();
+        Ok(CallToolResult::success(sites_text))
+    }
+
+    #[tool(
+        description = "Check that the trust surface of a repository, its `trusted` and `opaque` items and `assume` calls, hasn't grown beyond the baseline recorded in a JSON file in the repository. Returns `ok: false` with the new items if it has. Pass `update` to record the current surface as the baseline"
+    )]
+    async fn check_trust_budget(
+        &self,
+        Parameters(args): Parameters<CheckTrustBudgetArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner
+            .check_trust_budget(&args.repo_path, args.baseline_path.as_deref(), args.update)
+            .await
+        {
+            Ok(budget) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&budget).unwrap(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Checking the trust budget failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Get the JSON schemas of the `VerificationReport`, `VerificationSummary`, `Diagnostic` and `Lemma` objects the other tools return, keyed by type name, for validating responses or generating client bindings"
+    )]
+    async fn get_schemas(&self) -> Result<CallToolResult, McpErrorData> {
+        Ok(CallToolResult::success(vec![Content::text(flux_runner::output_schemas().to_string())]))
+    }
+
+    #[tool(
+        description = "Report the version of flux the server invokes, along with the cargo binary and rustup toolchain it runs under"
+    )]
+    async fn flux_version(&self) -> Result<CallToolResult, McpErrorData> {
+        let version = self
+            .version
+            .get_or_try_init(|| {
+                async {
+                    let runner = &self.runner;
+                    runner.flux_version().await
+                }
+            })
+            .await
+            .map_err(|err| runner_error("Failed to query flux", err))?;
+        Ok(CallToolResult::success(vec![Content::text(serde_json::to_string(version).unwrap())]))
+    }
+
+    #[tool(
+        description = "List the `-F` options the installed flux supports, e.g. `check-overflow`, `dump-lemmas` or `solver`, to check for a capability before relying on it"
+    )]
+    async fn supported_flags(&self) -> Result<CallToolResult, McpErrorData> {
+        let flags = self
+            .supported_flags
+            .get_or_try_init(|| {
+                async {
+                    let runner = &self.runner;
+                    runner.supported_flags().await
+                }
+            })
+            .await
+            .map_err(|err| runner_error("Failed to query flux", err))?;
+        Ok(CallToolResult::success(vec![Content::text(
+            serde_json::json!({ "flags": flags }).to_string(),
+        )]))
+    }
+
+    #[tool(
+        description = "Watch a repository and re-verify it whenever a Rust source file changes. Reports are sent as logging notifications"
+    )]
+    async fn start_watch(
+        &self,
+        Parameters(args): Parameters<StartWatchArgs>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let repo_path = std::fs::canonicalize(&args.repo_path).map_err(|err| {
+            McpErrorData::invalid_params(format!("Invalid repository path {err}"), None)
+        })?;
+        let mut watches = self.watches.lock().await;
+        if watches.contains_key(&repo_path) {
+            return Err(McpErrorData::invalid_request(
+                format!("Already watching {}", repo_path.display()),
+                None,
+            ));
+        }
+        let debounce = args
+            .debounce_ms
+            .map_or(watch::DEFAULT_DEBOUNCE, Duration::from_millis);
+        let watch = Watch::start(repo_path.clone(), debounce, self.runner.clone(), peer).map_err(
+            |err| McpErrorData::internal_error(format!("Failed to start watch {err}"), None),
+        )?;
+        let result_text = format!("Watching {}", repo_path.display());
+        watches.insert(repo_path, watch);
+        Ok(CallToolResult::success(vec![Content::text(result_text)]))
+    }
+
+    #[tool(
+        description = "Run Flux verification and send each diagnostic as a logging notification as soon as flux prints it, then return the summary of the full report. If the client reads notifications slowly, flux is paused rather than diagnostics being dropped"
+    )]
+    async fn verify_streaming(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let (sink, stream) = runner.diagnostic_channel();
+        let forwarder = tokio::spawn(async move {
+            let mut stream = stream;
+            while let Some(diagnostic) = stream.recv().await {
+                let notification = LoggingMessageNotificationParam {
+                    level: LoggingLevel::Info,
+                    logger: Some("flux-diagnostics".to_string()),
+                    data: serde_json::to_value(&diagnostic).unwrap(),
+                };
+                if let Err(err) = peer.notify_logging_message(notification).await {
+                    tracing::warn!("Stopping diagnostic notifications: {err}");
+                    break;
+                }
+            }
+        });
+        let result = runner
+            .verify_repository_streaming(&args.repo_path, RepoConfig::default(), sink)
+            .await;
+        let _ = forwarder.await;
+        match result {
+            Ok(report) => {
+                let summary = VerificationSummary::of(report.success, &report.diagnostics);
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&summary).unwrap(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Start verifying a repository in the background and return a `job_id` immediately. Use poll_verification to get the result"
+    )]
+    async fn verify_start(
+        &self,
+        Parameters(args): Parameters<VerifyStartArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = self.runner.clone();
+        let verify = VerifyRepositoryArgs {
+            repo_path: args.repo_path,
+            collect_stats: args.collect_stats,
+            include_external: args.include_external,
+            ..Default::default()
+        };
+        let job_id = self
+            .jobs
+            .lock()
+            .await
+            .start(async move { Self::verify_scoped(&runner, &verify).await });
+        Ok(CallToolResult::success(vec![Content::text(
+            serde_json::json!({ "job_id": job_id }).to_string(),
+        )]))
+    }
+
+    #[tool(
+        description = "Get the status of a job started with verify_start: `running` with the elapsed time, `done` with the report or `failed` with the error. The most recently used finished jobs are kept, so they can be polled again"
+    )]
+    async fn poll_verification(
+        &self,
+        Parameters(args): Parameters<PollVerificationArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let Some(status) = self.jobs.lock().await.poll(args.job_id).await else {
+            return Err(McpErrorData::invalid_params(
+                format!("No verification job {}", args.job_id),
+                None,
+            ));
+        };
+        Ok(CallToolResult::success(vec![Content::text(serde_json::to_string(&status).unwrap())]))
+    }
+
+    #[tool(
+        description = "Verify a repository and list every inline `flux_rs::assert` in it with its status: `ok`, `failing` with the error's message, or `not_checked` inside trusted items or when flux stopped early. A per-assertion checklist, finer than the per-function results"
+    )]
+    async fn inline_assert_status(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.inline_assert_status(&args.repo_path).await {
+            Ok(asserts) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&asserts).unwrap(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Verify a repository and return only the diagnostics that weren't there in the last successful run of this tool on it, i.e. what broke since the repository was last green. A successful run becomes the new green baseline. Until one is recorded, every diagnostic is returned"
+    )]
+    async fn regressions_only(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let report = runner
+            .verify_repository(&args.repo_path, false)
+            .await
+            .map_err(|err| runner_error("Verification failed", err))?;
+        let key = Path::new(&args.repo_path)
+            .canonicalize()
+            .unwrap_or_else(|_| PathBuf::from(&args.repo_path));
+        let mut baselines = self.green_baselines.lock().await;
+        let baseline = baselines.get(&key);
+        let regressions = match baseline {
+            Some(known) => diagnostics::introduced_since(&report.diagnostics, known),
+            None => report.diagnostics.clone(),
+        };
+        let summary = serde_json::json!({
+            "success": report.success,
+            "green_baseline": baseline.is_some(),
+            "regressions": regressions.len(),
+        });
+        if report.success {
+            let fingerprints = report
+                .diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.fingerprint.clone())
+                .collect();
+            baselines.insert(key, fingerprints);
+        }
+        let mut diagnostic_text = vec![Content::text(summary.to_string())];
+        diagnostic_text.extend(
+            regressions
+                .iter()
+                .map(|diagnostic| Content::text(serde_json::to_string(diagnostic).unwrap())),
+        );
+        Ok(CallToolResult::success(diagnostic_text))
+    }
+
+    #[tool(
+        description = "Verify a repository and return only what changed relative to the report of an earlier job: the resolved, introduced and unchanged diagnostics. The new report gets its own `job_id` to compare against next"
+    )]
+    async fn verify_delta(
+        &self,
+        Parameters(args): Parameters<VerifyDeltaArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let baseline = match self.jobs.lock().await.poll(args.baseline_job_id).await {
+            Some(JobStatus::Done { report }) => *report,
+            Some(JobStatus::Running { .. }) => {
+                return Err(McpErrorData::invalid_request(
+                    format!("Verification job {} is still running", args.baseline_job_id),
+                    None,
+                ));
+            }
+            Some(JobStatus::Failed { error }) => {
+                return Err(McpErrorData::invalid_request(
+                    format!("Verification job {} failed {error}", args.baseline_job_id),
+                    None,
+                ));
+            }
+            None => {
+                return Err(McpErrorData::invalid_params(
+                    format!("No verification job {}", args.baseline_job_id),
+                    None,
+                ));
+            }
+        };
+        let runner = &self.runner;
+        let verify = VerifyRepositoryArgs {
+            repo_path: args.repo_path.clone(),
+            include_external: args.include_external,
+            ..Default::default()
+        };
+        let report = Self::verify_scoped(runner, &verify)
+            .await
+            .map_err(|err| runner_error("Verification failed", err))?;
+        let diff = diagnostics::diff(&baseline, &report);
+        let job_id = self.jobs.lock().await.insert(report);
+        let summary = format!(
+            "{} resolved, {} introduced, {} unchanged",
+            diff.resolved.len(),
+            diff.introduced.len(),
+            diff.unchanged.len()
+        );
+        Ok(CallToolResult::success(vec![
+            Content::text(serde_json::json!({ "job_id": job_id }).to_string()),
+            Content::text(serde_json::to_string(&diff).unwrap()),
+            Content::text(summary),
+        ]))
+    }
+
+    #[tool(description = "Stop a watch started with start_watch")]
+    async fn stop_watch(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let repo_path = std::fs::canonicalize(&args.repo_path)
+            .unwrap_or_else(|_| PathBuf::from(&args.repo_path));
+        let Some(watch) = self.watches.lock().await.remove(&repo_path) else {
+            return Err(McpErrorData::invalid_request(
+                format!("Not watching {}", repo_path.display()),
+                None,
+            ));
+        };
+        watch.stop();
+        let result_text = format!("Stopped watching {}", repo_path.display());
+        Ok(CallToolResult::success(vec![Content::text(result_text)]))
+    }
+
+    #[tool(
+        description = "Check whether the single proof obligation at a line, e.g. an assertion or a returned value, holds. Verifies the repository and returns `discharged`, `failed` with the error whose primary span contains the line, or `not_checked` with the reason, e.g. the line is inside a trusted function"
+    )]
+    async fn goal_status(
+        &self,
+        Parameters(args): Parameters<GoalStatusArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner
+            .goal_status(&args.repo_path, &args.file_path, args.line)
+            .await
+        {
+            Ok(status) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&status).unwrap(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification and return the top-most failing refinement check as the next goal: the diagnostic, its kind, source snippet and enclosing item, and the lemmas most likely to help. Reports `no_failing_goals` when there is none"
+    )]
+    async fn next_goal(
+        &self,
+        Parameters(args): Parameters<RepoPathArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner.next_goal(&args.repo_path).await {
+            Ok(next) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&next).unwrap(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification and gather everything needed to fix the top-most failing refinement check in one bundle: the diagnostic with its rendering, snippet and kind, the source of the item containing it, the flux signatures of the functions that item calls, the `defs!` items those signatures mention, and the lemmas most likely to help. Kept under `max_chars` by dropping defs, then lemmas, then callee signatures, then cutting the item's source; `truncation` counts what was dropped. Reports `no_failing_goals` when there is none"
+    )]
+    async fn goal_context_bundle(
+        &self,
+        Parameters(args): Parameters<GoalContextBundleArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let max_chars = args.max_chars.unwrap_or(DEFAULT_BUNDLE_CHARS);
+        match runner.goal_context_bundle(&args.repo_path, max_chars).await {
+            Ok(context) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&context).unwrap(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Run Flux verification and pair every refinement error with the `top_k` (default 3) lemmas most likely to help, best first. Lemmas come from the snapshot `get_lemmas_cached` keeps, so repeated calls only re-dump them when the sources changed. Returns one `{ diagnostic, kind, suggested_lemmas }` item per error"
+    )]
+    async fn verify_and_suggest(
+        &self,
+        Parameters(args): Parameters<VerifyAndSuggestArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let snapshot = lemma_snapshot_path(&args.repo_path, args.snapshot_path);
+        let top_k = args.top_k.unwrap_or(DEFAULT_SUGGESTIONS);
+        let runner = &self.runner;
+        match runner
+            .verify_and_suggest(&args.repo_path, top_k, &snapshot)
+            .await
+        {
+            Ok(suggestions) => {
+                let suggestions_text: Vec<_> = suggestions
+                    .iter()
+                    .map(|item| Content::text(serde_json::to_string(item).unwrap()))
+                    .collect();
+                Ok(CallToolResult::success(suggestions_text))
+            }
+            Err(err) => Err(runner_error("Verification failed", err)),
+        }
+    }
+
+    #[tool(
+        description = "Get the whole file defining a lemma together with the lemma's 1-based `start_line..end_line`, for reading it in context. Files over 256 KiB are cut short and marked `truncated`"
+    )]
+    async fn get_lemma_context(
+        &self,
+        Parameters(args): Parameters<LemmaDefsArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        match runner
+            .lemma_context(&args.repo_path, &args.lemma_name)
+            .await
+        {
+            Ok(Some(context)) => {
+                let mut context_text =
+                    vec![Content::text(serde_json::to_string(&context).unwrap())];
+                if context.truncated {
+                    context_text.push(Content::text(format!(
+                        "The file was truncated to its first {} bytes",
+                        context.contents.len()
+                    )));
+                }
+                Ok(CallToolResult::success(context_text))
+            }
+            Ok(None) => {
+                Err(McpErrorData::invalid_params(
+                    format!("No lemma named {}", args.lemma_name),
+                    None,
+                ))
+            }
+            Err(err) => Err(runner_error("Failed to fetch lemmas", err)),
+        }
+    }
+
+    #[tool(
+        description = "Explain what a lemma proves by printing the `requires` and `ensures` clauses of its flux signature"
+    )]
+    async fn explain_lemma(
+        &self,
+        Parameters(args): Parameters<LemmaDefsArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let lemmas = runner
+            .get_lemmas(&args.repo_path, None)
+            .await
+            .map_err(|err| runner_error("Failed to fetch lemmas", err))?;
+        let Some(lemma) = lemmas.iter().find(|lemma| {
+            lemma.name == args.lemma_name || lemma.name.ends_with(&format!("::{}", args.lemma_name))
+        }) else {
+            return Err(McpErrorData::invalid_params(
+                format!("No lemma named {}", args.lemma_name),
+                None,
+            ));
+        };
+        let mut explanation = format!("{} ({}:{})", lemma.name, lemma.file_name, lemma.start_line);
+        for (keyword, clauses) in [("requires", &lemma.requires), ("ensures", &lemma.ensures)] {
+            for clause in clauses {
+                explanation.push_str(&format!("\n  {keyword} {clause}"));
+            }
+        }
+        Ok(CallToolResult::success(vec![
+            Content::text(serde_json::to_string(lemma).unwrap()),
+            Content::text(explanation),
+        ]))
+    }
+
+    #[tool(
+        description = "Get the `defs!` sorts and functions referenced by the signature of a lemma"
+    )]
+    async fn lemma_defs(
+        &self,
+        Parameters(args): Parameters<LemmaDefsArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let defs = runner
+            .lemma_referenced_defs(&args.repo_path, &args.lemma_name)
+            .await;
+        let defs_text: Vec<_> = defs
+            .iter()
+            .map(|def| Content::text(serde_json::to_string(def).unwrap()))
+            .collect();
+        Ok(CallToolResult::success(defs_text))
+    }
+
+    #[tool(
+        description = "Check that every primary span of a verification report points at an existing file and at lines and columns within it, listing each one that doesn't with its `kind`: `missing_file` (e.g. a generated file), `line_out_of_range`, `column_out_of_range` or `inverted`. Checks `report` if given and otherwise verifies `repo_path`; an empty list means every location can be opened"
+    )]
+    async fn validate_report(
+        &self,
+        Parameters(args): Parameters<ValidateReportArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let report = self
+            .report_from(args.report.as_deref(), Some(&args.repo_path))
+            .await?;
+        let root = report.resolved_root.as_deref().unwrap_or(&args.repo_path);
+        let problems = diagnostics::validate_spans(&report, Path::new(root));
+        Ok(CallToolResult::success(vec![Content::text(serde_json::to_string(&problems).unwrap())]))
+    }
+
+    #[tool(
+        description = "Show how a lemma's source changed between two git refs of a local repository, e.g. to review edits to trusted axioms during a refactor. The lemma is found by path at each ref, wherever its file moved, and the result holds its location at each ref, `change` (`added`, `removed`, `changed` or `unchanged`) and a unified diff of its source"
+    )]
+    async fn lemma_body_diff(
+        &self,
+        Parameters(args): Parameters<LemmaBodyDiffArgs>,
+    ) -> Result<CallToolResult, McpErrorData> {
+        let runner = &self.runner;
+        let result = runner
+            .lemma_body_diff(&args.repo_path, &args.lemma_name, &args.from_ref, &args.to_ref)
+            .await;
+        match result {
+            Ok(diff) => {
+                Ok(CallToolResult::success(vec![Content::text(
+                    serde_json::to_string(&diff).unwrap(),
+                )]))
+            }
+            Err(err) => Err(runner_error("Diffing the lemma failed", err)),
+        }
+    }
+}
+
+impl FluxMcp {
+    /// A handle for reaping in-flight flux processes once the server stops.
+    pub fn children(&self) -> ChildTracker {
+        self.children.clone()
+    }
+
+    /// Verifies the repository described by `args`, hiding the diagnostics that point outside
+    /// of it unless `include_external` is set.
+    async fn verify_scoped(
+        runner: &FluxRunner,
+        args: &VerifyRepositoryArgs,
+    ) -> Result<VerificationReport, FluxRunnerError> {
+        let mut report = runner
+            .verify_repository_with(&args.repo_path, Self::options(args), Self::overrides(args))
+            .await?;
+        if !args.include_external {
+            let root = report.resolved_root.as_deref().unwrap_or(&args.repo_path);
+            let root = PathBuf::from(root);
+            diagnostics::retain_local(&mut report.diagnostics, &root);
+        }
+        Ok(report)
+    }
+
+    /// How the run `args` asks for goes, whatever the repository's settings.
+    fn options(args: &VerifyRepositoryArgs) -> RunOptions {
+        RunOptions { collect_stats: args.collect_stats, fail_fast: args.fail_fast }
+    }
+
+    /// The settings in `args` that take precedence over the repository's `flux-mcp.toml`.
+    fn overrides(args: &VerifyRepositoryArgs) -> RepoConfig {
+        RepoConfig {
+            exclude_globs: args.exclude_globs.clone(),
+            solver: args.solver.clone(),
+            keep_going: args.keep_going.then_some(true),
+            timeout_secs: args.timeout_secs,
+            smt_timeout_ms: args.smt_timeout_ms,
+            check_overflow: args.check_overflow,
+            strict_toolchain: args.strict_toolchain.then_some(true),
+            env: args
+                .env
+                .as_ref()
+                .map(|env| env.clone().into_iter().collect()),
+            ..Default::default()
+        }
+    }
+
+    /// Reads the lemmas of the snapshot at `snapshot` if given, and otherwise dumps those of
+    /// `repo_path`.
+    async fn lemmas_from(
+        &self,
+        snapshot: Option<&str>,
+        repo_path: Option<&str>,
+    ) -> Result<Vec<Lemma>, McpErrorData> {
+        let runner = &self.runner;
+        let lemmas = match (snapshot, repo_path) {
+            (Some(snapshot), _) => {
+                FluxRunner::load_lemmas(Path::new(snapshot)).map(|snapshot| snapshot.lemmas)
+            }
+            (None, Some(repo_path)) => runner.get_lemmas(repo_path, None).await,
+            (None, None) => {
+                return Err(McpErrorData::invalid_request(
+                    "Expected either a snapshot or a repository path".to_string(),
+                    None,
+                ));
+            }
+        };
+        lemmas.map_err(|err| runner_error("Failed to fetch lemmas", err))
+    }
+
+    /// Deserializes `report` if given, and otherwise verifies `repo_path`.
+    async fn report_from(
+        &self,
+        report: Option<&str>,
+        repo_path: Option<&str>,
+    ) -> Result<VerificationReport, McpErrorData> {
+        match (report, repo_path) {
+            (Some(report), _) => {
+                serde_json::from_str(report).map_err(|err| {
+                    McpErrorData::invalid_params(format!("Invalid report {err}"), None)
+                })
+            }
+            (None, Some(repo_path)) => {
+                let runner = &self.runner;
+                runner
+                    .verify_repository(repo_path, false)
+                    .await
+                    .map_err(|err| runner_error("Verification failed", err))
+            }
+            (None, None) => {
+                Err(McpErrorData::invalid_request(
+                    "Expected either a report or a repository path".to_string(),
+                    None,
+                ))
+            }
+        }
+    }
+}
+
+#[tool_handler]
+impl ServerHandler for FluxMcp {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            instructions: Some("This server exposes Flux verification tools".to_string()),
+            capabilities: ServerCapabilities::builder()
+                .enable_tools()
+                .enable_logging()
+                .build(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Reports a failed run as the client's fault, which retrying won't fix, or as an internal error
+/// worth retrying.
+fn runner_error(context: &str, err: FluxRunnerError) -> McpErrorData {
+    let message = format!("{context} {err}");
+    if err.is_client_error() {
+        McpErrorData::invalid_params(message, None)
+    } else {
+        McpErrorData::internal_error(message, None)
+    }
+}
+
+/// Where the lemma snapshot of the repository lives: `snapshot_path` if given, otherwise
+/// `target/flux-lemmas.json` in the repository.
+fn lemma_snapshot_path(repo_path: &str, snapshot_path: Option<String>) -> PathBuf {
+    snapshot_path
+        .map_or_else(|| Path::new(repo_path).join("target/flux-lemmas.json"), PathBuf::from)
+}
+
+/// The requested `coordinate_base`, which must be 0 or 1 and defaults to flux's 1.
+fn coordinate_base(base: Option<u8>) -> Result<u8, McpErrorData> {
+    match base.unwrap_or(1) {
+        base @ (0 | 1) => Ok(base),
+        base => {
+            Err(McpErrorData::invalid_params(
+                format!("coordinate_base must be 0 or 1, not {base}"),
+                None,
+            ))
         }
     }
 }
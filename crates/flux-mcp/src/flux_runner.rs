@@ -1,195 +1,8113 @@
 use std::{
-    io::{BufRead, BufReader},
-    path::Path,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt, fs, io,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
     process::{Command, Stdio},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
 };
 
 use rmcp::schemars::{self, JsonSchema};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt},
+    sync::{OwnedSemaphorePermit, mpsc, watch},
+};
+
+use crate::{
+    diagnostics::{
+        self, Diagnostic, DiagnosticSpan, DiagnosticTarget, FluxErrorKind, Level, SortOrder,
+        parse_message, parse_target,
+    },
+    git,
+    lsp::{self, LspLocation, LspPosition, LspRange, PositionEncoding},
+    repo_config::{self, RepoConfig},
+    scratch::ScratchRepo,
+    source::{
+        self, AssumptionSite, Coverage, DefItem, DefKind, FnSig, LineRange, RefinedBy, TrustedItem,
+    },
+};
+
+pub struct FluxRunner {
+    config: FluxRunnerConfig,
+    children: ChildTracker,
+    slots: SpawnLimit,
+    in_flight: InFlight,
+}
+
+/// Waiting longer than this for a free slot is reported back to the caller.
+const QUEUED_NOTICE_AFTER: Duration = Duration::from_secs(1);
+
+/// The processes spawned by a [`FluxRunner`] that are still running, so they can be reaped when
+/// the server shuts down.
+#[derive(Debug, Clone, Default)]
+pub struct ChildTracker {
+    pids: Arc<std::sync::Mutex<HashSet<u32>>>,
+}
+
+impl ChildTracker {
+    fn track(&self, pid: u32) -> TrackedChild {
+        self.pids.lock().unwrap().insert(pid);
+        TrackedChild { tracker: self.clone(), pid }
+    }
+
+    fn running(&self) -> Vec<u32> {
+        self.pids.lock().unwrap().iter().copied().collect()
+    }
+
+    /// Waits up to `grace` for the running processes to finish, then kills whatever is left
+    /// along with its descendants.
+    pub async fn shutdown(&self, grace: Duration) {
+        let deadline = Instant::now() + grace;
+        while !self.running().is_empty() && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        for pid in self.running() {
+            tracing::warn!("Killing flux process {pid} after the shutdown grace period");
+            kill_process_group(pid);
+        }
+    }
+}
+
+/// Removes the child from its tracker once it has been waited on, or abandoned on an error.
+struct TrackedChild {
+    tracker: ChildTracker,
+    pid: u32,
+}
+
+impl Drop for TrackedChild {
+    fn drop(&mut self) {
+        self.tracker.pids.lock().unwrap().remove(&self.pid);
+    }
+}
+
+/// Caps how many flux processes run at once. Callers beyond the cap wait until a running
+/// process finishes, in the order they started waiting.
+#[derive(Debug, Clone)]
+struct SpawnLimit {
+    slots: Arc<tokio::sync::Semaphore>,
+}
+
+impl SpawnLimit {
+    fn new(max: usize) -> Self {
+        Self { slots: Arc::new(tokio::sync::Semaphore::new(max.max(1))) }
+    }
+
+    /// Waits until a slot is free, returning it along with how long that took. The slot is
+    /// freed when dropped.
+    async fn acquire(&self) -> (OwnedSemaphorePermit, Duration) {
+        let start = Instant::now();
+        let slot = self
+            .slots
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the spawn limit is never closed");
+        (slot, start.elapsed())
+    }
+}
+
+/// Runs the blocking `f` on tokio's blocking pool, so it doesn't hold up the other tasks of the
+/// worker awaiting it. A panic in `f` is resumed on the caller.
+pub(crate) async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+/// A running command spawned by [`FluxRunner::spawn_run`], whose stdout is read a line at a
+/// time. Dropping it before [`Self::finish`], e.g. when the task awaiting it is cancelled, kills
+/// the command and its descendants.
+struct FluxRun {
+    child: tokio::process::Child,
+    pid: u32,
+    reader: tokio::io::BufReader<tokio::process::ChildStdout>,
+    stderr: Option<tokio::task::JoinHandle<String>>,
+    line: Vec<u8>,
+    /// When the run times out, and the timeout it was given
+    timeout: Option<(tokio::time::Instant, Duration)>,
+    max_output_bytes: Option<usize>,
+    max_memory_mb: Option<u64>,
+    total: usize,
+    stopped: bool,
+    truncated: bool,
+    finished: bool,
+    queued: Duration,
+    _tracked: TrackedChild,
+    _slot: OwnedSemaphorePermit,
+}
+
+impl FluxRun {
+    /// The next line of output without its line ending, or `None` once the output has ended or
+    /// the run was stopped. Exceeding [`FluxRunnerConfig::max_output_bytes`] stops the run; no
+    /// more than one byte past it is ever read, however long the line.
+    async fn next_line(&mut self) -> Result<Option<&str>, FluxRunnerError> {
+        if self.stopped {
+            return Ok(None);
+        }
+        self.line.clear();
+        let limit = self
+            .max_output_bytes
+            .map_or(u64::MAX, |max| (max.saturating_sub(self.total) + 1) as u64);
+        let mut reader = (&mut self.reader).take(limit);
+        let read = within(self.pid, self.timeout, reader.read_until(b'\n', &mut self.line))
+            .await?
+            .map_err(FluxRunnerError::Read)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        self.total += read;
+        if self.max_output_bytes.is_some_and(|max| self.total > max) {
+            tracing::warn!("Stopping flux after {} bytes of output", self.total);
+            self.truncated = true;
+            self.stop();
+            return Ok(None);
+        }
+        let line = std::str::from_utf8(&self.line).map_err(|err| {
+            FluxRunnerError::Read(io::Error::new(io::ErrorKind::InvalidData, err))
+        })?;
+        Ok(Some(line.trim_end_matches(['\n', '\r'])))
+    }
+
+    /// Kills the command and its descendants; the rest of its output is never read.
+    fn stop(&mut self) {
+        self.stopped = true;
+        kill_process_group(self.pid);
+    }
+
+    /// Waits for the command to exit and tells how it went, telling apart the failures that
+    /// aren't about the code being verified from its stderr.
+    async fn finish(mut self) -> Result<RunStatus, FluxRunnerError> {
+        let status = within(self.pid, self.timeout, self.child.wait()).await?;
+        self.finished = true;
+        let status = status.map_err(FluxRunnerError::Wait)?;
+        let stderr = match self.stderr.take() {
+            Some(handle) => handle.await.unwrap_or_default(),
+            None => String::new(),
+        };
+        let (truncated, queued) = (self.truncated, self.queued);
+        if self.stopped {
+            let stderr = stderr_excerpt(&stderr);
+            return Ok(RunStatus { success: false, truncated, queued, ice: None, stderr });
+        }
+        if !status.success() && is_missing_subcommand(&stderr) {
+            return Err(FluxRunnerError::FluxNotInstalled);
+        }
+        if let Some(max_mb) = self.max_memory_mb
+            && !status.success()
+            && ran_out_of_memory(status, &stderr)
+        {
+            return Err(FluxRunnerError::ResourceExceeded(max_mb));
+        }
+        if !status.success()
+            && let Some(option) = unknown_flux_option(&stderr)
+        {
+            return Err(FluxRunnerError::UnsupportedOption(option));
+        }
+        let ice = if status.success() { None } else { ice_from_stderr(&stderr) };
+        let stderr = stderr_excerpt(&stderr);
+        Ok(RunStatus { success: status.success(), truncated, queued, ice, stderr })
+    }
+}
+
+impl Drop for FluxRun {
+    fn drop(&mut self) {
+        if !self.finished {
+            kill_process_group(self.pid);
+        }
+    }
+}
+
+/// Awaits `future`, unless the `timeout` of the run with process group `pid` passes first, in
+/// which case the group is killed.
+async fn within<T>(
+    pid: u32,
+    timeout: Option<(tokio::time::Instant, Duration)>,
+    future: impl Future<Output = T>,
+) -> Result<T, FluxRunnerError> {
+    let Some((deadline, timeout)) = timeout else {
+        return Ok(future.await);
+    };
+    tokio::time::timeout_at(deadline, future)
+        .await
+        .map_err(|_| {
+            kill_process_group(pid);
+            FluxRunnerError::Timeout(timeout)
+        })
+}
+
+/// The verifications running now, keyed by what they verify, so that an identical request can
+/// wait for the running one's report instead of starting flux again. A run publishes its report
+/// once it's done, or drops its sender without one if it failed.
+#[derive(Debug, Default)]
+struct InFlight {
+    runs: std::sync::Mutex<HashMap<String, watch::Receiver<Option<VerificationReport>>>>,
+}
+
+/// Removes its run from [`InFlight`] when dropped, including when the run is cancelled.
+struct InFlightEntry<'a> {
+    in_flight: &'a InFlight,
+    key: String,
+}
+
+impl Drop for InFlightEntry<'_> {
+    fn drop(&mut self) {
+        self.in_flight.runs.lock().unwrap().remove(&self.key);
+    }
+}
+
+pub struct FluxRunnerConfig {
+    /// The cargo binary used to invoke `cargo flux`
+    pub cargo_path: PathBuf,
+    /// How many times to retry spawning flux after a transient failure
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled after every attempt
+    pub retry_backoff: Duration,
+    /// The `flux-rs` crate that scratch packages depend on, if it can be found
+    pub flux_rs_path: Option<PathBuf>,
+    /// Stop reading, and kill flux, once it has printed this many bytes
+    pub max_output_bytes: Option<usize>,
+    /// How many flux processes may run at once, further runs wait for one of them to finish
+    pub max_concurrent_verifications: usize,
+    /// How long `verify_git` may spend fetching a repository
+    pub clone_timeout: Duration,
+    /// How large a checkout `verify_git` may fetch, in bytes
+    pub max_clone_bytes: u64,
+    /// Cap the address space of flux and everything it spawns, e.g. the SMT solver, at this
+    /// many megabytes. Only enforced on Unix. Defaults to `FLUX_MCP_MAX_MEMORY_MB`, if set
+    pub max_memory_mb: Option<u64>,
+    /// How many streamed diagnostics may wait for a slow consumer before reading flux's output
+    /// pauses, see [`FluxRunner::diagnostic_channel`]
+    pub stream_buffer: usize,
+    /// A directory for flux's cache of SMT query results, kept across runs so that verifying a
+    /// repository again only sends the changed queries to the solver. Created if missing.
+    /// Defaults to `FLUX_MCP_SMT_CACHE_DIR`, if set
+    pub smt_cache_dir: Option<PathBuf>,
+    /// The toolchain flux was built for, e.g. `nightly-2025-10-09`, which a toolchain pinned by
+    /// a repository's `rust-toolchain.toml` is checked against. Defaults to
+    /// `FLUX_MCP_TOOLCHAIN`, if set
+    pub flux_toolchain: Option<String>,
+}
+
+/// The file in [`FluxRunnerConfig::smt_cache_dir`] flux is told to keep its query cache in.
+pub const SMT_CACHE_FILE: &str = "flux-query-cache.json";
 
-use crate::diagnostics::{Diagnostic, DiagnosticTarget, parse_message, parse_target};
+impl Default for FluxRunnerConfig {
+    fn default() -> Self {
+        Self {
+            cargo_path: PathBuf::from("cargo"),
+            max_retries: 2,
+            retry_backoff: Duration::from_millis(100),
+            // The server is built from the flux repository, whose `flux-rs` is the best guess.
+            flux_rs_path: Some(PathBuf::from(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../../lib/flux-rs"
+            )))
+            .filter(|path| path.is_dir()),
+            max_output_bytes: Some(256 * 1024 * 1024),
+            max_concurrent_verifications: max_concurrent_verifications(),
+            clone_timeout: Duration::from_secs(300),
+            max_clone_bytes: 512 * 1024 * 1024,
+            stream_buffer: 64,
+            max_memory_mb: max_memory_mb(),
+            smt_cache_dir: std::env::var_os("FLUX_MCP_SMT_CACHE_DIR").map(PathBuf::from),
+            flux_toolchain: std::env::var("FLUX_MCP_TOOLCHAIN").ok(),
+        }
+    }
+}
+
+/// `FLUX_MCP_MAX_MEMORY_MB` if set to a number of megabytes.
+fn max_memory_mb() -> Option<u64> {
+    let max = std::env::var("FLUX_MCP_MAX_MEMORY_MB").ok()?;
+    match max.parse() {
+        Ok(max) if max > 0 => Some(max),
+        _ => {
+            tracing::warn!("Ignoring FLUX_MCP_MAX_MEMORY_MB={max}, running without a memory limit");
+            None
+        }
+    }
+}
+
+/// `FLUX_MCP_MAX_CONCURRENT_VERIFICATIONS` if set, otherwise the number of CPUs.
+fn max_concurrent_verifications() -> usize {
+    let cpus = thread::available_parallelism().map_or(1, usize::from);
+    let Ok(max) = std::env::var("FLUX_MCP_MAX_CONCURRENT_VERIFICATIONS") else { return cpus };
+    match max.parse() {
+        Ok(max) if max > 0 => max,
+        _ => {
+            tracing::warn!("Ignoring FLUX_MCP_MAX_CONCURRENT_VERIFICATIONS={max}, using {cpus}");
+            cpus
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FluxRunnerError {
+    /// The flux process could not be started
+    Spawn(io::Error),
+    /// The flux process didn't expose its stdout
+    MissingStdout,
+    /// Reading the output of the flux process failed
+    Read(io::Error),
+    /// Waiting for the flux process to finish failed
+    Wait(io::Error),
+    /// Creating a scratch directory for flux to dump into failed
+    TempDir(io::Error),
+    /// cargo ran but doesn't know the `flux` subcommand
+    FluxNotInstalled,
+    /// Reading or writing a lemma snapshot failed
+    Snapshot(io::Error),
+    /// The repository's `flux-mcp.toml` is invalid
+    Config(String),
+    /// Flux was killed after running for longer than the timeout
+    Timeout(Duration),
+    /// The installed flux doesn't know the given `-F` option
+    UnsupportedOption(String),
+    /// The repository path doesn't exist
+    InvalidRepoPath(PathBuf),
+    /// A source file to scan doesn't exist or isn't valid Rust
+    InvalidSource(PathBuf),
+    /// No single function of the given name has a `sig` attribute
+    UnknownItem(String),
+    /// Copying the repository to a scratch directory failed
+    Copy(io::Error),
+    /// `cargo metadata` failed or printed something unexpected
+    Metadata(String),
+    /// The workspace has no package of the given name
+    UnknownPackage(String),
+    /// `git` couldn't be run
+    Git(io::Error),
+    /// The patch to apply isn't a valid unified diff, with what `git apply` said about it
+    InvalidPatch(String),
+    /// The repository URL or ref isn't one `verify_git` accepts
+    InvalidGitUrl(String),
+    /// Fetching a remote repository failed or exceeded its limits
+    Clone(String),
+    /// A local git ref couldn't be read, with what git said about it
+    UnknownRef(String),
+    /// Flux or a process it spawned ran out of memory under `max_memory_mb`
+    ResourceExceeded(u64),
+    /// Flux was stopped for printing more than `max_output_bytes`, for a result that would be
+    /// wrong if only partly read
+    OutputTruncated(usize),
+    /// Flux failed without printing a single diagnostic, with the end of its stderr
+    Failed(String),
+    /// No lemma flux dumped has the given name, with the names of those it did dump
+    UnknownLemma(String, Vec<String>),
+    /// A lemma call can't be inserted where it was asked to be, and why
+    InvalidInsertion(String),
+    /// The diagnostic has no primary span inside an item, given as its location
+    NoEnclosingItem(String),
+    /// No workspace package contains the file
+    NoOwningPackage(PathBuf),
+    /// Writing the solver log of `debug_verify` failed
+    WriteLog(io::Error),
+    /// The repository pins the first toolchain, but flux was built for the second
+    ToolchainMismatch(String, String),
+    /// The primary span of the diagnostic, given as its location, isn't a call to a function
+    /// with a flux signature
+    NotACall(String),
+}
+
+impl FluxRunnerError {
+    /// Whether the error comes from what the client asked for rather than from the server or
+    /// its environment, so that retrying the same request can't help.
+    pub fn is_client_error(&self) -> bool {
+        matches!(
+            self,
+            FluxRunnerError::Config(_)
+                | FluxRunnerError::InvalidRepoPath(_)
+                | FluxRunnerError::InvalidSource(_)
+                | FluxRunnerError::UnknownItem(_)
+                | FluxRunnerError::UnknownPackage(_)
+                | FluxRunnerError::InvalidPatch(_)
+                | FluxRunnerError::InvalidGitUrl(_)
+                | FluxRunnerError::UnknownRef(_)
+                | FluxRunnerError::NoEnclosingItem(_)
+                | FluxRunnerError::NoOwningPackage(_)
+                | FluxRunnerError::NotACall(_)
+                | FluxRunnerError::UnknownLemma(..)
+                | FluxRunnerError::InvalidInsertion(_)
+        )
+    }
+}
+
+impl fmt::Display for FluxRunnerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FluxRunnerError::Spawn(err) => write!(f, "Failed to run Flux process: {err}"),
+            FluxRunnerError::MissingStdout => {
+                write!(f, "Failed to capture stdout from Flux process")
+            }
+            FluxRunnerError::Read(err) => write!(f, "Failed to read output: {err}"),
+            FluxRunnerError::Wait(err) => write!(f, "Process wait failed: {err}"),
+            FluxRunnerError::TempDir(err) => write!(f, "Failed to create temp directory: {err}"),
+            FluxRunnerError::Snapshot(err) => write!(f, "Failed to access lemma snapshot: {err}"),
+            FluxRunnerError::Config(err) => write!(f, "Invalid configuration: {err}"),
+            FluxRunnerError::Timeout(timeout) => {
+                write!(f, "Flux timed out after {} seconds", timeout.as_secs())
+            }
+            FluxRunnerError::FluxNotInstalled => {
+                write!(
+                    f,
+                    "cargo-flux is not installed; install it with `cargo install flux` or make \
+                     sure the toolchain that provides it is active"
+                )
+            }
+            FluxRunnerError::UnsupportedOption(option) => {
+                write!(f, "The option `{option}` is unsupported by this flux version")
+            }
+            FluxRunnerError::InvalidRepoPath(path) => {
+                write!(f, "No repository at {}", path.display())
+            }
+            FluxRunnerError::InvalidSource(path) => {
+                write!(f, "{} can't be read or isn't valid Rust", path.display())
+            }
+            FluxRunnerError::UnknownItem(item) => {
+                write!(f, "No single function `{item}` with a `sig` attribute")
+            }
+            FluxRunnerError::NoEnclosingItem(location) => {
+                write!(f, "The diagnostic at {location} isn't inside an item")
+            }
+            FluxRunnerError::ToolchainMismatch(pinned, flux) => {
+                write!(
+                    f,
+                    "The repository pins the toolchain `{pinned}` in rust-toolchain.toml, but flux \
+                     was built for `{flux}`. Use a flux built for `{pinned}` or change the pin"
+                )
+            }
+            FluxRunnerError::WriteLog(err) => write!(f, "Failed to write the solver log: {err}"),
+            FluxRunnerError::NotACall(location) => {
+                write!(
+                    f,
+                    "The diagnostic at {location} isn't at a call to a function with a flux signature"
+                )
+            }
+            FluxRunnerError::NoOwningPackage(path) => {
+                write!(f, "No workspace package contains {}", path.display())
+            }
+            FluxRunnerError::Copy(err) => write!(f, "Failed to copy the repository: {err}"),
+            FluxRunnerError::Metadata(err) => write!(f, "Failed to read cargo metadata: {err}"),
+            FluxRunnerError::UnknownPackage(package) => {
+                write!(f, "No package `{package}` in the workspace")
+            }
+            FluxRunnerError::Git(err) => write!(f, "Failed to run git: {err}"),
+            FluxRunnerError::InvalidPatch(err) => write!(f, "Invalid patch: {err}"),
+            FluxRunnerError::InvalidGitUrl(err) => write!(f, "Invalid git URL: {err}"),
+            FluxRunnerError::Clone(err) => write!(f, "Failed to fetch the repository: {err}"),
+            FluxRunnerError::UnknownRef(err) => write!(f, "Can't read the git ref {err}"),
+            FluxRunnerError::ResourceExceeded(max_mb) => {
+                write!(f, "Flux ran out of memory under the limit of {max_mb}MB")
+            }
+            FluxRunnerError::OutputTruncated(max_bytes) => {
+                write!(f, "Flux was stopped for printing more than {max_bytes} bytes of output")
+            }
+            FluxRunnerError::Failed(stderr) if stderr.is_empty() => {
+                write!(f, "Flux failed without printing any diagnostics or errors")
+            }
+            FluxRunnerError::Failed(stderr) => {
+                write!(f, "Flux failed without printing any diagnostics:\n{stderr}")
+            }
+            FluxRunnerError::UnknownLemma(name, known) if known.is_empty() => {
+                write!(f, "No lemma `{name}`, flux found no lemmas in the repository")
+            }
+            FluxRunnerError::UnknownLemma(name, known) => {
+                write!(f, "No lemma `{name}`, the known lemmas are {}", known.join(", "))
+            }
+            FluxRunnerError::InvalidInsertion(reason) => {
+                write!(f, "Can't insert the lemma call: {reason}")
+            }
+        }
+    }
+}
 
-pub struct FluxRunner {}
+impl std::error::Error for FluxRunnerError {}
 
+/// The arguments of the tools that only take the repository to work on.
 #[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct RepoPathArgs {
+    pub repo_path: String,
+}
+
+#[derive(Debug, Default, serde::Deserialize, JsonSchema)]
 pub struct VerifyRepositoryArgs {
     pub repo_path: String,
+    /// Ask flux to record timing information and report solver statistics
+    #[serde(default)]
+    pub collect_stats: bool,
+    /// Return the unparsed JSON messages emitted by `cargo flux` instead of diagnostics
+    #[serde(default)]
+    pub raw: bool,
+    /// Coalesce overlapping or adjacent spans within each diagnostic
+    #[serde(default)]
+    pub merge_spans: bool,
+    /// How to present each diagnostic, defaults to `json`
+    #[serde(default)]
+    pub format: OutputFormat,
+    /// Reorder the diagnostics, by default they are sorted by location, then message
+    pub sort: Option<SortOrder>,
+    /// Keep warnings alongside errors when filtering for syntax errors. Whether warnings fail
+    /// the run is up to `deny_warnings`
+    #[serde(default)]
+    pub include_warnings: bool,
+    /// Only keep the level, message, code and primary span, with its label, of each diagnostic
+    #[serde(default)]
+    pub minimal: bool,
+    /// Add the UTF-8 byte offsets of every span as `byte_start` and `byte_end`
+    #[serde(default)]
+    pub byte_offsets: bool,
+    /// Add the source text every span covers now as `text`, to check that it hasn't shifted
+    /// before editing it. A span that no longer fits in its file gets no text
+    #[serde(default)]
+    pub span_text: bool,
+    /// Report span paths relative to `repo_path`, or absolute if outside of it, and rewrite the
+    /// locations in the rendered text to match
+    #[serde(default)]
+    pub normalize_paths: bool,
+    /// Keep diagnostics pointing outside the repository, e.g. into registry dependencies
+    #[serde(default)]
+    pub include_external: bool,
+    /// Only keep diagnostics whose message or rendered text matches this regular expression
+    pub message_regex: Option<String>,
+    /// Drop diagnostics in files matching these globs, e.g. `src/generated/**`, relative to the
+    /// cargo root. Overrides `exclude_globs` in `flux-mcp.toml`
+    pub exclude_globs: Option<Vec<String>>,
+    /// The SMT solver to use, `z3` or `cvc5`. Overrides `solver` in `flux-mcp.toml`
+    pub solver: Option<String>,
+    /// Stop flux at the first error and only report that one
+    #[serde(default)]
+    pub fail_fast: bool,
+    /// Of the diagnostics that only differ in their target, keep the one for the `lib` target,
+    /// dropping the copies reported when the library is also compiled as a test harness
+    #[serde(default)]
+    pub prefer_lib_target: bool,
+    /// Return the `cargo flux` command line that would run, with its directory and
+    /// `FLUXFLAGS`, instead of running it
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Environment variables to set for flux, e.g. `{"Z3_PATH": "/opt/z3/bin/z3"}`. Overrides
+    /// `env` in `flux-mcp.toml`
+    pub env: Option<HashMap<String, String>>,
+    /// Error codes, e.g. `FLUX-OVERFLOW` or `E0308`, that are reported but don't fail the run
+    pub allowed_codes: Option<Vec<String>>,
+    /// Give up on a single SMT query after this many milliseconds, reporting its goal as
+    /// unproven and listing it in `smt_timeouts`. Overrides `smt_timeout_ms` in
+    /// `flux-mcp.toml`, and is dropped if the installed flux has no such option
+    pub smt_timeout_ms: Option<u64>,
+    /// Keep rustc's long-form explanation of each error code as `code_explanation`
+    #[serde(default)]
+    pub include_explanations: bool,
+    /// Whether span lines and columns count from 0 or 1, defaults to 1 as flux reports them.
+    /// The rendered text always uses rustc's 1-based locations
+    pub coordinate_base: Option<u8>,
+    /// Turn flux's overflow checking on or off, which decides whether "arithmetic operation may
+    /// overflow" errors can be reported. Overrides `check_overflow` in `flux-mcp.toml` and any
+    /// `-Fcheck-overflow` in its `default_flux_flags`
+    pub check_overflow: Option<bool>,
+    /// Put a `FLUX_RESULT errors=<n> warnings=<n> success=<bool>` line before the summary, for
+    /// shell scripts to grep
+    #[serde(default)]
+    pub status_line: bool,
+    /// Fail the run if flux printed any warning, even if it exited successfully. Unlike
+    /// `include_warnings`, which only decides whether warnings are shown, this decides `success`
+    #[serde(default)]
+    pub deny_warnings: bool,
+    /// Add how every line flux printed was parsed: the count of each `reason`, of lines that
+    /// aren't JSON and of compiler messages that didn't parse, to debug missing diagnostics
+    #[serde(default)]
+    pub debug_parse: bool,
+    /// Fail instead of only warning when the repository's `rust-toolchain.toml` pins a
+    /// toolchain other than the one flux was built for. Overrides `strict_toolchain` in
+    /// `flux-mcp.toml`
+    #[serde(default)]
+    pub strict_toolchain: bool,
+    /// Keep verifying the packages that don't depend on one that fails to build, with cargo's
+    /// `--keep-going`, and list the packages skipped because of a failed dependency
+    #[serde(default)]
+    pub keep_going: bool,
+    /// Keep the JSON diagnostics under this many characters, to stay within the client's
+    /// context. The rendered text goes first, then secondary spans, then the least severe
+    /// diagnostics, and a `truncated` item counts what was dropped. The summary still counts
+    /// every diagnostic
+    pub max_chars: Option<usize>,
+    /// Kill flux and its compiler processes, and fail with a timeout error, if the run takes
+    /// longer than this many seconds. The diagnostics printed before are discarded. Overrides
+    /// `timeout_secs` in `flux-mcp.toml`
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// The structured diagnostic, serialized as JSON
+    #[default]
+    Json,
+    /// rustc's human-readable rendering, reconstructed from the primary span and the source for
+    /// diagnostics that have none, such as minimal ones
+    Rendered,
+    /// The JSON diagnostic followed by its rendering
+    Both,
+    /// Every JSON diagnostic on its own line, all in a single item
+    Ndjson,
 }
 
 #[derive(Debug, serde::Deserialize, JsonSchema)]
 pub struct VerifyPackageArgs {
     pub repo_path: String,
     pub packages: Vec<String>,
+    /// Kill flux and its compiler processes, and fail with a timeout error, if the run takes
+    /// longer than this many seconds. The diagnostics printed before are discarded. When the
+    /// packages are verified one at a time, each of them gets this long
+    pub timeout_secs: Option<u64>,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct VerificationReport {
-    pub success: bool,
-    pub diagnostics: Vec<Diagnostic>,
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct ResolvePackageArgs {
+    pub repo_path: String,
+    /// A package name, e.g. `core`, or the `package_id` of a diagnostic
+    pub package: String,
 }
 
 #[derive(Debug, serde::Deserialize, JsonSchema)]
-pub struct GetLemmaArgs {
-    pub repo_path: String
+pub struct VerifyPackageTreeArgs {
+    pub repo_path: String,
+    /// The package to verify along with the workspace packages depending on it
+    pub package: String,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct Lemma {
-    pub name: String,
-    pub file_name: String,
-    pub start_line: i64,
-    pub start_col: i64,
-    pub end_line: i64,
-    pub end_col: i64,
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct DebugVerifyArgs {
+    pub repo_path: String,
+    /// The file to write flux's dumps to, relative to `repo_path` or absolute. It is
+    /// overwritten
+    pub out_log: PathBuf,
 }
 
-impl FluxRunner {
-    pub fn new() -> Self {
-        Self {}
-    }
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct VerifyOwningPackageArgs {
+    pub repo_path: String,
+    /// A file of the package to verify, relative to `repo_path` or absolute
+    pub file_path: String,
+}
 
-    fn flux_command(
-        repo_root: &str,
-        packages: Option<&[&str]>,
-        flux_flags: Option<&[&str]>,
-    ) -> Command {
-        let mut cmd = Command::new("cargo");
-        if let Some(flux_flags) = flux_flags {
-            cmd.env("FLUXFLAGS", flux_flags.join(" "));
-        }
-        let mut args = vec!["flux".to_string()];
-        if let Some(packages) = packages {
-            for package in packages {
-                args.push("-p".to_string());
-                args.push(package.to_string());
-            }
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct VerifyFunctionArgs {
+    pub repo_path: String,
+    /// The package containing the function
+    pub package: String,
+    /// Path of the function to check, e.g. `mycrate::module::foo` or `mycrate::Type::method`
+    pub function_path: String,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct VerifyFixturesArgs {
+    pub repo_path: String,
+    /// Which fixtures to check: `pos` fixtures must verify, `neg` fixtures must fail
+    pub expectation: Expectation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Expectation {
+    Pos,
+    Neg,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct SlowGoalsArgs {
+    pub repo_path: String,
+    /// How many goals to return, defaults to 10
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct VerifyManyArgs {
+    /// Independent repositories, verified concurrently
+    pub repo_paths: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct VerifyAgainstBaselineArgs {
+    pub repo_path: String,
+    /// JSON file holding the fingerprints of the known diagnostics
+    pub baseline_path: String,
+    /// Replace the baseline with the diagnostics of this run instead of checking against it
+    #[serde(default)]
+    pub update: bool,
+    /// Error codes, e.g. `FLUX-OVERFLOW` or `E0308`, that are reported but don't fail the run.
+    /// Applied after the baseline, so only new diagnostics are checked against it
+    pub allowed_codes: Option<Vec<String>>,
+    /// Keep diagnostics pointing outside the repository, e.g. into registry dependencies
+    #[serde(default)]
+    pub include_external: bool,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct DiffReportsArgs {
+    /// Repository to verify for the "before" state, used when `before_report` is absent
+    pub before_repo_path: Option<String>,
+    /// A previously returned verification report, serialized as JSON
+    pub before_report: Option<String>,
+    /// Repository to verify for the "after" state, used when `after_report` is absent
+    pub after_repo_path: Option<String>,
+    /// A previously returned verification report, serialized as JSON
+    pub after_report: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct LemmaDiffArgs {
+    /// Repository to dump the "before" lemmas of, used when `before_snapshot` is absent
+    pub before_repo_path: Option<String>,
+    /// The path of a lemma snapshot saved by `get_lemmas_cached`
+    pub before_snapshot: Option<String>,
+    /// Repository to dump the "after" lemmas of, used when `after_snapshot` is absent
+    pub after_repo_path: Option<String>,
+    /// The path of a lemma snapshot saved by `get_lemmas_cached`
+    pub after_snapshot: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct ValidateReportArgs {
+    /// The repository the report's paths are relative to, verified when `report` is absent
+    pub repo_path: String,
+    /// A previously returned verification report, serialized as JSON
+    pub report: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct LemmaBodyDiffArgs {
+    /// A local git repository, or a directory inside one
+    pub repo_path: String,
+    /// e.g. `RSeq::len_pos` or `crate::seq::len_pos`
+    pub lemma_name: String,
+    /// The branch, tag or commit to diff from, e.g. `main`
+    pub from_ref: String,
+    /// The branch, tag or commit to diff to, e.g. `HEAD`
+    pub to_ref: String,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct StartWatchArgs {
+    pub repo_path: String,
+    /// How long to wait for changes to settle before re-verifying, defaults to 500ms
+    pub debounce_ms: Option<u64>,
+}
+
+/// How a single verification runs, as opposed to the settings of the repository it runs on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunOptions {
+    /// Ask flux to record timing information and report solver statistics
+    pub collect_stats: bool,
+    /// Stop flux at the first flux error and only report the diagnostics up to it
+    pub fail_fast: bool,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, JsonSchema)]
+pub struct VerificationReport {
+    pub success: bool,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Wall-clock time spent in flux, summed over every spawned process.
+    #[serde(default)]
+    pub total_duration_ms: u64,
+    /// Per-package breakdown, only populated when packages are verified one at a time.
+    #[serde(default)]
+    pub package_timings: Vec<PackageTiming>,
+    /// Solver statistics, only present if they were requested and flux emitted any.
+    #[serde(default)]
+    pub solver_stats: Option<SolverStats>,
+    /// The directory flux was run from, when it differs from the requested path.
+    #[serde(default)]
+    pub resolved_root: Option<String>,
+    /// The SMT solver flux was asked to use, if not its default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub solver: Option<String>,
+    /// Whether flux checked that arithmetic doesn't overflow, without which no overflow errors
+    /// can be reported.
+    #[serde(default)]
+    pub check_overflow: bool,
+    /// Whether flux was stopped for printing more than `max_output_bytes`, so the diagnostics
+    /// are only those printed before.
+    #[serde(default)]
+    pub truncated: bool,
+    /// How long the run waited for other verifications to finish, if it had to wait noticeably.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queued_ms: Option<u64>,
+    /// The goals whose SMT query hit `smt_timeout_ms`, by enclosing item or location.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub smt_timeouts: Vec<String>,
+    /// The git commit verified, when the repository was fetched by `verify_git`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// The internal compiler error rustc or flux crashed with, if it did. The diagnostics are
+    /// then those printed before the crash, and the failure is a flux bug to report rather than
+    /// a proof to fix.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ice: Option<String>,
+    /// How flux's output was parsed, which `verify_repository` only shows with `debug_parse`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parse_stats: Option<ParseStats>,
+    /// The toolchain flux ran under: the one the repository pins, or else the one flux was
+    /// built for, if known.
+    #[serde(default)]
+    pub toolchain: Option<String>,
+    /// Why the toolchain the repository pins may not work with flux, if it may not.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toolchain_warning: Option<String>,
+    /// With `keep_going`, the workspace packages that were neither verified nor failed
+    /// themselves, because a package they depend on failed to build
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_packages: Vec<String>,
+}
+
+/// How the lines flux printed were parsed, to find out why an expected diagnostic is missing.
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, JsonSchema,
+)]
+pub struct ParseStats {
+    /// How many JSON messages had each `reason`, e.g. `compiler-message`, with `<none>` for
+    /// those without one
+    pub reasons: BTreeMap<String, usize>,
+    /// How many lines weren't valid JSON
+    pub invalid_json: usize,
+    /// How many `compiler-message`s didn't parse into a diagnostic
+    pub dropped_messages: usize,
+}
+
+impl ParseStats {
+    /// Counts `line`, and parses it into a diagnostic like [`FluxRunner::parse_flux_line`].
+    fn parse_line(&mut self, line: &str) -> Option<Diagnostic> {
+        tracing::trace!(target: PARSE_LOG_TARGET, "{line}");
+        let Ok(json_val) = serde_json::from_str::<serde_json::Value>(line) else {
+            self.invalid_json += 1;
+            return None;
+        };
+        let reason = json_val.get("reason").and_then(serde_json::Value::as_str);
+        *self
+            .reasons
+            .entry(reason.unwrap_or("<none>").to_string())
+            .or_insert(0) += 1;
+        let diagnostic = FluxRunner::parse_flux_value(&json_val);
+        if diagnostic.is_none() && reason == Some("compiler-message") {
+            self.dropped_messages += 1;
         }
-        args.push("--message-format=json".to_string());
-        cmd.current_dir(Path::new(repo_root));
-        cmd.args(&args);
-        cmd.stdout(Stdio::piped());
-        cmd.stderr(Stdio::piped());
-        cmd
+        diagnostic
     }
+}
 
-    fn parse_flux_output(output: &str) -> Vec<Diagnostic> {
-        let mut res = Vec::new();
-        for line in output.lines() {
-            let Ok(json_val) = serde_json::from_str::<serde_json::Value>(line) else { continue };
-            let Some(reason) = json_val.get("reason") else { continue };
-            if reason.as_str() == Some("compiler-message") {
-                let Some(message) = json_val.get("message").and_then(parse_message) else {
-                    continue;
-                };
-                let target: Option<DiagnosticTarget> =
-                    json_val.get("target").and_then(parse_target);
-                let package_id = json_val.get("package_id").map(|id| id.to_string());
-                res.push(Diagnostic { message, package_id, target })
+/// The header leading the output of the verification tools.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, JsonSchema)]
+pub struct VerificationSummary {
+    pub success: bool,
+    /// Number of diagnostics returned after the header
+    pub diagnostics: usize,
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+impl VerificationSummary {
+    pub(crate) fn new<'a>(success: bool, levels: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut summary = Self { success, ..Default::default() };
+        for level in levels {
+            summary.diagnostics += 1;
+            match level {
+                "error" => summary.errors += 1,
+                "warning" => summary.warnings += 1,
+                _ => {}
             }
         }
-        res
+        summary
     }
 
-    fn parse_lemma(message: &serde_json::Value) -> Option<Lemma> {
-        tracing::info!("{message}");
-        let name = message.get("lemma_name")?.as_str()?.to_string();
-        let file_name = message.get("file_name")?.as_str()?.to_string();
-        let start_line = message.get("start_line")?.as_i64()?;
-        let end_line = message.get("end_line")?.as_i64()?;
-        let start_col = message.get("start_col")?.as_i64()?;
-        let end_col = message.get("end_col")?.as_i64()?;
-        Some(Lemma { name, file_name, start_line, start_col, end_line, end_col })
+    pub(crate) fn of(success: bool, diagnostics: &[Diagnostic]) -> Self {
+        Self::new(success, diagnostics.iter().map(|diag| diag.message.level.as_str()))
     }
+}
 
-    fn parse_flux_lemmas(output: &str) -> Vec<Lemma> {
-        let mut res = Vec::new();
-        tracing::info!("ABOUT TO PARSE LEMMAS");
-        for line in output.lines() {
-            let Ok(json_val) = serde_json::from_str::<serde_json::Value>(line) else { continue };
-            let Some(reason) = json_val.get("reason") else { continue };
-            if reason.as_str() == Some("compiler-message") {
-                let Some(lemma) = json_val.get("message").and_then(Self::parse_lemma) else { continue };
-                res.push(lemma);
-            }
-        }
-        res
-    }
+/// The JSON schemas of what the verification and lemma tools return, keyed by type name, for
+/// clients generating bindings. Nested types are under each schema's `$defs`.
+pub fn output_schemas() -> serde_json::Value {
+    serde_json::json!({
+        "VerificationReport": schemars::schema_for!(VerificationReport),
+        "VerificationSummary": schemars::schema_for!(VerificationSummary),
+        "Diagnostic": schemars::schema_for!(Diagnostic),
+        "Lemma": schemars::schema_for!(Lemma),
+    })
+}
 
-    pub async fn verify_repository(&self, repo_path: &str) -> Result<VerificationReport, String> {
-        let mut cmd = Self::flux_command(repo_path, None, None);
-        tracing::info!("About to execute command {:?}", cmd);
-        let mut child = cmd
-            .spawn()
-            .map_err(|_| "Failed to run Flux process".to_string())?;
-        let stdout = child
-            .stdout
-            .take()
-            .map(Ok)
-            .unwrap_or(Err("Failed to capture stdout from Flux process".to_string()))?;
-        let mut output = String::new();
-        let reader = BufReader::new(stdout);
-        for line in reader.lines() {
-            let line = line.map_err(|err| format!("Failed to read output: {err}"))?;
-            output.push_str(&line);
-            output.push('\n');
-        }
-        let status = child
-            .wait()
-            .map_err(|err| format!("Process wait failed: {err}"))?;
-        let diagnostics = Self::parse_flux_output(&output);
-
-        Ok(VerificationReport { success: status.success(), diagnostics })
-    }
-
-    pub async fn verify_package(&self, repo_path: &str, packages: Option<&[&str]>) -> Result<VerificationReport, String> {
-        let mut cmd = Self::flux_command(repo_path, packages, None);
-        tracing::info!("About to execute command {:?}", cmd);
-        let mut child = cmd
-            .spawn()
-            .map_err(|_| "Failed to run Flux process".to_string())?;
-        let stdout = child
-            .stdout
-            .take()
-            .map(Ok)
-            .unwrap_or(Err("Failed to capture stdout from Flux process".to_string()))?;
-        let mut output = String::new();
-        let reader = BufReader::new(stdout);
-        for line in reader.lines() {
-            let line = line.map_err(|err| format!("Failed to read output: {err}"))?;
-            output.push_str(&line);
-            output.push('\n');
-        }
-        let status = child
-            .wait()
-            .map_err(|err| format!("Process wait failed: {err}"))?;
-        let diagnostics = Self::parse_flux_output(&output);
-
-        Ok(VerificationReport { success: status.success(), diagnostics })
-    }
-
-    pub async fn get_lemmas(&self, repo_path: &str) -> Result<Vec<Lemma>, String> {
-        let flux_flags = ["-Fdump-lemmas"];
-        let mut cmd = Self::flux_command(repo_path, None, Some(&flux_flags));
-        tracing::info!("About to execute command {:?}", cmd);
-        let mut child = cmd
-            .spawn()
-            .map_err(|_| "Failed to run Flux process".to_string())?;
-        let stdout = child
-            .stdout
-            .take()
-            .map(Ok)
-            .unwrap_or(Err("Failed to capture stdout from Flux process".to_string()))?;
-        let mut output = String::new();
-        let reader = BufReader::new(stdout);
-        for line in reader.lines() {
-            let line = line.map_err(|err| format!("Failed to read ouptut: {err}"))?;
-            output.push_str(&line);
-            output.push('\n');
-        }
-        let status = child
-            .wait()
-            .map_err(|err| format!("Process wait failed: {err}"))?;
-        let lemmas = Self::parse_flux_lemmas(&output);
-        Ok(lemmas)
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, JsonSchema)]
+pub struct PackageTiming {
+    pub package: String,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, JsonSchema)]
+pub struct SolverStats {
+    /// Number of fixpoint queries sent to the solver
+    pub queries: usize,
+    /// Time spent running fixpoint queries
+    pub time_ms: u64,
+    /// Number of functions checked by flux
+    pub functions: usize,
+    /// Total time spent in the flux analysis, as measured by the driver
+    pub total_ms: u64,
+    /// Queries answered from the SMT cache, if flux reported it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_hits: Option<u64>,
+    /// Queries the cache had no answer for, if flux reported it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_misses: Option<u64>,
+    /// The time spent checking each function, for those the dumps name
+    #[serde(default)]
+    pub goals: Vec<GoalTiming>,
+}
+
+/// How long flux spent checking one item.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, JsonSchema)]
+pub struct GoalTiming {
+    /// A function as flux names it, or a package when per-function timings aren't available
+    pub item: String,
+    pub time_ms: u64,
+}
+
+/// The slowest goals of a run, slowest first.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SlowGoals {
+    /// `function` if flux timed each function, or `package` if the timings are of whole
+    /// packages verified one at a time instead
+    pub granularity: &'static str,
+    pub goals: Vec<GoalTiming>,
+}
+
+/// How many goals `slow_goals` returns unless told otherwise.
+pub const DEFAULT_SLOW_GOALS: usize = 10;
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct VerifyStartArgs {
+    pub repo_path: String,
+    /// Collect per-package timings and solver statistics
+    #[serde(default)]
+    pub collect_stats: bool,
+    /// Keep diagnostics pointing outside the repository, e.g. into registry dependencies
+    #[serde(default)]
+    pub include_external: bool,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct PollVerificationArgs {
+    /// The id returned by `verify_start`
+    pub job_id: u64,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct VerifyDeltaArgs {
+    /// The job whose report to compare against, from `verify_start` or an earlier `verify_delta`
+    pub baseline_job_id: u64,
+    pub repo_path: String,
+    /// Keep diagnostics pointing outside the repository, e.g. into registry dependencies
+    #[serde(default)]
+    pub include_external: bool,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct CheckLemmaSyntaxArgs {
+    /// The source of the proposed lemma, attributes included
+    pub lemma: String,
+    /// The items the lemma refers to, e.g. the `flux_rs::defs!` block declaring the functions
+    /// in its spec and the types in its signature
+    pub context: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct FormatSigArgs {
+    /// The signature, bare or as the whole `#[flux::sig(...)]` attribute
+    pub sig_text: String,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct VerifySourceArgs {
+    /// The contents of a single flux-annotated Rust file
+    pub source: String,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct GetLemmaArgs {
+    pub repo_path: String,
+    /// Only dump the lemmas of these packages, defaults to every package in the repository
+    pub packages: Option<Vec<String>>,
+    /// Whether lemma lines count from 0 or 1, defaults to 1 as flux reports them. Flux already
+    /// reports lemma columns 0-based, so they are left alone
+    pub coordinate_base: Option<u8>,
+    /// Only return the `#[flux::trusted]` lemmas, which are assumed rather than proven
+    #[serde(default)]
+    pub trusted_lemmas: bool,
+    /// Also send each lemma as a logging notification as soon as flux prints it, before the
+    /// dump finishes. Streamed lemmas only have their location and aren't filtered
+    #[serde(default)]
+    pub stream: bool,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct GetLemmaLocationsArgs {
+    pub repo_path: String,
+    /// Only dump the lemmas of these packages, defaults to every package in the repository
+    pub packages: Option<Vec<String>>,
+    /// How to count columns: `utf-8`, `utf-16` (for VS Code) or `utf-32`, the default
+    #[serde(default)]
+    pub position_encoding: PositionEncoding,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct GetLemmasCachedArgs {
+    pub repo_path: String,
+    /// Where the snapshot is stored, defaults to `target/flux-lemmas.json` in the repository
+    pub snapshot_path: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct LemmaDefsArgs {
+    pub repo_path: String,
+    /// The name of the lemma function, e.g. `head_cons_eq`
+    pub lemma_name: String,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct LemmasAboutArgs {
+    pub repo_path: String,
+    /// The function symbol the clauses should mention, e.g. `head`
+    pub symbol: String,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct LemmasForSortArgs {
+    pub repo_path: String,
+    /// The sort the lemmas should be about, e.g. `ISeq`
+    pub sort_name: String,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct GoalContextBundleArgs {
+    pub repo_path: String,
+    /// Keep the bundle under this many characters of JSON, 32768 by default
+    pub max_chars: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct GoalStatusArgs {
+    pub repo_path: String,
+    /// The file of the goal, relative to `repo_path` or absolute
+    pub file_path: String,
+    /// The 1-based line of the goal, e.g. of the assertion or returned value
+    pub line: usize,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct VerifyAndSuggestArgs {
+    pub repo_path: String,
+    /// How many lemmas to suggest per error, defaults to 3
+    pub top_k: Option<usize>,
+    /// Where the lemma snapshot is stored, defaults to `target/flux-lemmas.json` in the
+    /// repository
+    pub snapshot_path: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct GetConstraintsArgs {
+    pub repo_path: String,
+    /// Only return the constraints of functions with this name or path, e.g. `push` or
+    /// `Foo::push`
+    pub function: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct LemmaGraphArgs {
+    pub repo_path: String,
+    /// Also render the graph in Graphviz's DOT language
+    #[serde(default)]
+    pub dot: bool,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct SpecEditImpactArgs {
+    pub repo_path: String,
+    /// The function whose signature to replace, e.g. `push` or `Foo::push`
+    pub item: String,
+    /// The new contents of its `sig` attribute, e.g. `fn(i32[@n]) -> i32[n + 1]`
+    pub new_sig: String,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct VerifyWithExtraLemmasArgs {
+    pub repo_path: String,
+    /// The lemma calls to insert, all at once
+    pub calls: Vec<LemmaCall>,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct TryLemmaArgs {
+    pub repo_path: String,
+    /// The file to insert the call in, relative to `repo_path`
+    pub file_path: String,
+    /// The 1-based line to insert the call on, inside the body of the failing function
+    pub line: usize,
+    /// The 1-based column the call goes before, defaults to the first non-blank one of the line
+    pub column: Option<usize>,
+    /// The lemma as `get_lemmas` names it, e.g. `head_cons_eq`. It is written in the call as
+    /// given, so qualify it if the lemma isn't in scope where the call goes
+    pub name: String,
+    /// The argument expressions, passed as written, e.g. `["&v", "x"]` for a lemma taking a
+    /// reference
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// A lemma instantiation to try, inserted as a statement of its own.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, JsonSchema)]
+pub struct LemmaCall {
+    /// The file to insert the call in, relative to `repo_path`
+    pub file_path: String,
+    /// The 1-based line the call is inserted before, counted in the unedited file
+    pub line: usize,
+    /// The call, e.g. `head_cons_eq(v, &elems)`, with or without the trailing `;`
+    pub call_text: String,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct SuggestTrustedAnnotationArgs {
+    pub repo_path: String,
+    /// A failing diagnostic as returned by `verify_repository`
+    pub diagnostic: Diagnostic,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct SuggestPreconditionArgs {
+    pub repo_path: String,
+    /// A diagnostic as returned by `verify_repository`, pointing at a call whose precondition
+    /// flux couldn't prove
+    pub diagnostic: Diagnostic,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct VerifyAndFmtArgs {
+    pub repo_path: String,
+    /// The files to return formatted, relative to `repo_path`, by default every file
+    /// formatting changes
+    pub files: Option<Vec<String>>,
+    /// Write the formatted files back to the repository instead of only returning them
+    #[serde(default)]
+    pub write: bool,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct VerifyPatchArgs {
+    pub repo_path: String,
+    /// A unified diff, e.g. the output of `git diff`, with paths relative to `repo_path`
+    pub patch: String,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct VerifyGitArgs {
+    /// An https, http, ssh or git URL of the repository
+    pub repo_url: String,
+    /// The branch, tag or commit to verify, defaults to the remote's default branch
+    #[serde(rename = "ref")]
+    pub git_ref: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct MinimizeLemmasArgs {
+    pub repo_path: String,
+    /// The candidate lemma calls, which together should make verification pass
+    pub calls: Vec<LemmaCall>,
+    /// How many verifications to run at most, defaults to 32
+    pub max_attempts: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct ExtractSigsArgs {
+    pub repo_path: String,
+    /// The file to scan, relative to `repo_path`
+    pub file_path: String,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct SpecCoverageArgs {
+    pub repo_path: String,
+    /// The workspace package to measure, defaults to every source file in the repository
+    pub package: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct CalleesArgs {
+    pub repo_path: String,
+    /// The function, e.g. `push` or `RVec::push`
+    pub item: String,
+    /// How many levels of calls to follow, defaults to 1 for the direct callees only and is
+    /// capped at 5
+    pub depth: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct ItemRangeArgs {
+    pub repo_path: String,
+    /// e.g. `crate::seq::RSeq::push`, or a suffix of it such as `RSeq::push` or `push`
+    pub item_path: String,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct RefinedByArgs {
+    pub repo_path: String,
+    /// The struct or enum, e.g. `Foo` or `crate::seq::Foo`
+    pub type_name: String,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct CheckTrustBudgetArgs {
+    pub repo_path: String,
+    /// JSON file recording the allowed trust surface, relative to `repo_path`. Defaults to
+    /// `flux-trust-baseline.json` at the repository root
+    pub baseline_path: Option<String>,
+    /// Record the current trust surface as the baseline instead of checking against it
+    #[serde(default)]
+    pub update: bool,
+}
+
+/// The opaque sorts and uninterpreted functions of a repository, as returned by
+/// [`FluxRunner::list_sorts_and_funcs`].
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct SortsAndFuncs {
+    pub sorts: Vec<DefItem>,
+    /// Functions declared without a body, whose arity is the length of their `params`
+    pub funcs: Vec<DefItem>,
+}
+
+/// The lemmas of a repository as saved on disk by [`FluxRunner::save_lemmas`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LemmaSnapshot {
+    /// The [`source::fingerprint`] of the inputs the lemmas were dumped from
+    pub fingerprint: String,
+    pub lemmas: Vec<Lemma>,
+}
+
+/// How a lemma changed between two revisions, see [`FluxRunner::lemma_body_diff`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BodyChange {
+    Added,
+    Removed,
+    Changed,
+    Unchanged,
+}
+
+/// The change to one lemma's source between two revisions.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LemmaBodyDiff {
+    pub lemma: String,
+    pub change: BodyChange,
+    /// Where the lemma is at the first revision, if it exists there
+    pub from: Option<LineRange>,
+    /// Where the lemma is at the second revision, if it exists there
+    pub to: Option<LineRange>,
+    /// A unified diff of the lemma's source, empty if it is unchanged
+    pub diff: String,
+}
+
+/// How the lemmas of two dumps differ, matched by name.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct LemmaDiff {
+    pub added: Vec<Lemma>,
+    pub removed: Vec<Lemma>,
+    /// Lemmas with the same name at a different location
+    pub moved: Vec<MovedLemma>,
+    /// How many lemmas are in both at the same location
+    pub unchanged: usize,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MovedLemma {
+    pub before: Lemma,
+    pub after: Lemma,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, JsonSchema)]
+pub struct Lemma {
+    pub name: String,
+    pub file_name: String,
+    pub start_line: i64,
+    pub start_col: i64,
+    pub end_line: i64,
+    pub end_col: i64,
+    /// The preconditions of the lemma's flux signature, read from the source
+    #[serde(default)]
+    pub requires: Vec<String>,
+    /// What the lemma proves, read from the source
+    #[serde(default)]
+    pub ensures: Vec<String>,
+    /// The refinement parameters its signature binds with `@`, which an instantiation has to
+    /// supply
+    #[serde(default)]
+    pub params: Vec<String>,
+    /// Whether the lemma is `#[flux::trusted]`, an axiom taken on faith rather than a proven
+    /// fact
+    #[serde(default)]
+    pub trusted: bool,
+}
+
+impl Lemma {
+    /// Shifts the lines of the lemma from the 1-based lines flux reports to `base`, which is 0
+    /// or 1. Its columns are 0-based already and stay that way.
+    pub(crate) fn rebase_coordinates(&mut self, base: u8) {
+        let shift = i64::from(base) - 1;
+        self.start_line += shift;
+        self.end_line += shift;
+    }
+
+    /// The location of the lemma as an LSP location. Flux reports 1-based lines and 0-based
+    /// columns for lemmas, so only the lines are shifted. The location is 0-based whatever
+    /// `coordinate_base` a tool was asked for, since LSP always is. Columns other than `utf-32` need the
+    /// lemma's source lines; if the file can't be read they are left as flux reported them.
+    pub fn to_location(&self, repo_path: &str, encoding: PositionEncoding) -> LspLocation {
+        let path = lsp::resolve_path(Path::new(repo_path), &self.file_name);
+        let contents = match encoding {
+            PositionEncoding::Utf32 => None,
+            _ => fs::read_to_string(&path).ok(),
+        };
+        let position = |line: i64, col: i64| {
+            let line = line.saturating_sub(1).max(0) as u32;
+            let col = col.max(0) as usize;
+            let character = contents
+                .as_deref()
+                .and_then(|contents| contents.lines().nth(line as usize))
+                .map_or(col as u32, |text| encoding.encode_column(text, col));
+            LspPosition { line, character }
+        };
+        LspLocation {
+            uri: lsp::file_uri(&path),
+            range: LspRange {
+                start: position(self.start_line, self.start_col),
+                end: position(self.end_line, self.end_col),
+            },
+        }
+    }
+}
+
+/// Which lemmas build on which: an edge `from -> to` means the body or signature of `from`
+/// refers to `to`. Lemmas that refer to each other simply give two edges.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct LemmaGraph {
+    pub nodes: Vec<Lemma>,
+    pub edges: Vec<LemmaEdge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LemmaEdge {
+    pub from: String,
+    pub to: String,
+}
+
+impl LemmaGraph {
+    /// Renders the graph in Graphviz's DOT language.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph lemmas {\n");
+        for lemma in &self.nodes {
+            dot.push_str(&format!("    {:?};\n", lemma.name));
+        }
+        for edge in &self.edges {
+            dot.push_str(&format!("    {:?} -> {:?};\n", edge.from, edge.to));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// The whole file defining a lemma, for reading the lemma alongside its imports and neighbours.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LemmaContext {
+    pub lemma: Lemma,
+    /// The contents of the lemma's file, cut at [`MAX_CONTEXT_BYTES`]
+    pub contents: String,
+    /// The 1-based lines of the lemma within `contents`
+    pub start_line: i64,
+    pub end_line: i64,
+    /// Whether `contents` was cut short
+    pub truncated: bool,
+}
+
+/// The largest file [`FluxRunner::lemma_context`] returns in full.
+pub const MAX_CONTEXT_BYTES: usize = 256 * 1024;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FixtureResult {
+    pub file_name: String,
+    /// Number of errors flux reported for the fixture
+    pub errors: usize,
+    /// Whether the fixture met its expectation
+    pub passed: bool,
+}
+
+/// A constraint flux generated while checking a function, as dumped by `-Fdump-constraint`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ConstraintDump {
+    /// The function's path as flux writes it in file names, e.g. `Foo-push`
+    pub function: String,
+    /// `body` for the function's own constraint, `impl` for the one checking that a trait
+    /// method implementation is a subtype of the trait's
+    pub kind: &'static str,
+    /// The constraint after flux's simplifications, before it is handed to the solver
+    pub constraint_text: String,
+}
+
+/// What replacing the signature of one function did to the verification of a repository.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SpecEditImpact {
+    /// The edited function, as named by [`FnSig::name`]
+    pub item: String,
+    pub file_name: String,
+    /// Whether the function verified with its old signature
+    pub passed_before: bool,
+    /// Whether it verifies with the new one
+    pub passes_after: bool,
+    /// The other items with errors after the edit that had none before
+    pub broken: Vec<String>,
+    /// The other items with errors before the edit that have none after
+    pub fixed: Vec<String>,
+    /// Every diagnostic that changed, matched without regard to line numbers
+    pub diff: diagnostics::ReportDiff,
+}
+
+/// The attribute that would silence a diagnostic by trusting the item it is reported in. This
+/// is an escape hatch, not a fix: flux stops checking the item instead of proving it.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TrustedAnnotation {
+    /// The innermost item containing the diagnostic, e.g. `Type::method`
+    pub item: String,
+    pub file_name: String,
+    /// The 1-based line to insert `text` on, moving the item's first line, or first attribute,
+    /// down
+    pub insert_line: usize,
+    /// `#[flux::trusted]`, indented like the item
+    pub text: String,
+    /// The items flux would no longer check, the item itself and those nested in it
+    pub unverified: Vec<String>,
+    pub warning: String,
+}
+
+/// How likely a suggested precondition is to be what the caller needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Confidence {
+    /// Every parameter of the callee's clause is passed one of the caller's refinement
+    /// parameters
+    High,
+    /// Some parameter is passed an expression, rewritten in terms of the caller's parameters
+    /// as far as they are known
+    Medium,
+    /// Some parameter of the clause couldn't be related to the caller, so it is left as is
+    Low,
+}
+
+/// A `requires` clause the caller of a failing call could add to promise the callee's
+/// precondition.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PreconditionSuggestion {
+    pub requires: String,
+    pub confidence: Confidence,
+    pub rationale: String,
+}
+
+/// Candidate preconditions for the caller of a call flux couldn't prove the precondition of,
+/// most likely first. These are heuristic suggestions, not guarantees.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PreconditionSuggestions {
+    /// The item containing the call, e.g. `Type::method`
+    pub caller: String,
+    pub callee: String,
+    pub suggestions: Vec<PreconditionSuggestion>,
+    pub warning: String,
+}
+
+/// How a repository verified with a batch of lemma calls inserted.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ExtraLemmasResult {
+    /// Whether the repository verifies with every accepted call inserted
+    pub success: bool,
+    /// The requested calls, in order
+    pub calls: Vec<InsertedCall>,
+    /// The diagnostics flux still reports, located in the edited copy
+    pub remaining: Vec<Diagnostic>,
+}
+
+/// What inserting a lemma call did to verification, see [`FluxRunner::try_lemma`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LemmaOutcome {
+    /// Fewer refinement errors in the enclosing item, and none new
+    Helped,
+    /// As many refinement errors in the enclosing item, and none new
+    NoChange,
+    /// Errors at the call or refinement errors that weren't there before, whatever happened to
+    /// the enclosing item
+    NewErrors,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TryLemmaResult {
+    /// The lemma as `get_lemmas` names it
+    pub lemma: String,
+    /// The statement inserted, e.g. `head_cons_eq(&v, x);`
+    pub call: String,
+    /// The innermost item the call was inserted in
+    pub item: String,
+    pub outcome: LemmaOutcome,
+    /// The flux refinement errors in `item` without the call
+    pub errors_before: usize,
+    /// The flux refinement errors in `item` with the call, not counting those at the call
+    pub errors_after: usize,
+    /// The errors at the call itself, e.g. an unproven precondition of the lemma or a call that
+    /// doesn't compile
+    pub call_errors: Vec<Diagnostic>,
+    /// The flux refinement errors anywhere that only appear with the call
+    pub introduced: Vec<Diagnostic>,
+    /// Whether the repository verifies with the call
+    pub success: bool,
+    /// The internal compiler error flux crashed with on the edited copy, in which case the
+    /// counts say little
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ice: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct InsertedCall {
+    #[serde(flatten)]
+    pub call: LemmaCall,
+    /// Whether the call parsed and its line exists, so it was inserted
+    pub accepted: bool,
+    /// Why the call was left out, if it wasn't accepted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// The line the call ended up on in the edited copy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inserted_line: Option<usize>,
+    /// The errors flux reports at the call itself, e.g. an unproven precondition of the lemma
+    pub errors: usize,
+}
+
+/// A part of a patch that didn't apply.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RejectedHunk {
+    pub file_name: String,
+    /// The 1-based index of the hunk within its file, or `None` if none of the file's hunks
+    /// could be applied, e.g. because the file doesn't exist
+    pub hunk: Option<usize>,
+}
+
+/// A Rust file as `cargo fmt` left it.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FormattedFile {
+    /// Relative to the repository path
+    pub file_name: String,
+    pub contents: String,
+    /// Whether formatting changed the file
+    pub changed: bool,
+}
+
+/// What formatting a repository did, independently of how it verified.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct FmtResult {
+    pub files: Vec<FormattedFile>,
+    /// Whether the formatted files were written back to the repository
+    pub written: bool,
+    /// Why `cargo fmt` failed, in which case no file was formatted
+    pub error: Option<String>,
+}
+
+/// The smallest set of lemma calls found that still makes a repository verify.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MinimizedLemmas {
+    /// Whether the repository verifies with all the candidate calls, without which there is
+    /// nothing to minimize and `calls` is empty
+    pub success: bool,
+    /// The calls left, in the order they were given
+    pub calls: Vec<LemmaCall>,
+    /// How many verifications ran, including the one with every call
+    pub attempts: usize,
+    /// Whether the attempts ran out before every call was tried without, so `calls` may not be
+    /// minimal
+    pub exhausted: bool,
+    /// Wall-clock time spent verifying, over all attempts
+    pub duration_ms: u64,
+}
+
+/// The flags `debug_verify` adds to dump flux's constraints and its checker's trace.
+const DEBUG_FLUX_FLAGS: &[&str] = &["-Fdump-constraint=true", "-Fdump-checker-trace=true"];
+
+/// How many verifications `minimize_lemmas` runs unless told otherwise.
+pub const DEFAULT_MINIMIZE_ATTEMPTS: usize = 32;
+
+/// How one fixture of the test corpus behaved compared to the directory it lives in.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CorpusEntry {
+    /// The fixture, relative to `tests/tests`
+    pub file_name: String,
+    pub expected: Expectation,
+    /// `neg` if flux reported errors for the fixture, `pos` otherwise
+    pub actual: Expectation,
+    pub ok: bool,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct CorpusResult {
+    pub entries: Vec<CorpusEntry>,
+    /// Number of entries that aren't `ok`
+    pub mismatches: usize,
+}
+
+/// How one of the repositories given to [`FluxRunner::verify_many`] verified.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RepoResult {
+    pub repo_path: String,
+    /// Wall-clock time from the start of the batch until this repository was done, including
+    /// any time spent waiting for a verification slot
+    pub duration_ms: u64,
+    /// Set if flux ran
+    pub report: Option<VerificationReport>,
+    /// Why flux couldn't be run, set instead of `report`
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct ManyResult {
+    /// Whether every repository was verified and passed
+    pub all_passed: bool,
+    /// In the order the repositories were given
+    pub repos: Vec<RepoResult>,
+}
+
+/// How a command run by [`FluxRunner::run_command_until`] ended.
+struct RunStatus {
+    success: bool,
+    /// Whether the output was cut short at [`FluxRunnerConfig::max_output_bytes`]
+    truncated: bool,
+    /// How long the command waited for a slot under
+    /// [`FluxRunnerConfig::max_concurrent_verifications`] before it was spawned
+    queued: Duration,
+    /// The internal compiler error the command's stderr shows it crashed with
+    ice: Option<String>,
+    /// The end of the command's stderr, at most [`STDERR_EXCERPT_BYTES`] of it
+    stderr: String,
+}
+
+impl RunStatus {
+    /// Errs with the stderr of a run that failed without `diagnostics`, which means flux
+    /// couldn't check anything: a build script failed, the toolchain is broken or flux crashed
+    /// outside of rustc's ICE handler. The report would only say it failed.
+    fn check_silent_failure(&self, diagnostics: usize) -> Result<(), FluxRunnerError> {
+        if self.success || self.truncated || self.ice.is_some() || diagnostics > 0 {
+            return Ok(());
+        }
+        Err(FluxRunnerError::Failed(self.stderr.clone()))
+    }
+}
+
+/// The flux installation the server will invoke.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FluxVersion {
+    /// The output of `cargo flux --version`, if it succeeded
+    pub version: Option<String>,
+    /// The cargo binary, resolved through `PATH` when possible
+    pub cargo_path: String,
+    /// The active rustup toolchain, if rustup is available
+    pub toolchain: Option<String>,
+}
+
+/// The failing refinement check to work on next, with what is needed to attack it.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Goal {
+    pub diagnostic: Diagnostic,
+    /// The [code](FluxErrorKind::code) of the kind of error
+    pub kind: &'static str,
+    /// The source lines of the primary span
+    pub snippet: String,
+    /// The lemmas whose signatures share the most identifiers with the snippet, best first
+    pub suggested_lemmas: Vec<Lemma>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum NextGoal {
+    Failing { goal: Box<Goal> },
+    NoFailingGoals { summary: VerificationSummary },
+}
+
+/// Everything needed to work on one failing goal, see [`FluxRunner::goal_context_bundle`].
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GoalContextBundle {
+    /// The goal, with its diagnostic's rendering moved to `rendered`
+    pub goal: Goal,
+    /// The diagnostic as rustc renders it, with its notes and help
+    pub rendered: String,
+    /// The innermost item containing the goal, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enclosing_item: Option<ItemSource>,
+    /// The flux signatures of the functions the enclosing item calls directly
+    pub callee_sigs: Vec<FnSig>,
+    /// The `defs!` items the signatures of the enclosing item and its callees mention
+    pub defs: Vec<DefItem>,
+    /// What was left out to stay within the size bound, if anything
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncation: Option<BundleTruncation>,
+}
+
+/// An item and its source.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ItemSource {
+    /// e.g. `foo` or `Type::method`
+    pub name: String,
+    pub file_name: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub source: String,
+}
+
+/// What [`FluxRunner::goal_context_bundle`] dropped to fit its bound, least relevant first.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct BundleTruncation {
+    pub dropped_defs: usize,
+    pub dropped_lemmas: usize,
+    pub dropped_callee_sigs: usize,
+    /// Whether the enclosing item's source was cut short
+    pub source_cut: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum GoalContext {
+    Failing { bundle: Box<GoalContextBundle> },
+    NoFailingGoals { summary: VerificationSummary },
+}
+
+/// The size [`FluxRunner::goal_context_bundle`] keeps its bundle under by default, in
+/// characters of JSON.
+pub const DEFAULT_BUNDLE_CHARS: usize = 32 * 1024;
+
+/// Whether the proof obligation at a location holds, see [`FluxRunner::goal_status`].
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum GoalStatus {
+    Discharged,
+    Failed {
+        diagnostic: Box<Diagnostic>,
+    },
+    /// Flux doesn't check anything at the location, or didn't get to it
+    NotChecked {
+        reason: String,
+    },
+}
+
+/// Whether flux proved an inline `assert`, see [`FluxRunner::inline_assert_status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssertStatus {
+    Ok,
+    Failing,
+    /// The assertion is in a trusted item, or flux stopped before checking everything
+    NotChecked,
+}
+
+/// An inline `flux_rs::assert` and whether it verifies.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct InlineAssert {
+    pub file_name: String,
+    pub line: usize,
+    /// The source text of the assertion, e.g. `x > 0`
+    pub expr: String,
+    pub status: AssertStatus,
+    /// The message of the error at the assertion, when it fails
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// How many lemmas [`FluxRunner::next_goal`] suggests.
+const SUGGESTED_LEMMAS: usize = 5;
+
+/// A refinement error with the lemmas most likely to discharge it.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Suggestions {
+    pub diagnostic: Diagnostic,
+    /// The [code](FluxErrorKind::code) of the kind of error
+    pub kind: &'static str,
+    /// Best first
+    pub suggested_lemmas: Vec<Lemma>,
+}
+
+/// How many lemmas `verify_and_suggest` suggests per error unless told otherwise.
+pub const DEFAULT_SUGGESTIONS: usize = 3;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LemmaLocation {
+    pub name: String,
+    pub location: LspLocation,
+}
+
+/// A workspace package by both of the names cargo knows it by.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PackageInfo {
+    /// The name packages are selected by, e.g. in `verify_packages`
+    pub name: String,
+    /// The opaque id diagnostics carry as `package_id`
+    pub id: String,
+    pub version: Option<String>,
+    pub manifest_path: String,
+}
+
+/// How many levels of calls [`FluxRunner::callees`] follows at most.
+pub const MAX_CALLEE_DEPTH: usize = 5;
+
+/// How many callees [`FluxRunner::callees`] returns at most.
+pub const MAX_CALLEES: usize = 200;
+
+/// The functions an item calls, directly or through other functions of the repository.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Callees {
+    /// The called paths, direct callees first, then level by level. Each level is sorted.
+    pub callees: Vec<String>,
+    /// Whether the callees were cut at [`MAX_CALLEES`]
+    pub truncated: bool,
+}
+
+/// The file the trust surface of a repository is recorded in by default, at its root.
+pub const TRUST_BASELINE_FILE: &str = "flux-trust-baseline.json";
+
+/// One item of the trust surface: something flux takes on faith rather than proves. Lines
+/// aren't recorded, so that moving an item doesn't count as adding one.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct TrustSurfaceItem {
+    /// `trusted` or `opaque` for an attribute, `assume` for a call to the intrinsic
+    pub kind: String,
+    /// The item carrying the attribute, or the fact assumed
+    pub name: String,
+    pub file_name: String,
+}
+
+/// How the trust surface of a repository compares to its recorded baseline.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TrustBudget {
+    /// Whether nothing is trusted beyond the baseline
+    pub ok: bool,
+    /// What is trusted now that the baseline doesn't allow
+    pub added: Vec<TrustSurfaceItem>,
+    /// What the baseline allows that is no longer trusted, which can be dropped from it
+    pub removed: Vec<TrustSurfaceItem>,
+    pub baseline_size: usize,
+    pub current_size: usize,
+}
+
+/// Lemmas declared in several places under the same bare name, so that naming one is ambiguous.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LemmaConflict {
+    pub name: String,
+    /// The file and 1-based line of each declaration, sorted
+    pub locations: Vec<(String, i64)>,
+}
+
+/// A lemma with a one-line preview of its declaration, for listing lemmas to pick from.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LemmaPreview {
+    pub name: String,
+    pub file: String,
+    /// The 1-based line the lemma starts on
+    pub line: i64,
+    /// The trimmed source of that line, usually the `fn` declaration. Empty if it can't be read
+    pub preview: String,
+}
+
+impl FluxRunner {
+    pub fn new() -> Self {
+        Self::with_config(FluxRunnerConfig::default())
+    }
+
+    pub fn with_config(config: FluxRunnerConfig) -> Self {
+        let slots = SpawnLimit::new(config.max_concurrent_verifications);
+        Self { config, children: ChildTracker::default(), slots, in_flight: InFlight::default() }
+    }
+
+    /// A handle on the processes this runner has in flight.
+    pub fn children(&self) -> ChildTracker {
+        self.children.clone()
+    }
+
+    fn flux_command(
+        &self,
+        repo_root: &str,
+        packages: Option<&[&str]>,
+        flux_flags: Option<&[&str]>,
+    ) -> Command {
+        let mut cmd = Command::new(&self.config.cargo_path);
+        if let Some(flux_flags) = flux_flags {
+            cmd.env("FLUXFLAGS", flux_flags.join(" "));
+        }
+        if let Some(dir) = &self.config.smt_cache_dir {
+            match fs::create_dir_all(dir) {
+                Ok(()) => {
+                    cmd.env("FLUX_CACHE", dir.join(SMT_CACHE_FILE));
+                }
+                Err(err) => {
+                    tracing::warn!("Running without the SMT cache in {}: {err}", dir.display());
+                }
+            }
+        }
+        let mut args = vec!["flux".to_string()];
+        if let Some(packages) = packages {
+            for package in packages {
+                args.push("-p".to_string());
+                args.push(package.to_string());
+            }
+        }
+        args.push("--message-format=json".to_string());
+        cmd.current_dir(Path::new(repo_root));
+        cmd.args(&args);
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+        cmd
+    }
+
+    /// Runs `cmd` to completion, feeding each line of its stdout to `on_line` as soon as it is
+    /// read, so the output is never held in memory as a whole. Returns whether the command
+    /// exited successfully.
+    async fn run_command(
+        &self,
+        cmd: Command,
+        on_line: impl FnMut(&str),
+    ) -> Result<bool, FluxRunnerError> {
+        self.run_command_with_timeout(cmd, None, on_line).await
+    }
+
+    /// Like [`Self::run_command`], but kills the command and its descendants if it is still
+    /// running after `timeout`. Whatever was read before the kill is discarded in favor of a
+    /// [`FluxRunnerError::Timeout`], and likewise for a [`FluxRunnerError::OutputTruncated`]
+    /// once the output exceeds [`FluxRunnerConfig::max_output_bytes`].
+    async fn run_command_with_timeout(
+        &self,
+        cmd: Command,
+        timeout: Option<Duration>,
+        mut on_line: impl FnMut(&str),
+    ) -> Result<bool, FluxRunnerError> {
+        let status = self
+            .run_command_until(cmd, timeout, |line| {
+                on_line(line);
+                ControlFlow::Continue(())
+            })
+            .await?;
+        self.check_complete(&status)?;
+        Ok(status.success)
+    }
+
+    /// Errs if the output of the run was cut short at [`FluxRunnerConfig::max_output_bytes`].
+    fn check_complete(&self, status: &RunStatus) -> Result<(), FluxRunnerError> {
+        if status.truncated {
+            let max_bytes = self.config.max_output_bytes.unwrap_or_default();
+            return Err(FluxRunnerError::OutputTruncated(max_bytes));
+        }
+        Ok(())
+    }
+
+    /// Like [`Self::run_command_with_timeout`], but `on_line` can stop the command early by
+    /// breaking, in which case it is killed and reaped and the run counts as unsuccessful. The
+    /// same happens once the output exceeds [`FluxRunnerConfig::max_output_bytes`].
+    async fn run_command_until(
+        &self,
+        cmd: Command,
+        timeout: Option<Duration>,
+        mut on_line: impl FnMut(&str) -> ControlFlow<()>,
+    ) -> Result<RunStatus, FluxRunnerError> {
+        let mut run = self.spawn_run(cmd, timeout).await?;
+        while let Some(line) = run.next_line().await? {
+            if on_line(line).is_break() {
+                run.stop();
+            }
+        }
+        run.finish().await
+    }
+
+    /// Spawns `cmd` for its output to be read through the returned [`FluxRun`]. The command
+    /// isn't spawned until fewer than [`FluxRunnerConfig::max_concurrent_verifications`] are
+    /// running, and the `timeout` only starts once it is.
+    async fn spawn_run(
+        &self,
+        mut cmd: Command,
+        timeout: Option<Duration>,
+    ) -> Result<FluxRun, FluxRunnerError> {
+        tracing::debug!("About to execute command {:?}", cmd);
+        // Give cargo its own process group so that the rustc processes it spawns can be killed
+        // together with it.
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+        if let Some(max_mb) = self.config.max_memory_mb {
+            limit_memory(&mut cmd, max_mb);
+        }
+        let (slot, queued) = self.slots.acquire().await;
+        if queued >= QUEUED_NOTICE_AFTER {
+            tracing::info!("Waited {}ms for a free verification slot", queued.as_millis());
+        }
+        let mut cmd = tokio::process::Command::from(cmd);
+        let mut child = self
+            .spawn_with_retry(|| cmd.spawn())
+            .await
+            .map_err(FluxRunnerError::Spawn)?;
+        let pid = child.id().expect("a child that wasn't waited on has a pid");
+        let tracked = self.children.track(pid);
+        let stdout = child.stdout.take().ok_or(FluxRunnerError::MissingStdout)?;
+        // Drain stderr on the side so a chatty process can't block on a full pipe.
+        let stderr = child.stderr.take().map(|mut stderr| {
+            tokio::spawn(async move {
+                let mut bytes = vec![];
+                let _ = stderr.read_to_end(&mut bytes).await;
+                String::from_utf8_lossy(&bytes).into_owned()
+            })
+        });
+        Ok(FluxRun {
+            child,
+            pid,
+            reader: tokio::io::BufReader::new(stdout),
+            stderr,
+            line: Vec::new(),
+            timeout: timeout.map(|timeout| (tokio::time::Instant::now() + timeout, timeout)),
+            max_output_bytes: self.config.max_output_bytes,
+            max_memory_mb: self.config.max_memory_mb,
+            total: 0,
+            stopped: false,
+            truncated: false,
+            finished: false,
+            queued,
+            _tracked: tracked,
+            _slot: slot,
+        })
+    }
+
+    /// Calls `spawn` until it succeeds, retrying with exponential backoff as long as the failure
+    /// looks transient (e.g. `EAGAIN` on a busy machine). Errors such as a missing binary are
+    /// returned immediately.
+    async fn spawn_with_retry<C>(&self, mut spawn: impl FnMut() -> io::Result<C>) -> io::Result<C> {
+        let mut backoff = self.config.retry_backoff;
+        let mut attempt = 0;
+        loop {
+            match spawn() {
+                Ok(child) => return Ok(child),
+                Err(err) if attempt < self.config.max_retries && is_transient(&err) => {
+                    attempt += 1;
+                    tracing::warn!(
+                        "Spawning flux failed ({err}), retrying in {backoff:?} (attempt {attempt} of {})",
+                        self.config.max_retries
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Parses one line of `cargo flux --message-format=json` output into a diagnostic.
+    pub fn parse_flux_line(line: &str) -> Option<Diagnostic> {
+        tracing::trace!(target: PARSE_LOG_TARGET, "{line}");
+        let json_val = serde_json::from_str::<serde_json::Value>(line).ok()?;
+        Self::parse_flux_value(&json_val)
+    }
+
+    fn parse_flux_value(json_val: &serde_json::Value) -> Option<Diagnostic> {
+        let reason = json_val.get("reason")?;
+        if reason.as_str() != Some("compiler-message") {
+            return None;
+        }
+        let message_val = json_val.get("message")?;
+        let message = parse_message(message_val)?;
+        let target: Option<DiagnosticTarget> = json_val.get("target").and_then(parse_target);
+        let package_id = json_val.get("package_id").map(|id| id.to_string());
+        let mut diagnostic = Diagnostic {
+            message,
+            package_id,
+            target,
+            enclosing_item: None,
+            synthetic_code: String::new(),
+            fingerprint: String::new(),
+            counterexample: diagnostics::parse_counterexample(message_val),
+        };
+        diagnostic.synthetic_code = diagnostics::synthetic_code(&diagnostic);
+        Some(diagnostic)
+    }
+
+    /// Collects the `<crate>-timings.json` files that `-Ftimings` dumps into `log_dir`. Returns
+    /// `None` if flux didn't dump any.
+    fn parse_timings_dumps(log_dir: &Path) -> Option<SolverStats> {
+        let mut stats: Option<SolverStats> = None;
+        for entry in fs::read_dir(log_dir).ok()?.flatten() {
+            let path = entry.path();
+            let is_timings_dump = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.ends_with("-timings.json"));
+            if !is_timings_dump {
+                continue;
+            }
+            let Ok(contents) = fs::read_to_string(&path) else { continue };
+            let Ok(dump) = serde_json::from_str::<serde_json::Value>(&contents) else { continue };
+            let stats = stats.get_or_insert_default();
+            stats.total_ms += dump
+                .get("total")
+                .and_then(|total| total.as_u64())
+                .unwrap_or(0);
+            for (key, count) in
+                [("cache_hits", &mut stats.cache_hits), ("cache_misses", &mut stats.cache_misses)]
+            {
+                if let Some(n) = dump.get(key).and_then(serde_json::Value::as_u64) {
+                    *count = Some(count.unwrap_or(0) + n);
+                }
+            }
+            if let Some(functions) = dump.get("functions").and_then(|fns| fns.as_array()) {
+                stats.functions += functions.len();
+                stats.goals.extend(functions.iter().filter_map(|function| {
+                    let item = ["def", "name"]
+                        .iter()
+                        .find_map(|key| function.get(key)?.as_str())?;
+                    let time_ms = function.get("time_ms")?.as_u64()?;
+                    Some(GoalTiming { item: item.to_string(), time_ms })
+                }));
+            }
+            if let Some(queries) = dump.get("queries").and_then(|queries| queries.as_array()) {
+                stats.queries += queries.len();
+                stats.time_ms += queries
+                    .iter()
+                    .filter_map(|query| query.get("time_ms")?.as_u64())
+                    .sum::<u64>();
+            }
+        }
+        stats
+    }
+
+    /// Parses a lemma record. The name and file are required; missing coordinates default to 0
+    /// so that a partial record still identifies the lemma.
+    fn parse_lemma(message: &serde_json::Value) -> Option<Lemma> {
+        tracing::trace!(target: PARSE_LOG_TARGET, "{message}");
+        let name = message.get("lemma_name")?.as_str()?.to_string();
+        let file_name = message.get("file_name")?.as_str()?.to_string();
+        let mut missing = vec![];
+        let mut coordinate = |field| {
+            message
+                .get(field)
+                .and_then(serde_json::Value::as_i64)
+                .unwrap_or_else(|| {
+                    missing.push(field);
+                    0
+                })
+        };
+        let start_line = coordinate("start_line");
+        let end_line = coordinate("end_line");
+        let start_col = coordinate("start_col");
+        let end_col = coordinate("end_col");
+        if !missing.is_empty() {
+            tracing::warn!("Lemma `{name}` is missing {}", missing.join(", "));
+        }
+        Some(Lemma {
+            name,
+            file_name,
+            start_line,
+            start_col,
+            end_line,
+            end_col,
+            requires: vec![],
+            ensures: vec![],
+            params: vec![],
+            trusted: false,
+        })
+    }
+
+    /// The refinement parameters bound with `@` in the flux signature `sig_text`, e.g. `n` for
+    /// `fn(i32[@n]) -> i32[n + 1]`. Empty if the text doesn't tokenize.
+    pub fn extract_refinement_params(sig_text: &str) -> Vec<String> {
+        sig_text
+            .parse()
+            .map(source::refinement_params)
+            .unwrap_or_default()
+    }
+
+    /// Parses one line of `-Fdump-lemmas` output into a lemma.
+    pub fn parse_lemma_line(line: &str) -> Option<Lemma> {
+        let json_val = serde_json::from_str::<serde_json::Value>(line).ok()?;
+        let reason = json_val.get("reason")?;
+        if reason.as_str() != Some("compiler-message") {
+            return None;
+        }
+        json_val.get("message").and_then(Self::parse_lemma)
+    }
+
+    /// Verifies the repository using the settings of its `flux-mcp.toml`, if any.
+    pub async fn verify_repository(
+        &self,
+        repo_path: &str,
+        collect_stats: bool,
+    ) -> Result<VerificationReport, FluxRunnerError> {
+        let options = RunOptions { collect_stats, ..Default::default() };
+        self.verify_repository_with(repo_path, options, RepoConfig::default())
+            .await
+    }
+
+    /// Verifies the repository, with the settings in `overrides` taking precedence over those of
+    /// its `flux-mcp.toml`.
+    pub async fn verify_repository_with(
+        &self,
+        repo_path: &str,
+        options: RunOptions,
+        overrides: RepoConfig,
+    ) -> Result<VerificationReport, FluxRunnerError> {
+        // Identical requests for unchanged sources, e.g. from an editor verifying on every save,
+        // share one run. Telling the sources unchanged hashes them, on the blocking pool.
+        let key = format!(
+            "{repo_path}\0{options:?}\0{overrides:?}\0{}",
+            Self::inputs_fingerprint(repo_path, overrides.clone()).await?
+        );
+        let joined = {
+            let mut runs = self.in_flight.runs.lock().unwrap();
+            match runs.get(&key) {
+                Some(running) => Err(running.clone()),
+                None => {
+                    let (tx, rx) = watch::channel(None);
+                    runs.insert(key.clone(), rx);
+                    Ok(tx)
+                }
+            }
+        };
+        match joined {
+            Ok(tx) => {
+                let entry = InFlightEntry { in_flight: &self.in_flight, key };
+                let result = self
+                    .verify_repository_to(repo_path, options, overrides, None)
+                    .await;
+                drop(entry);
+                if let Ok(report) = &result {
+                    let _ = tx.send(Some(report.clone()));
+                }
+                result
+            }
+            Err(mut running) => {
+                tracing::debug!("Waiting for the identical verification of {repo_path} running");
+                if let Ok(report) = running.wait_for(Option::is_some).await {
+                    return Ok(report.clone().unwrap());
+                }
+                // The run failed or was cancelled, so run again for an error of our own.
+                self.verify_repository_to(repo_path, options, overrides, None)
+                    .await
+            }
+        }
+    }
+
+    /// A channel for [`Self::verify_repository_streaming`], holding at most
+    /// [`FluxRunnerConfig::stream_buffer`] diagnostics.
+    pub fn diagnostic_channel(&self) -> (mpsc::Sender<Diagnostic>, mpsc::Receiver<Diagnostic>) {
+        mpsc::channel(self.config.stream_buffer.max(1))
+    }
+
+    /// Like [`Self::verify_repository_with`], but also sends every diagnostic to `sink` as soon
+    /// as flux prints it, before the report's filtering and sorting. The policy on a full
+    /// channel is backpressure: reading flux's output waits for the consumer, so flux stalls on
+    /// its own output instead of diagnostics being dropped or merged. Once the receiver is gone
+    /// the rest are only collected in the report, which always holds every diagnostic.
+    pub async fn verify_repository_streaming(
+        &self,
+        repo_path: &str,
+        overrides: RepoConfig,
+        sink: mpsc::Sender<Diagnostic>,
+    ) -> Result<VerificationReport, FluxRunnerError> {
+        self.verify_repository_to(repo_path, RunOptions::default(), overrides, Some(sink))
+            .await
+    }
+
+    async fn verify_repository_to(
+        &self,
+        repo_path: &str,
+        options: RunOptions,
+        overrides: RepoConfig,
+        mut sink: Option<mpsc::Sender<Diagnostic>>,
+    ) -> Result<VerificationReport, FluxRunnerError> {
+        let stats_dir = if options.collect_stats {
+            Some(tempfile::tempdir().map_err(FluxRunnerError::TempDir)?)
+        } else {
+            None
+        };
+        let (requested, root, mut config) = Self::resolve_repository(repo_path, overrides)?;
+        let (toolchain, toolchain_warning) = self.check_toolchain(&root, &config)?;
+        let start = Instant::now();
+        let mut diagnostics = Vec::new();
+        let mut parse_stats = ParseStats::default();
+        let keep_going = config.keep_going.unwrap_or(false);
+        let mut built = HashSet::new();
+        let status = loop {
+            let cmd = self.repository_command(
+                &root,
+                &config,
+                stats_dir.as_ref().map(tempfile::TempDir::path),
+            )?;
+            let status = self
+                .collect_diagnostics(
+                    cmd,
+                    config.timeout(),
+                    options.fail_fast,
+                    keep_going.then_some(&mut built),
+                    &mut parse_stats,
+                    &mut diagnostics,
+                    &mut sink,
+                )
+                .await;
+            match status {
+                // Older flux versions have no per-query timeout; run without one rather than fail.
+                Err(FluxRunnerError::UnsupportedOption(option))
+                    if option == repo_config::SMT_TIMEOUT_OPTION
+                        && config.smt_timeout_ms.is_some() =>
+                {
+                    tracing::warn!("This flux has no `{option}` option, running without it");
+                    config.smt_timeout_ms = None;
+                    diagnostics.clear();
+                    parse_stats = ParseStats::default();
+                    built.clear();
+                }
+                status => break status?,
+            }
+        };
+        status.check_silent_failure(diagnostics.len())?;
+        let RunStatus { success, truncated, queued, ice, .. } = status;
+        let total_duration_ms = (start.elapsed() - queued).as_millis() as u64;
+        let solver_stats = stats_dir.and_then(|dir| Self::parse_timings_dumps(dir.path()));
+        let skipped_packages = if keep_going && !success && !truncated {
+            self.skipped_packages(&root, &config, &built, &diagnostics)
+                .await?
+        } else {
+            vec![]
+        };
+
+        // When asked to verify a subdirectory, flux still checks the whole enclosing project,
+        // so only keep the diagnostics pointing inside the subdirectory.
+        let resolved_root = match requested.strip_prefix(&root) {
+            Ok(scope) if !scope.as_os_str().is_empty() => {
+                diagnostics.retain(|diag| {
+                    diag.message.primary_span().is_none_or(|span| {
+                        let file = Path::new(&span.file_name);
+                        file.starts_with(scope) || file.starts_with(&requested)
+                    })
+                });
+                Some(root.display().to_string())
+            }
+            _ => None,
+        };
+        if let Some(globs) = &config.exclude_globs {
+            diagnostics::exclude_by_glob(&mut diagnostics, globs, &root)
+                .map_err(|err| FluxRunnerError::Config(err.to_string()))?;
+        }
+        diagnostics::sort_canonically(&mut diagnostics);
+        diagnostics::tag_enclosing_items(&mut diagnostics, &root);
+        diagnostics::add_fingerprints(&mut diagnostics, &root);
+        let ice = diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.message.parsed_level == Level::Ice)
+            .map(|diagnostic| diagnostic.message.message.clone())
+            .or(ice);
+        let smt_timeouts = if config.smt_timeout_ms.is_some() {
+            diagnostics::timed_out_goals(&diagnostics)
+        } else {
+            vec![]
+        };
+
+        Ok(VerificationReport {
+            success,
+            diagnostics,
+            total_duration_ms,
+            smt_timeouts,
+            solver_stats,
+            resolved_root,
+            check_overflow: config.overflow_checked(),
+            solver: config.solver,
+            truncated,
+            queued_ms: (queued >= QUEUED_NOTICE_AFTER).then_some(queued.as_millis() as u64),
+            ice,
+            parse_stats: Some(parse_stats),
+            toolchain,
+            toolchain_warning,
+            skipped_packages,
+            ..Default::default()
+        })
+    }
+
+    /// Runs `cmd`, collecting the diagnostics it prints and sending each to `sink` as it's read,
+    /// and the ids of the packages it reports `built`. With `fail_fast` it is stopped at the
+    /// first flux error, i.e. one of a [`FluxErrorKind`].
+    #[allow(clippy::too_many_arguments)]
+    async fn collect_diagnostics(
+        &self,
+        cmd: Command,
+        timeout: Option<Duration>,
+        fail_fast: bool,
+        mut built: Option<&mut HashSet<String>>,
+        parse_stats: &mut ParseStats,
+        diagnostics: &mut Vec<Diagnostic>,
+        sink: &mut Option<mpsc::Sender<Diagnostic>>,
+    ) -> Result<RunStatus, FluxRunnerError> {
+        let mut run = self.spawn_run(cmd, timeout).await?;
+        while let Some(line) = run.next_line().await? {
+            if let Some(built) = &mut built {
+                built.extend(built_package(line));
+            }
+            let Some(diagnostic) = parse_stats.parse_line(line) else {
+                continue;
+            };
+            let is_flux_error = FluxErrorKind::of(&diagnostic.message.message).is_some();
+            if let Some(tx) = &*sink
+                && tx.send(diagnostic.clone()).await.is_err()
+            {
+                tracing::debug!("Diagnostic stream closed, only collecting the rest");
+                *sink = None;
+            }
+            diagnostics.push(diagnostic);
+            if fail_fast && is_flux_error {
+                run.stop();
+            }
+        }
+        run.finish().await
+    }
+
+    /// The workspace packages in the scope of `config` that a `--keep-going` run didn't build,
+    /// given the ids of those it `built`, and that have no errors of their own: those whose
+    /// build cargo skipped for a failed dependency.
+    async fn skipped_packages(
+        &self,
+        root: &Path,
+        config: &RepoConfig,
+        built: &HashSet<String>,
+        diagnostics: &[Diagnostic],
+    ) -> Result<Vec<String>, FluxRunnerError> {
+        let mut skipped = vec![];
+        for package in self.workspace_packages(&root.to_string_lossy()).await? {
+            let (Some(id), Some(name)) =
+                (package.get("id"), package.get("name").and_then(|name| name.as_str()))
+            else {
+                continue;
+            };
+            // Diagnostics keep the package id as the JSON flux printed.
+            let id = id.to_string();
+            let in_scope = config
+                .packages
+                .as_ref()
+                .is_none_or(|packages| packages.iter().any(|package| package == name));
+            let failed = diagnostics.iter().any(|diagnostic| {
+                diagnostic.message.parsed_level >= Level::Error
+                    && diagnostic.package_id.as_deref() == Some(&id)
+            });
+            if in_scope && !failed && !built.contains(&id) {
+                skipped.push(name.to_string());
+            }
+        }
+        Ok(skipped)
+    }
+
+    /// Checks the toolchain pinned for `root` against the one flux was built for, returning the
+    /// toolchain flux will run under and a warning if the two differ. With `strict_toolchain`,
+    /// a difference is an error instead.
+    fn check_toolchain(
+        &self,
+        root: &Path,
+        config: &RepoConfig,
+    ) -> Result<(Option<String>, Option<String>), FluxRunnerError> {
+        let pinned = repo_config::pinned_toolchain(root);
+        let flux = self.config.flux_toolchain.clone();
+        match (pinned, flux) {
+            (Some(pinned), Some(flux)) if !repo_config::same_toolchain(&pinned, &flux) => {
+                if config.strict_toolchain.unwrap_or(false) {
+                    return Err(FluxRunnerError::ToolchainMismatch(pinned, flux));
+                }
+                let warning = format!(
+                    "The repository pins the toolchain `{pinned}`, but flux was built for `{flux}`, \
+                     so it may fail to build the repository or load its dependencies"
+                );
+                tracing::warn!("{warning}");
+                Ok((Some(pinned), Some(warning)))
+            }
+            (pinned, flux) => Ok((pinned.or(flux), None)),
+        }
+    }
+
+    /// Renders the command [`Self::verify_repository_with`] would run, without running it.
+    /// The log directory used for statistics is a placeholder, since it only exists during a run.
+    pub fn dry_run(
+        &self,
+        repo_path: &str,
+        collect_stats: bool,
+        overrides: RepoConfig,
+    ) -> Result<String, FluxRunnerError> {
+        let (_, root, config) = Self::resolve_repository(repo_path, overrides)?;
+        let stats_dir = collect_stats.then(|| Path::new("<log-dir>"));
+        Ok(render_command(&self.repository_command(&root, &config, stats_dir)?))
+    }
+
+    /// Canonicalizes `repo_path` and finds the cargo root flux runs in, along with the settings
+    /// for the run.
+    pub(crate) fn resolve_repository(
+        repo_path: &str,
+        overrides: RepoConfig,
+    ) -> Result<(PathBuf, PathBuf, RepoConfig), FluxRunnerError> {
+        let requested = Path::new(repo_path)
+            .canonicalize()
+            .map_err(|_| FluxRunnerError::InvalidRepoPath(PathBuf::from(repo_path)))?;
+        let root = find_cargo_root(&requested).unwrap_or_else(|| requested.clone());
+        let config = RepoConfig::load(&requested)
+            .map_err(FluxRunnerError::Config)?
+            .merge(overrides);
+        Ok((requested, root, config))
+    }
+
+    /// The fingerprint of the files the results for `repo_path` depend on, as configured by its
+    /// `flux-mcp.toml` and `overrides`. Hashing them reads every file, so it runs on the
+    /// blocking pool.
+    async fn inputs_fingerprint(
+        repo_path: &str,
+        overrides: RepoConfig,
+    ) -> Result<String, FluxRunnerError> {
+        let repo_path = repo_path.to_string();
+        blocking(move || {
+            let (requested, _, config) = Self::resolve_repository(&repo_path, overrides)?;
+            config
+                .inputs_fingerprint(&requested)
+                .map_err(FluxRunnerError::Config)
+        })
+        .await
+    }
+
+    /// The flux command verifying `root` with `config`, logging statistics to `stats_dir`.
+    fn repository_command(
+        &self,
+        root: &Path,
+        config: &RepoConfig,
+        stats_dir: Option<&Path>,
+    ) -> Result<Command, FluxRunnerError> {
+        let mut flux_flags = config.default_flux_flags.clone().unwrap_or_default();
+        flux_flags.extend(config.solver_flag().map_err(FluxRunnerError::Config)?);
+        flux_flags.extend(config.smt_timeout_flag());
+        flux_flags.extend(config.check_overflow_flag());
+        if let Some(dir) = stats_dir {
+            flux_flags.push("-Ftimings".to_string());
+            flux_flags.push(format!("-Flog-dir={}", dir.display()));
+        }
+        let flux_flags: Vec<&str> = flux_flags.iter().map(String::as_str).collect();
+        let packages: Option<Vec<&str>> = config
+            .packages
+            .as_ref()
+            .map(|packages| packages.iter().map(String::as_str).collect());
+        let mut cmd = self.flux_command(
+            &root.to_string_lossy(),
+            packages.as_deref(),
+            Some(&flux_flags)
+                .filter(|flags| !flags.is_empty())
+                .map(Vec::as_slice),
+        );
+        if config.keep_going.unwrap_or(false) {
+            cmd.arg("--keep-going");
+        }
+        cmd.envs(config.env().map_err(FluxRunnerError::Config)?);
+        Ok(cmd)
+    }
+
+    /// The flux command running in `repo_path` with the settings of its `flux-mcp.toml`, on
+    /// `packages` rather than the configured ones when given, and with `flux_flags` added to its
+    /// `default_flux_flags`. Returns the settings too, for the timeout.
+    fn configured_command(
+        &self,
+        repo_path: &str,
+        packages: Option<&[&str]>,
+        flux_flags: &[&str],
+    ) -> Result<(Command, RepoConfig), FluxRunnerError> {
+        let overrides = RepoConfig {
+            packages: packages.map(|packages| packages.iter().map(|p| p.to_string()).collect()),
+            ..Default::default()
+        };
+        let (requested, _, mut config) = Self::resolve_repository(repo_path, overrides)?;
+        config
+            .default_flux_flags
+            .get_or_insert_default()
+            .extend(flux_flags.iter().map(|flag| flag.to_string()));
+        let cmd = self.repository_command(&requested, &config, None)?;
+        Ok((cmd, config))
+    }
+
+    /// Runs flux on the repository and returns every JSON message it printed, without going
+    /// through [`Self::parse_flux_line`].
+    pub async fn verify_repository_raw(
+        &self,
+        repo_path: &str,
+    ) -> Result<(bool, Vec<serde_json::Value>), FluxRunnerError> {
+        let cmd = self.flux_command(repo_path, None, None);
+        let mut messages = Vec::new();
+        let success = self
+            .run_command(cmd, |line| {
+                messages.extend(serde_json::from_str::<serde_json::Value>(line).ok());
+            })
+            .await?;
+        Ok((success, messages))
+    }
+
+    pub async fn verify_package(
+        &self,
+        repo_path: &str,
+        packages: Option<&[&str]>,
+    ) -> Result<VerificationReport, FluxRunnerError> {
+        self.verify_package_with_timeout(repo_path, packages, None)
+            .await
+    }
+
+    /// Like [`Self::verify_package`], but kills flux and its process group with a
+    /// [`FluxRunnerError::Timeout`] if it is still running after `timeout`.
+    pub async fn verify_package_with_timeout(
+        &self,
+        repo_path: &str,
+        packages: Option<&[&str]>,
+        timeout: Option<Duration>,
+    ) -> Result<VerificationReport, FluxRunnerError> {
+        let (cmd, config) = self.configured_command(repo_path, packages, &[])?;
+        let start = Instant::now();
+        let mut diagnostics = Vec::new();
+        let status = self
+            .run_command_until(cmd, timeout.or(config.timeout()), |line| {
+                diagnostics.extend(Self::parse_flux_line(line));
+                ControlFlow::Continue(())
+            })
+            .await?;
+        status.check_silent_failure(diagnostics.len())?;
+        let RunStatus { success, truncated, .. } = status;
+        let total_duration_ms = start.elapsed().as_millis() as u64;
+        diagnostics::sort_canonically(&mut diagnostics);
+        diagnostics::tag_enclosing_items(&mut diagnostics, Path::new(repo_path));
+        diagnostics::add_fingerprints(&mut diagnostics, Path::new(repo_path));
+
+        Ok(VerificationReport {
+            success,
+            diagnostics,
+            total_duration_ms,
+            truncated,
+            ..Default::default()
+        })
+    }
+
+    /// Verifies `package` together with every workspace package depending on it, directly or
+    /// not, since a changed spec can break its callers downstream. Returns the packages verified,
+    /// in workspace order, with the report.
+    pub async fn verify_package_tree(
+        &self,
+        repo_path: &str,
+        package: &str,
+    ) -> Result<(Vec<String>, VerificationReport), FluxRunnerError> {
+        let packages = self.package_tree(repo_path, package).await?;
+        let names: Vec<&str> = packages.iter().map(String::as_str).collect();
+        let report = self.verify_package(repo_path, Some(&names)).await?;
+        Ok((packages, report))
+    }
+
+    /// Verifies only the workspace package owning `file_path`, for the quickest check after
+    /// editing a single file. Returns the package's name with the report.
+    pub async fn verify_owning_package(
+        &self,
+        repo_path: &str,
+        file_path: &str,
+    ) -> Result<(String, VerificationReport), FluxRunnerError> {
+        let package = self.owning_package(repo_path, file_path).await?;
+        let report = self.verify_package(repo_path, Some(&[&package])).await?;
+        Ok((package, report))
+    }
+
+    /// The workspace package whose directory contains `file_path`, relative to `repo_path` if
+    /// it isn't absolute. With nested packages, the innermost one owns the file.
+    async fn owning_package(
+        &self,
+        repo_path: &str,
+        file_path: &str,
+    ) -> Result<String, FluxRunnerError> {
+        let canonical = |path: PathBuf| fs::canonicalize(&path).unwrap_or(path);
+        let file = canonical(Path::new(repo_path).join(file_path));
+        self.workspace_packages(repo_path)
+            .await?
+            .iter()
+            .filter_map(|package| {
+                let name = package.get("name")?.as_str()?;
+                let manifest = package.get("manifest_path")?.as_str()?;
+                let dir = canonical(Path::new(manifest).parent()?.to_path_buf());
+                file.starts_with(&dir)
+                    .then(|| (dir.components().count(), name.to_string()))
+            })
+            .max()
+            .map(|(_, name)| name)
+            .ok_or(FluxRunnerError::NoOwningPackage(file))
+    }
+
+    /// The reverse-dependency closure of `package` within the workspace, read from
+    /// `cargo metadata`. Only path dependencies count, so registry and git packages are never
+    /// pulled in.
+    async fn package_tree(
+        &self,
+        repo_path: &str,
+        package: &str,
+    ) -> Result<Vec<String>, FluxRunnerError> {
+        let members = self.workspace_packages(repo_path).await?;
+
+        // Each workspace package with the workspace packages it depends on through a path.
+        let mut dependencies: Vec<(&str, Vec<&str>)> = vec![];
+        for member in &members {
+            let Some(name) = member.get("name").and_then(serde_json::Value::as_str) else {
+                continue;
+            };
+            let deps = member
+                .get("dependencies")
+                .and_then(serde_json::Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter(|dep| dep.get("path").is_some_and(|path| !path.is_null()))
+                .filter_map(|dep| dep.get("name")?.as_str())
+                .collect();
+            dependencies.push((name, deps));
+        }
+        if !dependencies.iter().any(|(name, _)| *name == package) {
+            return Err(FluxRunnerError::UnknownPackage(package.to_string()));
+        }
+
+        let mut tree: HashSet<&str> = HashSet::from([package]);
+        let mut grew = true;
+        while grew {
+            grew = false;
+            for (name, deps) in &dependencies {
+                if !tree.contains(name) && deps.iter().any(|dep| tree.contains(dep)) {
+                    tree.insert(name);
+                    grew = true;
+                }
+            }
+        }
+        Ok(dependencies
+            .iter()
+            .filter(|(name, _)| tree.contains(name))
+            .map(|(name, _)| name.to_string())
+            .collect())
+    }
+
+    /// The packages of the workspace at `repo_path`, as listed by `cargo metadata`.
+    async fn workspace_packages(
+        &self,
+        repo_path: &str,
+    ) -> Result<Vec<serde_json::Value>, FluxRunnerError> {
+        let mut cmd = Command::new(&self.config.cargo_path);
+        cmd.args(["metadata", "--format-version", "1", "--no-deps"]);
+        cmd.current_dir(repo_path);
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+        let mut output = String::new();
+        let success = self.run_command(cmd, |line| output.push_str(line)).await?;
+        if !success {
+            return Err(FluxRunnerError::Metadata("cargo metadata exited unsuccessfully".into()));
+        }
+        let mut metadata: serde_json::Value = serde_json::from_str(&output)
+            .map_err(|err| FluxRunnerError::Metadata(err.to_string()))?;
+        match metadata.get_mut("packages").map(serde_json::Value::take) {
+            Some(serde_json::Value::Array(packages)) => Ok(packages),
+            _ => Err(FluxRunnerError::Metadata("no `packages` in the output".into())),
+        }
+    }
+
+    /// Looks up a workspace package by its name or its package id, as found on diagnostics,
+    /// with `cargo metadata`.
+    pub async fn resolve_package(
+        &self,
+        repo_path: &str,
+        name_or_id: &str,
+    ) -> Result<PackageInfo, FluxRunnerError> {
+        let field = |package: &serde_json::Value, key: &str| {
+            package
+                .get(key)
+                .and_then(serde_json::Value::as_str)
+                .map(str::to_string)
+        };
+        let package = self
+            .workspace_packages(repo_path)
+            .await?
+            .into_iter()
+            .find(|package| {
+                field(package, "id").as_deref() == Some(name_or_id)
+                    || field(package, "name").as_deref() == Some(name_or_id)
+            })
+            .ok_or_else(|| FluxRunnerError::UnknownPackage(name_or_id.to_string()))?;
+        let required = |key: &str| {
+            field(&package, key).ok_or_else(|| {
+                FluxRunnerError::Metadata(format!("no `{key}` for package {name_or_id}"))
+            })
+        };
+        Ok(PackageInfo {
+            name: required("name")?,
+            id: required("id")?,
+            version: field(&package, "version"),
+            manifest_path: required("manifest_path")?,
+        })
+    }
+
+    /// Counts how many of the public functions of `package`, or of the whole repository if
+    /// `None`, have a flux signature. The package's directory is found with `cargo metadata`.
+    pub async fn spec_coverage(
+        &self,
+        repo_path: &str,
+        package: Option<&str>,
+    ) -> Result<Coverage, FluxRunnerError> {
+        let Some(package) = package else {
+            return Ok(source::spec_coverage(Path::new(repo_path)));
+        };
+        let members = self.workspace_packages(repo_path).await?;
+        let dir = members
+            .iter()
+            .find(|member| member.get("name").and_then(serde_json::Value::as_str) == Some(package))
+            .ok_or_else(|| FluxRunnerError::UnknownPackage(package.to_string()))?
+            .get("manifest_path")
+            .and_then(serde_json::Value::as_str)
+            .and_then(|manifest| Path::new(manifest).parent())
+            .ok_or_else(|| {
+                FluxRunnerError::Metadata(format!("no `manifest_path` for {package}"))
+            })?;
+        Ok(source::spec_coverage(dir))
+    }
+
+    /// Verifies a single function. Flux is asked to only check defs whose path contains the
+    /// function path (minus the crate name), and the diagnostics are then restricted to those
+    /// located inside the function, since flux matches defs by substring.
+    pub async fn verify_function(
+        &self,
+        repo_path: &str,
+        package: &str,
+        function_path: &str,
+    ) -> Result<VerificationReport, FluxRunnerError> {
+        let def_path = function_path
+            .split_once("::")
+            .map_or(function_path, |(_, rest)| rest);
+        let include = format!("-Finclude=def:{def_path}");
+        let packages = [package];
+        let (cmd, config) = self.configured_command(repo_path, Some(&packages), &[&include])?;
+        let start = Instant::now();
+        let mut diagnostics = Vec::new();
+        let RunStatus { success, truncated, .. } = self
+            .run_command_until(cmd, config.timeout(), |line| {
+                diagnostics.extend(Self::parse_flux_line(line));
+                ControlFlow::Continue(())
+            })
+            .await?;
+        let total_duration_ms = start.elapsed().as_millis() as u64;
+
+        let ranges = source::find_fn_ranges(Path::new(repo_path), function_path);
+        if !ranges.is_empty() {
+            diagnostics.retain(|diag| {
+                diag.message
+                    .spans
+                    .iter()
+                    .filter(|span| span.is_primary)
+                    .any(|span| {
+                        ranges
+                            .iter()
+                            .any(|range| range.contains(&span.file_name, span.line_start as usize))
+                    })
+            });
+        }
+        diagnostics::sort_canonically(&mut diagnostics);
+        diagnostics::tag_enclosing_items(&mut diagnostics, Path::new(repo_path));
+        diagnostics::add_fingerprints(&mut diagnostics, Path::new(repo_path));
+
+        Ok(VerificationReport {
+            success,
+            diagnostics,
+            total_duration_ms,
+            truncated,
+            ..Default::default()
+        })
+    }
+
+    /// The `limit` slowest goals of verifying the repository, by the per-function timings flux
+    /// dumps with `-Ftimings`. If the dumps name no functions, the workspace packages are
+    /// verified one at a time and timed instead.
+    pub async fn slow_goals(
+        &self,
+        repo_path: &str,
+        limit: usize,
+    ) -> Result<SlowGoals, FluxRunnerError> {
+        let report = self.verify_repository(repo_path, true).await?;
+        let mut slow = match report.solver_stats {
+            Some(stats) if !stats.goals.is_empty() => {
+                SlowGoals { granularity: "function", goals: stats.goals }
+            }
+            _ => {
+                let packages = self.workspace_packages(repo_path).await?;
+                let names: Vec<&str> = packages
+                    .iter()
+                    .filter_map(|package| package.get("name")?.as_str())
+                    .collect();
+                let report = self.verify_per_package(repo_path, &names, None).await?;
+                let goals = report
+                    .package_timings
+                    .into_iter()
+                    .map(|timing| GoalTiming { item: timing.package, time_ms: timing.duration_ms })
+                    .collect();
+                SlowGoals { granularity: "package", goals }
+            }
+        };
+        slow.goals
+            .sort_by(|a, b| b.time_ms.cmp(&a.time_ms).then_with(|| a.item.cmp(&b.item)));
+        slow.goals.truncate(limit);
+        Ok(slow)
+    }
+
+    /// Verifies each package in its own flux process so that the time spent on every package
+    /// can be reported separately. Each process is killed once it runs for longer than
+    /// `timeout`.
+    pub async fn verify_per_package(
+        &self,
+        repo_path: &str,
+        packages: &[&str],
+        timeout: Option<Duration>,
+    ) -> Result<VerificationReport, FluxRunnerError> {
+        let mut merged =
+            VerificationReport { success: true, diagnostics: vec![], ..Default::default() };
+        for package in packages {
+            let report = self
+                .verify_package_with_timeout(repo_path, Some(&[package]), timeout)
+                .await?;
+            merged.success &= report.success;
+            merged.diagnostics.extend(report.diagnostics);
+            merged.total_duration_ms += report.total_duration_ms;
+            merged.package_timings.push(PackageTiming {
+                package: package.to_string(),
+                duration_ms: report.total_duration_ms,
+            });
+        }
+        Ok(merged)
+    }
+
+    pub async fn get_lemmas(
+        &self,
+        repo_path: &str,
+        packages: Option<&[&str]>,
+    ) -> Result<Vec<Lemma>, FluxRunnerError> {
+        self.get_lemmas_to(repo_path, packages, None).await
+    }
+
+    /// A channel for [`Self::get_lemmas_streaming`], holding at most
+    /// [`FluxRunnerConfig::stream_buffer`] lemmas.
+    pub fn lemma_channel(&self) -> (mpsc::Sender<Lemma>, mpsc::Receiver<Lemma>) {
+        mpsc::channel(self.config.stream_buffer.max(1))
+    }
+
+    /// Like [`Self::get_lemmas`], but also sends every lemma to `sink` as soon as flux prints
+    /// it, which on a large workspace is long before the dump finishes. The lemmas sent only
+    /// have their location, since their clauses are read from the source once all are known;
+    /// the returned ones are complete. A full channel pauses reading flux's output as for
+    /// [`Self::verify_repository_streaming`].
+    pub async fn get_lemmas_streaming(
+        &self,
+        repo_path: &str,
+        packages: Option<&[&str]>,
+        sink: mpsc::Sender<Lemma>,
+    ) -> Result<Vec<Lemma>, FluxRunnerError> {
+        self.get_lemmas_to(repo_path, packages, Some(sink)).await
+    }
+
+    async fn get_lemmas_to(
+        &self,
+        repo_path: &str,
+        packages: Option<&[&str]>,
+        mut sink: Option<mpsc::Sender<Lemma>>,
+    ) -> Result<Vec<Lemma>, FluxRunnerError> {
+        let (cmd, config) = self.configured_command(repo_path, packages, &["-Fdump-lemmas"])?;
+        let mut lemmas = Vec::new();
+        // The errors keeping flux from dumping any lemmas, which are on stdout.
+        let mut diagnostics = 0;
+        tracing::debug!("Dumping the lemmas of {repo_path}");
+        let mut run = self.spawn_run(cmd, config.timeout()).await?;
+        while let Some(line) = run.next_line().await? {
+            let Some(lemma) = Self::parse_lemma_line(line) else {
+                diagnostics += usize::from(Self::parse_flux_line(line).is_some());
+                continue;
+            };
+            if let Some(tx) = &sink
+                && tx.send(lemma.clone()).await.is_err()
+            {
+                sink = None;
+            }
+            lemmas.push(lemma);
+        }
+        drop(sink);
+        let status = run.finish().await?;
+        self.check_complete(&status)?;
+        status.check_silent_failure(lemmas.len() + diagnostics)?;
+        let names = lemmas.iter().map(|lemma| short_name(&lemma.name)).collect();
+        let mut clauses = source::sig_clauses(Path::new(repo_path), &names);
+        for lemma in &mut lemmas {
+            if let Some(clauses) = clauses.remove(short_name(&lemma.name)) {
+                lemma.requires = clauses.requires;
+                lemma.ensures = clauses.ensures;
+                lemma.params = clauses.params;
+                lemma.trusted = clauses.trusted;
+            }
+        }
+        Ok(lemmas)
+    }
+
+    /// Dumps the lemmas of the repository and writes them to `out` as JSON, along with the
+    /// fingerprint of the sources they were dumped from.
+    pub async fn save_lemmas(
+        &self,
+        repo_path: &str,
+        out: &Path,
+    ) -> Result<LemmaSnapshot, FluxRunnerError> {
+        // Taken before dumping, so that edits made meanwhile invalidate the snapshot.
+        let fingerprint = Self::inputs_fingerprint(repo_path, RepoConfig::default()).await?;
+        let lemmas = self.get_lemmas(repo_path, None).await?;
+        if let Some(parent) = out.parent() {
+            fs::create_dir_all(parent).map_err(FluxRunnerError::Snapshot)?;
+        }
+        let snapshot = LemmaSnapshot { fingerprint, lemmas };
+        let json = serde_json::to_string(&snapshot).unwrap();
+        fs::write(out, json).map_err(FluxRunnerError::Snapshot)?;
+        Ok(snapshot)
+    }
+
+    /// Reads a snapshot written by [`Self::save_lemmas`].
+    pub fn load_lemmas(path: &Path) -> Result<LemmaSnapshot, FluxRunnerError> {
+        let json = fs::read_to_string(path).map_err(FluxRunnerError::Snapshot)?;
+        serde_json::from_str(&json).map_err(|err| FluxRunnerError::Snapshot(err.into()))
+    }
+
+    /// Loads the lemmas from the snapshot at `snapshot` if its fingerprint matches the current
+    /// inputs of the repository, and otherwise dumps them again and refreshes the snapshot.
+    pub async fn get_lemmas_cached(
+        &self,
+        repo_path: &str,
+        snapshot: &Path,
+    ) -> Result<LemmaSnapshot, FluxRunnerError> {
+        if snapshot.exists() {
+            let fingerprint = Self::inputs_fingerprint(repo_path, RepoConfig::default()).await?;
+            match Self::load_lemmas(snapshot) {
+                Ok(saved) if saved.fingerprint == fingerprint => {
+                    return Ok(saved);
+                }
+                Ok(_) => tracing::debug!("Lemma snapshot is stale, dumping again"),
+                Err(err) => tracing::warn!("Ignoring lemma snapshot: {err}"),
+            }
+        }
+        self.save_lemmas(repo_path, snapshot).await
+    }
+
+    /// Counts the lemmas declared in each file, keyed by the file name flux reports.
+    pub async fn lemma_index(
+        &self,
+        repo_path: &str,
+        packages: Option<&[&str]>,
+    ) -> Result<BTreeMap<String, usize>, FluxRunnerError> {
+        let mut index = BTreeMap::new();
+        for lemma in self.get_lemmas(repo_path, packages).await? {
+            *index.entry(lemma.file_name).or_insert(0) += 1;
+        }
+        Ok(index)
+    }
+
+    /// The bare lemma names declared in more than one place, e.g. `len_pos` in both
+    /// `seq::len_pos` and `vec::len_pos`. A lemma flux reports twice at the same location, once
+    /// per target, isn't a conflict.
+    pub async fn detect_lemma_conflicts(
+        &self,
+        repo_path: &str,
+    ) -> Result<Vec<LemmaConflict>, FluxRunnerError> {
+        let mut locations: BTreeMap<String, BTreeSet<(String, i64)>> = BTreeMap::new();
+        for lemma in self.get_lemmas(repo_path, None).await? {
+            locations
+                .entry(short_name(&lemma.name).to_string())
+                .or_default()
+                .insert((lemma.file_name, lemma.start_line));
+        }
+        Ok(locations
+            .into_iter()
+            .filter(|(_, locations)| locations.len() > 1)
+            .map(|(name, locations)| {
+                LemmaConflict { name, locations: locations.into_iter().collect() }
+            })
+            .collect())
+    }
+
+    /// The lemmas of the repository, each with the trimmed source of its first line, which is
+    /// lighter than fetching every lemma's whole definition.
+    pub async fn lemma_previews(
+        &self,
+        repo_path: &str,
+    ) -> Result<Vec<LemmaPreview>, FluxRunnerError> {
+        let mut sources: HashMap<String, Option<String>> = HashMap::new();
+        let mut previews = vec![];
+        for lemma in self.get_lemmas(repo_path, None).await? {
+            let contents = sources
+                .entry(lemma.file_name.clone())
+                .or_insert_with_key(|file| {
+                    fs::read_to_string(lsp::resolve_path(Path::new(repo_path), file)).ok()
+                });
+            let preview = usize::try_from(lemma.start_line - 1)
+                .ok()
+                .and_then(|i| contents.as_deref()?.lines().nth(i))
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            previews.push(LemmaPreview {
+                name: lemma.name,
+                file: lemma.file_name,
+                line: lemma.start_line,
+                preview,
+            });
+        }
+        Ok(previews)
+    }
+
+    /// Checks every fixture under `tests/tests/pos` or `tests/tests/neg` (or directly under
+    /// `pos`/`neg` when `repo_path` is the fixtures directory). Fixtures are standalone files, so
+    /// each one is verified as the library of a scratch package depending on the repository's
+    /// `flux-rs`. The scratch packages share a target directory so dependencies build once.
+    pub async fn verify_fixtures(
+        &self,
+        repo_path: &str,
+        expectation: Expectation,
+    ) -> Result<Vec<FixtureResult>, FluxRunnerError> {
+        let repo_path = Path::new(repo_path);
+        let kind = match expectation {
+            Expectation::Pos => "pos",
+            Expectation::Neg => "neg",
+        };
+        let Some(fixtures_dir) = [repo_path.join("tests/tests").join(kind), repo_path.join(kind)]
+            .into_iter()
+            .find(|dir| dir.is_dir())
+        else {
+            return Ok(vec![]);
+        };
+        let flux_rs = find_cargo_root(repo_path)
+            .map(|root| root.join("lib/flux-rs"))
+            .filter(|dir| dir.is_dir())
+            .or_else(|| self.config.flux_rs_path.clone());
+        let target_dir = tempfile::tempdir().map_err(FluxRunnerError::TempDir)?;
+
+        let mut results = Vec::new();
+        for fixture in source::rust_files(&fixtures_dir) {
+            let package = tempfile::tempdir().map_err(FluxRunnerError::TempDir)?;
+            let fixture = fixture.canonicalize().unwrap_or(fixture);
+            write_scratch_manifest(package.path(), &fixture, flux_rs.as_deref())
+                .map_err(FluxRunnerError::TempDir)?;
+
+            let mut cmd = self.flux_command(&package.path().to_string_lossy(), None, None);
+            cmd.env("CARGO_TARGET_DIR", target_dir.path());
+            let mut errors = 0;
+            self.run_command(cmd, |line| {
+                let Some(diagnostic) = Self::parse_flux_line(line) else { return };
+                // Skip summaries such as "aborting due to 2 previous errors".
+                if diagnostic.message.level == "error"
+                    && diagnostic.message.primary_span().is_some()
+                {
+                    errors += 1;
+                }
+            })
+            .await?;
+            let passed = match expectation {
+                Expectation::Pos => errors == 0,
+                Expectation::Neg => errors > 0,
+            };
+            results.push(FixtureResult {
+                file_name: source::display_path(&fixtures_dir, &fixture),
+                errors,
+                passed,
+            });
+        }
+        Ok(results)
+    }
+
+    /// Checks the whole test corpus under `tests/tests`, i.e. both the `pos` and `neg` fixtures,
+    /// reporting for each whether it still behaves as its directory says it should.
+    pub async fn verify_corpus(&self, repo_path: &str) -> Result<CorpusResult, FluxRunnerError> {
+        let mut corpus = CorpusResult::default();
+        for (expected, kind) in [(Expectation::Pos, "pos"), (Expectation::Neg, "neg")] {
+            for fixture in self.verify_fixtures(repo_path, expected).await? {
+                let actual = if fixture.errors > 0 { Expectation::Neg } else { Expectation::Pos };
+                corpus.mismatches += usize::from(!fixture.passed);
+                corpus.entries.push(CorpusEntry {
+                    file_name: format!("{kind}/{}", fixture.file_name),
+                    expected,
+                    actual,
+                    ok: fixture.passed,
+                });
+            }
+        }
+        Ok(corpus)
+    }
+
+    /// Verifies a standalone source file by making it the library of a scratch package, which
+    /// is deleted afterwards. Spans in the source are reported against [`SOURCE_FILE_NAME`],
+    /// with the same line numbers as in `source`.
+    /// Verifies several independent repositories, as many at once as
+    /// [`FluxRunnerConfig::max_concurrent_verifications`] allows. A repository that can't be
+    /// verified is reported with its error without affecting the others.
+    pub async fn verify_many(self: &Arc<Self>, repo_paths: &[String]) -> ManyResult {
+        let start = Instant::now();
+        let runs: Vec<_> = repo_paths
+            .iter()
+            .map(|repo_path| {
+                let runner = self.clone();
+                let repo_path = repo_path.clone();
+                tokio::spawn(async move {
+                    let result = runner.verify_repository(&repo_path, false).await;
+                    (result, start.elapsed())
+                })
+            })
+            .collect();
+        let mut res = ManyResult { all_passed: true, repos: vec![] };
+        for (repo_path, run) in repo_paths.iter().zip(runs) {
+            let (report, error, elapsed) = match run.await {
+                Ok((Ok(report), elapsed)) => (Some(report), None, elapsed),
+                Ok((Err(err), elapsed)) => (None, Some(err.to_string()), elapsed),
+                Err(err) => (None, Some(format!("Verification panicked: {err}")), start.elapsed()),
+            };
+            res.all_passed &= report.as_ref().is_some_and(|report| report.success);
+            res.repos.push(RepoResult {
+                repo_path: repo_path.clone(),
+                duration_ms: elapsed.as_millis() as u64,
+                report,
+                error,
+            });
+        }
+        res
+    }
+
+    pub async fn verify_source(&self, source: &str) -> Result<VerificationReport, FluxRunnerError> {
+        self.verify_source_with(source, None).await
+    }
+
+    /// Checks that `lemma` parses and its spec is well-formed, without asking the solver
+    /// anything: the scratch package is verified with all code trusted, and only the syntax and
+    /// spec errors are kept. `context` holds the definitions the lemma refers to and is placed
+    /// after it, so spans in the lemma keep its line numbers.
+    pub async fn check_lemma_syntax(
+        &self,
+        lemma: &str,
+        context: Option<&str>,
+    ) -> Result<VerificationReport, FluxRunnerError> {
+        let mut source = lemma.to_string();
+        if let Some(context) = context {
+            if !source.ends_with('\n') {
+                source.push('\n');
+            }
+            source.push_str(context);
+        }
+        let mut report = self
+            .verify_source_with(&source, Some(&["-Ftrusted=true"]))
+            .await?;
+        report.diagnostics = diagnostics::retain_only_syntax_errors(report.diagnostics);
+        Ok(report)
+    }
+
+    async fn verify_source_with(
+        &self,
+        source: &str,
+        flux_flags: Option<&[&str]>,
+    ) -> Result<VerificationReport, FluxRunnerError> {
+        let package = tempfile::tempdir().map_err(FluxRunnerError::TempDir)?;
+        let lib = package.path().join(SOURCE_FILE_NAME);
+        fs::write(&lib, source).map_err(FluxRunnerError::TempDir)?;
+        write_scratch_manifest(package.path(), &lib, self.config.flux_rs_path.as_deref())
+            .map_err(FluxRunnerError::TempDir)?;
+
+        let mut cmd = self.flux_command(&package.path().to_string_lossy(), None, flux_flags);
+        // Share a target directory between runs so that `flux-rs` is only built once.
+        cmd.env("CARGO_TARGET_DIR", std::env::temp_dir().join("flux-mcp-target"));
+        let start = Instant::now();
+        let mut diagnostics = Vec::new();
+        let RunStatus { success, truncated, .. } = self
+            .run_command_until(cmd, None, |line| {
+                diagnostics.extend(Self::parse_flux_line(line));
+                ControlFlow::Continue(())
+            })
+            .await?;
+        let total_duration_ms = start.elapsed().as_millis() as u64;
+        let lib = lib.canonicalize().unwrap_or(lib);
+        for span in diagnostics
+            .iter_mut()
+            .flat_map(|diagnostic| &mut diagnostic.message.spans)
+        {
+            if lsp::resolve_path(package.path(), &span.file_name) == lib {
+                span.file_name = SOURCE_FILE_NAME.to_string();
+            }
+        }
+        diagnostics::sort_canonically(&mut diagnostics);
+        Ok(VerificationReport {
+            success,
+            diagnostics,
+            total_duration_ms,
+            truncated,
+            ..Default::default()
+        })
+    }
+
+    /// Asks cargo-flux for its version and reports which cargo and toolchain it runs under.
+    pub async fn flux_version(&self) -> Result<FluxVersion, FluxRunnerError> {
+        let mut cmd = Command::new(&self.config.cargo_path);
+        cmd.args(["flux", "--version"]);
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+        let mut version = None;
+        let success = self
+            .run_command(cmd, |line| {
+                if version.is_none() && !line.trim().is_empty() {
+                    version = Some(line.trim().to_string());
+                }
+            })
+            .await?;
+        let cargo_path = find_in_path(&self.config.cargo_path)
+            .unwrap_or_else(|| self.config.cargo_path.clone())
+            .display()
+            .to_string();
+        Ok(FluxVersion {
+            version: version.filter(|_| success),
+            cargo_path,
+            toolchain: blocking(active_toolchain).await,
+        })
+    }
+
+    /// Lists the `-F` options the installed flux knows, by name without the `-F`, as its help
+    /// text names them.
+    pub async fn supported_flags(&self) -> Result<Vec<String>, FluxRunnerError> {
+        let mut cmd = Command::new(&self.config.cargo_path);
+        cmd.args(["flux", "--help"]);
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+        let mut help = String::new();
+        let success = self
+            .run_command(cmd, |line| {
+                help.push_str(line);
+                help.push('\n');
+            })
+            .await?;
+        if !success {
+            return Err(FluxRunnerError::FluxNotInstalled);
+        }
+        Ok(flux_option_names(&help))
+    }
+
+    /// Returns the sorts and uninterpreted functions declared in the `flux_rs::defs!` blocks of
+    /// the repository.
+    pub async fn get_definitions(&self, repo_path: &str) -> Vec<DefItem> {
+        source::find_definitions(Path::new(repo_path))
+    }
+
+    /// Returns the opaque sorts and uninterpreted functions of the repository, leaving out
+    /// qualifiers, properties and functions with a body.
+    pub async fn list_sorts_and_funcs(&self, repo_path: &str) -> SortsAndFuncs {
+        let mut grouped = SortsAndFuncs::default();
+        for def in self.get_definitions(repo_path).await {
+            match def.kind {
+                DefKind::Sort => grouped.sorts.push(def),
+                DefKind::Func if !def.signature.trim_end().ends_with('}') => {
+                    grouped.funcs.push(def);
+                }
+                _ => {}
+            }
+        }
+        grouped
+    }
+
+    /// The refinement index of the struct or enum `type_name`, e.g. `elems: ISeq` for a `Foo`
+    /// declared with `#[flux::refined_by(elems: ISeq)]`, or `None` if it has none.
+    pub async fn refined_by(&self, repo_path: &str, type_name: &str) -> Option<RefinedBy> {
+        source::find_refined_by(Path::new(repo_path), type_name)
+    }
+
+    /// The file and lines of the item `item_path`, e.g. `crate::seq::RSeq::push`, `RSeq::push`
+    /// or `len`, resolved as [`source::find_item`] does, or `None` if no item matches.
+    pub async fn item_range(&self, repo_path: &str, item_path: &str) -> Option<LineRange> {
+        source::find_item(Path::new(repo_path), item_path)
+    }
+
+    /// The functions called by the function `item`, e.g. `push` or `RVec::push`, as written at
+    /// the call sites, so that their specs can be looked up too. With a `depth` above 1, the
+    /// callees defined in the repository are followed in turn, up to [`MAX_CALLEE_DEPTH`]
+    /// levels. Calls are found syntactically, so a method call only gives the method's name
+    /// and a callee is followed into every function of that name. Returns `None` if no
+    /// function matches `item`.
+    pub async fn callees(&self, repo_path: &str, item: &str, depth: usize) -> Option<Callees> {
+        let graph = source::call_graph(Path::new(repo_path));
+        let callees_of = |path: &str| {
+            graph
+                .iter()
+                .filter(|calls| calls.matches(path))
+                .flat_map(|calls| calls.callees.iter().cloned())
+                .collect::<BTreeSet<_>>()
+        };
+        if !graph.iter().any(|calls| calls.matches(item)) {
+            return None;
+        }
+        let depth = depth.clamp(1, MAX_CALLEE_DEPTH);
+        let mut seen = BTreeSet::from([item.to_string()]);
+        let mut res = Callees { callees: vec![], truncated: false };
+        let mut level = callees_of(item);
+        'levels: for current in 1..=depth {
+            let mut next = BTreeSet::new();
+            for callee in level {
+                if !seen.insert(callee.clone()) {
+                    continue;
+                }
+                if res.callees.len() == MAX_CALLEES {
+                    res.truncated = true;
+                    break 'levels;
+                }
+                if current < depth {
+                    next.extend(callees_of(&callee));
+                }
+                res.callees.push(callee);
+            }
+            level = next;
+        }
+        Some(res)
+    }
+
+    /// Extracts the flux signatures of the functions in `file_path`, which is relative to
+    /// `repo_path` unless absolute.
+    pub async fn extract_sigs(
+        &self,
+        repo_path: &str,
+        file_path: &str,
+    ) -> Result<Vec<FnSig>, FluxRunnerError> {
+        let path = Path::new(repo_path).join(file_path);
+        source::file_sigs(&path).ok_or(FluxRunnerError::InvalidSource(path))
+    }
+
+    /// Verifies a scratch copy of the repository before and after replacing the signature of
+    /// `item` (e.g. `push` or `Foo::push`) with `new_sig`, and reports which items the edit
+    /// made pass or fail. The repository itself is never touched.
+    pub async fn spec_edit_impact(
+        &self,
+        repo_path: &str,
+        item: &str,
+        new_sig: &str,
+    ) -> Result<SpecEditImpact, FluxRunnerError> {
+        let scratch = ScratchRepo::from_path(repo_path).await?;
+        let copy = scratch.path();
+
+        let mut matches = vec![];
+        for path in source::rust_files(copy) {
+            let Some((contents, sites)) = source::sig_sites(&path) else { continue };
+            for site in sites {
+                let name = &site.sig.name;
+                if name == item || name.ends_with(&format!("::{item}")) {
+                    matches.push((path.clone(), contents.clone(), site));
+                }
+            }
+        }
+        let [(path, contents, site)] = &matches[..] else {
+            return Err(FluxRunnerError::UnknownItem(item.to_string()));
+        };
+        let copy_path = copy.to_string_lossy();
+        let before = self.verify_repository(&copy_path, false).await?;
+        let mut edited = contents.clone();
+        edited.replace_range(site.range.clone(), new_sig);
+        fs::write(path, edited).map_err(FluxRunnerError::Copy)?;
+        let after = self.verify_repository(&copy_path, false).await?;
+
+        let failing = |report: &VerificationReport| -> BTreeSet<String> {
+            report
+                .diagnostics
+                .iter()
+                .filter(|diag| diag.message.parsed_level >= Level::Error)
+                .filter_map(|diag| diag.enclosing_item.clone())
+                .collect()
+        };
+        let (failing_before, failing_after) = (failing(&before), failing(&after));
+        let name = &site.sig.name;
+        let others = |a: &BTreeSet<String>, b: &BTreeSet<String>| -> Vec<String> {
+            a.difference(b)
+                .filter(|other| *other != name)
+                .cloned()
+                .collect()
+        };
+        Ok(SpecEditImpact {
+            item: name.clone(),
+            file_name: source::display_path(scratch.root(), path),
+            passed_before: !failing_before.contains(name),
+            passes_after: !failing_after.contains(name),
+            broken: others(&failing_after, &failing_before),
+            fixed: others(&failing_before, &failing_after),
+            diff: diagnostics::diff(&before, &after),
+        })
+    }
+
+    /// Inserts every call of `calls` in a scratch copy of the repository and verifies it, so a
+    /// batch of lemma instantiations is tried in one run. Calls that don't parse or point past
+    /// the end of their file are reported and left out. The repository itself is never touched.
+    pub async fn verify_with_extra_lemmas(
+        &self,
+        repo_path: &str,
+        calls: &[LemmaCall],
+    ) -> Result<ExtraLemmasResult, FluxRunnerError> {
+        let scratch = ScratchRepo::from_path(repo_path).await?;
+        let copy = scratch.path();
+
+        let mut inserted: Vec<InsertedCall> = calls
+            .iter()
+            .map(|call| {
+                InsertedCall {
+                    call: call.clone(),
+                    accepted: false,
+                    reason: None,
+                    inserted_line: None,
+                    errors: 0,
+                }
+            })
+            .collect();
+        // Group the calls by file, keeping each file's lines as they were in the repository.
+        let mut files: BTreeMap<PathBuf, Vec<usize>> = BTreeMap::new();
+        for (i, result) in inserted.iter_mut().enumerate() {
+            let call_text = result.call.call_text.trim().trim_end_matches(';');
+            if let Err(err) = syn::parse_str::<syn::Expr>(call_text) {
+                result.reason = Some(format!("not an expression: {err}"));
+                continue;
+            }
+            let Ok(path) = scratch.resolve(&result.call.file_path) else {
+                result.reason = Some(format!("no such file `{}`", result.call.file_path));
+                continue;
+            };
+            files.entry(path).or_default().push(i);
+        }
+        for (path, indices) in &files {
+            let contents = fs::read_to_string(path).map_err(FluxRunnerError::Copy)?;
+            let lines: Vec<&str> = contents.lines().collect();
+            let mut by_line: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+            for &i in indices {
+                let line = inserted[i].call.line;
+                if line == 0 || line > lines.len() + 1 {
+                    inserted[i].reason = Some(format!("line {line} is past the end of the file"));
+                    continue;
+                }
+                by_line.entry(line).or_default().push(i);
+            }
+            let mut edited = String::new();
+            let mut shift = 0;
+            for (n, line) in lines.iter().chain([&""]).enumerate() {
+                let line_no = n + 1;
+                // Indent the calls like the statement they go in front of.
+                let indent = &line[..line.len() - line.trim_start().len()];
+                for &i in by_line.get(&line_no).into_iter().flatten() {
+                    let call_text = inserted[i].call.call_text.trim().trim_end_matches(';');
+                    edited.push_str(&format!("{indent}{call_text};\n"));
+                    shift += 1;
+                    inserted[i].accepted = true;
+                    inserted[i].inserted_line = Some(line_no + shift - 1);
+                }
+                if line_no <= lines.len() {
+                    edited.push_str(line);
+                    edited.push('\n');
+                }
+            }
+            fs::write(path, edited).map_err(FluxRunnerError::Copy)?;
+        }
+
+        let report = self
+            .verify_repository(&copy.to_string_lossy(), false)
+            .await?;
+        for result in &mut inserted {
+            let Some(line) = result.inserted_line else { continue };
+            let Ok(path) = scratch.resolve(&result.call.file_path) else { continue };
+            result.errors = report
+                .diagnostics
+                .iter()
+                .filter(|diag| diag.message.parsed_level >= Level::Error)
+                .filter_map(|diag| diag.message.primary_span())
+                .filter(|span| {
+                    span.line_start as usize == line && copy.join(&span.file_name) == path
+                })
+                .count();
+        }
+        Ok(ExtraLemmasResult {
+            success: report.success,
+            calls: inserted,
+            remaining: report.diagnostics,
+        })
+    }
+
+    /// Inserts the call `name(args);` before `column` of `line` in `file_path`, in a scratch copy
+    /// of the repository, and compares the flux refinement errors with and without it. The call
+    /// goes on the line itself, so the other diagnostics keep their lines, and those after it on
+    /// the line are shifted back to where they were. The repository itself is never touched,
+    /// even if flux crashes.
+    pub async fn try_lemma(
+        &self,
+        repo_path: &str,
+        file_path: &str,
+        line: usize,
+        column: Option<usize>,
+        name: &str,
+        args: &[String],
+    ) -> Result<TryLemmaResult, FluxRunnerError> {
+        let lemmas = self.get_lemmas(repo_path, None).await?;
+        let lemma = lemmas.iter().find(|lemma| lemma.name == name).or_else(|| {
+            lemmas
+                .iter()
+                .find(|lemma| short_name(&lemma.name) == short_name(name))
+        });
+        let Some(lemma) = lemma else {
+            let known: BTreeSet<String> = lemmas.iter().map(|lemma| lemma.name.clone()).collect();
+            return Err(FluxRunnerError::UnknownLemma(
+                name.to_string(),
+                known.into_iter().collect(),
+            ));
+        };
+        let call = format!("{name}({});", args.join(", "));
+        if let Err(err) = syn::parse_str::<syn::Expr>(call.trim_end_matches(';')) {
+            return Err(FluxRunnerError::InvalidInsertion(format!(
+                "`{call}` isn't an expression: {err}"
+            )));
+        }
+
+        let (_, root, _) = Self::resolve_repository(repo_path, RepoConfig::default())?;
+        let scratch = ScratchRepo::from_path(repo_path).await?;
+        let path = scratch.resolve(file_path)?;
+        let contents = fs::read_to_string(&path).map_err(FluxRunnerError::Copy)?;
+        let lines: Vec<&str> = contents.lines().collect();
+        let Some(text) = line.checked_sub(1).and_then(|i| lines.get(i)) else {
+            return Err(FluxRunnerError::InvalidInsertion(format!(
+                "line {line} is outside of {file_path}, which has {} lines",
+                lines.len()
+            )));
+        };
+        let width = text.chars().count();
+        let column =
+            column.unwrap_or_else(|| text.chars().take_while(|c| c.is_whitespace()).count() + 1);
+        if column == 0 || column > width + 1 {
+            return Err(FluxRunnerError::InvalidInsertion(format!(
+                "column {column} is outside of line {line}, which has {width} characters"
+            )));
+        }
+        let Some(item) = source::item_ranges(&path)
+            .into_iter()
+            .filter(|item| item.start_line <= line && line <= item.end_line)
+            .min_by_key(|item| item.end_line - item.start_line)
+        else {
+            return Err(FluxRunnerError::InvalidInsertion(format!(
+                "line {line} of {file_path} isn't inside an item"
+            )));
+        };
+        let inserted = format!("{call} ");
+        let line_start: usize = contents
+            .split_inclusive('\n')
+            .take(line - 1)
+            .map(str::len)
+            .sum();
+        let at = text
+            .char_indices()
+            .nth(column - 1)
+            .map_or(text.len(), |(i, _)| i);
+        let mut edited = contents.clone();
+        edited.insert_str(line_start + at, &inserted);
+        fs::write(&path, edited).map_err(FluxRunnerError::Copy)?;
+
+        let copy_path = scratch.path().to_string_lossy();
+        let before = self.verify_repository(repo_path, false).await?;
+        let mut after = self.verify_repository(&copy_path, false).await?;
+
+        // Spans are compared relative to the root of the cargo project they were reported in.
+        let target = path.strip_prefix(scratch.root()).unwrap_or(&path);
+        let in_target = |span: &DiagnosticSpan, root: &Path| {
+            let file = Path::new(&span.file_name);
+            file.strip_prefix(root).unwrap_or(file) == target
+        };
+        let (call_start, call_end) = (column as i64, (column + inserted.chars().count()) as i64);
+        let shift = call_end - call_start;
+        let mut call_errors = vec![];
+        after.diagnostics.retain_mut(|diagnostic| {
+            let at_call = diagnostic.message.primary_span().is_some_and(|span| {
+                in_target(span, scratch.root())
+                    && span.line_start == line as i64
+                    && (call_start..call_end).contains(&span.column_start)
+            });
+            if at_call {
+                if diagnostic.message.parsed_level >= Level::Error {
+                    call_errors.push(diagnostic.clone());
+                }
+                return false;
+            }
+            for span in &mut diagnostic.message.spans {
+                if !in_target(span, scratch.root()) {
+                    continue;
+                }
+                if span.line_start == line as i64 && span.column_start >= call_end {
+                    span.column_start -= shift;
+                }
+                if span.line_end == line as i64 && span.column_end >= call_end {
+                    span.column_end -= shift;
+                }
+            }
+            true
+        });
+
+        let flux_errors = |report: &VerificationReport| {
+            VerificationReport {
+                diagnostics: diagnostics::retain_only_flux_errors(report.diagnostics.clone()),
+                ..Default::default()
+            }
+        };
+        let (before_errors, after_errors) = (flux_errors(&before), flux_errors(&after));
+        let in_item = |report: &VerificationReport, root: &Path| {
+            report
+                .diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.enclosing_item.as_ref() == Some(&item.name))
+                .filter(|diagnostic| {
+                    diagnostic
+                        .message
+                        .primary_span()
+                        .is_some_and(|span| in_target(span, root))
+                })
+                .count()
+        };
+        let errors_before = in_item(&before_errors, &root);
+        let errors_after = in_item(&after_errors, scratch.root());
+        let introduced = diagnostics::diff(&before_errors, &after_errors).introduced;
+        let outcome = if !call_errors.is_empty() || !introduced.is_empty() {
+            LemmaOutcome::NewErrors
+        } else if errors_after < errors_before {
+            LemmaOutcome::Helped
+        } else {
+            LemmaOutcome::NoChange
+        };
+        Ok(TryLemmaResult {
+            lemma: lemma.name.clone(),
+            call,
+            item: item.name,
+            outcome,
+            errors_before,
+            errors_after,
+            call_errors,
+            introduced,
+            success: after.success,
+            ice: after.ice,
+        })
+    }
+
+    /// Verifies the repository, then runs `cargo fmt` on a scratch copy of it and returns the
+    /// formatted contents of `files`, relative to `repo_path`, or of every file formatting
+    /// changed if none are given. The repository is verified as it is, so a formatting failure
+    /// never hides the report. With `write`, the returned files that changed are written back to
+    /// the repository, and nothing else is touched.
+    pub async fn verify_and_fmt(
+        &self,
+        repo_path: &str,
+        files: Option<&[String]>,
+        write: bool,
+    ) -> Result<(VerificationReport, FmtResult), FluxRunnerError> {
+        let report = self.verify_repository(repo_path, false).await?;
+        let scratch = ScratchRepo::from_path(repo_path).await?;
+        let copy = scratch.path();
+        let candidates: Vec<PathBuf> = match files {
+            Some(files) => {
+                let mut candidates = vec![];
+                for file in files {
+                    candidates.push(scratch.resolve(file)?);
+                }
+                candidates
+            }
+            None => source::rust_files(copy),
+        };
+        let output = tokio::process::Command::new(&self.config.cargo_path)
+            .args(["fmt", "--all"])
+            .current_dir(scratch.root())
+            .output()
+            .await;
+        let error = match output {
+            Ok(output) if output.status.success() => None,
+            Ok(output) => Some(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+            Err(err) => Some(format!("Failed to run cargo fmt: {err}")),
+        };
+        if let Some(error) = error {
+            return Ok((report, FmtResult { error: Some(error), ..Default::default() }));
+        }
+
+        let mut result = FmtResult { written: write, ..Default::default() };
+        for path in candidates {
+            let relative = path.strip_prefix(copy).unwrap_or(&path);
+            let original = scratch.original().join(relative);
+            let Ok(contents) = fs::read_to_string(&path) else { continue };
+            let changed = fs::read_to_string(&original).ok().as_ref() != Some(&contents);
+            if files.is_none() && !changed {
+                continue;
+            }
+            if write && changed {
+                fs::write(&original, &contents).map_err(FluxRunnerError::Copy)?;
+            }
+            let file_name = relative.to_string_lossy().into_owned();
+            result
+                .files
+                .push(FormattedFile { file_name, contents, changed });
+        }
+        Ok((report, result))
+    }
+
+    /// Applies the unified diff `patch` to a scratch copy of the repository with `git apply` and
+    /// verifies the result. Hunks that don't apply are left out and returned with the report.
+    /// The repository itself is never touched.
+    pub async fn verify_patch(
+        &self,
+        repo_path: &str,
+        patch: &str,
+    ) -> Result<(VerificationReport, Vec<RejectedHunk>), FluxRunnerError> {
+        let scratch = ScratchRepo::from_path(repo_path).await?;
+        let copy = scratch.path();
+
+        let patch_file = tempfile::NamedTempFile::new().map_err(FluxRunnerError::TempDir)?;
+        fs::write(patch_file.path(), patch).map_err(FluxRunnerError::Copy)?;
+        let output = tokio::process::Command::new("git")
+            .args(["apply", "--reject", "--verbose"])
+            .arg(patch_file.path())
+            .current_dir(copy)
+            .output()
+            .await
+            .map_err(FluxRunnerError::Git)?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let Some(rejected) = rejected_hunks(&stderr) else {
+            return Err(FluxRunnerError::InvalidPatch(stderr.trim().to_string()));
+        };
+
+        let report = self
+            .verify_repository(&copy.to_string_lossy(), false)
+            .await?;
+        Ok((report, rejected))
+    }
+
+    /// Fetches `git_ref`, or the default branch, of the remote repository at `repo_url` into a
+    /// temporary directory with a shallow clone and verifies it. The fetch is killed once it
+    /// exceeds `clone_timeout` or `max_clone_bytes`, and the report records the commit checked
+    /// out.
+    pub async fn verify_git(
+        &self,
+        repo_url: &str,
+        git_ref: Option<&str>,
+    ) -> Result<VerificationReport, FluxRunnerError> {
+        git::validate_url(repo_url)?;
+        if let Some(git_ref) = git_ref {
+            git::validate_ref(git_ref)?;
+        }
+        let checkout = tempfile::tempdir().map_err(FluxRunnerError::TempDir)?;
+        let limits = git::FetchLimits {
+            timeout: self.config.clone_timeout,
+            max_bytes: self.config.max_clone_bytes,
+        };
+        let (url, git_ref, dir) =
+            (repo_url.to_string(), git_ref.map(str::to_string), checkout.path().to_path_buf());
+        let commit = tokio::task::spawn_blocking(move || {
+            git::shallow_fetch(&url, git_ref.as_deref(), &dir, limits)
+        })
+        .await
+        .map_err(|err| FluxRunnerError::Clone(err.to_string()))??;
+
+        let mut report = self
+            .verify_repository(&checkout.path().to_string_lossy(), false)
+            .await?;
+        report.commit = Some(commit);
+        Ok(report)
+    }
+
+    /// Finds a minimal subset of `calls` that still makes the repository verify, by dropping
+    /// each call in turn and keeping it out if verification still passes. Every attempt runs on
+    /// a scratch copy through [`FluxRunner::verify_with_extra_lemmas`], and at most
+    /// `max_attempts` are made.
+    pub async fn minimize_lemmas(
+        &self,
+        repo_path: &str,
+        calls: &[LemmaCall],
+        max_attempts: usize,
+    ) -> Result<MinimizedLemmas, FluxRunnerError> {
+        let start = Instant::now();
+        let mut attempts = 0;
+        let mut result = MinimizedLemmas {
+            success: false,
+            calls: vec![],
+            attempts: 0,
+            exhausted: false,
+            duration_ms: 0,
+        };
+        if max_attempts > 0 {
+            attempts += 1;
+            let all = self.verify_with_extra_lemmas(repo_path, calls).await?;
+            result.success = all.success;
+            if all.success {
+                // Calls that weren't inserted can't have helped.
+                result.calls = all
+                    .calls
+                    .into_iter()
+                    .filter(|call| call.accepted)
+                    .map(|call| call.call)
+                    .collect();
+            }
+        } else {
+            result.exhausted = true;
+        }
+
+        let mut i = 0;
+        while result.success && i < result.calls.len() {
+            if attempts == max_attempts {
+                result.exhausted = true;
+                break;
+            }
+            attempts += 1;
+            let mut without = result.calls.clone();
+            without.remove(i);
+            if self
+                .verify_with_extra_lemmas(repo_path, &without)
+                .await?
+                .success
+            {
+                result.calls = without;
+            } else {
+                i += 1;
+            }
+        }
+        result.attempts = attempts;
+        result.duration_ms = start.elapsed().as_millis() as u64;
+        Ok(result)
+    }
+
+    /// Lists the items of the repository that flux trusts instead of checking, i.e. those marked
+    /// `trusted` or `opaque`.
+    pub async fn list_trusted(&self, repo_path: &str) -> Vec<TrustedItem> {
+        source::find_trusted(Path::new(repo_path))
+    }
+
+    /// The edit marking the item `diagnostic` is reported in as `#[flux::trusted]`, which
+    /// silences it by giving up on checking the item. Span paths are resolved against the cargo
+    /// root of `repo_path`, as flux reports them.
+    pub async fn suggest_trusted_annotation(
+        &self,
+        repo_path: &str,
+        diagnostic: &Diagnostic,
+    ) -> Result<TrustedAnnotation, FluxRunnerError> {
+        let (_, root, _) = Self::resolve_repository(repo_path, RepoConfig::default())?;
+        let span = diagnostic
+            .message
+            .primary_span()
+            .ok_or_else(|| FluxRunnerError::NoEnclosingItem("no primary span".to_string()))?;
+        let (path, item) =
+            diagnostics::enclosing_item_range(span, &root, &mut diagnostics::ItemCache::default())
+                .ok_or_else(|| {
+                    FluxRunnerError::NoEnclosingItem(format!(
+                        "{}:{}:{}",
+                        span.file_name, span.line_start, span.column_start
+                    ))
+                })?;
+        let contents =
+            fs::read_to_string(&path).map_err(|_| FluxRunnerError::InvalidSource(path.clone()))?;
+        let item_line = contents
+            .lines()
+            .nth(item.start_line - 1)
+            .unwrap_or_default();
+        let indent = &item_line[..item_line.len() - item_line.trim_start().len()];
+        let unverified = source::item_ranges(&path)
+            .into_iter()
+            .filter(|inner| {
+                item.start_line <= inner.start_line
+                    && inner.end_line <= item.end_line
+                    && !inner.name.starts_with("impl ")
+            })
+            .map(|inner| inner.name)
+            .collect();
+        let warning = format!(
+            "Escape hatch, not a fix: trusting `{}` silences the error by no longer checking it, so \
+             flux guarantees nothing about the items listed in `unverified`, and their callers rely \
+             on signatures that are assumed rather than proved. Prefer a lemma or spec change that \
+             makes the check pass",
+            item.name
+        );
+        Ok(TrustedAnnotation {
+            file_name: source::display_path(&root, &path),
+            insert_line: item.start_line,
+            text: format!("{indent}#[flux::trusted]"),
+            item: item.name,
+            unverified,
+            warning,
+        })
+    }
+
+    /// Lists the calls to `assume` and `assert` in the repository, the facts stated inside bodies
+    /// rather than in signatures.
+    pub async fn list_assumptions(&self, repo_path: &str) -> Vec<AssumptionSite> {
+        source::find_assumptions(Path::new(repo_path))
+    }
+
+    /// Lists the calls to `flux_rs::assert` in the repository, the obligations stated inside
+    /// bodies.
+    pub async fn list_inline_asserts(&self, repo_path: &str) -> Vec<AssumptionSite> {
+        let mut sites = self.list_assumptions(repo_path).await;
+        sites.retain(|site| site.intrinsic == "assert");
+        sites
+    }
+
+    /// Verifies the repository and tells for each inline `assert` whether it holds: it fails
+    /// when an error's primary span covers its line.
+    pub async fn inline_assert_status(
+        &self,
+        repo_path: &str,
+    ) -> Result<Vec<InlineAssert>, FluxRunnerError> {
+        let sites = self.list_inline_asserts(repo_path).await;
+        if sites.is_empty() {
+            return Ok(vec![]);
+        }
+        let report = self.verify_repository(repo_path, false).await?;
+        let same_file = |a: &str, b: &str| Path::new(a).ends_with(b) || Path::new(b).ends_with(a);
+        let trusted: Vec<TrustedItem> = source::find_trusted(Path::new(repo_path))
+            .into_iter()
+            .filter(|trusted| trusted.attribute == "trusted")
+            .collect();
+        let mut items: HashMap<String, Vec<source::ItemRange>> = HashMap::new();
+        let complete = report.ice.is_none() && !report.truncated;
+        Ok(sites
+            .into_iter()
+            .map(|site| {
+                let failing = report.diagnostics.iter().find(|diagnostic| {
+                    diagnostic.message.level == "error"
+                        && diagnostic
+                            .message
+                            .spans
+                            .iter()
+                            .filter(|span| span.is_primary)
+                            .map(diagnostics::user_facing_span)
+                            .any(|span| {
+                                same_file(&span.file_name, &site.file_name)
+                                    && span.line_start as usize <= site.line
+                                    && site.line <= span.line_end as usize
+                            })
+                });
+                let ranges = items.entry(site.file_name.clone()).or_insert_with(|| {
+                    source::item_ranges(&Path::new(repo_path).join(&site.file_name))
+                });
+                let in_trusted = ranges
+                    .iter()
+                    .filter(|item| item.start_line <= site.line && site.line <= item.end_line)
+                    .any(|item| {
+                        trusted.iter().any(|trusted| {
+                            trusted.name == item.name && trusted.file_name == site.file_name
+                        })
+                    });
+                let status = match failing {
+                    Some(_) => AssertStatus::Failing,
+                    None if in_trusted || !complete => AssertStatus::NotChecked,
+                    None => AssertStatus::Ok,
+                };
+                InlineAssert {
+                    file_name: site.file_name,
+                    line: site.line,
+                    expr: site.expr,
+                    status,
+                    message: failing.map(|diagnostic| diagnostic.message.message.clone()),
+                }
+            })
+            .collect())
+    }
+
+    /// The trusted and opaque items and the assumed facts of the repository, sorted.
+    pub fn trust_surface(repo_path: &str) -> Vec<TrustSurfaceItem> {
+        let repo_path = Path::new(repo_path);
+        let mut surface: Vec<_> = source::find_trusted(repo_path)
+            .into_iter()
+            .map(|item| {
+                TrustSurfaceItem {
+                    kind: item.attribute,
+                    name: item.name,
+                    file_name: item.file_name,
+                }
+            })
+            .chain(
+                source::find_assumptions(repo_path)
+                    .into_iter()
+                    .filter(|site| site.intrinsic == "assume")
+                    .map(|site| {
+                        TrustSurfaceItem {
+                            kind: site.intrinsic,
+                            name: site.expr,
+                            file_name: site.file_name,
+                        }
+                    }),
+            )
+            .collect();
+        surface.sort();
+        surface
+    }
+
+    /// Compares the trust surface of the repository against the baseline at `baseline_path`,
+    /// relative to `repo_path`, or records it there with `update`. The surface is compared as a
+    /// multiset, so a second identical `assume` in a file counts as new.
+    pub async fn check_trust_budget(
+        &self,
+        repo_path: &str,
+        baseline_path: Option<&str>,
+        update: bool,
+    ) -> Result<TrustBudget, FluxRunnerError> {
+        let repo = Path::new(repo_path);
+        if !repo.is_dir() {
+            return Err(FluxRunnerError::InvalidRepoPath(repo.to_path_buf()));
+        }
+        let baseline_path = repo.join(baseline_path.unwrap_or(TRUST_BASELINE_FILE));
+        let current = Self::trust_surface(repo_path);
+        if update {
+            let json = serde_json::to_string_pretty(&current).unwrap();
+            fs::write(&baseline_path, json).map_err(FluxRunnerError::Snapshot)?;
+        }
+        let baseline: Vec<TrustSurfaceItem> = match fs::read_to_string(&baseline_path) {
+            Ok(contents) => {
+                serde_json::from_str(&contents).map_err(|err| {
+                    FluxRunnerError::Config(format!("{}: {err}", baseline_path.display()))
+                })?
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(FluxRunnerError::Config(format!(
+                    "No trust baseline at {}, record one with `update`",
+                    baseline_path.display()
+                )));
+            }
+            Err(err) => return Err(FluxRunnerError::Snapshot(err)),
+        };
+        let mut removed = baseline.clone();
+        let mut added = vec![];
+        for item in &current {
+            match removed.iter().position(|allowed| allowed == item) {
+                Some(i) => {
+                    removed.remove(i);
+                }
+                None => added.push(item.clone()),
+            }
+        }
+        Ok(TrustBudget {
+            ok: added.is_empty(),
+            added,
+            removed,
+            baseline_size: baseline.len(),
+            current_size: current.len(),
+        })
+    }
+
+    /// Verifies the repository and returns the functions with a flux signature that passed:
+    /// those without an error inside them and not trusted. When an error can't be placed in a
+    /// function, or flux failed without reporting one, nothing is known to have passed and the
+    /// list is empty.
+    pub async fn verified_items(&self, repo_path: &str) -> Result<Vec<String>, FluxRunnerError> {
+        let report = self.verify_repository(repo_path, false).await?;
+        let repo = Path::new(repo_path)
+            .canonicalize()
+            .map_err(|_| FluxRunnerError::InvalidRepoPath(PathBuf::from(repo_path)))?;
+        let root = report
+            .resolved_root
+            .as_deref()
+            .map_or_else(|| repo.clone(), PathBuf::from);
+        let mut failed = HashSet::new();
+        for diagnostic in &report.diagnostics {
+            if diagnostic.message.parsed_level < Level::Error {
+                continue;
+            }
+            let (Some(span), Some(item)) =
+                (diagnostic.message.primary_span(), &diagnostic.enclosing_item)
+            else {
+                return Ok(vec![]);
+            };
+            failed.insert((lsp::resolve_path(&root, &span.file_name), item.clone()));
+        }
+        if !report.success && failed.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let trusted: HashSet<(String, String)> = source::find_trusted(&repo)
+            .into_iter()
+            .map(|item| (item.file_name, item.name))
+            .collect();
+        let mut verified = vec![];
+        for path in source::rust_files(&repo) {
+            let file_name = source::display_path(&repo, &path);
+            for sig in source::file_sigs(&path).unwrap_or_default() {
+                let key = (path.clone(), sig.name);
+                if !failed.contains(&key) && !trusted.contains(&(file_name.clone(), key.1.clone()))
+                {
+                    verified.push(key.1);
+                }
+            }
+        }
+        Ok(verified)
+    }
+
+    /// The lemmas whose `requires` or `ensures` clauses mention `symbol`, e.g. `head`. A
+    /// qualified symbol like `seq::head` matches on its last segment, since that's how clauses
+    /// refer to it.
+    pub async fn lemmas_about(
+        &self,
+        repo_path: &str,
+        symbol: &str,
+    ) -> Result<Vec<Lemma>, FluxRunnerError> {
+        let symbol = short_name(symbol);
+        let mut lemmas = self.get_lemmas(repo_path, None).await?;
+        lemmas.retain(|lemma| {
+            lemma
+                .requires
+                .iter()
+                .chain(&lemma.ensures)
+                .any(|clause| source::text_idents(clause).contains(symbol))
+        });
+        Ok(lemmas)
+    }
+
+    /// The lemmas whose signature involves a value of the sort `sort_name`, e.g. `ISeq`: it is
+    /// named in the signature, or the signature mentions a `defs!` function taking or returning
+    /// it, or a type refined by it with `#[flux::refined_by(...)]`.
+    pub async fn lemmas_for_sort(
+        &self,
+        repo_path: &str,
+        sort_name: &str,
+    ) -> Result<Vec<Lemma>, FluxRunnerError> {
+        let sort = short_name(sort_name);
+        let mentions_sort = |text: &str| source::text_idents(text).contains(sort);
+        let mut lemmas = self.get_lemmas(repo_path, None).await?;
+        let sig_idents = {
+            let names = lemmas.iter().map(|lemma| short_name(&lemma.name)).collect();
+            source::sig_idents_of(Path::new(repo_path), &names)
+        };
+        let sort_funcs: HashSet<String> = self
+            .get_definitions(repo_path)
+            .await
+            .into_iter()
+            .filter(|def| {
+                def.return_sort.as_deref().is_some_and(mentions_sort)
+                    || def.params.iter().any(|param| mentions_sort(param))
+            })
+            .map(|def| def.name)
+            .collect();
+        // Whether each type named in a signature is refined by the sort, looked up once.
+        let mut refined_types: HashMap<String, bool> = HashMap::new();
+        lemmas.retain(|lemma| {
+            let Some(idents) = sig_idents.get(short_name(&lemma.name)) else { return false };
+            idents.iter().any(|ident| {
+                ident == sort
+                    || sort_funcs.contains(ident)
+                    || (ident.starts_with(char::is_uppercase)
+                        && *refined_types.entry(ident.clone()).or_insert_with(|| {
+                            source::find_refined_by(Path::new(repo_path), ident).is_some_and(
+                                |refined_by| {
+                                    refined_by
+                                        .fields
+                                        .iter()
+                                        .any(|(_, sort)| mentions_sort(sort))
+                                },
+                            )
+                        }))
+            })
+        });
+        Ok(lemmas)
+    }
+
+    /// Pairs every lemma with the number of times it is used in the sources of `repo_path`.
+    /// Lemmas used zero times are candidates for removal.
+    pub async fn lemma_usage(
+        &self,
+        repo_path: &str,
+    ) -> Result<Vec<(Lemma, usize)>, FluxRunnerError> {
+        let lemmas = self.get_lemmas(repo_path, None).await?;
+        let names = lemmas.iter().map(|lemma| short_name(&lemma.name)).collect();
+        let counts = source::use_counts(Path::new(repo_path), &names);
+        Ok(lemmas
+            .into_iter()
+            .map(|lemma| {
+                let count = counts.get(short_name(&lemma.name)).copied().unwrap_or(0);
+                (lemma, count)
+            })
+            .collect())
+    }
+
+    /// Verifies the repository with constraint dumps enabled and returns the simplified
+    /// constraints of every function, or only those whose path is or ends with `function`
+    /// (e.g. `push` or `Foo::push`).
+    pub async fn get_constraints(
+        &self,
+        repo_path: &str,
+        function: Option<&str>,
+    ) -> Result<Vec<ConstraintDump>, FluxRunnerError> {
+        let log_dir = tempfile::tempdir().map_err(FluxRunnerError::TempDir)?;
+        let (_, root, mut config) = Self::resolve_repository(repo_path, RepoConfig::default())?;
+        config.default_flux_flags.get_or_insert_default().extend([
+            "-Fdump-constraint=true".to_string(),
+            format!("-Flog-dir={}", log_dir.path().display()),
+        ]);
+        let cmd = self.repository_command(&root, &config, None)?;
+        self.run_command_with_timeout(cmd, config.timeout(), |_| {})
+            .await?;
+
+        let function = function.map(|function| function.replace("::", "-"));
+        let mut dumps = Vec::new();
+        for entry in fs::read_dir(log_dir.path())
+            .map_err(FluxRunnerError::TempDir)?
+            .flatten()
+        {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some((name, kind)) = parse_constraint_file_name(&file_name) else { continue };
+            if let Some(function) = &function
+                && name != *function
+                && !name.ends_with(&format!("-{function}"))
+            {
+                continue;
+            }
+            let Ok(constraint_text) = fs::read_to_string(entry.path()) else { continue };
+            dumps.push(ConstraintDump { function: name.to_string(), kind, constraint_text });
+        }
+        dumps.sort_by(|a, b| (&a.function, a.kind).cmp(&(&b.function, b.kind)));
+        Ok(dumps)
+    }
+
+    /// Verifies the repository with flux's constraint and checker trace dumps enabled, and
+    /// writes every dump into `out_log`, relative to `repo_path` unless absolute, each file under
+    /// a `==> name <==` header. Returns the path of the log and the report as
+    /// [`Self::verify_repository_with`] makes it, so the dumps never end up in a response.
+    pub async fn debug_verify(
+        &self,
+        repo_path: &str,
+        out_log: &Path,
+    ) -> Result<(PathBuf, VerificationReport), FluxRunnerError> {
+        let log_dir = tempfile::tempdir().map_err(FluxRunnerError::TempDir)?;
+        let (requested, _, config) = Self::resolve_repository(repo_path, RepoConfig::default())?;
+        let mut flux_flags = config.default_flux_flags.unwrap_or_default();
+        flux_flags.extend(DEBUG_FLUX_FLAGS.iter().map(|flag| flag.to_string()));
+        flux_flags.push(format!("-Flog-dir={}", log_dir.path().display()));
+        let overrides = RepoConfig { default_flux_flags: Some(flux_flags), ..Default::default() };
+        let report = self
+            .verify_repository_with(repo_path, RunOptions::default(), overrides)
+            .await?;
+
+        let mut dumps: Vec<_> = fs::read_dir(log_dir.path())
+            .map_err(FluxRunnerError::TempDir)?
+            .flatten()
+            .map(|entry| entry.path())
+            .collect();
+        dumps.sort();
+        let mut log = String::new();
+        for dump in dumps {
+            let Ok(contents) = fs::read_to_string(&dump) else { continue };
+            let name = dump.file_name().unwrap_or_default().to_string_lossy();
+            log.push_str(&format!("==> {name} <==\n{contents}\n"));
+        }
+        let out_log = requested.join(out_log);
+        fs::write(&out_log, log).map_err(FluxRunnerError::WriteLog)?;
+        Ok((out_log, report))
+    }
+
+    /// Builds the graph of how the lemmas of `repo_path` refer to each other.
+    pub async fn lemma_graph(&self, repo_path: &str) -> Result<LemmaGraph, FluxRunnerError> {
+        let nodes = self.get_lemmas(repo_path, None).await?;
+        let names: BTreeSet<&str> = nodes.iter().map(|lemma| short_name(&lemma.name)).collect();
+        let references = source::fn_references(Path::new(repo_path), &names);
+        let mut edges = vec![];
+        for from in &nodes {
+            let Some(targets) = references.get(short_name(&from.name)) else { continue };
+            for to in &nodes {
+                if to.name != from.name && targets.contains(short_name(&to.name)) {
+                    edges.push(LemmaEdge { from: from.name.clone(), to: to.name.clone() });
+                }
+            }
+        }
+        Ok(LemmaGraph { nodes, edges })
+    }
+
+    /// Returns the file defining the lemma `lemma_name`, or `None` if there is no such lemma.
+    /// Lemmas are matched by their full or unqualified name.
+    pub async fn lemma_context(
+        &self,
+        repo_path: &str,
+        lemma_name: &str,
+    ) -> Result<Option<LemmaContext>, FluxRunnerError> {
+        let lemmas = self.get_lemmas(repo_path, None).await?;
+        let Some(lemma) = lemmas
+            .into_iter()
+            .find(|lemma| lemma.name == lemma_name || short_name(&lemma.name) == lemma_name)
+        else {
+            return Ok(None);
+        };
+        let path = lsp::resolve_path(Path::new(repo_path), &lemma.file_name);
+        let mut contents = fs::read_to_string(&path).map_err(FluxRunnerError::Read)?;
+        let truncated = contents.len() > MAX_CONTEXT_BYTES;
+        if truncated {
+            // Cut at the last line break before the limit so no line is left half-way.
+            let end = contents[..contents.floor_char_boundary(MAX_CONTEXT_BYTES)]
+                .rfind('\n')
+                .map_or(0, |i| i + 1);
+            contents.truncate(end);
+        }
+        Ok(Some(LemmaContext {
+            start_line: lemma.start_line,
+            end_line: lemma.end_line,
+            lemma,
+            contents,
+            truncated,
+        }))
+    }
+
+    /// Whether the goal at `line` of `file_path`, relative to `repo_path` unless absolute, e.g.
+    /// an assertion or a returned value, is discharged. It fails if an error's primary span
+    /// contains the line. Lines outside of any item or inside a trusted one aren't checked,
+    /// and neither is anything when flux crashed or was cut off.
+    pub async fn goal_status(
+        &self,
+        repo_path: &str,
+        file_path: &str,
+        line: usize,
+    ) -> Result<GoalStatus, FluxRunnerError> {
+        let path = Path::new(repo_path).join(file_path);
+        let enclosing: Vec<_> = source::item_ranges(&path)
+            .into_iter()
+            .filter(|item| item.start_line <= line && line <= item.end_line)
+            .collect();
+        if enclosing.is_empty() {
+            let reason = format!("{file_path}:{line} isn't inside an item");
+            return Ok(GoalStatus::NotChecked { reason });
+        }
+        let same_file = |file_name: &str| {
+            Path::new(file_name).ends_with(file_path) || Path::new(file_path).ends_with(file_name)
+        };
+        let trusted = source::find_trusted(Path::new(repo_path))
+            .into_iter()
+            .filter(|trusted| trusted.attribute == "trusted" && same_file(&trusted.file_name))
+            .find(|trusted| enclosing.iter().any(|item| item.name == trusted.name));
+        if let Some(trusted) = trusted {
+            let reason = format!("`{}` is trusted, so its body isn't checked", trusted.name);
+            return Ok(GoalStatus::NotChecked { reason });
+        }
+
+        let report = self.verify_repository(repo_path, false).await?;
+        let failing = report.diagnostics.iter().find(|diagnostic| {
+            diagnostic.message.level == "error"
+                && diagnostic
+                    .message
+                    .spans
+                    .iter()
+                    .filter(|span| span.is_primary)
+                    .map(diagnostics::user_facing_span)
+                    .any(|span| {
+                        same_file(&span.file_name)
+                            && span.line_start as usize <= line
+                            && line <= span.line_end as usize
+                    })
+        });
+        if let Some(diagnostic) = failing {
+            return Ok(GoalStatus::Failed { diagnostic: Box::new(diagnostic.clone()) });
+        }
+        if report.ice.is_some() || report.truncated {
+            let reason = "flux stopped before checking everything".to_string();
+            return Ok(GoalStatus::NotChecked { reason });
+        }
+        Ok(GoalStatus::Discharged)
+    }
+
+    /// Suggests `requires` clauses for the caller of the call `diagnostic` points at, when flux
+    /// couldn't prove the callee's precondition there. Each precondition of the callee's
+    /// signature is rewritten in terms of the caller's refinement parameters by following the
+    /// arguments of the call, and those the caller already requires are left out.
+    pub async fn suggest_precondition(
+        &self,
+        repo_path: &str,
+        diagnostic: &Diagnostic,
+    ) -> Result<PreconditionSuggestions, FluxRunnerError> {
+        let (_, root, _) = Self::resolve_repository(repo_path, RepoConfig::default())?;
+        let span = diagnostic
+            .message
+            .primary_span()
+            .map(diagnostics::user_facing_span)
+            .ok_or_else(|| FluxRunnerError::NotACall("no primary span".to_string()))?;
+        let location = format!("{}:{}:{}", span.file_name, span.line_start, span.column_start);
+        let call = diagnostics::span_text(&span, &root)
+            .and_then(|text| source::parse_call(&text))
+            .ok_or_else(|| FluxRunnerError::NotACall(location.clone()))?;
+        let callee = source::fn_params(&root, &call.callee)
+            .filter(|callee| !callee.binders.is_empty())
+            .ok_or_else(|| FluxRunnerError::NotACall(location.clone()))?;
+        let (_, item) =
+            diagnostics::enclosing_item_range(&span, &root, &mut diagnostics::ItemCache::default())
+                .ok_or(FluxRunnerError::NoEnclosingItem(location))?;
+        let caller = source::fn_params(&root, short_name(&item.name)).unwrap_or_default();
+
+        // The refinement parameter of each of the caller's arguments that binds exactly one.
+        let caller_binders: HashMap<&str, &str> = caller
+            .names
+            .iter()
+            .zip(&caller.binders)
+            .filter_map(|(name, binders)| {
+                match binders.as_slice() {
+                    [binder] => Some((name.as_str(), binder.as_str())),
+                    _ => None,
+                }
+            })
+            .collect();
+        // What each of the callee's refinement parameters is at the call.
+        let mut bound: HashMap<&str, (String, Confidence)> = HashMap::new();
+        for (binders, arg) in callee.binders.iter().zip(&call.args) {
+            let [binder] = binders.as_slice() else { continue };
+            let arg = arg
+                .trim_start_matches("&mut ")
+                .trim_start_matches('&')
+                .trim();
+            let value = match caller_binders.get(arg) {
+                Some(caller_binder) => (caller_binder.to_string(), Confidence::High),
+                None => {
+                    let text = substitute_idents(arg, |ident| {
+                        caller_binders.get(ident).map(|binder| binder.to_string())
+                    });
+                    let simple = syn::parse_str::<syn::Ident>(&text).is_ok()
+                        || syn::parse_str::<syn::Lit>(&text).is_ok();
+                    let text = if simple { text } else { format!("({text})") };
+                    (text, Confidence::Medium)
+                }
+            };
+            bound.insert(binder, value);
+        }
+
+        let normalized = |clause: &str| clause.split_whitespace().collect::<Vec<_>>().join(" ");
+        let existing: HashSet<String> = caller.requires.iter().map(|c| normalized(c)).collect();
+        let callee_binders: HashSet<&str> = callee
+            .binders
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        let mut suggestions: Vec<_> = callee
+            .requires
+            .iter()
+            .filter_map(|clause| {
+                let used: Vec<String> = source::text_idents(clause)
+                    .into_iter()
+                    .filter(|ident| callee_binders.contains(ident.as_str()))
+                    .collect();
+                if used.is_empty() {
+                    return None;
+                }
+                let confidence = used
+                    .iter()
+                    .map(|ident| {
+                        bound
+                            .get(ident.as_str())
+                            .map_or(Confidence::Low, |(_, c)| *c)
+                    })
+                    .max()
+                    .unwrap_or(Confidence::Low);
+                let requires = normalized(&substitute_idents(clause, |ident| {
+                    bound.get(ident).map(|(value, _)| value.clone())
+                }));
+                if existing.contains(&requires) {
+                    return None;
+                }
+                let bindings: Vec<String> = used
+                    .iter()
+                    .filter_map(|ident| {
+                        let (value, _) = bound.get(ident.as_str())?;
+                        Some(format!("`{ident}` is `{value}`"))
+                    })
+                    .collect();
+                let mut rationale = format!("`{}` requires `{clause}`", call.callee);
+                if !bindings.is_empty() {
+                    rationale.push_str(&format!(", and at the call {}", bindings.join(", ")));
+                }
+                Some(PreconditionSuggestion { requires, confidence, rationale })
+            })
+            .collect();
+        suggestions.sort_by_key(|suggestion| suggestion.confidence);
+        Ok(PreconditionSuggestions {
+            caller: item.name,
+            callee: call.callee,
+            suggestions,
+            warning: "Suggestions, not guarantees: each clause is the callee's precondition \
+                      rewritten along the call's arguments, which can be wrong when an argument \
+                      is computed in the body. A `requires` also moves the obligation to every \
+                      caller of the caller, so check that they can meet it."
+                .to_string(),
+        })
+    }
+
+    /// Verifies the repository and picks the top-most refinement error in it as the next goal,
+    /// or reports that there is none.
+    pub async fn next_goal(&self, repo_path: &str) -> Result<NextGoal, FluxRunnerError> {
+        let mut report = self.verify_repository(repo_path, false).await?;
+        let root = PathBuf::from(report.resolved_root.as_deref().unwrap_or(repo_path));
+        diagnostics::retain_local(&mut report.diagnostics, &root);
+        diagnostics::sort(&mut report.diagnostics, SortOrder::ByLocation);
+        let first = report.diagnostics.iter().find_map(|diagnostic| {
+            let kind = FluxErrorKind::of(&diagnostic.message.message)?;
+            let span = diagnostic.message.primary_span()?;
+            (diagnostic.message.level == "error").then_some((diagnostic, kind, span))
+        });
+        let Some((diagnostic, kind, span)) = first else {
+            let summary = VerificationSummary::of(report.success, &report.diagnostics);
+            return Ok(NextGoal::NoFailingGoals { summary });
+        };
+
+        let snippet = primary_snippet(&root, span);
+        let lemmas = self.get_lemmas(repo_path, None).await?;
+        let sigs = lemma_sig_idents(repo_path, &lemmas);
+        let suggested_lemmas = rank_lemmas(&lemmas, &sigs, &snippet, SUGGESTED_LEMMAS);
+
+        let goal =
+            Goal { diagnostic: diagnostic.clone(), kind: kind.code(), snippet, suggested_lemmas };
+        Ok(NextGoal::Failing { goal: Box::new(goal) })
+    }
+
+    /// Gathers what is needed to work on the [next goal](Self::next_goal) in one bundle: its
+    /// diagnostic, rendering, snippet and suggested lemmas, the source of the item containing
+    /// it, the signatures of the functions that item calls, and the `defs!` items those
+    /// signatures mention. The bundle is kept under `max_chars` characters of JSON by dropping
+    /// the defs, then the lemmas from the least likely, then the callee signatures, and as a
+    /// last resort cutting the item's source at a line break.
+    pub async fn goal_context_bundle(
+        &self,
+        repo_path: &str,
+        max_chars: usize,
+    ) -> Result<GoalContext, FluxRunnerError> {
+        let mut goal = match self.next_goal(repo_path).await? {
+            NextGoal::Failing { goal } => *goal,
+            NextGoal::NoFailingGoals { summary } => {
+                return Ok(GoalContext::NoFailingGoals { summary });
+            }
+        };
+        let (_, root, _) = Self::resolve_repository(repo_path, RepoConfig::default())?;
+        let rendered = match goal.diagnostic.message.rendered.take() {
+            Some(rendered) => rendered,
+            None => diagnostics::render_with_children(&goal.diagnostic, &root),
+        };
+
+        let enclosing = goal.diagnostic.message.primary_span().and_then(|span| {
+            diagnostics::enclosing_item_range(span, &root, &mut diagnostics::ItemCache::default())
+        });
+        let enclosing_item = enclosing.map(|(path, item)| {
+            let contents = fs::read_to_string(&path).unwrap_or_default();
+            let source: Vec<&str> = contents
+                .lines()
+                .skip(item.start_line - 1)
+                .take(item.end_line + 1 - item.start_line)
+                .collect();
+            ItemSource {
+                file_name: source::display_path(&root, &path),
+                start_line: item.start_line,
+                end_line: item.end_line,
+                source: source.join("\n") + "\n",
+                name: item.name,
+            }
+        });
+
+        let mut callee_sigs = vec![];
+        let mut idents = BTreeSet::new();
+        if let Some(item) = &enclosing_item {
+            idents.extend(source::sig_idents(&root, short_name(&item.name)));
+            let callees = self.callees(&root.to_string_lossy(), &item.name, 1).await;
+            let callees = callees.map(|callees| callees.callees).unwrap_or_default();
+            for path in source::rust_files(&root) {
+                for sig in source::file_sigs(&path).unwrap_or_default() {
+                    let called = callees.iter().any(|callee| {
+                        sig.name == *callee || sig.name.ends_with(&format!("::{callee}"))
+                    });
+                    if called {
+                        idents.extend(source::text_idents(&sig.sig_text));
+                        callee_sigs.push(sig);
+                    }
+                }
+            }
+        }
+        let mut defs = self.get_definitions(&root.to_string_lossy()).await;
+        defs.retain(|def| idents.contains(&def.name));
+
+        let mut bundle = GoalContextBundle {
+            goal,
+            rendered,
+            enclosing_item,
+            callee_sigs,
+            defs,
+            truncation: None,
+        };
+        fit_bundle(&mut bundle, max_chars);
+        Ok(GoalContext::Failing { bundle: Box::new(bundle) })
+    }
+
+    /// Verifies the repository and pairs each of its refinement errors with the `top_k` lemmas
+    /// most likely to help, ranked like [`FluxRunner::next_goal`] does. The lemmas come from the
+    /// snapshot at `snapshot`, see [`FluxRunner::get_lemmas_cached`].
+    pub async fn verify_and_suggest(
+        &self,
+        repo_path: &str,
+        top_k: usize,
+        snapshot: &Path,
+    ) -> Result<Vec<Suggestions>, FluxRunnerError> {
+        let mut report = self.verify_repository(repo_path, false).await?;
+        let root = PathBuf::from(report.resolved_root.as_deref().unwrap_or(repo_path));
+        diagnostics::retain_local(&mut report.diagnostics, &root);
+        diagnostics::sort(&mut report.diagnostics, SortOrder::ByLocation);
+        let errors: Vec<_> = report
+            .diagnostics
+            .into_iter()
+            .filter_map(|diagnostic| {
+                let kind = FluxErrorKind::of(&diagnostic.message.message)?;
+                (diagnostic.message.level == "error").then_some((diagnostic, kind))
+            })
+            .collect();
+        if errors.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let lemmas = self.get_lemmas_cached(repo_path, snapshot).await?.lemmas;
+        let sigs = lemma_sig_idents(repo_path, &lemmas);
+        Ok(errors
+            .into_iter()
+            .map(|(diagnostic, kind)| {
+                let suggested_lemmas = match diagnostic.message.primary_span() {
+                    Some(span) => {
+                        let snippet = primary_snippet(&root, span);
+                        rank_lemmas(&lemmas, &sigs, &snippet, top_k)
+                    }
+                    None => vec![],
+                };
+                Suggestions { diagnostic, kind: kind.code(), suggested_lemmas }
+            })
+            .collect())
+    }
+
+    /// Returns the `defs!` items mentioned in the signature of the lemma `lemma_name`. Empty if
+    /// the lemma doesn't exist or mentions none.
+    pub async fn lemma_referenced_defs(&self, repo_path: &str, lemma_name: &str) -> Vec<DefItem> {
+        let idents = source::sig_idents(Path::new(repo_path), lemma_name);
+        if idents.is_empty() {
+            return vec![];
+        }
+        let mut defs = self.get_definitions(repo_path).await;
+        defs.retain(|def| idents.contains(&def.name));
+        defs
+    }
+
+    /// Diffs the source of the lemma `lemma_name`, attributes included, between the git refs
+    /// `from_ref` and `to_ref` of the repository. The lemma is looked up at each ref like
+    /// [`Self::item_range`] does, so it is followed across files; one that only exists at one
+    /// of the refs is reported as added or removed, with a diff from or to nothing.
+    pub async fn lemma_body_diff(
+        &self,
+        repo_path: &str,
+        lemma_name: &str,
+        from_ref: &str,
+        to_ref: &str,
+    ) -> Result<LemmaBodyDiff, FluxRunnerError> {
+        let (repo_path, lemma_name) = (PathBuf::from(repo_path), lemma_name.to_string());
+        let (from_ref, to_ref) = (from_ref.to_string(), to_ref.to_string());
+        // Exporting a tree runs git and writes every file of the ref, on the blocking pool.
+        blocking(move || lemma_body_diff(&repo_path, &lemma_name, &from_ref, &to_ref)).await
+    }
+}
+
+/// See [`FluxRunner::lemma_body_diff`].
+fn lemma_body_diff(
+    repo_path: &Path,
+    lemma_name: &str,
+    from_ref: &str,
+    to_ref: &str,
+) -> Result<LemmaBodyDiff, FluxRunnerError> {
+    let body_at = |git_ref: &str| -> Result<Option<(LineRange, String)>, FluxRunnerError> {
+        git::validate_ref(git_ref)?;
+        let checkout = tempfile::tempdir().map_err(FluxRunnerError::TempDir)?;
+        git::export_tree(repo_path, git_ref, checkout.path())?;
+        let Some(range) = source::find_item(checkout.path(), lemma_name) else {
+            return Ok(None);
+        };
+        let contents =
+            fs::read_to_string(checkout.path().join(&range.file_name)).unwrap_or_default();
+        let mut body: String = contents
+            .lines()
+            .skip(range.start_line - 1)
+            .take(range.end_line + 1 - range.start_line)
+            .collect::<Vec<_>>()
+            .join("\n");
+        body.push('\n');
+        Ok(Some((range, body)))
+    };
+    let (from, to) = (body_at(from_ref)?, body_at(to_ref)?);
+    let label = |git_ref: &str, side: &Option<(LineRange, String)>| {
+        match side {
+            Some((range, _)) => format!("{git_ref}:{}", range.file_name),
+            None => "/dev/null".to_string(),
+        }
+    };
+    let body = |side: &Option<(LineRange, String)>| {
+        side.as_ref()
+            .map_or(String::new(), |(_, body)| body.clone())
+    };
+    let change = match (&from, &to) {
+        (None, None) => return Err(FluxRunnerError::UnknownItem(lemma_name.to_string())),
+        (None, Some(_)) => BodyChange::Added,
+        (Some(_), None) => BodyChange::Removed,
+        (Some((_, before)), Some((_, after))) if before == after => BodyChange::Unchanged,
+        (Some(_), Some(_)) => BodyChange::Changed,
+    };
+    let diff =
+        git::unified_diff(&body(&from), &body(&to), &label(from_ref, &from), &label(to_ref, &to))?;
+    Ok(LemmaBodyDiff {
+        lemma: lemma_name.to_string(),
+        change,
+        from: from.map(|(range, _)| range),
+        to: to.map(|(range, _)| range),
+        diff,
+    })
+}
+
+/// Replaces the identifiers of `text` that `replacement` maps, leaving field names after a `.`
+/// alone.
+fn substitute_idents(text: &str, replacement: impl Fn(&str) -> Option<String>) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(|c: char| c.is_alphabetic() || c == '_') {
+        let (before, from) = rest.split_at(start);
+        // Not an identifier of its own when it continues a number or another word.
+        let glued = res
+            .chars()
+            .chain(before.chars())
+            .last()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let len = from
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(from.len());
+        let (ident, after) = from.split_at(len);
+        res.push_str(before);
+        let field = res.trim_end().ends_with('.');
+        match replacement(ident) {
+            Some(replaced) if !glued && !field => res.push_str(&replaced),
+            _ => res.push_str(ident),
+        }
+        rest = after;
+    }
+    res.push_str(rest);
+    res
+}
+
+/// The source lines covered by the user-facing part of the primary span `span`, or nothing if
+/// the file can't be read.
+fn primary_snippet(root: &Path, span: &DiagnosticSpan) -> String {
+    let span = diagnostics::user_facing_span(span);
+    let path = lsp::resolve_path(root, &span.file_name);
+    fs::read_to_string(path)
+        .map(|contents| {
+            let start = (span.line_start.max(1) - 1) as usize;
+            let len = (span.line_end - span.line_start + 1).max(1) as usize;
+            contents
+                .lines()
+                .skip(start)
+                .take(len)
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .unwrap_or_default()
+}
+
+/// The identifiers in the signature of each of `lemmas`, keyed by their short name.
+fn lemma_sig_idents(repo_path: &str, lemmas: &[Lemma]) -> BTreeMap<String, BTreeSet<String>> {
+    let names = lemmas.iter().map(|lemma| short_name(&lemma.name)).collect();
+    source::sig_idents_of(Path::new(repo_path), &names)
+}
+
+/// The (at most) `k` lemmas whose signatures share the most identifiers with `snippet`, best
+/// first. Lemmas sharing none are left out.
+fn rank_lemmas(
+    lemmas: &[Lemma],
+    sigs: &BTreeMap<String, BTreeSet<String>>,
+    snippet: &str,
+    k: usize,
+) -> Vec<Lemma> {
+    let wanted = source::text_idents(snippet);
+    let mut scored: Vec<_> = lemmas
+        .iter()
+        .filter_map(|lemma| {
+            let sig = sigs.get(short_name(&lemma.name))?;
+            let score = sig.intersection(&wanted).count();
+            (score > 0).then_some((score, lemma))
+        })
+        .collect();
+    // The sort is stable, so lemmas with the same score keep flux's order.
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored
+        .into_iter()
+        .take(k)
+        .map(|(_, lemma)| lemma.clone())
+        .collect()
+}
+
+/// The last segment of a possibly qualified name like `crate::lemmas::head_cons_eq`.
+/// Shrinks `bundle` to at most `max_chars` characters of JSON as
+/// [`FluxRunner::goal_context_bundle`] describes, cutting the item's source a line at a time,
+/// and records what was dropped in its `truncation`. A bound too small for the goal itself
+/// isn't met.
+fn fit_bundle(bundle: &mut GoalContextBundle, max_chars: usize) {
+    // The record of what was dropped counts towards the bound too.
+    let fits = |bundle: &mut GoalContextBundle, dropped: &BundleTruncation| {
+        bundle.truncation = (*dropped != BundleTruncation::default()).then(|| dropped.clone());
+        serde_json::to_string(bundle).map_or(0, |text| text.chars().count()) <= max_chars
+    };
+    let mut dropped = BundleTruncation::default();
+    while !fits(bundle, &dropped) && bundle.defs.pop().is_some() {
+        dropped.dropped_defs += 1;
+    }
+    while !fits(bundle, &dropped) && bundle.goal.suggested_lemmas.pop().is_some() {
+        dropped.dropped_lemmas += 1;
+    }
+    while !fits(bundle, &dropped) && bundle.callee_sigs.pop().is_some() {
+        dropped.dropped_callee_sigs += 1;
+    }
+    const MARKER: &str = "// ...\n";
+    while !fits(bundle, &dropped) {
+        let Some(item) = &mut bundle.enclosing_item else { break };
+        let kept = item.source.strip_suffix(MARKER).unwrap_or(&item.source);
+        if kept.is_empty() {
+            break;
+        }
+        // Drop the last line, the source always ending with a line break.
+        let end = kept[..kept.len() - 1].rfind('\n').map_or(0, |i| i + 1);
+        item.source = format!("{}{MARKER}", &kept[..end]);
+        dropped.source_cut = true;
+    }
+}
+
+/// The package id, as JSON, of a `compiler-artifact` message, which cargo prints for each
+/// target it built.
+fn built_package(line: &str) -> Option<String> {
+    let message: serde_json::Value = serde_json::from_str(line).ok()?;
+    if message.get("reason")?.as_str()? != "compiler-artifact" {
+        return None;
+    }
+    Some(message.get("package_id")?.to_string())
+}
+
+fn short_name(name: &str) -> &str {
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// Compares two sets of lemmas by name, in name order. Lemmas sharing a name are paired up in
+/// the order they were dumped.
+pub fn diff_lemmas(before: &[Lemma], after: &[Lemma]) -> LemmaDiff {
+    let location = |lemma: &Lemma| {
+        (lemma.file_name.clone(), lemma.start_line, lemma.start_col, lemma.end_line, lemma.end_col)
+    };
+    let mut unmatched: BTreeMap<&str, Vec<&Lemma>> = BTreeMap::new();
+    for lemma in before.iter().rev() {
+        unmatched.entry(&lemma.name).or_default().push(lemma);
+    }
+    let mut diff = LemmaDiff::default();
+    let mut after: Vec<&Lemma> = after.iter().collect();
+    after.sort_by(|a, b| a.name.cmp(&b.name));
+    for lemma in after {
+        match unmatched.get_mut(lemma.name.as_str()).and_then(Vec::pop) {
+            Some(old) if location(old) == location(lemma) => diff.unchanged += 1,
+            Some(old) => {
+                diff.moved
+                    .push(MovedLemma { before: old.clone(), after: lemma.clone() });
+            }
+            None => diff.added.push(lemma.clone()),
+        }
+    }
+    diff.removed = unmatched.into_values().flatten().cloned().collect();
+    diff
+}
+
+/// Renders `cmd` as a shell command line, e.g.
+/// `cd /repo && FLUXFLAGS=-Ftimings cargo flux --message-format=json`.
+fn render_command(cmd: &Command) -> String {
+    let mut parts = vec![];
+    if let Some(dir) = cmd.get_current_dir() {
+        parts.push(format!("cd {} &&", shell_quote(&dir.to_string_lossy())));
+    }
+    for (key, value) in cmd.get_envs() {
+        if let Some(value) = value {
+            parts.push(format!(
+                "{}={}",
+                key.to_string_lossy(),
+                shell_quote(&value.to_string_lossy())
+            ));
+        }
+    }
+    parts.push(shell_quote(&cmd.get_program().to_string_lossy()));
+    parts.extend(
+        cmd.get_args()
+            .map(|arg| shell_quote(&arg.to_string_lossy())),
+    );
+    parts.join(" ")
+}
+
+/// Quotes `word` for a POSIX shell if it contains anything but safe characters.
+fn shell_quote(word: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:@,+".contains(c);
+    if !word.is_empty() && word.chars().all(safe) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// The target of the per-line logging of flux's output, which can be silenced on its own with
+/// `RUST_LOG=flux_mcp::parse=off` or `FLUX_MCP_QUIET_PARSE=1`.
+pub const PARSE_LOG_TARGET: &str = "flux_mcp::parse";
+
+/// The file name under which [`FluxRunner::verify_source`] reports the verified source.
+pub const SOURCE_FILE_NAME: &str = "source.rs";
+
+/// Writes the manifest of a scratch package in `dir` whose library is the file `lib`, with flux
+/// enabled and, if given, a dependency on `flux_rs`.
+fn write_scratch_manifest(dir: &Path, lib: &Path, flux_rs: Option<&Path>) -> io::Result<()> {
+    let mut manifest = format!(
+        "[package]\nname = \"scratch\"\nversion = \"0.0.0\"\nedition = \"2021\"\n\n\
+         [lib]\npath = {:?}\n\n[package.metadata.flux]\nenabled = true\n",
+        lib.display().to_string(),
+    );
+    if let Some(flux_rs) = flux_rs {
+        manifest.push_str(&format!(
+            "\n[dependencies]\nflux-rs = {{ path = {:?} }}\n",
+            flux_rs.display().to_string()
+        ));
+    }
+    fs::write(dir.join("Cargo.toml"), manifest)
+}
+
+/// Kills the process group led by `pid`.
+#[cfg(unix)]
+fn kill_process_group(pid: u32) {
+    let Ok(pid) = libc::pid_t::try_from(pid) else { return };
+    // SAFETY: `kill` has no memory safety requirements, at worst the group no longer exists.
+    unsafe {
+        libc::kill(-pid, libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_process_group(pid: u32) {
+    let _ = Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &pid.to_string()])
+        .status();
+}
+
+/// Caps the address space of `cmd`, and of the processes it spawns, at `max_mb` megabytes.
+#[cfg(unix)]
+fn limit_memory(cmd: &mut Command, max_mb: u64) {
+    let max_bytes = max_mb.saturating_mul(1024 * 1024) as libc::rlim_t;
+    let limit = libc::rlimit { rlim_cur: max_bytes, rlim_max: max_bytes };
+    // SAFETY: the hook runs between fork and exec, where it only calls `setrlimit`, which is
+    // async-signal-safe, on a copy of `limit`.
+    unsafe {
+        std::os::unix::process::CommandExt::pre_exec(cmd, move || {
+            if libc::setrlimit(libc::RLIMIT_AS, &limit) == 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn limit_memory(_cmd: &mut Command, max_mb: u64) {
+    tracing::warn!("Ignoring the {max_mb}MB memory limit, only supported on Unix");
+}
+
+/// Whether a failed run looks like it hit the memory limit: a process either failed to allocate
+/// and said so, or was killed or aborted because of it.
+fn ran_out_of_memory(status: std::process::ExitStatus, stderr: &str) -> bool {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status)
+        && matches!(signal, libc::SIGKILL | libc::SIGABRT | libc::SIGSEGV)
+    {
+        return true;
+    }
+    #[cfg(not(unix))]
+    let _ = status;
+    ["memory allocation of", "out of memory", "std::bad_alloc", "Cannot allocate memory"]
+        .iter()
+        .any(|marker| stderr.contains(marker))
+}
+
+/// The lines most useful in a bug report, starting from where `stderr` shows rustc or flux
+/// panicking, if it does.
+fn ice_from_stderr(stderr: &str) -> Option<String> {
+    const MARKERS: &[&str] =
+        &["error: internal compiler error", "thread 'rustc' panicked", "query stack during panic"];
+    const MAX_LINES: usize = 20;
+    let lines: Vec<&str> = stderr.lines().collect();
+    let start = lines
+        .iter()
+        .position(|line| MARKERS.iter().any(|marker| line.contains(marker)))?;
+    let end = (start + MAX_LINES).min(lines.len());
+    Some(lines[start..end].join("\n"))
+}
+
+/// Resolves a bare program name the way the shell would, by searching `PATH`.
+fn find_in_path(program: &Path) -> Option<PathBuf> {
+    if program.components().count() > 1 {
+        return program.canonicalize().ok();
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+/// The toolchain rustup would pick, e.g. `nightly-2025-10-09-x86_64-unknown-linux-gnu`.
+fn active_toolchain() -> Option<String> {
+    if let Ok(toolchain) = std::env::var("RUSTUP_TOOLCHAIN") {
+        return Some(toolchain);
+    }
+    let output = Command::new("rustup")
+        .args(["show", "active-toolchain"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.split_whitespace().next().map(str::to_string)
+}
+
+/// The hunks `git apply --reject --verbose` reports it couldn't apply, or `None` if it didn't
+/// get as far as checking any file, i.e. the patch is malformed.
+fn rejected_hunks(stderr: &str) -> Option<Vec<RejectedHunk>> {
+    let mut checked = vec![];
+    let mut applied = HashSet::new();
+    let mut rejected = vec![];
+    let mut current = None;
+    for line in stderr.lines() {
+        if let Some(file_name) = line
+            .strip_prefix("Checking patch ")
+            .and_then(|rest| rest.strip_suffix("..."))
+        {
+            checked.push(file_name.to_string());
+        } else if let Some(file_name) = line
+            .strip_prefix("Applied patch ")
+            .and_then(|rest| rest.strip_suffix(" cleanly."))
+        {
+            applied.insert(file_name.to_string());
+        } else if let Some((file_name, _)) = line
+            .strip_prefix("Applying patch ")
+            .and_then(|rest| rest.rsplit_once(" with "))
+        {
+            applied.insert(file_name.to_string());
+            current = Some(file_name.to_string());
+        } else if let Some(hunk) = line
+            .strip_prefix("Rejected hunk #")
+            .and_then(|rest| rest.strip_suffix('.'))
+            .and_then(|hunk| hunk.parse().ok())
+            && let Some(file_name) = &current
+        {
+            rejected.push(RejectedHunk { file_name: file_name.clone(), hunk: Some(hunk) });
+        }
+    }
+    if checked.is_empty() {
+        return None;
+    }
+    let failed = checked
+        .into_iter()
+        .filter(|file_name| !applied.contains(file_name))
+        .map(|file_name| RejectedHunk { file_name, hunk: None });
+    Some(failed.chain(rejected).collect())
+}
+
+/// How much of the end of a command's stderr [`RunStatus`] keeps, which is where cargo and
+/// flux say why they stopped.
+const STDERR_EXCERPT_BYTES: usize = 4096;
+
+/// The end of `stderr`, cut at a line boundary to at most [`STDERR_EXCERPT_BYTES`].
+fn stderr_excerpt(stderr: &str) -> String {
+    let stderr = stderr.trim_end();
+    if stderr.len() <= STDERR_EXCERPT_BYTES {
+        return stderr.to_string();
+    }
+    let mut start = stderr.len() - STDERR_EXCERPT_BYTES;
+    while !stderr.is_char_boundary(start) {
+        start += 1;
+    }
+    let tail = &stderr[start..];
+    let tail = tail.split_once('\n').map_or(tail, |(_, rest)| rest);
+    format!("...\n{tail}")
+}
+
+/// Whether cargo's stderr says it has no `flux` subcommand. Older versions of cargo say
+/// "no such subcommand", newer ones "no such command".
+fn is_missing_subcommand(stderr: &str) -> bool {
+    stderr.lines().any(|line| {
+        (line.contains("no such subcommand") || line.contains("no such command"))
+            && line.contains("flux")
+    })
+}
+
+/// Splits the name of a simplified constraint dump, `<crate>.<item>.simp.fluxc` for a body or
+/// `<crate>.<item>.simp.sub.fluxc` for an impl, into the item and the kind of constraint.
+fn parse_constraint_file_name(file_name: &str) -> Option<(&str, &'static str)> {
+    let (_crate, rest) = file_name.split_once('.')?;
+    if let Some(item) = rest.strip_suffix(".simp.sub.fluxc") {
+        Some((item, "impl"))
+    } else {
+        Some((rest.strip_suffix(".simp.fluxc")?, "body"))
+    }
+}
+
+/// The option flux's stderr says it doesn't know, if any.
+fn unknown_flux_option(stderr: &str) -> Option<String> {
+    stderr.lines().find_map(|line| {
+        let option = line.split_once("unknown flux option: `")?.1;
+        Some(option.split_once('`')?.0.to_string())
+    })
+}
+
+/// The names of the `-F` options mentioned in flux's help text, e.g. `check-overflow` for
+/// `-Fcheck-overflow=<strict|none>`, sorted and without duplicates.
+fn flux_option_names(help: &str) -> Vec<String> {
+    let mut names = BTreeSet::new();
+    for (start, _) in help.match_indices("-F") {
+        let preceded_by_word = help[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '-');
+        if preceded_by_word {
+            continue;
+        }
+        let rest = &help[start + 2..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+            .unwrap_or(rest.len());
+        let name = rest[..len].trim_end_matches('-');
+        if name.starts_with(|c: char| c.is_ascii_lowercase()) {
+            names.insert(name.to_string());
+        }
+    }
+    names.into_iter().collect()
+}
+
+/// Walks upward from `path` looking for the directory cargo should be run from: the nearest
+/// manifest declaring a `[workspace]`, or failing that, the nearest manifest at all.
+pub(crate) fn find_cargo_root(path: &Path) -> Option<PathBuf> {
+    let path = path.canonicalize().ok()?;
+    let mut nearest_package = None;
+    for dir in path.ancestors() {
+        let Ok(manifest) = fs::read_to_string(dir.join("Cargo.toml")) else { continue };
+        let is_workspace = manifest.lines().any(|line| {
+            let line = line.trim();
+            line == "[workspace]" || line.starts_with("[workspace.")
+        });
+        if is_workspace {
+            return Some(dir.to_path_buf());
+        }
+        nearest_package.get_or_insert_with(|| dir.to_path_buf());
+    }
+    nearest_package
+}
+
+fn is_transient(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::ResourceBusy
+            | io::ErrorKind::TimedOut
+    )
+}
+
+#[cfg(all(test, unix))]
+pub(crate) mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt, path::PathBuf};
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    const MESSAGE: &str = r#"{"reason":"compiler-message","package_id":"foo 0.1.0","target":{"name":"foo","kind":["lib"]},"message":{"level":"error","message":"refinement type error","code":null,"rendered":"error: refinement type error","spans":[{"file_name":"src/lib.rs","line_start":3,"column_start":5,"line_end":3,"column_end":9,"is_primary":true}]}}"#;
+
+    /// The line `-Fdump-lemmas` prints for the lemma `name` defined at `line` of `file`.
+    fn lemma_line(name: &str, file: &str, line: usize) -> String {
+        format!(
+            r#"{{"reason":"compiler-message","message":{{"lemma_name":"{name}","file_name":"{file}","start_line":{line},"start_col":0,"end_line":{line},"end_col":3}}}}"#
+        )
+    }
+
+    /// Writes an executable shell script standing in for `cargo` into a fresh temp directory.
+    pub(crate) fn fake_cargo(script: &str) -> (TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cargo");
+        fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        (dir, path)
+    }
+
+    pub(crate) fn fake_runner(script: &str) -> (TempDir, FluxRunner) {
+        let (dir, cargo_path) = fake_cargo(script);
+        (dir, FluxRunner::with_config(FluxRunnerConfig { cargo_path, ..Default::default() }))
+    }
+
+    #[tokio::test]
+    async fn spec_coverage_counts_public_fns_of_the_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let core = dir.path().join("core");
+        fs::create_dir_all(core.join("src")).unwrap();
+        fs::write(
+            core.join("src/lib.rs"),
+            "#[flux::sig(fn(i32[@n]) -> i32[n + 1])]\npub fn incr(n: i32) -> i32 { n + 1 }\n\
+             #[flux::trusted]\npub fn fast() {}\n\
+             fn private() {}\n\
+             pub struct S;\n\
+             impl S {\n    #[flux::sig(fn(&S) -> i32)]\n    pub fn get(&self) -> i32 { 0 }\n    pub(crate) fn hidden(&self) {}\n}\n\
+             impl Clone for S {\n    fn clone(&self) -> S { S }\n}\n\
+             pub trait T {\n    fn required(&self);\n}\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("other.rs"), "pub fn elsewhere() {}\n").unwrap();
+        let manifest = core.join("Cargo.toml");
+        let metadata = format!(
+            r#"{{"packages":[{{"name":"core","manifest_path":"{}","dependencies":[]}}]}}"#,
+            manifest.display()
+        );
+        let (_cargo_dir, runner) = fake_runner(&format!("echo '{metadata}'"));
+        let repo_path = dir.path().to_str().unwrap();
+
+        let coverage = runner.spec_coverage(repo_path, Some("core")).await.unwrap();
+        assert_eq!(coverage, Coverage { total_fns: 4, specced_fns: 2, trusted_fns: 1, ratio: 0.5 });
+        let coverage = runner.spec_coverage(repo_path, None).await.unwrap();
+        assert_eq!(coverage.total_fns, 5);
+        let err = runner
+            .spec_coverage(repo_path, Some("app"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::UnknownPackage(_)));
+    }
+
+    #[tokio::test]
+    async fn the_innermost_package_owns_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::create_dir_all(dir.path().join("crates/core/src")).unwrap();
+        fs::write(dir.path().join("src/main.rs"), "").unwrap();
+        fs::write(dir.path().join("crates/core/src/lib.rs"), "").unwrap();
+        let root = dir.path().display();
+        let metadata = format!(
+            r#"{{"packages":[{{"name":"app","manifest_path":"{root}/Cargo.toml"}},{{"name":"core","manifest_path":"{root}/crates/core/Cargo.toml"}}]}}"#
+        );
+        let (cargo_dir, runner) = fake_runner(&format!(
+            "if [ \"$1\" = metadata ]; then echo '{metadata}'; exit 0; fi\n\
+             echo \"$@\" > \"$(dirname \"$0\")/args\"\nexit 0"
+        ));
+        let repo_path = dir.path().to_str().unwrap();
+
+        let (package, report) = runner
+            .verify_owning_package(repo_path, "crates/core/src/lib.rs")
+            .await
+            .unwrap();
+        assert_eq!(package, "core");
+        assert!(report.success);
+        let args = fs::read_to_string(cargo_dir.path().join("args")).unwrap();
+        assert_eq!(args.trim(), "flux -p core --message-format=json");
+        let main = dir.path().join("src/main.rs");
+        let (package, _) = runner
+            .verify_owning_package(repo_path, main.to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(package, "app");
+        let err = runner
+            .verify_owning_package(repo_path, "/elsewhere/lib.rs")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::NoOwningPackage(_)));
+    }
+
+    #[tokio::test]
+    async fn packages_resolve_by_name_or_id() {
+        let metadata = r#"{"packages":[{"name":"core","id":"path+file:///repo/core#0.1.0","version":"0.1.0","manifest_path":"/repo/core/Cargo.toml"},{"name":"app","id":"path+file:///repo/app#0.2.0","version":"0.2.0","manifest_path":"/repo/app/Cargo.toml"}]}"#;
+        let (dir, runner) = fake_runner(&format!("echo '{metadata}'"));
+        let repo_path = dir.path().to_str().unwrap();
+
+        let by_name = runner.resolve_package(repo_path, "app").await.unwrap();
+        assert_eq!(by_name.id, "path+file:///repo/app#0.2.0");
+        assert_eq!(by_name.manifest_path, "/repo/app/Cargo.toml");
+        let by_id = runner
+            .resolve_package(repo_path, "path+file:///repo/core#0.1.0")
+            .await
+            .unwrap();
+        assert_eq!(by_id.name, "core");
+        assert_eq!(by_id.version.as_deref(), Some("0.1.0"));
+        let err = runner.resolve_package(repo_path, "cli").await.unwrap_err();
+        assert!(matches!(err, FluxRunnerError::UnknownPackage(_)));
+    }
+
+    #[tokio::test]
+    async fn verifying_a_workspace_member_keeps_only_its_diagnostics() {
+        let workspace = tempfile::tempdir().unwrap();
+        let root = workspace.path().canonicalize().unwrap();
+        fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = [\"core\", \"cli\"]\n").unwrap();
+        for member in ["core", "cli"] {
+            fs::create_dir_all(root.join(member).join("src")).unwrap();
+            fs::write(root.join(member).join("Cargo.toml"), "[package]\n").unwrap();
+        }
+        let core = MESSAGE.replace("src/lib.rs", "core/src/lib.rs");
+        let cli = MESSAGE.replace("src/lib.rs", "cli/src/main.rs");
+        let (_cargo_dir, runner) = fake_runner(&format!("echo '{core}'\necho '{cli}'\nexit 1"));
+
+        let report = runner
+            .verify_repository(root.join("core").to_str().unwrap(), false)
+            .await
+            .unwrap();
+        let files: Vec<_> = report
+            .diagnostics
+            .iter()
+            .map(|diag| diag.message.spans[0].file_name.as_str())
+            .collect();
+        assert_eq!(files, ["core/src/lib.rs"]);
+        assert_eq!(report.resolved_root.as_deref(), root.to_str());
+
+        let report = runner
+            .verify_repository(root.to_str().unwrap(), false)
+            .await
+            .unwrap();
+        assert_eq!(report.diagnostics.len(), 2);
+        assert_eq!(report.resolved_root, None);
+    }
+
+    #[tokio::test]
+    async fn slow_stream_consumers_see_every_diagnostic() {
+        let lines: Vec<_> = (1..=20)
+            .map(|line| {
+                let message = MESSAGE
+                    .replace("\"line_start\":3", &format!("\"line_start\":{line}"))
+                    .replace("\"line_end\":3", &format!("\"line_end\":{line}"));
+                format!("echo '{message}'")
+            })
+            .collect();
+        let (dir, cargo_path) = fake_cargo(&format!("{}\nexit 1", lines.join("\n")));
+        let runner = FluxRunner::with_config(FluxRunnerConfig {
+            cargo_path,
+            stream_buffer: 1,
+            ..Default::default()
+        });
+        let (sink, stream) = runner.diagnostic_channel();
+        let consumer = tokio::spawn(async move {
+            let mut stream = stream;
+            let mut lines = vec![];
+            while let Some(diagnostic) = stream.recv().await {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                lines.push(diagnostic.message.spans[0].line_start);
+            }
+            lines
+        });
+
+        let report = runner
+            .verify_repository_streaming(dir.path().to_str().unwrap(), RepoConfig::default(), sink)
+            .await
+            .unwrap();
+        assert_eq!(consumer.await.unwrap(), (1..=20).collect::<Vec<_>>());
+        assert_eq!(report.diagnostics.len(), 20);
+    }
+
+    #[tokio::test]
+    async fn package_trees_stop_at_the_workspace_boundary() {
+        let metadata = r#"{"packages":[
+            {"name":"core","dependencies":[{"name":"serde","path":null}]},
+            {"name":"app","dependencies":[{"name":"mid","path":"/ws/mid"}]},
+            {"name":"mid","dependencies":[{"name":"core","path":"/ws/core"}]},
+            {"name":"other","dependencies":[{"name":"core","path":null}]},
+            {"name":"serde","dependencies":[]}
+        ]}"#;
+        let (dir, runner) = fake_runner(&format!(
+            "if [ \"$1\" = metadata ]; then echo '{metadata}'; exit 0; fi\n\
+             echo \"$@\" > \"$(dirname \"$0\")/args\"\nexit 0"
+        ));
+        let repo_path = dir.path().to_str().unwrap();
+
+        let (packages, report) = runner.verify_package_tree(repo_path, "core").await.unwrap();
+        assert_eq!(packages, ["core", "app", "mid"]);
+        assert!(report.success);
+        let args = fs::read_to_string(dir.path().join("args")).unwrap();
+        assert_eq!(args.trim(), "flux -p core -p app -p mid --message-format=json");
+
+        let err = runner
+            .verify_package_tree(repo_path, "missing")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::UnknownPackage(_)));
+    }
+
+    #[test]
+    fn output_schemas_match_the_serialized_fields() {
+        let schemas = output_schemas();
+        let properties = |schema: &serde_json::Value| -> BTreeSet<String> {
+            schema["properties"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect()
+        };
+        let mut diagnostic = FluxRunner::parse_flux_line(MESSAGE).unwrap();
+        diagnostic.counterexample = Some(diagnostics::Counterexample { assignments: vec![] });
+        diagnostic.fingerprint = "0123456789abcdef".to_string();
+        let report = VerificationReport {
+            diagnostics: vec![diagnostic],
+            queued_ms: Some(1),
+            solver: Some("z3".to_string()),
+            commit: Some("0123abc".to_string()),
+            smt_timeouts: vec!["f".to_string()],
+            ice: Some("panicked".to_string()),
+            parse_stats: Some(ParseStats::default()),
+            toolchain_warning: Some("pinned".to_string()),
+            skipped_packages: vec!["app".to_string()],
+            ..Default::default()
+        };
+        let serialized = serde_json::to_value(&report).unwrap();
+        let keys: BTreeSet<String> = serialized.as_object().unwrap().keys().cloned().collect();
+        assert_eq!(keys, properties(&schemas["VerificationReport"]));
+        let diagnostic = &serialized["diagnostics"][0];
+        let keys: BTreeSet<String> = diagnostic.as_object().unwrap().keys().cloned().collect();
+        assert_eq!(keys, properties(&schemas["Diagnostic"]));
+        assert!(schemas["Lemma"]["properties"]["requires"].is_object());
+    }
+
+    #[tokio::test]
+    async fn pinned_toolchains_other_than_fluxs_warn_or_fail() {
+        let (dir, cargo_path) = fake_cargo("exit 0");
+        let runner = FluxRunner::with_config(FluxRunnerConfig {
+            cargo_path,
+            flux_toolchain: Some("nightly-2025-10-09-x86_64-unknown-linux-gnu".to_string()),
+            ..Default::default()
+        });
+        let repo_path = dir.path().to_str().unwrap();
+        let pin = |channel: &str| {
+            fs::write(
+                dir.path().join("rust-toolchain.toml"),
+                format!("[toolchain]\nchannel = \"{channel}\"\n"),
+            )
+            .unwrap();
+        };
+
+        let report = runner.verify_repository(repo_path, false).await.unwrap();
+        assert_eq!(
+            report.toolchain.as_deref(),
+            Some("nightly-2025-10-09-x86_64-unknown-linux-gnu")
+        );
+        assert!(report.toolchain_warning.is_none());
+
+        pin("nightly-2025-10-09");
+        let report = runner.verify_repository(repo_path, false).await.unwrap();
+        assert_eq!(report.toolchain.as_deref(), Some("nightly-2025-10-09"));
+        assert!(report.toolchain_warning.is_none());
+
+        pin("nightly-2024-01-01");
+        let report = runner.verify_repository(repo_path, false).await.unwrap();
+        assert_eq!(report.toolchain.as_deref(), Some("nightly-2024-01-01"));
+        assert!(
+            report
+                .toolchain_warning
+                .unwrap()
+                .contains("nightly-2024-01-01")
+        );
+        let strict = RepoConfig { strict_toolchain: Some(true), ..Default::default() };
+        let err = runner
+            .verify_repository_with(repo_path, RunOptions::default(), strict)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::ToolchainMismatch(..)));
+    }
+
+    #[tokio::test]
+    async fn inline_asserts_fail_where_an_error_points() {
+        let (dir, runner) = fake_runner(&format!("echo '{MESSAGE}'\nexit 1"));
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "fn f(x: i32) {\n    flux_rs::assert(x > 0);\n    flux_rs::assert(x > 1);\n    \
+             flux_rs::assume(x > 2);\n}\n\
+             #[flux::trusted]\nfn g(x: i32) {\n    flux_rs::assert(x > 3);\n}\n",
+        )
+        .unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+
+        let asserts = runner.list_inline_asserts(repo_path).await;
+        let exprs: Vec<_> = asserts.iter().map(|site| site.expr.as_str()).collect();
+        assert_eq!(exprs, ["x > 0", "x > 1", "x > 3"]);
+        let statuses: Vec<_> = runner
+            .inline_assert_status(repo_path)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|assert| (assert.line, assert.status, assert.message))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                (2, AssertStatus::Ok, None),
+                (3, AssertStatus::Failing, Some("refinement type error".to_string())),
+                (8, AssertStatus::NotChecked, None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn parse_stats_count_reasons_and_dropped_lines() {
+        let (dir, runner) = fake_runner(&format!(
+            "echo '{MESSAGE}'\n\
+             echo '{{\"reason\":\"compiler-artifact\"}}'\n\
+             echo '{{\"reason\":\"compiler-message\",\"message\":{{}}}}'\n\
+             echo '{{\"other\":1}}'\n\
+             echo 'Compiling foo'\nexit 1"
+        ));
+        let report = runner
+            .verify_repository(dir.path().to_str().unwrap(), false)
+            .await
+            .unwrap();
+        assert_eq!(report.diagnostics.len(), 1);
+        let stats = report.parse_stats.unwrap();
+        let reasons: Vec<_> = stats
+            .reasons
+            .iter()
+            .map(|(reason, count)| (reason.as_str(), *count))
+            .collect();
+        assert_eq!(reasons, [("<none>", 1), ("compiler-artifact", 1), ("compiler-message", 2)]);
+        assert_eq!((stats.invalid_json, stats.dropped_messages), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn diagnostics_come_out_in_the_same_order_every_run() {
+        let later = MESSAGE
+            .replace("\"line_start\":3", "\"line_start\":8")
+            .replace("\"line_end\":3", "\"line_end\":8");
+        let other = MESSAGE.replace("refinement type error", "assertion might fail");
+        // Emit the same diagnostics in a different order on each run, as a parallel build may.
+        let (_cargo_dir, runner) = fake_runner(&format!(
+            "if [ -f \"$0.seen\" ]; then echo '{MESSAGE}'; echo '{later}'; echo '{other}'\n\
+             else touch \"$0.seen\"; echo '{later}'; echo '{other}'; echo '{MESSAGE}'; fi\nexit 1"
+        ));
+        let dir = tempfile::tempdir().unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+        let order = |report: VerificationReport| -> Vec<(i64, String)> {
+            report
+                .diagnostics
+                .iter()
+                .map(|diag| (diag.message.spans[0].line_start, diag.message.message.clone()))
+                .collect()
+        };
+
+        let first = order(runner.verify_repository(repo_path, false).await.unwrap());
+        let second = order(runner.verify_repository(repo_path, false).await.unwrap());
+        assert_eq!(first, second);
+        assert_eq!(
+            first,
+            [
+                (3, "assertion might fail".to_string()),
+                (3, "refinement type error".to_string()),
+                (8, "refinement type error".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn entry_points_share_the_read_loop() {
+        let (dir, runner) = fake_runner(&format!("echo '{MESSAGE}'\nexit 1"));
+        let repo_path = dir.path().to_str().unwrap();
+
+        let repo = runner.verify_repository(repo_path, false).await.unwrap();
+        let package = runner.verify_package(repo_path, None).await.unwrap();
+        assert!(!repo.success);
+        assert!(!package.success);
+        assert_eq!(repo.diagnostics.len(), 1);
+        assert_eq!(
+            serde_json::to_string(&repo.diagnostics).unwrap(),
+            serde_json::to_string(&package.diagnostics).unwrap()
+        );
+
+        let lemmas = runner.get_lemmas(repo_path, None).await.unwrap();
+        assert!(lemmas.is_empty());
+    }
+
+    #[tokio::test]
+    async fn large_output_is_parsed_line_by_line() {
+        // Roughly 10MB of output, which `run_command` hands to the parser one line at a time
+        // instead of accumulating it.
+        let lines = 30_000;
+        let (dir, runner) = fake_runner(&format!("yes '{MESSAGE}' | head -n {lines}"));
+        let repo_path = dir.path().to_str().unwrap();
+
+        let mut bytes = 0;
+        let mut longest = 0;
+        let cmd = runner.flux_command(repo_path, None, None);
+        runner
+            .run_command(cmd, |line| {
+                bytes += line.len();
+                longest = longest.max(line.len());
+            })
+            .await
+            .unwrap();
+        assert_eq!(bytes, lines * MESSAGE.len());
+        assert_eq!(longest, MESSAGE.len());
+
+        let report = runner.verify_repository(repo_path, false).await.unwrap();
+        assert_eq!(report.diagnostics.len(), lines);
+    }
+
+    #[tokio::test]
+    async fn missing_flux_subcommand_is_reported() {
+        let (dir, runner) = fake_runner("echo 'error: no such subcommand: `flux`' >&2\nexit 101");
+        let repo_path = dir.path().to_str().unwrap();
+
+        let err = runner
+            .verify_repository(repo_path, false)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::FluxNotInstalled));
+        assert!(err.to_string().contains("cargo install flux"));
+    }
+
+    #[test]
+    fn partial_lemma_records_keep_name_and_file() {
+        let lemma = FluxRunner::parse_lemma(&serde_json::json!({
+            "lemma_name": "head_cons_eq",
+            "file_name": "src/lib.rs",
+            "start_line": 12,
+            "added_in_a_later_version": true,
+        }))
+        .unwrap();
+        assert_eq!(lemma.name, "head_cons_eq");
+        assert_eq!(lemma.file_name, "src/lib.rs");
+        assert_eq!(lemma.start_line, 12);
+        assert_eq!((lemma.start_col, lemma.end_line, lemma.end_col), (0, 0, 0));
+
+        let nameless = serde_json::json!({ "file_name": "src/lib.rs", "start_line": 12 });
+        assert!(FluxRunner::parse_lemma(&nameless).is_none());
+    }
+
+    #[tokio::test]
+    async fn fixtures_are_checked_against_their_expectation() {
+        let (dir, runner) = fake_runner(&format!("echo '{MESSAGE}'\nexit 1"));
+        for kind in ["pos", "neg"] {
+            fs::create_dir_all(dir.path().join(kind)).unwrap();
+            fs::write(dir.path().join(kind).join("fixture.rs"), "fn main() {}").unwrap();
+        }
+        let repo_path = dir.path().to_str().unwrap();
+
+        let pos = runner
+            .verify_fixtures(repo_path, Expectation::Pos)
+            .await
+            .unwrap();
+        let neg = runner
+            .verify_fixtures(repo_path, Expectation::Neg)
+            .await
+            .unwrap();
+        assert_eq!((pos.len(), pos[0].errors, pos[0].passed), (1, 1, false));
+        assert_eq!((neg.len(), neg[0].errors, neg[0].passed), (1, 1, true));
+        assert_eq!(neg[0].file_name, "fixture.rs");
+    }
+
+    #[tokio::test]
+    async fn corpus_mismatches_are_pos_fixtures_that_fail() {
+        let (dir, runner) = fake_runner(&format!("echo '{MESSAGE}'\nexit 1"));
+        for kind in ["pos", "neg"] {
+            fs::create_dir_all(dir.path().join("tests/tests").join(kind)).unwrap();
+            fs::write(dir.path().join("tests/tests").join(kind).join("fixture.rs"), "fn main() {}")
+                .unwrap();
+        }
+
+        let corpus = runner
+            .verify_corpus(dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(corpus.mismatches, 1);
+        let entries: Vec<_> = corpus
+            .entries
+            .iter()
+            .map(|entry| (entry.file_name.as_str(), entry.expected, entry.actual, entry.ok))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("pos/fixture.rs", Expectation::Pos, Expectation::Neg, false),
+                ("neg/fixture.rs", Expectation::Neg, Expectation::Neg, true),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn lemmas_sharing_a_bare_name_conflict() {
+        let lines = [
+            lemma_line("seq::len_pos", "src/seq.rs", 4),
+            lemma_line("vec::len_pos", "src/vec.rs", 9),
+            lemma_line("head_cons_eq", "src/seq.rs", 12),
+            lemma_line("head_cons_eq", "src/seq.rs", 12),
+        ];
+        let script: Vec<_> = lines.iter().map(|line| format!("echo '{line}'")).collect();
+        let (dir, runner) = fake_runner(&script.join("\n"));
+
+        let conflicts = runner
+            .detect_lemma_conflicts(dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            conflicts,
+            [LemmaConflict {
+                name: "len_pos".to_string(),
+                locations: vec![("src/seq.rs".to_string(), 4), ("src/vec.rs".to_string(), 9)],
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn lemma_indexes_count_the_lemmas_of_each_file() {
+        let lines = [
+            lemma_line("seq::len_pos", "src/seq.rs", 4),
+            lemma_line("vec::len_pos", "src/vec.rs", 9),
+            lemma_line("seq::head_cons_eq", "src/seq.rs", 12),
+        ];
+        let script: Vec<_> = lines.iter().map(|line| format!("echo '{line}'")).collect();
+        let (dir, runner) = fake_runner(&script.join("\n"));
+
+        let index = runner
+            .lemma_index(dir.path().to_str().unwrap(), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            index,
+            BTreeMap::from([("src/seq.rs".to_string(), 2), ("src/vec.rs".to_string(), 1)])
+        );
+    }
+
+    #[tokio::test]
+    async fn lemma_previews_are_their_first_lines() {
+        let (dir, runner) = fake_runner(&format!(
+            "echo '{}'\necho '{}'",
+            lemma_line("head_cons_eq", "src/lib.rs", 2),
+            lemma_line("gone", "src/lib.rs", 9)
+        ));
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "#[flux::sig(fn(i32[@x]) ensures head(cons(x)) == x)]\n    \
+             pub fn head_cons_eq(x: i32) {}\n",
+        )
+        .unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+
+        let previews = runner.lemma_previews(repo_path).await.unwrap();
+        assert_eq!(previews[0].name, "head_cons_eq");
+        assert_eq!(previews[0].file, "src/lib.rs");
+        assert_eq!(previews[0].line, 2);
+        assert_eq!(previews[0].preview, "pub fn head_cons_eq(x: i32) {}");
+        assert_eq!(previews[1].preview, "");
+    }
+
+    #[tokio::test]
+    async fn lemma_snapshots_are_reused_until_sources_change() {
+        let lemma = lemma_line("head_cons_eq", "src/lib.rs", 1);
+        let (dir, runner) = fake_runner(&format!("echo '{lemma}'"));
+        let repo_path = dir.path().to_str().unwrap();
+        let snapshot = dir.path().join("target/flux-lemmas.json");
+
+        fs::write(dir.path().join("lib.rs"), "fn f() {}").unwrap();
+        let saved = runner
+            .get_lemmas_cached(repo_path, &snapshot)
+            .await
+            .unwrap();
+        assert_eq!(saved.lemmas.len(), 1);
+        assert_eq!(FluxRunner::load_lemmas(&snapshot).unwrap().lemmas[0].name, "head_cons_eq");
+
+        // A fresh snapshot is used as is, even if it no longer matches what flux would dump.
+        let empty = LemmaSnapshot { fingerprint: saved.fingerprint.clone(), lemmas: vec![] };
+        fs::write(&snapshot, serde_json::to_string(&empty).unwrap()).unwrap();
+        // Rewriting a file without changing it keeps the snapshot fresh.
+        fs::write(dir.path().join("lib.rs"), "fn f() {}").unwrap();
+        let cached = runner
+            .get_lemmas_cached(repo_path, &snapshot)
+            .await
+            .unwrap();
+        assert!(cached.lemmas.is_empty());
+        assert_eq!(cached.fingerprint, saved.fingerprint);
+
+        fs::write(dir.path().join("lib.rs"), "fn g() {}").unwrap();
+        let dumped = runner
+            .get_lemmas_cached(repo_path, &snapshot)
+            .await
+            .unwrap();
+        assert_eq!(dumped.lemmas.len(), 1);
+        assert_ne!(dumped.fingerprint, saved.fingerprint);
+    }
+
+    #[test]
+    fn refinement_params_are_found_in_nested_brackets() {
+        let params = FluxRunner::extract_refinement_params(
+            "fn(&Self[@elems], i32[@v]) -> Foo[cons(v, elems)]",
+        );
+        assert_eq!(params, ["elems", "v"]);
+        let params = FluxRunner::extract_refinement_params(
+            "fn(&RVec<i32{x: x > 0}[@n]>, [i32; 3][@m], i32[@n]) ensures len(@n) > 0",
+        );
+        assert_eq!(params, ["n", "m"]);
+        assert!(FluxRunner::extract_refinement_params("fn() -> bool[true]").is_empty());
+        assert!(FluxRunner::extract_refinement_params("fn(i32[@n)").is_empty());
+    }
+
+    #[tokio::test]
+    async fn function_verifications_keep_the_diagnostics_inside_the_function() {
+        let inside = MESSAGE.replace("\"line_start\":3", "\"line_start\":5");
+        let (dir, runner) = fake_runner(&format!(
+            "echo \"$FLUXFLAGS|$*\" > \"$(dirname \"$0\")/args\"\n\
+             echo '{MESSAGE}'\necho '{inside}'\nexit 1"
+        ));
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "struct Foo;\n\nimpl Foo {\n    fn f() {\n        g();\n    }\n}\n",
+        )
+        .unwrap();
+
+        let report = runner
+            .verify_function(dir.path().to_str().unwrap(), "foo", "foo::Foo::f")
+            .await
+            .unwrap();
+        assert!(!report.success);
+        let lines: Vec<_> = report
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message.primary_span().unwrap().line_start)
+            .collect();
+        assert_eq!(lines, [5]);
+        let args = fs::read_to_string(dir.path().join("args")).unwrap();
+        assert_eq!(args.trim(), "-Finclude=def:Foo::f|flux -p foo --message-format=json");
+    }
+
+    #[tokio::test]
+    async fn sigs_are_extracted_from_functions_methods_and_trait_items() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("lib.rs"),
+            "#[flux::sig(fn(i32[@n]) -> i32[n + 1])]\nfn incr(n: i32) -> i32 { n + 1 }\n\n\
+             impl Foo {\n    #[flux_rs::sig(\n        fn(&Self[@elems]) -> i32\n    )]\n    fn head(&self) -> i32 { 0 }\n}\n\n\
+             trait Bar {\n    #[sig(fn() -> bool[true])]\n    fn bar() -> bool;\n}\n\nfn unsigned() {}\n",
+        )
+        .unwrap();
+        let runner = FluxRunner::new();
+        let repo_path = dir.path().to_str().unwrap();
+
+        let sigs = runner.extract_sigs(repo_path, "lib.rs").await.unwrap();
+        let sigs: Vec<_> = sigs
+            .iter()
+            .map(|sig| (sig.name.as_str(), sig.sig_text.as_str(), sig.line))
+            .collect();
+        assert_eq!(
+            sigs,
+            [
+                ("incr", "fn(i32[@n]) -> i32[n + 1]", 1),
+                ("Foo::head", "fn(&Self[@elems]) -> i32", 5),
+                ("Bar::bar", "fn() -> bool[true]", 12),
+            ]
+        );
+        let params = runner.extract_sigs(repo_path, "lib.rs").await.unwrap()[1]
+            .params
+            .clone();
+        assert_eq!(params, ["elems"]);
+        let err = runner
+            .extract_sigs(repo_path, "missing.rs")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::InvalidSource(_)));
+    }
+
+    #[tokio::test]
+    async fn spec_edits_are_verified_on_a_copy() {
+        let caller_error = MESSAGE
+            .replace("src/lib.rs", "lib.rs")
+            .replace("\"line_start\":3", "\"line_start\":7")
+            .replace("\"line_end\":3", "\"line_end\":7");
+        let incr_error = MESSAGE.replace("src/lib.rs", "lib.rs");
+        let (_cargo_dir, runner) = fake_runner(&format!(
+            "if grep -q 'n + 2' lib.rs; then echo '{caller_error}'; else echo '{incr_error}'; fi\n\
+             exit 1"
+        ));
+        let dir = tempfile::tempdir().unwrap();
+        let source = "#[flux::sig(fn(i32[@n]) -> i32[n])]\nfn incr(n: i32) -> i32 {\n    n + 1\n}\n\n\
+                      fn caller() -> i32 {\n    incr(1)\n}\n";
+        fs::write(dir.path().join("lib.rs"), source).unwrap();
+        fs::create_dir(dir.path().join("target")).unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+
+        let impact = runner
+            .spec_edit_impact(repo_path, "incr", "fn(i32[@n]) -> i32[n + 2]")
+            .await
+            .unwrap();
+        assert_eq!(impact.file_name, "lib.rs");
+        assert!(!impact.passed_before);
+        assert!(impact.passes_after);
+        assert_eq!(impact.broken, ["caller"]);
+        assert!(impact.fixed.is_empty());
+        assert_eq!((impact.diff.resolved.len(), impact.diff.introduced.len()), (1, 1));
+        assert_eq!(fs::read_to_string(dir.path().join("lib.rs")).unwrap(), source);
+
+        let err = runner
+            .spec_edit_impact(repo_path, "caller", "fn() -> i32")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::UnknownItem(_)));
+    }
+
+    #[tokio::test]
+    async fn extra_lemmas_are_inserted_in_a_copy() {
+        let call_error = MESSAGE
+            .replace("src/lib.rs", "lib.rs")
+            .replace("\"line_start\":3", "\"line_start\":7")
+            .replace("\"line_end\":3", "\"line_end\":7");
+        let (_cargo_dir, runner) =
+            fake_runner(&format!("grep -q 'lemma_b(1);' lib.rs && echo '{call_error}'\nexit 1"));
+        let dir = tempfile::tempdir().unwrap();
+        let source = "fn f(v: i32) {\n    g(v);\n}\n\nfn h() {\n    g(1);\n}\n";
+        fs::write(dir.path().join("lib.rs"), source).unwrap();
+        let call = |line, call_text: &str| {
+            LemmaCall { file_path: "lib.rs".to_string(), line, call_text: call_text.to_string() }
+        };
+
+        let result = runner
+            .verify_with_extra_lemmas(
+                dir.path().to_str().unwrap(),
+                &[
+                    call(2, "lemma_a(v);"),
+                    call(6, "lemma_b(1)"),
+                    call(2, "lemma_c(v"),
+                    call(20, "lemma_d()"),
+                ],
+            )
+            .await
+            .unwrap();
+        assert!(!result.success);
+        assert_eq!(result.remaining.len(), 1);
+        let calls: Vec<_> = result
+            .calls
+            .iter()
+            .map(|call| (call.accepted, call.inserted_line, call.errors))
+            .collect();
+        assert_eq!(
+            calls,
+            [(true, Some(2), 0), (true, Some(7), 1), (false, None, 0), (false, None, 0)]
+        );
+        assert!(
+            result.calls[2]
+                .reason
+                .as_ref()
+                .unwrap()
+                .starts_with("not an expression")
+        );
+        assert_eq!(fs::read_to_string(dir.path().join("lib.rs")).unwrap(), source);
+    }
+
+    #[tokio::test]
+    async fn lemma_tries_compare_the_errors_of_the_enclosing_item() {
+        let lemma = lemma_line("head_cons_eq", "lib.rs", 1);
+        let error = MESSAGE.replace("src/lib.rs", "lib.rs");
+        // The lemma's precondition fails at the call, and the original error moves over.
+        let at_call = error.replace("refinement type error", "precondition might not hold");
+        let shifted = error
+            .replace("\"column_start\":5", "\"column_start\":26")
+            .replace("\"column_end\":9", "\"column_end\":30");
+        let (dir, runner) = fake_runner(&format!(
+            "case \"$FLUXFLAGS\" in *dump-lemmas*) echo '{lemma}'; exit 0;; esac\n\
+             grep -q 'head_cons_eq(&v, 1);' lib.rs && exit 0\n\
+             grep -q 'head_cons_eq(&v, 2);' lib.rs && echo '{at_call}' && echo '{shifted}' && exit 1\n\
+             echo '{error}'\nexit 1"
+        ));
+        let source = "fn head_cons_eq(v: &RVec, n: i32) {}\nfn f(v: RVec) {\n    g(&v, 1);\n}\n";
+        fs::write(dir.path().join("lib.rs"), source).unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+        let args =
+            |args: &[&str]| -> Vec<String> { args.iter().map(|arg| arg.to_string()).collect() };
+        let try_lemma = |name: &'static str, line, column, call_args: Vec<String>| {
+            let runner = &runner;
+            async move {
+                runner
+                    .try_lemma(repo_path, "lib.rs", line, column, name, &call_args)
+                    .await
+            }
+        };
+
+        let result = try_lemma("head_cons_eq", 3, None, args(&["&v", "1"]))
+            .await
+            .unwrap();
+        assert_eq!(result.call, "head_cons_eq(&v, 1);");
+        assert_eq!(result.item, "f");
+        assert_eq!(result.outcome, LemmaOutcome::Helped);
+        assert_eq!((result.errors_before, result.errors_after), (1, 0));
+        assert!(result.success);
+
+        let result = try_lemma("head_cons_eq", 3, Some(5), args(&["&v", "2"]))
+            .await
+            .unwrap();
+        assert_eq!(result.outcome, LemmaOutcome::NewErrors);
+        assert_eq!((result.errors_before, result.errors_after), (1, 1));
+        assert_eq!(result.call_errors.len(), 1);
+        assert!(result.introduced.is_empty());
+
+        let result = try_lemma("crate::head_cons_eq", 3, Some(14), args(&["&v", "3"]))
+            .await
+            .unwrap();
+        assert_eq!(result.outcome, LemmaOutcome::NoChange);
+
+        let err = try_lemma("tail_eq", 3, None, vec![]).await.unwrap_err();
+        assert!(matches!(err, FluxRunnerError::UnknownLemma(..)));
+        assert_eq!(err.to_string(), "No lemma `tail_eq`, the known lemmas are head_cons_eq");
+        for (line, column, call_args) in
+            [(9, None, args(&["&v"])), (3, Some(20), args(&["&v"])), (3, None, args(&["v +"]))]
+        {
+            let err = try_lemma("head_cons_eq", line, column, call_args)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, FluxRunnerError::InvalidInsertion(_)), "{err}");
+        }
+        assert_eq!(fs::read_to_string(dir.path().join("lib.rs")).unwrap(), source);
+    }
+
+    #[tokio::test]
+    async fn edits_never_leave_the_scratch_copy() {
+        let lemma = lemma_line("head_cons_eq", "lib.rs", 1);
+        let (dir, runner) = fake_runner(&format!(
+            "case \"$FLUXFLAGS\" in *dump-lemmas*) echo '{lemma}';; esac\nexit 0"
+        ));
+        let source = "fn head_cons_eq(v: &RVec, n: i32) {}\nfn f(v: RVec) {\n    g(&v, 1);\n}\n";
+        fs::write(dir.path().join("lib.rs"), source).unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let victim = outside.path().join("lib.rs");
+        fs::write(&victim, source).unwrap();
+        // Enough `..` to climb from the copy to `/`, then down to a file outside of the copy.
+        let escape =
+            format!("{}{}", "../".repeat(32), victim.to_str().unwrap().trim_start_matches('/'));
+        let repo_path = dir.path().to_str().unwrap();
+
+        let args = vec!["&v".to_string(), "1".to_string()];
+        let err = runner
+            .try_lemma(repo_path, &escape, 3, None, "head_cons_eq", &args)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::InvalidSource(_)), "{err}");
+
+        let call =
+            LemmaCall { file_path: escape.clone(), line: 3, call_text: "g(&v, 2)".to_string() };
+        let result = runner
+            .verify_with_extra_lemmas(repo_path, &[call])
+            .await
+            .unwrap();
+        assert_eq!(result.calls[0].reason, Some(format!("no such file `{escape}`")));
+
+        let err = runner
+            .verify_and_fmt(repo_path, Some(&[escape]), true)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::InvalidSource(_)), "{err}");
+        assert_eq!(fs::read_to_string(&victim).unwrap(), source);
+        assert_eq!(fs::read_to_string(dir.path().join("lib.rs")).unwrap(), source);
+    }
+
+    #[test]
+    fn rejected_hunks_are_read_from_git_apply() {
+        let stderr = "Checking patch src/lib.rs...\n\
+                      Checking patch src/missing.rs...\n\
+                      error: while searching for:\nfoo\n\n\
+                      error: patch failed: src/lib.rs:9\n\
+                      error: src/missing.rs: No such file or directory\n\
+                      Applying patch src/lib.rs with 1 reject...\n\
+                      Hunk #1 applied cleanly.\n\
+                      Rejected hunk #2.\n";
+        let hunk = |file_name: &str, hunk| RejectedHunk { file_name: file_name.to_string(), hunk };
+        assert_eq!(
+            rejected_hunks(stderr).unwrap(),
+            [hunk("src/missing.rs", None), hunk("src/lib.rs", Some(2))]
+        );
+        assert_eq!(
+            rejected_hunks("Checking patch a.rs...\nApplied patch a.rs cleanly.\n").unwrap(),
+            []
+        );
+        assert!(rejected_hunks("error: No valid patches in input\n").is_none());
+    }
+
+    #[tokio::test]
+    async fn patches_are_verified_on_a_copy() {
+        let (_cargo_dir, runner) = fake_runner("grep -q 'n + 1' lib.rs");
+        let dir = tempfile::tempdir().unwrap();
+        let source = "fn incr(n: i32) -> i32 {\n    n\n}\n";
+        fs::write(dir.path().join("lib.rs"), source).unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+        let patch = "--- a/lib.rs\n+++ b/lib.rs\n@@ -1,3 +1,3 @@\n fn incr(n: i32) -> i32 {\n-    n\n+    n + 1\n }\n";
+
+        let (report, rejected) = runner.verify_patch(repo_path, patch).await.unwrap();
+        assert!(report.success);
+        assert!(rejected.is_empty());
+        assert_eq!(fs::read_to_string(dir.path().join("lib.rs")).unwrap(), source);
+
+        let err = runner
+            .verify_patch(repo_path, "not a patch")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::InvalidPatch(_)));
+    }
+
+    #[tokio::test]
+    async fn lemma_calls_are_minimized_greedily() {
+        let (_cargo_dir, runner) =
+            fake_runner(&format!("grep -q 'lemma_b' lib.rs || {{ echo '{MESSAGE}'; exit 1; }}"));
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("lib.rs"), "fn f() {\n    g();\n}\n").unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+        let calls: Vec<_> = ["lemma_a()", "lemma_b()", "lemma_c()", "lemma_d("]
+            .into_iter()
+            .map(|call_text| {
+                LemmaCall { file_path: "lib.rs".to_string(), line: 2, call_text: call_text.into() }
+            })
+            .collect();
+        let texts = |result: &MinimizedLemmas| -> Vec<String> {
+            result
+                .calls
+                .iter()
+                .map(|call| call.call_text.clone())
+                .collect()
+        };
+
+        let result = runner.minimize_lemmas(repo_path, &calls, 32).await.unwrap();
+        assert!(result.success);
+        assert_eq!(texts(&result), ["lemma_b()"]);
+        assert_eq!((result.attempts, result.exhausted), (4, false));
+
+        let result = runner.minimize_lemmas(repo_path, &calls, 2).await.unwrap();
+        assert_eq!(texts(&result), ["lemma_b()", "lemma_c()"]);
+        assert_eq!((result.attempts, result.exhausted), (2, true));
+
+        let result = runner
+            .minimize_lemmas(repo_path, &calls[2..], 32)
+            .await
+            .unwrap();
+        assert!(!result.success);
+        assert!(result.calls.is_empty());
+    }
+
+    #[tokio::test]
+    async fn callees_are_followed_level_by_level() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("lib.rs"),
+            "struct RVec;
+             impl RVec {
+    fn new() -> RVec { RVec }
+    fn len(&self) -> usize { helper(0) }
+                 fn push(&mut self) {
+        let n = Self::len(self);
+        assert!(check(n));
+                     fn nested() { hidden() }
+    }
+}
+             fn helper(n: usize) -> usize { n.max(1) }
+             fn check(n: usize) -> bool { check(n) }
+             fn main() {
+    let mut v = RVec::new();
+    v.push();
+}
+",
+        )
+        .unwrap();
+        let runner = FluxRunner::new();
+        let repo_path = dir.path().to_str().unwrap();
+
+        let direct = runner.callees(repo_path, "main", 1).await.unwrap();
+        assert_eq!(direct.callees, ["RVec::new", "push"]);
+        let deep = runner.callees(repo_path, "RVec::push", 3).await.unwrap();
+        assert_eq!(deep.callees, ["RVec::len", "check", "helper", "max"]);
+        assert!(!deep.truncated);
+        assert!(runner.callees(repo_path, "missing", 1).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn item_paths_resolve_through_modules_and_impls() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src/seq")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "mod seq;\nfn len() {}\n").unwrap();
+        fs::write(
+            dir.path().join("src/seq/mod.rs"),
+            "struct RSeq;\n\nimpl RSeq {\n    fn len(&self) -> usize {\n        0\n    }\n}\n\n\
+             trait Sized {\n    const N: usize;\n}\n\nmod inner {\n    fn len() {}\n}\n",
+        )
+        .unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+        let runner = FluxRunner::new();
+        let range = |path| {
+            let runner = &runner;
+            async move {
+                let range = runner.item_range(repo_path, path).await?;
+                Some((range.file_name, range.start_line, range.end_line))
+            }
+        };
+
+        assert_eq!(range("len").await, Some(("src/lib.rs".to_string(), 2, 2)));
+        let method = Some(("src/seq/mod.rs".to_string(), 4, 6));
+        assert_eq!(range("RSeq::len").await, method);
+        assert_eq!(range("crate::seq::RSeq::len").await, method);
+        assert_eq!(range("seq::inner::len").await, Some(("src/seq/mod.rs".to_string(), 14, 14)));
+        assert_eq!(range("Sized::N").await, Some(("src/seq/mod.rs".to_string(), 10, 10)));
+        assert_eq!(range("crate::RSeq::len").await, None);
+        assert_eq!(range("RVec::len").await, None);
+    }
+
+    #[tokio::test]
+    async fn refined_by_fields_keep_their_sorts_as_written() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("lib.rs"),
+            "struct Bar;\n\n\
+             #[derive(Clone)]\n#[flux::refined_by(elems: ISeq, len: int, f: Map<int, bool>)]\n\
+             struct Foo {}\n\n\
+             #[flux_rs::refined_by(b: bool)]\nenum E { A }\n",
+        )
+        .unwrap();
+        let runner = FluxRunner::new();
+        let repo_path = dir.path().to_str().unwrap();
+
+        let foo = runner.refined_by(repo_path, "crate::Foo").await.unwrap();
+        assert_eq!(foo.file_name, "lib.rs");
+        assert_eq!(foo.line, 4);
+        let fields: Vec<_> = foo
+            .fields
+            .iter()
+            .map(|(name, sort)| (name.as_str(), sort.as_str()))
+            .collect();
+        assert_eq!(fields, [("elems", "ISeq"), ("len", "int"), ("f", "Map<int, bool>")]);
+        let e = runner.refined_by(repo_path, "E").await.unwrap();
+        assert_eq!(e.fields, [("b".to_string(), "bool".to_string())]);
+        assert_eq!(runner.refined_by(repo_path, "Bar").await, None);
+    }
+
+    #[tokio::test]
+    async fn trusted_items_are_listed_with_their_owner() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("lib.rs"),
+            "#[flux::opaque]\n#[flux::refined_by(elems: ISeq)]\nstruct Foo {}\n\n\
+             #[flux_rs::trusted]\nimpl Foo {\n    #[trusted]\n    fn push(&self) {}\n\
+             \x20   #[flux::trusted(no)]\n    fn pop(&self) {}\n}\n\n\
+             #[flux::lemma]\n#[flux::trusted]\nfn head_cons_eq() {}\n\n#[other::trusted]\nfn f() {}\n",
+        )
+        .unwrap();
+
+        let items = FluxRunner::new()
+            .list_trusted(dir.path().to_str().unwrap())
+            .await;
+        let items: Vec<_> = items
+            .iter()
+            .map(|item| (item.name.as_str(), item.attribute.as_str(), item.line))
+            .collect();
+        assert_eq!(
+            items,
+            [
+                ("Foo", "opaque", 1),
+                ("impl Foo", "trusted", 5),
+                ("Foo::push", "trusted", 7),
+                ("head_cons_eq", "trusted", 13),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn assumptions_are_found_in_code_but_not_comments() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("lib.rs"),
+            "use flux_rs::assume;\n\nfn f(x: i32) {\n    // flux_rs::assume(x > 1)\n    \
+             assume(x >\n        0);\n    let _ = \"flux::assert(false)\";\n    \
+             flux_rs::assert(x != 0);\n    other::assume(true);\n}\n",
+        )
+        .unwrap();
+
+        let sites = FluxRunner::new()
+            .list_assumptions(dir.path().to_str().unwrap())
+            .await;
+        let sites: Vec<_> = sites
+            .iter()
+            .map(|site| (site.intrinsic.as_str(), site.line, site.expr.as_str()))
+            .collect();
+        assert_eq!(sites, [("assume", 5, "x > 0"), ("assert", 8, "x != 0")]);
+    }
+
+    #[tokio::test]
+    async fn lemma_usage_counts_calls_but_not_comments_or_methods() {
+        let (dir, runner) = fake_runner(&format!(
+            "echo '{}'\necho '{}'",
+            lemma_line("lemmas::used", "lib.rs", 1),
+            lemma_line("unused", "lib.rs", 1)
+        ));
+        fs::write(
+            dir.path().join("lib.rs"),
+            "mod lemmas { pub fn used() {} }\n\
+             use lemmas::used as renamed;\n\
+             fn unused() {}\n\
+             fn f() {\n\
+                 lemmas::used();\n\
+                 renamed();\n\
+                 assert!(used() == ());\n\
+                 x.unused();\n\
+                 // unused();\n\
+                 let _ = \"unused()\";\n\
+             }\n",
+        )
+        .unwrap();
+
+        let usage = runner
+            .lemma_usage(dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        let counts: Vec<_> = usage
+            .iter()
+            .map(|(lemma, count)| (lemma.name.as_str(), *count))
+            .collect();
+        assert_eq!(counts, [("lemmas::used", 3), ("unused", 0)]);
+    }
+
+    #[tokio::test]
+    async fn trusted_annotations_go_on_the_enclosing_item() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "impl Foo {\n    \
+             #[flux::sig(fn(i32) -> i32)]\n    \
+             fn get(x: i32) -> i32 {\n        \
+             x\n    \
+             }\n\
+             }\n",
+        )
+        .unwrap();
+        let runner = FluxRunner::new();
+        let repo_path = dir.path().to_string_lossy();
+        let mut diagnostic = FluxRunner::parse_flux_line(MESSAGE).unwrap();
+
+        let annotation = runner
+            .suggest_trusted_annotation(&repo_path, &diagnostic)
+            .await
+            .unwrap();
+        assert_eq!(annotation.item, "Foo::get");
+        assert_eq!(annotation.file_name, "src/lib.rs");
+        assert_eq!(annotation.insert_line, 2);
+        assert_eq!(annotation.text, "    #[flux::trusted]");
+        assert_eq!(annotation.unverified, ["Foo::get"]);
+
+        diagnostic.message.spans[0].line_start = 9;
+        let err = runner
+            .suggest_trusted_annotation(&repo_path, &diagnostic)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::NoEnclosingItem(_)));
+    }
+
+    #[tokio::test]
+    async fn preconditions_are_rewritten_along_the_call_arguments() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "#[flux::sig(fn(i32[@n]) -> i32 requires n > 0)]\n\
+             fn callee(n: i32) -> i32 { n }\n\
+             #[flux::sig(fn(i32[@a], i32[@b]) -> i32 requires b >= 0)]\n\
+             fn caller(x: i32, y: i32) -> i32 {\n    \
+             callee(x) + callee(y + 1) + callee(1)\n\
+             }\n",
+        )
+        .unwrap();
+        let runner = FluxRunner::new();
+        let repo_path = dir.path().to_string_lossy();
+        let mut diagnostic = FluxRunner::parse_flux_line(MESSAGE).unwrap();
+        let at = |diagnostic: &mut Diagnostic, start, end| {
+            let span = &mut diagnostic.message.spans[0];
+            (span.line_start, span.line_end) = (5, 5);
+            (span.column_start, span.column_end) = (start, end);
+        };
+
+        at(&mut diagnostic, 5, 14);
+        let suggested = runner
+            .suggest_precondition(&repo_path, &diagnostic)
+            .await
+            .unwrap();
+        assert_eq!((suggested.caller.as_str(), suggested.callee.as_str()), ("caller", "callee"));
+        let [suggestion] = suggested.suggestions.as_slice() else { panic!("{suggested:?}") };
+        assert_eq!(suggestion.requires, "a > 0");
+        assert_eq!(suggestion.confidence, Confidence::High);
+
+        at(&mut diagnostic, 17, 30);
+        let suggested = runner
+            .suggest_precondition(&repo_path, &diagnostic)
+            .await
+            .unwrap();
+        let [suggestion] = suggested.suggestions.as_slice() else { panic!("{suggested:?}") };
+        assert_eq!(suggestion.requires, "(b + 1) > 0");
+        assert_eq!(suggestion.confidence, Confidence::Medium);
+
+        at(&mut diagnostic, 1, 3);
+        let err = runner
+            .suggest_precondition(&repo_path, &diagnostic)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::NotACall(_)));
+    }
+
+    #[tokio::test]
+    async fn trust_budgets_flag_new_trusted_items_and_assumptions() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+        fs::write(dir.path().join("lib.rs"), "#[flux::trusted]\nfn f() {}\n").unwrap();
+        let runner = FluxRunner::new();
+        assert!(matches!(
+            runner.check_trust_budget(repo_path, None, false).await,
+            Err(FluxRunnerError::Config(_))
+        ));
+
+        let recorded = runner
+            .check_trust_budget(repo_path, None, true)
+            .await
+            .unwrap();
+        assert!(recorded.ok);
+        assert_eq!(recorded.baseline_size, 1);
+        assert!(dir.path().join(TRUST_BASELINE_FILE).exists());
+
+        fs::write(
+            dir.path().join("lib.rs"),
+            "\n\n#[flux::trusted]\nfn f() {}\n\
+             #[flux::trusted]\nfn g() {}\n\
+             fn h(x: i32) { flux_rs::assume(x > 0); flux_rs::assert(x > 0); }\n",
+        )
+        .unwrap();
+        let budget = runner
+            .check_trust_budget(repo_path, None, false)
+            .await
+            .unwrap();
+        assert!(!budget.ok);
+        let added: Vec<_> = budget
+            .added
+            .iter()
+            .map(|item| (item.kind.as_str(), item.name.as_str()))
+            .collect();
+        assert_eq!(added, [("assume", "x > 0"), ("trusted", "g")]);
+        assert!(budget.removed.is_empty());
+        assert_eq!(budget.current_size, 3);
+    }
+
+    #[tokio::test]
+    async fn verified_items_leave_out_failed_and_trusted_fns() {
+        let (_cargo_dir, runner) = fake_runner(&format!("echo '{MESSAGE}'\nexit 1"));
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "#[flux::sig(fn() -> i32[0])]\n\
+             fn bad() -> i32 {\n    1\n}\n\
+             #[flux::sig(fn() -> i32[1])]\n\
+             fn good() -> i32 { 1 }\n\
+             #[flux::trusted]\n\
+             #[flux::sig(fn() -> i32[2])]\n\
+             fn assumed() -> i32 { 0 }\n\
+             fn unspecced() {}\n",
+        )
+        .unwrap();
+
+        let verified = runner
+            .verified_items(dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(verified, ["good"]);
+    }
+
+    #[tokio::test]
+    async fn lemmas_about_a_symbol_mention_it_in_a_clause() {
+        let script = ["head_cons", "len_pos", "tail_head"]
+            .map(|name| format!("echo '{}'", lemma_line(name, "lib.rs", 1)))
+            .join("\n");
+        let (dir, runner) = fake_runner(&script);
+        fs::write(
+            dir.path().join("lib.rs"),
+            "#[flux::sig(fn(i32[@x], Seq[@s]) ensures head(cons(x, s)) == x)]\n\
+             fn head_cons(x: i32, s: Seq) {}\n\
+             #[flux::sig(fn(Seq[@s]) ensures len(s) >= 0)]\n\
+             fn len_pos(s: Seq) {}\n\
+             #[flux::sig(fn(Seq[@s]) requires 0 < head(s) ensures len(tail(s)) >= 0)]\n\
+             fn tail_head(s: Seq) {}\n",
+        )
+        .unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+
+        let names = |lemmas: Vec<Lemma>| -> Vec<String> {
+            lemmas.into_iter().map(|lemma| lemma.name).collect()
+        };
+        let about = runner.lemmas_about(repo_path, "seq::head").await.unwrap();
+        assert_eq!(names(about), ["head_cons", "tail_head"]);
+        let about = runner.lemmas_about(repo_path, "cons").await.unwrap();
+        assert_eq!(names(about), ["head_cons"]);
+    }
+
+    #[tokio::test]
+    async fn lemmas_for_a_sort_mention_it_directly_or_through_funcs_and_types() {
+        let script = ["len_pos", "vec_len", "seq_eq", "abs_pos"]
+            .map(|name| format!("echo '{}'", lemma_line(name, "lib.rs", 1)))
+            .join("\n");
+        let (dir, runner) = fake_runner(&script);
+        fs::write(
+            dir.path().join("lib.rs"),
+            "flux_rs::defs! {\n    opaque sort ISeq;\n    fn len(elems: ISeq) -> int;\n}\n\
+             #[flux::refined_by(elems: ISeq)]\nstruct RVec;\n\
+             #[flux::sig(fn(i32[@s]) ensures len(s) >= 0)]\n\
+             fn len_pos(s: i32) {}\n\
+             #[flux::sig(fn(&RVec[@v]))]\n\
+             fn vec_len(v: &RVec) {}\n\
+             #[flux::sig(fn(x: ISeq) ensures x == x)]\n\
+             fn seq_eq(x: i32) {}\n\
+             #[flux::sig(fn(i32[@n]) ensures n * n >= 0)]\n\
+             fn abs_pos(n: i32) {}\n",
+        )
+        .unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+
+        let lemmas = runner.lemmas_for_sort(repo_path, "ISeq").await.unwrap();
+        let names: Vec<_> = lemmas.iter().map(|lemma| lemma.name.as_str()).collect();
+        assert_eq!(names, ["len_pos", "vec_len", "seq_eq"]);
+        assert!(
+            runner
+                .lemmas_for_sort(repo_path, "Set")
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn every_refinement_error_gets_suggestions() {
+        let second = MESSAGE
+            .replace("\"line_start\":3", "\"line_start\":4")
+            .replace("\"line_end\":3", "\"line_end\":4");
+        let warning = MESSAGE.replace("\"level\":\"error\"", "\"level\":\"warning\"");
+        let script = format!(
+            "echo '{MESSAGE}'\necho '{second}'\necho '{warning}'\necho '{}'\necho '{}'\nexit 1",
+            lemma_line("len_push", "src/lib.rs", 1),
+            lemma_line("head_cons", "src/lib.rs", 1)
+        );
+        let (dir, runner) = fake_runner(&script);
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "#[flux::sig(fn(v: &RVec<i32>[@n]) ensures len(push(v)) == n + 1)]\n\
+             fn len_push() {}\n\
+             fn f(v: RVec<i32>) -> usize { len(v) }\n\
+             fn g(xs: Seq) -> i32 { head(cons(1, xs)) }\n\
+             #[flux::sig(fn() ensures head(cons(v, elems)) == v)]\n\
+             fn head_cons() {}\n",
+        )
+        .unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+        let snapshot = dir.path().join("lemmas.json");
+
+        let suggestions = runner
+            .verify_and_suggest(repo_path, 1, &snapshot)
+            .await
+            .unwrap();
+        let suggested: Vec<_> = suggestions
+            .iter()
+            .map(|item| {
+                let names: Vec<_> = item
+                    .suggested_lemmas
+                    .iter()
+                    .map(|l| l.name.as_str())
+                    .collect();
+                (item.diagnostic.enclosing_item.as_deref(), names)
+            })
+            .collect();
+        assert_eq!(suggested, [(Some("f"), vec!["len_push"]), (Some("g"), vec!["head_cons"])]);
+        assert!(snapshot.exists());
+    }
+
+    #[tokio::test]
+    async fn next_goal_pairs_the_first_error_with_relevant_lemmas() {
+        let script = format!(
+            "echo '{MESSAGE}'\necho '{}'\necho '{}'\nexit 1",
+            lemma_line("unrelated", "src/lib.rs", 1),
+            lemma_line("len_push", "src/lib.rs", 1)
+        );
+        let (dir, runner) = fake_runner(&script);
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "#[flux::sig(fn(v: &RVec<i32>[@n]) ensures len(push(v)) == n + 1)]\n\
+             fn len_push() {}\n\
+             fn f(v: RVec<i32>) -> usize { len(v) }\n\
+             #[flux::sig(fn() ensures true)]\n\
+             fn unrelated() {}\n",
+        )
+        .unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+
+        let NextGoal::Failing { goal } = runner.next_goal(repo_path).await.unwrap() else {
+            panic!("expected a failing goal");
+        };
+        assert_eq!(goal.kind, "FLUX-REFINEMENT");
+        assert_eq!(goal.snippet, "fn f(v: RVec<i32>) -> usize { len(v) }");
+        assert_eq!(goal.diagnostic.enclosing_item.as_deref(), Some("f"));
+        let suggested: Vec<_> = goal
+            .suggested_lemmas
+            .iter()
+            .map(|lemma| &lemma.name)
+            .collect();
+        assert_eq!(suggested, ["len_push"]);
+
+        let (dir, runner) = fake_runner("exit 0");
+        let next = runner
+            .next_goal(dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        assert!(matches!(next, NextGoal::NoFailingGoals { summary } if summary.success));
+    }
+
+    #[tokio::test]
+    async fn goal_bundles_gather_the_item_its_callees_and_their_defs() {
+        let (dir, runner) = fake_runner(&format!("echo '{MESSAGE}'\nexit 1"));
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "#[flux::sig(fn(v: RVec[@n]) -> usize[len(n)])]\n\
+             fn f(v: RVec) -> usize {\n    push(v)\n}\n\
+             #[flux::sig(fn(v: RVec) -> RVec[@n] ensures size(n))]\n\
+             fn push(v: RVec) -> RVec { v }\n\
+             #[flux::sig(fn() -> i32[unused()])]\n\
+             fn unrelated() {}\n\
+             flux_rs::defs! {\n    fn len(n: int) -> int;\n    fn size(n: int) -> bool;\n    \
+             fn unused() -> int;\n}\n",
+        )
+        .unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+
+        let GoalContext::Failing { bundle } = runner
+            .goal_context_bundle(repo_path, DEFAULT_BUNDLE_CHARS)
+            .await
+            .unwrap()
+        else {
+            panic!("expected a failing goal");
+        };
+        assert_eq!(bundle.rendered, "error: refinement type error");
+        assert!(bundle.goal.diagnostic.message.rendered.is_none());
+        let item = bundle.enclosing_item.as_ref().unwrap();
+        assert_eq!((item.name.as_str(), item.start_line, item.end_line), ("f", 1, 4));
+        assert!(item.source.ends_with("    push(v)\n}\n"));
+        let sigs: Vec<_> = bundle
+            .callee_sigs
+            .iter()
+            .map(|sig| sig.name.as_str())
+            .collect();
+        assert_eq!(sigs, ["push"]);
+        let defs: Vec<_> = bundle.defs.iter().map(|def| def.name.as_str()).collect();
+        assert_eq!(defs, ["len", "size"]);
+        assert!(bundle.truncation.is_none());
+
+        let GoalContext::Failing { bundle } =
+            runner.goal_context_bundle(repo_path, 860).await.unwrap()
+        else {
+            panic!("expected a failing goal");
+        };
+        let truncation = bundle.truncation.clone().unwrap();
+        assert_eq!((truncation.dropped_defs, truncation.dropped_callee_sigs), (2, 1));
+        assert!(truncation.source_cut);
+        let source = &bundle.enclosing_item.as_ref().unwrap().source;
+        assert!(source.starts_with("#[flux::sig") && source.ends_with("// ...\n"));
+        assert!(serde_json::to_string(&*bundle).unwrap().len() <= 860);
+    }
+
+    #[tokio::test]
+    async fn lemma_context_is_the_whole_file_cut_at_the_limit() {
+        let lemma = lemma_line("lemmas::head_cons_eq", "lib.rs", 2);
+        let (dir, runner) = fake_runner(&format!("echo '{lemma}'"));
+        let repo_path = dir.path().to_str().unwrap();
+        fs::write(dir.path().join("lib.rs"), "use foo::Bar;\nfn head_cons_eq() {}\n").unwrap();
+
+        let context = runner
+            .lemma_context(repo_path, "head_cons_eq")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(context.contents, "use foo::Bar;\nfn head_cons_eq() {}\n");
+        assert_eq!((context.start_line, context.end_line), (2, 2));
+        assert!(!context.truncated);
+        assert!(
+            runner
+                .lemma_context(repo_path, "missing")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let line = format!("// {}\n", "x".repeat(1000));
+        fs::write(dir.path().join("lib.rs"), line.repeat(MAX_CONTEXT_BYTES / 1000)).unwrap();
+        let context = runner
+            .lemma_context(repo_path, "head_cons_eq")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(context.truncated);
+        assert!(context.contents.len() <= MAX_CONTEXT_BYTES);
+        assert!(context.contents.ends_with('\n'));
+    }
+
+    #[test]
+    fn lemma_diffs_match_by_name() {
+        let lemma = |name: &str, line: i64| {
+            Lemma {
+                name: name.to_string(),
+                file_name: "lib.rs".to_string(),
+                start_line: line,
+                start_col: 0,
+                end_line: line,
+                end_col: 10,
+                requires: vec![],
+                ensures: vec![],
+                params: vec![],
+                trusted: false,
+            }
+        };
+        let before = [lemma("kept", 1), lemma("moved", 2), lemma("gone", 3)];
+        let after = [lemma("new", 1), lemma("moved", 5), lemma("kept", 1)];
+
+        let diff = diff_lemmas(&before, &after);
+        let names = |lemmas: &[Lemma]| -> Vec<String> {
+            lemmas.iter().map(|lemma| lemma.name.clone()).collect()
+        };
+        assert_eq!(names(&diff.added), ["new"]);
+        assert_eq!(names(&diff.removed), ["gone"]);
+        assert_eq!(diff.moved.len(), 1);
+        assert_eq!((diff.moved[0].before.start_line, diff.moved[0].after.start_line), (2, 5));
+        assert_eq!(diff.unchanged, 1);
+    }
+
+    #[test]
+    fn lemma_locations_count_utf16_code_units() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("lib.rs"), "// 😀\n/* 漢😀 */ fn lemma() {}\n").unwrap();
+        let lemma = Lemma {
+            name: "lemma".to_string(),
+            file_name: "lib.rs".to_string(),
+            start_line: 2,
+            start_col: 9,
+            end_line: 2,
+            end_col: 21,
+            requires: vec![],
+            ensures: vec![],
+            params: vec![],
+            trusted: false,
+        };
+        let repo_path = dir.path().to_str().unwrap();
+
+        let range = lemma.to_location(repo_path, PositionEncoding::Utf16).range;
+        assert_eq!((range.start.line, range.start.character), (1, 10));
+        assert_eq!(range.end.character, 22);
+        let range = lemma.to_location(repo_path, PositionEncoding::Utf32).range;
+        assert_eq!((range.start.character, range.end.character), (9, 21));
+    }
+
+    #[test]
+    fn coordinates_are_rebased_for_spans_and_lemmas() {
+        let mut diagnostic = FluxRunner::parse_flux_line(MESSAGE).unwrap();
+        let mut expansion_span = diagnostic.message.spans[0].clone();
+        expansion_span.line_start = 10;
+        diagnostic.message.spans[0].expansion =
+            Some(Box::new(diagnostics::DiagnosticSpanExpansion {
+                span: expansion_span,
+                macro_decl_name: "m!".to_string(),
+                def_site_span: None,
+            }));
+        let mut diagnostics = vec![diagnostic];
+
+        diagnostics::rebase_coordinates(&mut diagnostics, 1);
+        let span = &diagnostics[0].message.spans[0];
+        assert_eq!((span.line_start, span.column_start), (3, 5));
+        diagnostics::rebase_coordinates(&mut diagnostics, 0);
+        let span = &diagnostics[0].message.spans[0];
+        assert_eq!(
+            (span.line_start, span.column_start, span.line_end, span.column_end),
+            (2, 4, 2, 8)
+        );
+        assert_eq!(span.expansion.as_ref().unwrap().span.line_start, 9);
+
+        let mut lemma = Lemma {
+            name: "lemma".to_string(),
+            file_name: "lib.rs".to_string(),
+            start_line: 2,
+            start_col: 4,
+            end_line: 3,
+            end_col: 1,
+            requires: vec![],
+            ensures: vec![],
+            params: vec![],
+            trusted: false,
+        };
+        lemma.rebase_coordinates(1);
+        assert_eq!((lemma.start_line, lemma.start_col, lemma.end_line), (2, 4, 3));
+        lemma.rebase_coordinates(0);
+        assert_eq!((lemma.start_line, lemma.start_col, lemma.end_line), (1, 4, 2));
+    }
+
+    #[tokio::test]
+    async fn lemma_graph_edges_follow_bodies_and_signatures() {
+        let script = ["a", "b", "c"]
+            .map(|name| format!("echo '{}'", lemma_line(name, "lib.rs", 1)))
+            .join("\n");
+        let (dir, runner) = fake_runner(&script);
+        fs::write(
+            dir.path().join("lib.rs"),
+            "fn a() { b(); a(); }\n\
+             fn b() { a(); }\n\
+             #[flux::sig(fn() ensures a())]\n\
+             fn c() {}\n",
+        )
+        .unwrap();
+
+        let graph = runner
+            .lemma_graph(dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        let edges: Vec<_> = graph
+            .edges
+            .iter()
+            .map(|edge| (edge.from.as_str(), edge.to.as_str()))
+            .collect();
+        assert_eq!(edges, [("a", "b"), ("b", "a"), ("c", "a")]);
+        assert!(graph.to_dot().contains("    \"c\" -> \"a\";\n"));
+    }
+
+    #[tokio::test]
+    async fn lemma_clauses_are_read_from_the_signature() {
+        let lemma = lemma_line("head_cons_eq", "lib.rs", 1);
+        let (dir, runner) = fake_runner(&format!("echo '{lemma}'"));
+        fs::write(
+            dir.path().join("lib.rs"),
+            "#[flux::sig(fn(i32[@v], &Foo[@elems]) requires len(elems) > 0, v > 0\n\
+             ensures head(cons(v, elems)) ==\n    v)]\n\
+             fn head_cons_eq(v: i32, elems: &Foo) {}\n",
+        )
+        .unwrap();
+
+        let lemmas = runner
+            .get_lemmas(dir.path().to_str().unwrap(), None)
+            .await
+            .unwrap();
+        assert_eq!(lemmas[0].requires, ["len(elems) > 0", "v > 0"]);
+        assert_eq!(lemmas[0].params, ["v", "elems"]);
+        assert_eq!(lemmas[0].ensures, ["head(cons(v, elems)) == v"]);
+    }
+
+    #[tokio::test]
+    async fn goals_are_matched_by_span_containment() {
+        let (dir, runner) = fake_runner(&format!("echo '{MESSAGE}'\nexit 1"));
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "fn f(x: i32) -> i32 {\n    let y = x;\n    y + 1\n}\n\
+             #[flux::trusted]\nfn g() {\n    h()\n}\nfn h() {}\n\n",
+        )
+        .unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+        let status = |line| {
+            let runner = &runner;
+            async move {
+                runner
+                    .goal_status(repo_path, "src/lib.rs", line)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let GoalStatus::Failed { diagnostic } = status(3).await else { panic!() };
+        assert_eq!(diagnostic.message.message, "refinement type error");
+        assert!(matches!(status(4).await, GoalStatus::Discharged));
+        assert!(matches!(status(9).await, GoalStatus::Discharged));
+        let GoalStatus::NotChecked { reason } = status(7).await else { panic!() };
+        assert!(reason.contains("`g` is trusted"), "{reason}");
+        assert!(matches!(status(10).await, GoalStatus::NotChecked { .. }));
+    }
+
+    #[tokio::test]
+    async fn lemmas_are_streamed_as_flux_prints_them() {
+        let (dir, cargo_path) = fake_cargo(&format!(
+            "echo '{}'\necho '{}'\necho '{}'",
+            lemma_line("a", "lib.rs", 1),
+            lemma_line("b", "lib.rs", 1),
+            lemma_line("c", "lib.rs", 1)
+        ));
+        let runner = FluxRunner::with_config(FluxRunnerConfig {
+            cargo_path,
+            stream_buffer: 1,
+            ..Default::default()
+        });
+        let (sink, stream) = runner.lemma_channel();
+        let consumer = tokio::spawn(async move {
+            let mut stream = stream;
+            let mut names = vec![];
+            while let Some(lemma) = stream.recv().await {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                names.push(lemma.name);
+            }
+            names
+        });
+
+        let lemmas = runner
+            .get_lemmas_streaming(dir.path().to_str().unwrap(), None, sink)
+            .await
+            .unwrap();
+        assert_eq!(consumer.await.unwrap(), ["a", "b", "c"]);
+        assert_eq!(lemmas.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn trusted_lemmas_are_told_apart_from_proven_ones() {
+        let (dir, runner) = fake_runner(&format!(
+            "echo '{}'\necho '{}'\necho '{}'",
+            lemma_line("head_cons_eq", "lib.rs", 1),
+            lemma_line("len_pos", "lib.rs", 1),
+            lemma_line("opted_out", "lib.rs", 1)
+        ));
+        fs::write(
+            dir.path().join("lib.rs"),
+            "#[flux::trusted]\n#[flux::sig(fn(i32[@v], &Foo[@elems]) ensures head(cons(v, elems)) == v)]\n\
+             fn head_cons_eq(v: i32, elems: &Foo) {}\n\
+             #[flux::sig(fn(&Foo[@elems]) ensures len(elems) >= 0)]\nfn len_pos(elems: &Foo) {}\n\
+             #[flux::trusted(no)]\n#[flux::sig(fn(bool[true]))]\nfn opted_out(b: bool) {}\n",
+        )
+        .unwrap();
+
+        let lemmas = runner
+            .get_lemmas(dir.path().to_str().unwrap(), None)
+            .await
+            .unwrap();
+        let trusted: Vec<_> = lemmas
+            .iter()
+            .map(|lemma| (lemma.name.as_str(), lemma.trusted))
+            .collect();
+        assert_eq!(trusted, [("head_cons_eq", true), ("len_pos", false), ("opted_out", false)]);
+    }
+
+    #[tokio::test]
+    async fn lemma_defs_are_found_in_the_synthesis_fixtures() {
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/tests/pos/synthesis");
+        let runner = FluxRunner::new();
+        let defs = runner.lemma_referenced_defs(fixtures, "head_cons_eq").await;
+        let mut names: Vec<_> = defs.iter().map(|def| def.name.as_str()).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names, ["cons", "head"]);
+        assert!(
+            runner
+                .lemma_referenced_defs(fixtures, "missing")
+                .await
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn definitions_are_listed_with_their_kind_and_source() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src/seq")).unwrap();
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "mod seq;\n\nflux_rs::defs! {\n    opaque sort ISeq;\n    #[hide]\n    \
+             pub fn len(elems: ISeq) -> int;\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("src/seq/mod.rs"),
+            "defs! {\n    qualifier Pos(x: int) { x > 0 }\n    property Nonneg(x: int) { x >= 0 }\n}\n",
+        )
+        .unwrap();
+
+        let mut defs = FluxRunner::new()
+            .get_definitions(dir.path().to_str().unwrap())
+            .await;
+        defs.sort_by(|a, b| (&a.file_name, a.line).cmp(&(&b.file_name, b.line)));
+        let listed: Vec<_> = defs
+            .iter()
+            .map(|def| (def.kind, def.name.as_str(), def.file_name.as_str(), def.line))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                (DefKind::Sort, "ISeq", "src/lib.rs", 4),
+                (DefKind::Func, "len", "src/lib.rs", 6),
+                (DefKind::Qualifier, "Pos", "src/seq/mod.rs", 2),
+                (DefKind::Property, "Nonneg", "src/seq/mod.rs", 3),
+            ]
+        );
+        assert_eq!(defs[1].signature, "fn len(elems: ISeq) -> int");
+        assert_eq!(defs[2].signature, "qualifier Pos(x: int) { x > 0 }");
+    }
+
+    #[tokio::test]
+    async fn sorts_and_funcs_are_listed_with_their_arities() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(
+            dir.path().join("src/lib.rs"),
+            "flux_rs::defs! {\n    opaque sort ISeq;\n    fn cons(v: int, elems: ISeq) -> ISeq;\n    \
+             fn len(elems: ISeq) -> int;\n    fn two() -> int { 2 }\n    qualifier Pos(x: int) { x > 0 }\n}\n",
+        )
+        .unwrap();
+        let runner = FluxRunner::new();
+        let grouped = runner
+            .list_sorts_and_funcs(dir.path().to_str().unwrap())
+            .await;
+        let sorts: Vec<_> = grouped.sorts.iter().map(|def| def.name.as_str()).collect();
+        assert_eq!(sorts, ["ISeq"]);
+        let funcs: Vec<_> = grouped
+            .funcs
+            .iter()
+            .map(|def| (def.name.as_str(), def.params.len(), def.return_sort.as_deref()))
+            .collect();
+        assert_eq!(funcs, [("cons", 2, Some("ISeq")), ("len", 1, Some("int"))]);
+        assert_eq!(grouped.funcs[0].params, ["v: int", "elems: ISeq"]);
+    }
+
+    #[tokio::test]
+    async fn shutdown_kills_processes_past_the_grace_period() {
+        let (dir, runner) = fake_runner("sleep 30");
+        let runner = Arc::new(runner);
+        let children = runner.children();
+        let repo_path = dir.path().to_str().unwrap().to_string();
+        let run = tokio::spawn({
+            let runner = runner.clone();
+            async move {
+                let cmd = runner.flux_command(&repo_path, None, None);
+                runner.run_command(cmd, |_| {}).await
+            }
+        });
+        while children.running().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let start = Instant::now();
+        children.shutdown(Duration::from_millis(200)).await;
+        let success = run.await.unwrap().unwrap();
+        assert!(!success);
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(children.running().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn verifications_of_different_repos_run_in_parallel() {
+        let (_cargo_dir, cargo_path) = fake_cargo(&format!("sleep 1\necho '{MESSAGE}'"));
+        let runner = Arc::new(FluxRunner::with_config(FluxRunnerConfig {
+            cargo_path,
+            max_concurrent_verifications: 2,
+            ..Default::default()
+        }));
+        let repos = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+
+        let start = Instant::now();
+        let runs: Vec<_> = repos
+            .iter()
+            .map(|repo| {
+                let runner = runner.clone();
+                let repo_path = repo.path().to_str().unwrap().to_string();
+                tokio::spawn(async move { runner.verify_repository(&repo_path, false).await })
+            })
+            .collect();
+        for run in runs {
+            assert_eq!(run.await.unwrap().unwrap().diagnostics.len(), 1);
+        }
+        assert!(start.elapsed() < Duration::from_millis(1900));
+    }
+
+    #[tokio::test]
+    async fn a_running_verification_leaves_the_worker_free() {
+        let (_cargo_dir, cargo_path) = fake_cargo(&format!("sleep 1\necho '{MESSAGE}'"));
+        let runner = Arc::new(FluxRunner::with_config(FluxRunnerConfig {
+            cargo_path,
+            max_concurrent_verifications: 2,
+            ..Default::default()
+        }));
+        let repos = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+        let verify = |repo: &tempfile::TempDir| {
+            let runner = runner.clone();
+            let repo_path = repo.path().to_str().unwrap().to_string();
+            tokio::spawn(async move { runner.verify_repository(&repo_path, false).await })
+        };
+
+        // On a current-thread runtime the second run only starts while the first is still
+        // waiting on flux if waiting doesn't block the thread.
+        let start = Instant::now();
+        let (first, second) = tokio::join!(verify(&repos[0]), verify(&repos[1]));
+        assert_eq!(first.unwrap().unwrap().diagnostics.len(), 1);
+        assert_eq!(second.unwrap().unwrap().diagnostics.len(), 1);
+        assert!(start.elapsed() < Duration::from_millis(1900));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn batch_verifications_isolate_failing_repos() {
+        let (_cargo_dir, cargo_path) = fake_cargo("exit 0");
+        let runner = Arc::new(FluxRunner::with_config(FluxRunnerConfig {
+            cargo_path,
+            ..Default::default()
+        }));
+        let repo = tempfile::tempdir().unwrap();
+        let repo_paths =
+            ["/nonexistent/repo".to_string(), repo.path().to_str().unwrap().to_string()];
+
+        let result = runner.verify_many(&repo_paths).await;
+        assert!(!result.all_passed);
+        assert_eq!(result.repos[0].repo_path, repo_paths[0]);
+        assert!(result.repos[0].report.is_none());
+        assert!(
+            result.repos[0]
+                .error
+                .as_ref()
+                .unwrap()
+                .contains("/nonexistent/repo")
+        );
+        assert!(result.repos[1].report.as_ref().unwrap().success);
+
+        let result = runner.verify_many(&repo_paths[1..]).await;
+        assert!(result.all_passed);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn identical_verifications_in_flight_share_one_run() {
+        let (cargo_dir, cargo_path) =
+            fake_cargo(&format!("echo run >> \"$0.runs\"\nsleep 1\necho '{MESSAGE}'\nexit 1"));
+        let runner = Arc::new(FluxRunner::with_config(FluxRunnerConfig {
+            cargo_path,
+            max_concurrent_verifications: 4,
+            ..Default::default()
+        }));
+        let repo = tempfile::tempdir().unwrap();
+        let runs = || {
+            let log = fs::read_dir(cargo_dir.path())
+                .unwrap()
+                .flatten()
+                .find(|entry| entry.path().extension().is_some_and(|ext| ext == "runs"))
+                .unwrap()
+                .path();
+            fs::read_to_string(log).unwrap().lines().count()
+        };
+
+        let verifications: Vec<_> = (0..3)
+            .map(|i| {
+                let runner = runner.clone();
+                let repo_path = repo.path().to_str().unwrap().to_string();
+                let options = RunOptions { fail_fast: i == 2, ..Default::default() };
+                tokio::spawn(async move {
+                    runner
+                        .verify_repository_with(&repo_path, options, RepoConfig::default())
+                        .await
+                })
+            })
+            .collect();
+        for verification in verifications {
+            assert_eq!(verification.await.unwrap().unwrap().diagnostics.len(), 1);
+        }
+        // The first two are identical, the third asks for fail-fast.
+        assert_eq!(runs(), 2);
+
+        runner
+            .verify_repository(repo.path().to_str().unwrap(), false)
+            .await
+            .unwrap();
+        assert_eq!(runs(), 3);
+    }
+
+    #[tokio::test]
+    async fn edited_sources_do_not_join_the_run_for_the_old_ones() {
+        let (dir, runner) =
+            fake_runner(&format!("echo run >> \"$0.runs\"\nsleep 1\necho '{MESSAGE}'\nexit 1"));
+        let runner = Arc::new(runner);
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "fn f() {}\n").unwrap();
+        let repo_path = dir.path().to_str().unwrap().to_string();
+
+        let first = tokio::spawn({
+            let (runner, repo_path) = (runner.clone(), repo_path.clone());
+            async move { runner.verify_repository(&repo_path, false).await }
+        });
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        fs::write(dir.path().join("src/lib.rs"), "fn g() {}\n").unwrap();
+        runner.verify_repository(&repo_path, false).await.unwrap();
+        first.await.unwrap().unwrap();
+        let runs = fs::read_to_string(dir.path().join("cargo.runs")).unwrap();
+        assert_eq!(runs.lines().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn inline_source_spans_point_into_the_source() {
+        let message = MESSAGE.replace("src/lib.rs", SOURCE_FILE_NAME);
+        let (dir, runner) =
+            fake_runner(&format!("pwd > \"$(dirname \"$0\")/package\"\necho '{message}'\nexit 1"));
+
+        let report = runner.verify_source("fn f() {}\n").await.unwrap();
+        assert!(!report.success);
+        assert_eq!(report.diagnostics[0].message.spans[0].file_name, SOURCE_FILE_NAME);
+        assert_eq!(report.diagnostics[0].message.spans[0].line_start, 3);
+        let package = fs::read_to_string(dir.path().join("package")).unwrap();
+        assert!(!Path::new(package.trim()).exists());
+    }
+
+    #[tokio::test]
+    async fn lemma_syntax_checks_trust_everything_and_keep_spec_errors() {
+        let spec_error = MESSAGE
+            .replace("src/lib.rs", SOURCE_FILE_NAME)
+            .replace("refinement type error", "cannot find value `elems` in this scope");
+        let (_dir, runner) = fake_runner(&format!(
+            "[ \"$FLUXFLAGS\" = -Ftrusted=true ] || exit 2\n\
+             [ \"$(head -n 1 source.rs)\" = '#[flux::lemma]' ] || exit 2\n\
+             grep -q 'struct Foo' source.rs || exit 2\n\
+             echo '{MESSAGE}'\necho '{spec_error}'\nexit 1"
+        ));
+
+        let report = runner
+            .check_lemma_syntax("#[flux::lemma]\nfn l() {}", Some("struct Foo;\n"))
+            .await
+            .unwrap();
+        assert!(!report.success);
+        assert_eq!(report.diagnostics.len(), 1);
+        assert!(
+            report.diagnostics[0]
+                .message
+                .message
+                .starts_with("cannot find value")
+        );
+    }
+
+    #[tokio::test]
+    async fn runs_past_the_timeout_are_killed() {
+        let (dir, runner) = fake_runner(&format!("echo '{MESSAGE}'\nsleep 30"));
+        let repo_path = dir.path().to_str().unwrap();
+        let overrides = RepoConfig { timeout_secs: Some(1), ..Default::default() };
+
+        let start = Instant::now();
+        let err = runner
+            .verify_repository_with(repo_path, RunOptions::default(), overrides)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::Timeout(timeout) if timeout.as_secs() == 1));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(runner.children().running().is_empty());
+
+        let start = Instant::now();
+        let err = runner
+            .verify_package_with_timeout(repo_path, Some(&["foo"]), Some(Duration::from_secs(1)))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Flux timed out after 1 seconds");
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(runner.children().running().is_empty());
+
+        let start = Instant::now();
+        let err = runner
+            .verify_per_package(repo_path, &["foo", "bar"], Some(Duration::from_secs(1)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::Timeout(_)));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(runner.children().running().is_empty());
+    }
+
+    #[tokio::test]
+    async fn fail_fast_stops_at_the_first_flux_error() {
+        let build_error = MESSAGE.replace("refinement type error", "mismatched types");
+        let (dir, runner) = fake_runner(&format!(
+            "echo '{build_error}'\necho '{MESSAGE}'\necho '{MESSAGE}'\nsleep 30"
+        ));
+        let options = RunOptions { fail_fast: true, ..Default::default() };
+
+        let start = Instant::now();
+        let report = runner
+            .verify_repository_with(dir.path().to_str().unwrap(), options, RepoConfig::default())
+            .await
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(!report.success);
+        let messages: Vec<_> = report
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message.message.as_str())
+            .collect();
+        assert_eq!(messages, ["mismatched types", "refinement type error"]);
+        assert!(runner.children().running().is_empty());
+    }
+
+    #[tokio::test]
+    async fn keep_going_lists_the_packages_a_build_failure_skipped() {
+        let metadata = r#"{"packages":[{"name":"foo","id":"foo 0.1.0"},{"name":"util","id":"util 0.1.0"},{"name":"app","id":"app 0.1.0"}]}"#;
+        let (dir, runner) = fake_runner(&format!(
+            "if [ \"$1\" = metadata ]; then echo '{metadata}'; exit 0; fi\n\
+             case \"$*\" in *--keep-going*) ;; *) echo '{MESSAGE}'; exit 101;; esac\n\
+             echo '{{\"reason\":\"compiler-artifact\",\"package_id\":\"util 0.1.0\"}}'\n\
+             echo '{MESSAGE}'\nexit 101"
+        ));
+        let repo_path = dir.path().to_str().unwrap();
+        let keep_going = RepoConfig { keep_going: Some(true), ..Default::default() };
+
+        let report = runner
+            .verify_repository_with(repo_path, RunOptions::default(), keep_going)
+            .await
+            .unwrap();
+        assert!(!report.success);
+        assert_eq!(report.diagnostics.len(), 1);
+        assert_eq!(report.skipped_packages, ["app"]);
+
+        let stopped = runner.verify_repository(repo_path, false).await.unwrap();
+        assert_eq!(stopped.diagnostics.len(), 1);
+        assert!(stopped.skipped_packages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn smt_timeouts_are_dropped_when_flux_lacks_them() {
+        let timed_out = MESSAGE.replace("refinement type error", "SMT query timed out");
+        let (dir, runner) = fake_runner(&format!(
+            "case \"$FLUXFLAGS\" in *-Fsmt-timeout=*) \
+             echo 'error: unknown flux option: `smt-timeout`' >&2; exit 1;; esac\n\
+             echo '{timed_out}'\nexit 1"
+        ));
+        let overrides = RepoConfig { smt_timeout_ms: Some(500), ..Default::default() };
+
+        let report = runner
+            .verify_repository_with(dir.path().to_str().unwrap(), RunOptions::default(), overrides)
+            .await
+            .unwrap();
+        assert_eq!(report.diagnostics.len(), 1);
+        // Without the option nothing can be attributed to it.
+        assert!(report.smt_timeouts.is_empty());
+
+        let (dir, runner) = fake_runner(&format!("echo '{timed_out}'\nexit 1"));
+        let overrides = RepoConfig { smt_timeout_ms: Some(500), ..Default::default() };
+        let report = runner
+            .verify_repository_with(dir.path().to_str().unwrap(), RunOptions::default(), overrides)
+            .await
+            .unwrap();
+        assert_eq!(report.smt_timeouts, ["src/lib.rs:3:5"]);
+    }
+
+    #[tokio::test]
+    async fn overflow_checking_can_be_turned_off_per_call() {
+        let (dir, runner) = fake_runner("echo \"$FLUXFLAGS\" > \"$(dirname \"$0\")/flags\"");
+        fs::write(
+            dir.path().join("flux-mcp.toml"),
+            "default_flux_flags = [\"-Fcheck-overflow=strict\"]\n",
+        )
+        .unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+
+        let report = runner
+            .verify_repository_with(repo_path, RunOptions::default(), RepoConfig::default())
+            .await
+            .unwrap();
+        assert!(report.check_overflow);
+        let overrides = RepoConfig { check_overflow: Some(false), ..Default::default() };
+        let report = runner
+            .verify_repository_with(repo_path, RunOptions::default(), overrides)
+            .await
+            .unwrap();
+        assert!(!report.check_overflow);
+        let flags = fs::read_to_string(dir.path().join("flags")).unwrap();
+        assert_eq!(flags.trim(), "-Fcheck-overflow=strict -Fcheck-overflow=none");
+    }
+
+    #[tokio::test]
+    async fn package_function_and_lemma_runs_use_the_repository_settings() {
+        let (dir, runner) =
+            fake_runner("echo \"$FLUXFLAGS|$Z3_PATH|$*\" >> \"$(dirname \"$0\")/runs\"");
+        fs::write(
+            dir.path().join("flux-mcp.toml"),
+            "default_flux_flags = [\"-Fcheck-overflow=strict\"]\nsolver = \"cvc5\"\n\
+             packages = [\"foo\"]\n[env]\nZ3_PATH = \"/opt/z3\"\n",
+        )
+        .unwrap();
+        let repo_path = dir.path().to_str().unwrap();
+
+        runner.verify_package(repo_path, None).await.unwrap();
+        runner
+            .verify_package(repo_path, Some(&["bar"]))
+            .await
+            .unwrap();
+        runner
+            .verify_function(repo_path, "foo", "foo::f")
+            .await
+            .unwrap();
+        runner.get_lemmas(repo_path, None).await.unwrap();
+        let runs = fs::read_to_string(dir.path().join("runs")).unwrap();
+        assert_eq!(
+            runs.lines().collect::<Vec<_>>(),
+            [
+                "-Fcheck-overflow=strict -Fsolver=cvc5|/opt/z3|flux -p foo --message-format=json",
+                "-Fcheck-overflow=strict -Fsolver=cvc5|/opt/z3|flux -p bar --message-format=json",
+                "-Fcheck-overflow=strict -Finclude=def:f -Fsolver=cvc5|/opt/z3|flux -p foo \
+             --message-format=json",
+                "-Fcheck-overflow=strict -Fdump-lemmas -Fsolver=cvc5|/opt/z3|flux -p foo \
+             --message-format=json",
+            ]
+        );
+    }
+
+    #[test]
+    fn dry_runs_render_the_command() {
+        let (dir, runner) = fake_runner("exit 1");
+        fs::write(dir.path().join("Cargo.toml"), "[package]\nname = \"foo\"\n").unwrap();
+        let overrides = RepoConfig {
+            default_flux_flags: Some(vec!["-Fcheck-overflow=strict".to_string()]),
+            packages: Some(vec!["foo".to_string()]),
+            solver: Some("cvc5".to_string()),
+            env: Some(BTreeMap::from([("Z3_PATH".to_string(), "/opt/z3".to_string())])),
+            ..Default::default()
+        };
+
+        let root = dir.path().canonicalize().unwrap();
+        let cargo = root.join("cargo");
+        assert_eq!(
+            runner
+                .dry_run(dir.path().to_str().unwrap(), true, overrides)
+                .unwrap(),
+            format!(
+                "cd {} && FLUXFLAGS='-Fcheck-overflow=strict -Fsolver=cvc5 -Ftimings -Flog-dir=<log-dir>' \
+                 Z3_PATH=/opt/z3 {} flux -p foo --message-format=json",
+                root.display(),
+                cargo.display()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn formatting_happens_in_a_copy_unless_written() {
+        let (dir, runner) = fake_runner(&format!(
+            "if [ \"$1\" = fmt ]; then sed -i 's/  */ /g' src/*.rs; exit 0; fi\n\
+             echo '{MESSAGE}'\nexit 1"
+        ));
+        let repo_path = dir.path().to_str().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "fn  f() {}\n").unwrap();
+        fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+
+        let (report, fmt) = runner.verify_and_fmt(repo_path, None, false).await.unwrap();
+        assert_eq!(report.diagnostics.len(), 1);
+        assert_eq!(
+            fmt.files,
+            [FormattedFile {
+                file_name: "src/lib.rs".to_string(),
+                contents: "fn f() {}\n".to_string(),
+                changed: true,
+            }]
+        );
+        assert_eq!(fs::read_to_string(dir.path().join("src/lib.rs")).unwrap(), "fn  f() {}\n");
+
+        let files = ["src/main.rs".to_string(), "src/lib.rs".to_string()];
+        let (_, fmt) = runner
+            .verify_and_fmt(repo_path, Some(&files), true)
+            .await
+            .unwrap();
+        let changed: Vec<_> = fmt.files.iter().map(|file| file.changed).collect();
+        assert_eq!(changed, [false, true]);
+        assert_eq!(fs::read_to_string(dir.path().join("src/lib.rs")).unwrap(), "fn f() {}\n");
+
+        let (dir, runner) =
+            fake_runner("[ \"$1\" = fmt ] && echo 'error: bad syntax' >&2 && exit 1\nexit 0");
+        let (report, fmt) = runner
+            .verify_and_fmt(dir.path().to_str().unwrap(), None, true)
+            .await
+            .unwrap();
+        assert!(report.success);
+        assert_eq!(fmt.error.as_deref(), Some("error: bad syntax"));
+    }
+
+    #[tokio::test]
+    async fn flux_versions_are_the_first_line_of_a_successful_run() {
+        let (dir, runner) = fake_runner(
+            "[ \"$*\" = 'flux --version' ] || exit 2\necho\necho ' flux 0.1.0 (abc123) '\necho more",
+        );
+        let version = runner.flux_version().await.unwrap();
+        assert_eq!(version.version.as_deref(), Some("flux 0.1.0 (abc123)"));
+        let cargo_path = dir.path().join("cargo").canonicalize().unwrap();
+        assert_eq!(version.cargo_path, cargo_path.display().to_string());
+
+        let (_dir, runner) = fake_runner("echo 'error: no such command: `flux`'\nexit 101");
+        assert_eq!(runner.flux_version().await.unwrap().version, None);
+    }
+
+    #[tokio::test]
+    async fn supported_flags_are_read_from_the_help_text() {
+        let (_dir, runner) = fake_runner(
+            "[ \"$2\" = --help ] || exit 1\n\
+             echo 'Usage: cargo flux [OPTIONS]'\n\
+             echo '  -Fcheck-overflow=<strict|none>  Check arithmetic for overflow'\n\
+             echo '  -Fsolver=<z3|cvc5>, -Fdump-lemmas'\n\
+             echo '  --features <FEATURES>, -F <FEATURES>  Cargo features'\n\
+             echo '  -Fsolver=z3'",
+        );
+        let flags = runner.supported_flags().await.unwrap();
+        assert_eq!(flags, ["check-overflow", "dump-lemmas", "solver"]);
+
+        let (_dir, runner) = fake_runner("exit 101");
+        let err = runner.supported_flags().await.unwrap_err();
+        assert!(matches!(err, FluxRunnerError::FluxNotInstalled));
+    }
+
+    #[tokio::test]
+    async fn debug_dumps_go_to_the_log_instead_of_the_report() {
+        let (dir, runner) = fake_runner(&format!(
+            "dir=$(echo \"$FLUXFLAGS\" | sed 's/.*-Flog-dir=//')\n\
+             echo '(constraint)' > \"$dir/foo-f.fluxc\"\n\
+             echo 'checking f' > \"$dir/foo-f.trace\"\n\
+             echo '{MESSAGE}'\nexit 1"
+        ));
+        let repo_path = dir.path().to_str().unwrap();
+
+        let (log_path, report) = runner
+            .debug_verify(repo_path, Path::new("solver.log"))
+            .await
+            .unwrap();
+        assert_eq!(log_path, dir.path().canonicalize().unwrap().join("solver.log"));
+        assert_eq!(report.diagnostics.len(), 1);
+        assert!(!report.success);
+        assert_eq!(
+            fs::read_to_string(log_path).unwrap(),
+            "==> foo-f.fluxc <==\n(constraint)\n\n==> foo-f.trace <==\nchecking f\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn slow_goals_are_ranked_from_the_timings_dumps() {
+        let (dir, runner) = fake_runner(
+            "dir=$(echo \"$FLUXFLAGS\" | sed 's/.*-Flog-dir=//')\n\
+             echo '{\"total\":9,\"functions\":[{\"def\":\"f\",\"time_ms\":2},\
+             {\"def\":\"g\",\"time_ms\":7},{\"def\":\"h\",\"time_ms\":1}]}' \
+             > \"$dir/foo-timings.json\"",
+        );
+        let repo_path = dir.path().to_str().unwrap();
+
+        let slow = runner.slow_goals(repo_path, 2).await.unwrap();
+        assert_eq!(slow.granularity, "function");
+        let goals: Vec<_> = slow
+            .goals
+            .iter()
+            .map(|goal| (goal.item.as_str(), goal.time_ms))
+            .collect();
+        assert_eq!(goals, [("g", 7), ("f", 2)]);
+
+        let (dir, runner) = fake_runner(
+            "[ \"$1\" = metadata ] && echo '{\"packages\":[{\"name\":\"a\"},{\"name\":\"b\"}]}'\n\
+             exit 0",
+        );
+        let repo_path = dir.path().to_str().unwrap();
+        let slow = runner
+            .slow_goals(repo_path, DEFAULT_SLOW_GOALS)
+            .await
+            .unwrap();
+        assert_eq!(slow.granularity, "package");
+        let mut items: Vec<_> = slow.goals.iter().map(|goal| goal.item.as_str()).collect();
+        items.sort();
+        assert_eq!(items, ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn smt_caches_are_created_and_their_hits_reported() {
+        let (_cargo_dir, cargo_path) = fake_cargo(
+            "echo \"$FLUX_CACHE\" > \"$0.cache\"\n\
+             dir=$(echo \"$FLUXFLAGS\" | sed 's/.*-Flog-dir=//')\n\
+             echo '{\"total\":1,\"cache_hits\":5,\"cache_misses\":2}' > \"$dir/foo-timings.json\"",
+        );
+        let cache = tempfile::tempdir().unwrap();
+        let smt_cache_dir = cache.path().join("smt");
+        let runner = FluxRunner::with_config(FluxRunnerConfig {
+            cargo_path: cargo_path.clone(),
+            smt_cache_dir: Some(smt_cache_dir.clone()),
+            ..Default::default()
+        });
+        let repo = tempfile::tempdir().unwrap();
+
+        let report = runner
+            .verify_repository(repo.path().to_str().unwrap(), true)
+            .await
+            .unwrap();
+        assert!(smt_cache_dir.is_dir());
+        let exported = fs::read_to_string(format!("{}.cache", cargo_path.display())).unwrap();
+        assert_eq!(exported.trim(), smt_cache_dir.join(SMT_CACHE_FILE).to_str().unwrap());
+        let stats = report.solver_stats.unwrap();
+        assert_eq!((stats.cache_hits, stats.cache_misses), (Some(5), Some(2)));
+    }
+
+    #[test]
+    fn timings_dumps_are_summed_across_crates() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(FluxRunner::parse_timings_dumps(dir.path()).is_none());
+        fs::write(
+            dir.path().join("foo-timings.json"),
+            r#"{"total":30,"functions":[{"def":"foo::f","time_ms":12},{"name":"g","time_ms":3}],
+                "queries":[{"time_ms":7},{"time_ms":5}]}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("bar-timings.json"),
+            r#"{"total":10,"functions":[{"time_ms":1}],"queries":[{"time_ms":2}]}"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("baz-timings.json"), "not json").unwrap();
+        fs::write(dir.path().join("foo.fluxc"), r#"{"total":100}"#).unwrap();
+
+        let stats = FluxRunner::parse_timings_dumps(dir.path()).unwrap();
+        assert_eq!((stats.total_ms, stats.functions, stats.queries, stats.time_ms), (40, 3, 3, 14));
+        assert_eq!((stats.cache_hits, stats.cache_misses), (None, None));
+        let mut goals: Vec<_> = stats
+            .goals
+            .iter()
+            .map(|goal| (goal.item.as_str(), goal.time_ms))
+            .collect();
+        goals.sort_unstable();
+        assert_eq!(goals, [("foo::f", 12), ("g", 3)]);
+    }
+
+    #[tokio::test]
+    async fn constraints_are_read_from_the_dumps() {
+        let (dir, runner) = fake_runner(
+            "dir=$(echo \"$FLUXFLAGS\" | sed 's/.*-Flog-dir=//')\n\
+             echo 'Foo-push' > \"$dir/foo.Foo-push.simp.fluxc\"\n\
+             echo 'raw' > \"$dir/foo.Foo-push.fluxc\"\n\
+             echo 'Bar-push' > \"$dir/foo.Bar-push.simp.sub.fluxc\"\n\
+             echo 'smt' > \"$dir/foo.Foo-push.smt2\"\n\
+             echo 'pop' > \"$dir/foo.pop.simp.fluxc\"",
+        );
+        let repo_path = dir.path().to_str().unwrap();
+
+        let all = runner.get_constraints(repo_path, None).await.unwrap();
+        let names: Vec<_> = all
+            .iter()
+            .map(|dump| (dump.function.as_str(), dump.kind))
+            .collect();
+        assert_eq!(names, [("Bar-push", "impl"), ("Foo-push", "body"), ("pop", "body")]);
+        assert_eq!(all[1].constraint_text, "Foo-push\n");
+
+        let pushes = runner
+            .get_constraints(repo_path, Some("push"))
+            .await
+            .unwrap();
+        assert_eq!(pushes.len(), 2);
+        let foo_push = runner
+            .get_constraints(repo_path, Some("Foo::push"))
+            .await
+            .unwrap();
+        assert_eq!(foo_push.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn internal_compiler_errors_are_reported_as_such() {
+        let ice = MESSAGE
+            .replace("\"level\":\"error\"", "\"level\":\"error: internal compiler error\"")
+            .replace("refinement type error", "unexpected type in fold");
+        let (dir, runner) = fake_runner(&format!("echo '{MESSAGE}'\necho '{ice}'\nexit 101"));
+        let repo_path = dir.path().to_str().unwrap();
+        let report = runner.verify_repository(repo_path, false).await.unwrap();
+        assert!(!report.success);
+        assert_eq!(report.ice.as_deref(), Some("unexpected type in fold"));
+
+        let (dir, runner) = fake_runner(
+            "echo 'Compiling foo' >&2\n\
+             echo \"thread 'rustc' panicked at flux-infer/src/fixpoint.rs:12:5:\" >&2\n\
+             echo 'called `Option::unwrap()` on a `None` value' >&2\n\
+             exit 101",
+        );
+        let repo_path = dir.path().to_str().unwrap();
+        let report = runner.verify_repository(repo_path, false).await.unwrap();
+        assert_eq!(
+            report.ice.as_deref(),
+            Some(
+                "thread 'rustc' panicked at flux-infer/src/fixpoint.rs:12:5:\n\
+                 called `Option::unwrap()` on a `None` value"
+            )
+        );
+
+        let (dir, runner) = fake_runner(&format!("echo '{MESSAGE}'\nexit 1"));
+        let repo_path = dir.path().to_str().unwrap();
+        assert_eq!(
+            runner
+                .verify_repository(repo_path, false)
+                .await
+                .unwrap()
+                .ice,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn memory_limits_apply_to_flux_and_are_reported() {
+        let (dir, cargo_path) = fake_cargo(
+            "ulimit -v > \"$(dirname \"$0\")/limit\"\n\
+             echo 'memory allocation of 4096 bytes failed' >&2\nexit 101",
+        );
+        let runner = FluxRunner::with_config(FluxRunnerConfig {
+            cargo_path,
+            max_memory_mb: Some(512),
+            ..Default::default()
+        });
+
+        let err = runner
+            .verify_repository(dir.path().to_str().unwrap(), false)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::ResourceExceeded(512)));
+        let limit = fs::read_to_string(dir.path().join("limit")).unwrap();
+        assert_eq!(limit.trim(), (512 * 1024).to_string());
+    }
+
+    #[tokio::test]
+    async fn unknown_flux_options_are_unsupported() {
+        let (dir, runner) =
+            fake_runner("echo 'error: unknown flux option: `dump-constraint`' >&2\nexit 1");
+        let err = runner
+            .get_constraints(dir.path().to_str().unwrap(), None)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, FluxRunnerError::UnsupportedOption(option) if option == "dump-constraint")
+        );
+    }
+
+    #[tokio::test]
+    async fn runaway_output_is_truncated() {
+        let (dir, cargo_path) = fake_cargo(&format!("while true; do echo '{MESSAGE}'; done"));
+        let runner = FluxRunner::with_config(FluxRunnerConfig {
+            cargo_path,
+            max_output_bytes: Some(10 * MESSAGE.len()),
+            ..Default::default()
+        });
+
+        let report = runner
+            .verify_repository(dir.path().to_str().unwrap(), false)
+            .await
+            .unwrap();
+        assert!(report.truncated);
+        assert!(!report.success);
+        assert_eq!(report.diagnostics.len(), 9);
+        assert!(runner.children().running().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_runaway_line_is_cut_short_too() {
+        let (dir, cargo_path) = fake_cargo("yes | tr -d '\\n'");
+        let runner = FluxRunner::with_config(FluxRunnerConfig {
+            cargo_path,
+            max_output_bytes: Some(1024),
+            ..Default::default()
+        });
+        let repo_path = dir.path().to_str().unwrap();
+
+        let report = runner.verify_package(repo_path, None).await.unwrap();
+        assert!(report.truncated);
+        assert!(report.diagnostics.is_empty());
+        // A partial list of lemmas would pass for the whole one.
+        let err = runner.get_lemmas(repo_path, None).await.unwrap_err();
+        assert!(matches!(err, FluxRunnerError::OutputTruncated(1024)));
+        assert!(runner.children().running().is_empty());
+    }
+
+    #[tokio::test]
+    async fn missing_repositories_are_the_clients_fault() {
+        let err = FluxRunner::new()
+            .verify_repository("/nonexistent/repo", false)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::InvalidRepoPath(_)));
+        assert!(err.is_client_error());
+        assert!(!FluxRunnerError::Timeout(Duration::from_secs(1)).is_client_error());
+    }
+
+    #[tokio::test]
+    async fn lemma_bodies_are_diffed_between_refs() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+                .args(args)
+                .current_dir(repo)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "--quiet"]);
+        fs::create_dir(repo.join("src")).unwrap();
+        fs::write(
+            repo.join("src/lib.rs"),
+            "#[flux::trusted]\nfn len_pos(n: usize) {\n    assert(n >= 0);\n}\n",
+        )
+        .unwrap();
+        git(&["add", "."]);
+        git(&["commit", "--quiet", "-m", "first"]);
+        git(&["tag", "v1"]);
+        fs::write(
+            repo.join("src/lib.rs"),
+            "fn helper() {}\n\n#[flux::trusted]\nfn len_pos(n: usize) {\n    assert(n > 0);\n}\n\n\
+             fn len_nonneg() {}\n",
+        )
+        .unwrap();
+        git(&["commit", "--quiet", "-am", "second"]);
+        let repo_path = repo.to_str().unwrap();
+        let runner = FluxRunner::new();
+
+        let changed = runner
+            .lemma_body_diff(repo_path, "len_pos", "v1", "HEAD")
+            .await
+            .unwrap();
+        assert_eq!(changed.change, BodyChange::Changed);
+        assert_eq!(changed.from.unwrap().start_line, 1);
+        assert_eq!(changed.to.unwrap().start_line, 3);
+        assert_eq!(
+            changed.diff,
+            "--- v1:src/lib.rs\n+++ HEAD:src/lib.rs\n\
+             @@ -1,4 +1,4 @@\n #[flux::trusted]\n fn len_pos(n: usize) {\n\
+             -    assert(n >= 0);\n+    assert(n > 0);\n }\n"
+        );
+        let added = runner
+            .lemma_body_diff(repo_path, "len_nonneg", "v1", "HEAD")
+            .await
+            .unwrap();
+        assert_eq!(added.change, BodyChange::Added);
+        assert!(
+            added
+                .diff
+                .starts_with("--- /dev/null\n+++ HEAD:src/lib.rs\n")
+        );
+        let same = runner
+            .lemma_body_diff(repo_path, "len_pos", "HEAD", "HEAD")
+            .await
+            .unwrap();
+        assert_eq!((same.change, same.diff.as_str()), (BodyChange::Unchanged, ""));
+
+        let err = runner
+            .lemma_body_diff(repo_path, "len_pos", "v2", "HEAD")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::UnknownRef(_)));
+        let err = runner
+            .lemma_body_diff(repo_path, "missing", "v1", "HEAD")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::UnknownItem(_)));
+    }
+
+    #[tokio::test]
+    async fn spawn_failure_is_reported() {
+        let runner = FluxRunner::with_config(FluxRunnerConfig {
+            cargo_path: PathBuf::from("/nonexistent/cargo"),
+            ..Default::default()
+        });
+        let err = runner.verify_repository(".", false).await.unwrap_err();
+        assert!(
+            matches!(&err, FluxRunnerError::Spawn(err) if err.kind() == io::ErrorKind::NotFound)
+        );
+        assert!(err.to_string().contains("No such file"));
+    }
+
+    #[tokio::test]
+    async fn silent_failures_report_the_stderr() {
+        let (dir, runner) = fake_runner(
+            "echo '   Compiling foo v0.1.0' >&2\n\
+             echo 'error: linker `cc` not found' >&2\nexit 1",
+        );
+        let repo_path = dir.path().to_str().unwrap();
+
+        let err = runner
+            .verify_repository(repo_path, false)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, FluxRunnerError::Failed(_)));
+        assert!(err.to_string().contains("error: linker `cc` not found"));
+        let err = runner.verify_package(repo_path, None).await.unwrap_err();
+        assert!(err.to_string().contains("error: linker `cc` not found"));
+        let err = runner.get_lemmas(repo_path, None).await.unwrap_err();
+        assert!(err.to_string().contains("error: linker `cc` not found"));
+
+        // With diagnostics to show, the failure is in the report instead.
+        let (dir, runner) =
+            fake_runner(&format!("echo '{MESSAGE}'\necho 'error: aborting' >&2\nexit 1"));
+        let report = runner
+            .verify_repository(dir.path().to_str().unwrap(), false)
+            .await
+            .unwrap();
+        assert!(!report.success);
+        assert_eq!(report.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn stderr_excerpts_keep_the_last_whole_lines() {
+        let stderr = format!("{}\nerror: the end\n", "warning: noise\n".repeat(1000));
+        let excerpt = stderr_excerpt(&stderr);
+        assert!(excerpt.len() <= STDERR_EXCERPT_BYTES + 4);
+        assert!(excerpt.starts_with("...\nwarning: noise\n"));
+        assert!(excerpt.ends_with("\nerror: the end"));
+        assert_eq!(stderr_excerpt("error: short\n"), "error: short");
+    }
+
+    #[test]
+    fn cargo_root_is_discovered_upward() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = [\"crates/*\"]\n").unwrap();
+        let member = root.join("crates/foo");
+        fs::create_dir_all(member.join("src/nested")).unwrap();
+        fs::write(member.join("Cargo.toml"), "[package]\nname = \"foo\"\n").unwrap();
+
+        assert_eq!(find_cargo_root(&root), Some(root.clone()));
+        assert_eq!(find_cargo_root(&member), Some(root.clone()));
+        assert_eq!(find_cargo_root(&member.join("src/nested")), Some(root.clone()));
+
+        let standalone = tempfile::tempdir().unwrap();
+        let package = standalone.path().canonicalize().unwrap();
+        fs::write(package.join("Cargo.toml"), "[package]\nname = \"bar\"\n").unwrap();
+        fs::create_dir_all(package.join("src")).unwrap();
+        assert_eq!(find_cargo_root(&package.join("src")), Some(package));
+    }
+
+    #[tokio::test]
+    async fn spawn_retries_transient_failures() {
+        let runner = FluxRunner::with_config(FluxRunnerConfig {
+            retry_backoff: Duration::from_millis(1),
+            ..Default::default()
+        });
+        let mut attempts = 0;
+        let mut child = runner
+            .spawn_with_retry(|| {
+                attempts += 1;
+                if attempts == 1 {
+                    Err(io::Error::from(io::ErrorKind::WouldBlock))
+                } else {
+                    Command::new("true").spawn()
+                }
+            })
+            .await
+            .unwrap();
+        child.wait().unwrap();
+        assert_eq!(attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn spawn_does_not_retry_missing_binary() {
+        let runner = FluxRunner::new();
+        let mut attempts = 0;
+        let err = runner
+            .spawn_with_retry(|| {
+                attempts += 1;
+                Err::<(), _>(io::Error::from(io::ErrorKind::NotFound))
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn spawns_beyond_the_limit_wait_for_a_free_slot() {
+        let limit = SpawnLimit::new(1);
+        let (slot, queued) = limit.acquire().await;
+        assert!(queued < QUEUED_NOTICE_AFTER);
+
+        let waiter = {
+            let limit = limit.clone();
+            tokio::spawn(async move { limit.acquire().await.1 })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!waiter.is_finished());
+        drop(slot);
+        assert!(waiter.await.unwrap() >= Duration::from_millis(100));
     }
-    
 }
@@ -0,0 +1,286 @@
+//! Fetching remote repositories to verify them without a local checkout, and reading local
+//! ones at other revisions.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::flux_runner::FluxRunnerError;
+
+/// How often a running git command is checked against the limits.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long and how large a fetch may get before it is killed.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FetchLimits {
+    pub timeout: Duration,
+    pub max_bytes: u64,
+}
+
+/// Checks that `url` names a remote repository over a network transport git considers safe,
+/// rather than a local path, an option, or a transport like `ext::` that runs commands.
+pub(crate) fn validate_url(url: &str) -> Result<(), FluxRunnerError> {
+    let invalid = |reason: &str| Err(FluxRunnerError::InvalidGitUrl(format!("{url}: {reason}")));
+    if url.starts_with('-') {
+        return invalid("must not start with `-`");
+    }
+    if url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return invalid("must not contain whitespace or control characters");
+    }
+    let scp_like = url
+        .split_once(':')
+        .is_some_and(|(host, path)| host.contains('@') && !host.contains('/') && !path.is_empty());
+    let network = ["https://", "http://", "ssh://", "git://"]
+        .iter()
+        .any(|scheme| {
+            url.strip_prefix(scheme)
+                .is_some_and(|rest| !rest.is_empty())
+        });
+    if !network && !scp_like {
+        return invalid("only https, http, ssh and git URLs are supported");
+    }
+    Ok(())
+}
+
+/// Checks that `git_ref` is a plausible branch, tag or commit, and not an option.
+pub(crate) fn validate_ref(git_ref: &str) -> Result<(), FluxRunnerError> {
+    let valid = !git_ref.is_empty()
+        && !git_ref.starts_with('-')
+        && !git_ref.contains("..")
+        && git_ref
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '/'));
+    if valid {
+        Ok(())
+    } else {
+        Err(FluxRunnerError::InvalidGitUrl(format!("invalid ref `{git_ref}`")))
+    }
+}
+
+/// Fetches `git_ref`, or the default branch, of `url` into the empty directory `dir` with a
+/// history depth of one and checks it out. Returns the commit checked out.
+pub(crate) fn shallow_fetch(
+    url: &str,
+    git_ref: Option<&str>,
+    dir: &Path,
+    limits: FetchLimits,
+) -> Result<String, FluxRunnerError> {
+    let start = Instant::now();
+    let git = |args: &[&str]| {
+        let mut cmd = Command::new("git");
+        cmd.args(args)
+            .current_dir(dir)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::null());
+        cmd
+    };
+    run(git(&["init", "--quiet"]), dir, start, limits)?;
+    // `--` keeps the URL and ref from ever being read as options.
+    run(
+        git(&["fetch", "--quiet", "--depth", "1", "--", url, git_ref.unwrap_or("HEAD")]),
+        dir,
+        start,
+        limits,
+    )?;
+    run(git(&["checkout", "--quiet", "FETCH_HEAD"]), dir, start, limits)?;
+    let output = git(&["rev-parse", "HEAD"])
+        .output()
+        .map_err(FluxRunnerError::Git)?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Writes the files of the local repository at `repo` as of `git_ref` into the empty directory
+/// `dir`, without touching the repository's working tree or index. When `repo` is a
+/// subdirectory of the repository, only that subdirectory is written, at the root of `dir`.
+pub(crate) fn export_tree(repo: &Path, git_ref: &str, dir: &Path) -> Result<(), FluxRunnerError> {
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(repo)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(FluxRunnerError::Git)?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(FluxRunnerError::UnknownRef(format!("{git_ref}: {}", stderr.trim())));
+        }
+        Ok(output.stdout)
+    };
+    let prefix = String::from_utf8_lossy(&git(&["rev-parse", "--show-prefix"])?)
+        .trim()
+        .to_string();
+    let archive = git(&["archive", "--format=tar", &format!("{git_ref}:{prefix}")])?;
+    let mut tar = Command::new("tar")
+        .args(["-x", "-C"])
+        .arg(dir)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(FluxRunnerError::Git)?;
+    tar.stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(&archive)
+        .map_err(FluxRunnerError::Git)?;
+    let output = tar.wait_with_output().map_err(FluxRunnerError::Git)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(FluxRunnerError::Git(io::Error::other(stderr.trim().to_string())));
+    }
+    Ok(())
+}
+
+/// A unified diff from `old` to `new` as `git diff` computes it, with three lines of context
+/// and the headers naming the two sides `old_label` and `new_label`. Empty when they're equal.
+pub(crate) fn unified_diff(
+    old: &str,
+    new: &str,
+    old_label: &str,
+    new_label: &str,
+) -> Result<String, FluxRunnerError> {
+    let dir = tempfile::tempdir().map_err(FluxRunnerError::TempDir)?;
+    fs::write(dir.path().join("old"), old).map_err(FluxRunnerError::Git)?;
+    fs::write(dir.path().join("new"), new).map_err(FluxRunnerError::Git)?;
+    let output = Command::new("git")
+        .args(["diff", "--no-index", "--no-color", "--unified=3", "--", "old", "new"])
+        .current_dir(dir.path())
+        .stdin(Stdio::null())
+        .output()
+        .map_err(FluxRunnerError::Git)?;
+    // `--no-index` exits with 1 when the files differ.
+    if !matches!(output.status.code(), Some(0 | 1)) {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(FluxRunnerError::Git(io::Error::other(stderr.trim().to_string())));
+    }
+    let diff = String::from_utf8_lossy(&output.stdout);
+    let Some(hunks) = diff.find("\n@@").map(|start| &diff[start + 1..]) else {
+        return Ok(String::new());
+    };
+    Ok(format!("--- {old_label}\n+++ {new_label}\n{hunks}"))
+}
+
+/// Runs `cmd` to completion, killing it if the fetch that started at `start` runs out of time
+/// or `dir` grows past the size limit.
+fn run(
+    mut cmd: Command,
+    dir: &Path,
+    start: Instant,
+    limits: FetchLimits,
+) -> Result<(), FluxRunnerError> {
+    let mut child = cmd
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(FluxRunnerError::Git)?;
+    loop {
+        if child.try_wait().map_err(FluxRunnerError::Git)?.is_some() {
+            break;
+        }
+        let exceeded = if start.elapsed() > limits.timeout {
+            Some(format!("timed out after {} seconds", limits.timeout.as_secs()))
+        } else if dir_size(dir) > limits.max_bytes {
+            Some(format!("the checkout grew past {} bytes", limits.max_bytes))
+        } else {
+            None
+        };
+        if let Some(exceeded) = exceeded {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(FluxRunnerError::Clone(exceeded));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    let output = child.wait_with_output().map_err(FluxRunnerError::Git)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(FluxRunnerError::Clone(stderr.trim().to_string()));
+    }
+    Ok(())
+}
+
+/// The total size of the files under `dir`, skipping anything that can't be read.
+fn dir_size(dir: &Path) -> u64 {
+    let mut size = 0;
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else { continue };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                size += metadata.len();
+            }
+        }
+    }
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_network_urls_are_accepted() {
+        for url in [
+            "https://github.com/flux-rs/flux.git",
+            "ssh://git@github.com/flux-rs/flux",
+            "git@github.com:flux-rs/flux.git",
+        ] {
+            assert!(validate_url(url).is_ok(), "{url}");
+        }
+        for url in [
+            "--upload-pack=touch /tmp/pwned",
+            "ext::sh -c touch% /tmp/pwned",
+            "file:///etc",
+            "/home/me/repo",
+            "https://",
+            "https://example.com/repo\n--help",
+        ] {
+            assert!(validate_url(url).is_err(), "{url}");
+        }
+        assert!(validate_ref("v1.0/release-2").is_ok());
+        assert!(validate_ref("--output=x").is_err());
+        assert!(validate_ref("main..dev").is_err());
+    }
+
+    #[test]
+    fn fetches_are_shallow_and_bounded() {
+        let origin = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+                .args(args)
+                .current_dir(origin.path())
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?}");
+        };
+        git(&["init", "--quiet"]);
+        fs::write(origin.path().join("lib.rs"), "fn f() {}\n").unwrap();
+        git(&["add", "lib.rs"]);
+        git(&["commit", "--quiet", "-m", "first"]);
+        git(&["tag", "v1"]);
+        fs::write(origin.path().join("lib.rs"), "fn g() {}\n").unwrap();
+        git(&["commit", "--quiet", "-am", "second"]);
+        let url = format!("file://{}", origin.path().display());
+        let limits = FetchLimits { timeout: Duration::from_secs(60), max_bytes: 1 << 30 };
+
+        let checkout = tempfile::tempdir().unwrap();
+        let commit = shallow_fetch(&url, Some("v1"), checkout.path(), limits).unwrap();
+        assert_eq!(commit.len(), 40);
+        let contents = fs::read_to_string(checkout.path().join("lib.rs")).unwrap();
+        assert_eq!(contents, "fn f() {}\n");
+
+        let checkout = tempfile::tempdir().unwrap();
+        let limits = FetchLimits { max_bytes: 0, ..limits };
+        let err = shallow_fetch(&url, None, checkout.path(), limits).unwrap_err();
+        assert!(matches!(err, FluxRunnerError::Clone(_)));
+    }
+}
@@ -0,0 +1,140 @@
+//! Verification runs that outlive the tool call that started them, for clients whose request
+//! timeouts are shorter than a full flux run.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Instant,
+};
+
+use tokio::task::JoinHandle;
+
+use crate::flux_runner::{FluxRunnerError, VerificationReport};
+
+/// How many finished jobs are kept around, evicting the least recently used one beyond that.
+pub(crate) const MAX_FINISHED_JOBS: usize = 16;
+
+/// The state of a job as reported to a polling client.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub(crate) enum JobStatus {
+    Running { elapsed_ms: u64 },
+    Done { report: Box<VerificationReport> },
+    Failed { error: String },
+}
+
+struct Job {
+    started: Instant,
+    task: JoinHandle<Result<VerificationReport, FluxRunnerError>>,
+}
+
+/// The jobs that are running, and the most recently used finished ones, keyed by job id.
+#[derive(Default)]
+pub(crate) struct Jobs {
+    next_id: u64,
+    running: HashMap<u64, Job>,
+    /// Least recently used first
+    finished: VecDeque<(u64, JobStatus)>,
+}
+
+impl Jobs {
+    /// Spawns `run` in the background and returns its job id.
+    pub(crate) fn start(
+        &mut self,
+        run: impl Future<Output = Result<VerificationReport, FluxRunnerError>> + Send + 'static,
+    ) -> u64 {
+        let id = self.next_id();
+        self.running
+            .insert(id, Job { started: Instant::now(), task: tokio::spawn(run) });
+        id
+    }
+
+    /// Records a report of a run that happened outside of the job store, so that it can be
+    /// referred to by its job id later.
+    pub(crate) fn insert(&mut self, report: VerificationReport) -> u64 {
+        let id = self.next_id();
+        self.finish(id, JobStatus::Done { report: Box::new(report) });
+        id
+    }
+
+    /// Reports on the job `id`, or `None` if there is no such job or it has been evicted.
+    pub(crate) async fn poll(&mut self, id: u64) -> Option<JobStatus> {
+        if let Some(job) = self.running.get(&id) {
+            if !job.task.is_finished() {
+                let elapsed_ms = job.started.elapsed().as_millis() as u64;
+                return Some(JobStatus::Running { elapsed_ms });
+            }
+            let job = self.running.remove(&id)?;
+            let status = match job.task.await {
+                Ok(Ok(report)) => JobStatus::Done { report: Box::new(report) },
+                Ok(Err(err)) => JobStatus::Failed { error: err.to_string() },
+                Err(err) => JobStatus::Failed { error: err.to_string() },
+            };
+            self.finish(id, status);
+        }
+        let i = self.finished.iter().position(|(job, _)| *job == id)?;
+        let entry = self.finished.remove(i)?;
+        let status = entry.1.clone();
+        self.finished.push_back(entry);
+        Some(status)
+    }
+
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn finish(&mut self, id: u64, status: JobStatus) {
+        self.finished.push_back((id, status));
+        if self.finished.len() > MAX_FINISHED_JOBS {
+            self.finished.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn jobs_report_running_until_they_finish() {
+        let mut jobs = Jobs::default();
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let id = jobs.start(async move {
+            rx.await.unwrap();
+            Ok(VerificationReport { success: true, ..Default::default() })
+        });
+        assert!(matches!(jobs.poll(id).await, Some(JobStatus::Running { .. })));
+
+        tx.send(()).unwrap();
+        while matches!(jobs.poll(id).await, Some(JobStatus::Running { .. })) {
+            tokio::task::yield_now().await;
+        }
+        // Finished jobs stay around to be polled again.
+        assert!(matches!(jobs.poll(id).await, Some(JobStatus::Done { report }) if report.success));
+
+        let id = jobs.start(async { Err(FluxRunnerError::FluxNotInstalled) });
+        tokio::task::yield_now().await;
+        loop {
+            match jobs.poll(id).await {
+                Some(JobStatus::Running { .. }) => tokio::task::yield_now().await,
+                status => break assert!(matches!(status, Some(JobStatus::Failed { .. }))),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn the_least_recently_used_finished_job_is_evicted() {
+        let mut jobs = Jobs::default();
+        let first = jobs.insert(VerificationReport::default());
+        let second = jobs.insert(VerificationReport::default());
+        for _ in 2..MAX_FINISHED_JOBS {
+            jobs.insert(VerificationReport::default());
+        }
+        // Using the first job makes the second the least recently used.
+        assert!(jobs.poll(first).await.is_some());
+        jobs.insert(VerificationReport::default());
+        assert!(jobs.poll(first).await.is_some());
+        assert!(jobs.poll(second).await.is_none());
+    }
+}
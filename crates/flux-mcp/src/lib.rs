@@ -0,0 +1,37 @@
+//! Runs flux on Rust repositories and turns its output into structured reports, both for the MCP
+//! server in `main.rs` and for tools embedding verification directly.
+//!
+//! ```no_run
+//! use flux_mcp::FluxRunner;
+//!
+//! # async fn run() -> Result<(), flux_mcp::FluxRunnerError> {
+//! let runner = FluxRunner::new();
+//! let report = runner.verify_repository("path/to/repo", false).await?;
+//! for diagnostic in &report.diagnostics {
+//!     println!("{}", diagnostic.message.render_short());
+//! }
+//! assert!(report.success);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod diagnostics;
+pub mod flux_mcp;
+pub mod flux_runner;
+mod git;
+mod jobs;
+pub mod lsp;
+pub mod repo_config;
+mod scratch;
+pub mod sigfmt;
+pub mod source;
+mod watch;
+
+pub use crate::{
+    diagnostics::{Diagnostic, DiagnosticMessage, DiagnosticSpan},
+    flux_runner::{
+        FluxRunner, FluxRunnerConfig, FluxRunnerError, Lemma, RunOptions, VerificationReport,
+        VerificationSummary,
+    },
+    repo_config::RepoConfig,
+};
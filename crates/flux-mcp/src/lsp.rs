@@ -0,0 +1,97 @@
+//! Conversions into the location types used by the Language Server Protocol.
+
+use std::path::{Path, PathBuf};
+
+use rmcp::schemars::{self, JsonSchema};
+
+/// A zero-based position, as in LSP.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct LspPosition {
+    pub line: u32,
+    pub character: u32,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct LspRange {
+    pub start: LspPosition,
+    pub end: LspPosition,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LspLocation {
+    pub uri: String,
+    pub range: LspRange,
+}
+
+/// How the `character` of an [`LspPosition`] counts, as negotiated through LSP's
+/// `positionEncoding`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, JsonSchema)]
+pub enum PositionEncoding {
+    /// UTF-8 bytes
+    #[serde(rename = "utf-8")]
+    Utf8,
+    /// UTF-16 code units, what VS Code expects
+    #[serde(rename = "utf-16")]
+    Utf16,
+    /// Unicode scalar values, i.e. the columns flux reports
+    #[default]
+    #[serde(rename = "utf-32")]
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Converts a column counted in chars on `line` into this encoding. Columns past the end of
+    /// the line count the missing characters as one unit each.
+    pub(crate) fn encode_column(self, line: &str, column: usize) -> u32 {
+        let prefix = line.chars().take(column);
+        let past_end = column.saturating_sub(line.chars().count());
+        let encoded = match self {
+            PositionEncoding::Utf8 => prefix.map(char::len_utf8).sum::<usize>(),
+            PositionEncoding::Utf16 => prefix.map(char::len_utf16).sum::<usize>(),
+            PositionEncoding::Utf32 => prefix.count(),
+        };
+        (encoded + past_end) as u32
+    }
+}
+
+/// Resolves `file_name` against `repo_path` (unless it is already absolute) and canonicalizes
+/// it when the file exists.
+pub(crate) fn resolve_path(repo_path: &Path, file_name: &str) -> PathBuf {
+    let path = Path::new(file_name);
+    let path = if path.is_absolute() { path.to_path_buf() } else { repo_path.join(path) };
+    path.canonicalize().unwrap_or(path)
+}
+
+/// Builds a `file://` URI for an absolute path, percent-encoding anything that isn't allowed in
+/// a URI path.
+pub(crate) fn file_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                uri.push(byte as char);
+            }
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_are_encoded_in_code_units() {
+        let line = "let s = \"😀漢\"; x";
+        let x = line.chars().position(|c| c == 'x').unwrap();
+        assert_eq!(PositionEncoding::Utf32.encode_column(line, x), 14);
+        assert_eq!(PositionEncoding::Utf16.encode_column(line, x), 15);
+        assert_eq!(PositionEncoding::Utf8.encode_column(line, x), 19);
+        assert_eq!(PositionEncoding::Utf16.encode_column("漢", 3), 3);
+    }
+}
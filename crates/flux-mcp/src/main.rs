@@ -1,27 +1,89 @@
-use anyhow::Result;
-use rmcp::{ServiceExt, transport::stdio};
-use tracing_subscriber::{self, EnvFilter};
+use std::{sync::Mutex, time::Duration};
 
-use crate::flux_mcp::FluxMcp;
+use anyhow::{Context, Result};
+use flux_mcp::{flux_mcp::FluxMcp, flux_runner::PARSE_LOG_TARGET};
+use rmcp::{ServiceExt, transport::stdio};
+use tracing_subscriber::{
+    self, EnvFilter,
+    fmt::writer::{BoxMakeWriter, MakeWriterExt},
+};
 
-mod diagnostics;
-mod flux_mcp;
-mod flux_runner;
+/// How long in-flight flux runs get to finish once the client goes away.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env().add_directive(tracing::Level::DEBUG.into()))
-        .with_writer(std::io::stderr)
-        .with_ansi(false)
-        .init();
+    // `RUST_LOG` decides what is logged, defaulting to high-level events only.
+    let mut filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    if std::env::var_os("FLUX_MCP_QUIET_PARSE").is_some_and(|quiet| quiet != "0") {
+        filter = filter.add_directive(format!("{PARSE_LOG_TARGET}=off").parse()?);
+    }
+    // Stdout carries the MCP protocol, so logs only ever go to stderr and, if asked, a file.
+    let writer = match std::env::var_os("FLUX_MCP_LOG_FILE").filter(|path| !path.is_empty()) {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("Can't open log file {}", path.display()))?;
+            BoxMakeWriter::new(std::io::stderr.and(Mutex::new(file)))
+        }
+        None => BoxMakeWriter::new(std::io::stderr),
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(false);
+    match std::env::var("FLUX_MCP_LOG_FORMAT").as_deref() {
+        Ok("json") => subscriber.json().init(),
+        Ok("" | "text") | Err(_) => subscriber.init(),
+        Ok(format) => {
+            subscriber.init();
+            tracing::warn!("Unknown FLUX_MCP_LOG_FORMAT `{format}`, logging as text");
+        }
+    }
 
     tracing::info!("Starting Flux MCP Server");
 
-    let service = FluxMcp::new().serve(stdio()).await.inspect_err(|err| {
+    let flux_mcp = FluxMcp::new();
+    let children = flux_mcp.children();
+    let service = flux_mcp.serve(stdio()).await.inspect_err(|err| {
         tracing::error!("serving error {:?}", err);
     })?;
 
-    service.waiting().await?;
-    Ok(())
+    let result = tokio::select! {
+        result = service.waiting() => result.map(|_| ()),
+        _ = shutdown_signal() => Ok(()),
+    };
+    tracing::info!("Shutting down, waiting for in-flight verifications");
+    children.shutdown(SHUTDOWN_GRACE).await;
+    result?;
+    // The runtime would wait on the way out for the blocking read of stdin, which only ends once
+    // the client closes it.
+    std::process::exit(0)
+}
+
+/// Resolves on Ctrl-C, which on Windows covers the console's Ctrl-C and Ctrl-Break events, or on
+/// `SIGTERM` on unix.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(err) => {
+                tracing::warn!("Can't listen for SIGTERM: {err}");
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
 }
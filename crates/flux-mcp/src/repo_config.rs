@@ -0,0 +1,355 @@
+//! Per-repository settings read from an optional `flux-mcp.toml` at the repository root.
+//!
+//! A setting passed with a tool call takes precedence over the same setting in the file, which
+//! in turn takes precedence over the server's defaults. Settings are merged field by field, so
+//! a call that only sets `timeout_secs` still picks up the file's `packages`.
+
+use std::{collections::BTreeMap, fs, io, path::Path, time::Duration};
+
+use crate::source;
+
+pub(crate) const FILE_NAME: &str = "flux-mcp.toml";
+
+/// The flux option bounding each SMT query, in milliseconds.
+pub(crate) const SMT_TIMEOUT_OPTION: &str = "smt-timeout";
+
+/// The flux option enabling the checks that arithmetic doesn't overflow.
+pub(crate) const CHECK_OVERFLOW_OPTION: &str = "check-overflow";
+
+/// The files hashed to tell whether cached results still hold, by default: the sources, the
+/// manifests and lockfiles, and the toolchain pins.
+pub(crate) const DEFAULT_CACHE_INPUTS: &[&str] =
+    &["**/*.rs", "**/Cargo.toml", "**/Cargo.lock", "**/rust-toolchain", "**/rust-toolchain.toml"];
+
+/// The SMT solvers flux can be asked to use with `-Fsolver`.
+pub(crate) const KNOWN_SOLVERS: &[&str] = &["z3", "cvc5"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepoConfig {
+    /// Flags added to `FLUXFLAGS` on every run, e.g. `["-Fcheck-overflow=strict"]`
+    pub default_flux_flags: Option<Vec<String>>,
+    /// Kill flux if it runs for longer than this
+    pub timeout_secs: Option<u64>,
+    /// Only verify these packages
+    pub packages: Option<Vec<String>>,
+    /// Drop diagnostics located in files matching these globs, relative to the cargo root
+    pub exclude_globs: Option<Vec<String>>,
+    /// The SMT solver flux should use, one of [`KNOWN_SOLVERS`]
+    pub solver: Option<String>,
+    /// Pass cargo's `--keep-going`, so that a package failing to build doesn't stop the
+    /// verification of the packages that don't depend on it
+    pub keep_going: Option<bool>,
+    /// Give up on a single SMT query after this long, reporting it as unproven
+    pub smt_timeout_ms: Option<u64>,
+    /// Environment variables set for flux on top of the server's, e.g. `Z3_PATH`
+    pub env: Option<BTreeMap<String, String>>,
+    /// Check that arithmetic doesn't overflow, which flux doesn't by default
+    pub check_overflow: Option<bool>,
+    /// Refuse to run flux when the repository pins a toolchain other than flux's, instead of
+    /// only warning
+    pub strict_toolchain: Option<bool>,
+    /// Globs of the files, relative to the repository, whose contents cached results are keyed
+    /// on, replacing [`DEFAULT_CACHE_INPUTS`]. Leaving out a file the results depend on, such as
+    /// a `build.rs` input, makes the cache return stale results after it changes; adding
+    /// irrelevant ones only costs needless reruns. Settings and flags aren't files, and always
+    /// key the cache anyway.
+    pub cache_inputs: Option<Vec<String>>,
+    /// Globs of files left out of `cache_inputs`, e.g. `["tests/**", "benches/**"]` when only
+    /// the library is verified. The same caveat applies: excluding an input that matters makes
+    /// the cache stale.
+    pub cache_exclude: Option<Vec<String>>,
+}
+
+impl RepoConfig {
+    /// Reads `flux-mcp.toml` from `repo_path`, returning the default config if there is none.
+    pub(crate) fn load(repo_path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(repo_path.join(FILE_NAME)) {
+            Ok(contents) => toml::from_str(&contents).map_err(|err| format!("{FILE_NAME}: {err}")),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(format!("{FILE_NAME}: {err}")),
+        }
+    }
+
+    /// Overlays the settings given with a call on top of these.
+    pub(crate) fn merge(self, call: RepoConfig) -> RepoConfig {
+        RepoConfig {
+            default_flux_flags: call.default_flux_flags.or(self.default_flux_flags),
+            timeout_secs: call.timeout_secs.or(self.timeout_secs),
+            packages: call.packages.or(self.packages),
+            exclude_globs: call.exclude_globs.or(self.exclude_globs),
+            solver: call.solver.or(self.solver),
+            keep_going: call.keep_going.or(self.keep_going),
+            smt_timeout_ms: call.smt_timeout_ms.or(self.smt_timeout_ms),
+            env: call.env.or(self.env),
+            check_overflow: call.check_overflow.or(self.check_overflow),
+            strict_toolchain: call.strict_toolchain.or(self.strict_toolchain),
+            cache_inputs: call.cache_inputs.or(self.cache_inputs),
+            cache_exclude: call.cache_exclude.or(self.cache_exclude),
+        }
+    }
+
+    /// A hash of the files cached results depend on, see [`Self::cache_inputs`].
+    pub(crate) fn inputs_fingerprint(&self, repo_path: &Path) -> Result<String, String> {
+        let default: Vec<String> = DEFAULT_CACHE_INPUTS
+            .iter()
+            .map(|glob| glob.to_string())
+            .collect();
+        let include = self.cache_inputs.as_deref().unwrap_or(&default);
+        let exclude = self.cache_exclude.as_deref().unwrap_or_default();
+        source::fingerprint(repo_path, include, exclude)
+            .map_err(|err| format!("Invalid cache input glob: {err}"))
+    }
+
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        self.timeout_secs.map(Duration::from_secs)
+    }
+
+    /// The configured environment variables, after checking that each name can be set.
+    /// `FLUXFLAGS` is built from the other settings, so it can't be set directly.
+    pub(crate) fn env(&self) -> Result<&BTreeMap<String, String>, String> {
+        static EMPTY: BTreeMap<String, String> = BTreeMap::new();
+        let env = self.env.as_ref().unwrap_or(&EMPTY);
+        for key in env.keys() {
+            if key.is_empty() || key.contains(['=', '\0']) {
+                return Err(format!("Invalid environment variable name `{key}`"));
+            }
+            if key == "FLUXFLAGS" {
+                return Err("Set `FLUXFLAGS` through `default_flux_flags` instead".to_string());
+            }
+        }
+        Ok(env)
+    }
+
+    /// The flag bounding each SMT query, if a timeout is configured.
+    pub(crate) fn smt_timeout_flag(&self) -> Option<String> {
+        self.smt_timeout_ms
+            .map(|ms| format!("-F{SMT_TIMEOUT_OPTION}={ms}"))
+    }
+
+    /// The flag turning overflow checking on or off, if configured. It goes after
+    /// `default_flux_flags` so that it wins over a `-Fcheck-overflow` there.
+    pub(crate) fn check_overflow_flag(&self) -> Option<String> {
+        self.check_overflow.map(|check| {
+            let mode = if check { "strict" } else { "none" };
+            format!("-F{CHECK_OVERFLOW_OPTION}={mode}")
+        })
+    }
+
+    /// Whether flux checks for overflows with these settings, so that overflow errors can be
+    /// reported at all: as configured, or else as the last `-Fcheck-overflow` of
+    /// `default_flux_flags` says, or else flux's default of not checking.
+    pub(crate) fn overflow_checked(&self) -> bool {
+        if let Some(check) = self.check_overflow {
+            return check;
+        }
+        let prefix = format!("-F{CHECK_OVERFLOW_OPTION}");
+        self.default_flux_flags
+            .iter()
+            .flatten()
+            .filter_map(|flag| flag.strip_prefix(&prefix))
+            .filter_map(|rest| {
+                match rest {
+                    "" => Some(true),
+                    _ => {
+                        rest.strip_prefix('=')
+                            .map(|mode| !matches!(mode, "none" | "off" | "false" | "no"))
+                    }
+                }
+            })
+            .next_back()
+            .unwrap_or(false)
+    }
+
+    /// The flag selecting the configured solver, if any.
+    pub(crate) fn solver_flag(&self) -> Result<Option<String>, String> {
+        match self.solver.as_deref() {
+            None => Ok(None),
+            Some(solver) if KNOWN_SOLVERS.contains(&solver) => {
+                Ok(Some(format!("-Fsolver={solver}")))
+            }
+            Some(solver) => {
+                Err(format!(
+                    "Unknown solver `{solver}`, expected one of {}",
+                    KNOWN_SOLVERS.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+/// The toolchain channel pinned by the `rust-toolchain.toml`, or the legacy `rust-toolchain`,
+/// of `root` or the nearest directory above it, as rustup would find it for a run in `root`.
+pub(crate) fn pinned_toolchain(root: &Path) -> Option<String> {
+    for dir in root.ancestors() {
+        if let Ok(contents) = fs::read_to_string(dir.join("rust-toolchain.toml")) {
+            return toolchain_channel(&contents);
+        }
+        if let Ok(contents) = fs::read_to_string(dir.join("rust-toolchain")) {
+            // The legacy file is either a bare channel or the same TOML.
+            let contents = contents.trim();
+            return toolchain_channel(contents).or_else(|| {
+                (!contents.is_empty() && !contents.contains(['\n', '=']))
+                    .then(|| contents.to_string())
+            });
+        }
+    }
+    None
+}
+
+fn toolchain_channel(contents: &str) -> Option<String> {
+    let file: toml::Table = toml::from_str(contents).ok()?;
+    let channel = file.get("toolchain")?.get("channel")?.as_str()?;
+    Some(channel.to_string())
+}
+
+/// Whether the toolchains `a` and `b` are the same, ignoring a host triple only one of them
+/// names, e.g. `nightly-2025-10-09` and `nightly-2025-10-09-x86_64-unknown-linux-gnu`.
+pub(crate) fn same_toolchain(a: &str, b: &str) -> bool {
+    let (shorter, longer) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    let Some(rest) = longer.strip_prefix(shorter) else { return false };
+    // A host triple has at least an architecture, a vendor and a system, unlike a date.
+    rest.is_empty()
+        || rest.strip_prefix('-').is_some_and(|triple| {
+            let parts: Vec<&str> = triple.split('-').collect();
+            parts.len() >= 3 && !parts[0].chars().all(|c| c.is_ascii_digit())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_settings_win_field_by_field() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(FILE_NAME),
+            "default_flux_flags = [\"-Fcheck-overflow=strict\"]\ntimeout_secs = 60\npackages = [\"foo\"]\n",
+        )
+        .unwrap();
+        let file = RepoConfig::load(dir.path()).unwrap();
+        let call = RepoConfig {
+            timeout_secs: Some(5),
+            exclude_globs: Some(vec!["src/generated/**".to_string()]),
+            ..Default::default()
+        };
+
+        let merged = file.merge(call);
+        assert_eq!(merged.default_flux_flags, Some(vec!["-Fcheck-overflow=strict".to_string()]));
+        assert_eq!(merged.timeout_secs, Some(5));
+        assert_eq!(merged.packages, Some(vec!["foo".to_string()]));
+        assert_eq!(merged.exclude_globs, Some(vec!["src/generated/**".to_string()]));
+    }
+
+    #[test]
+    fn missing_file_is_the_default_and_typos_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(RepoConfig::load(dir.path()).unwrap(), RepoConfig::default());
+
+        fs::write(dir.path().join(FILE_NAME), "timeout = 5\n").unwrap();
+        assert!(RepoConfig::load(dir.path()).is_err());
+    }
+
+    #[test]
+    fn pinned_toolchains_are_found_above_the_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("crates/foo");
+        fs::create_dir_all(&root).unwrap();
+        assert_eq!(pinned_toolchain(&root), None);
+
+        fs::write(dir.path().join("rust-toolchain"), "nightly-2025-01-01\n").unwrap();
+        assert_eq!(pinned_toolchain(&root).as_deref(), Some("nightly-2025-01-01"));
+        fs::write(
+            dir.path().join("crates/rust-toolchain.toml"),
+            "[toolchain]\nchannel = \"nightly-2025-10-09\"\ncomponents = [\"rustc-dev\"]\n",
+        )
+        .unwrap();
+        assert_eq!(pinned_toolchain(&root).as_deref(), Some("nightly-2025-10-09"));
+
+        assert!(same_toolchain(
+            "nightly-2025-10-09",
+            "nightly-2025-10-09-x86_64-unknown-linux-gnu"
+        ));
+        assert!(same_toolchain("stable", "stable"));
+        assert!(!same_toolchain("nightly-2025-10-09", "nightly-2025-10-1"));
+        assert!(!same_toolchain("nightly", "nightly-2025-10-09"));
+    }
+
+    #[test]
+    fn cache_inputs_default_to_sources_manifests_and_toolchains() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path();
+        fs::create_dir_all(repo.join("tests")).unwrap();
+        fs::write(repo.join("Cargo.toml"), "[package]\n").unwrap();
+        fs::write(repo.join("tests/it.rs"), "fn t() {}\n").unwrap();
+        let default = RepoConfig::default();
+        let libs_only =
+            RepoConfig { cache_exclude: Some(vec!["tests/**".to_string()]), ..Default::default() };
+        let fingerprints = || {
+            (default.inputs_fingerprint(repo).unwrap(), libs_only.inputs_fingerprint(repo).unwrap())
+        };
+
+        let before = fingerprints();
+        fs::write(repo.join("README.md"), "docs\n").unwrap();
+        assert_eq!(fingerprints(), before);
+        fs::write(repo.join("tests/it.rs"), "fn u() {}\n").unwrap();
+        let after = fingerprints();
+        assert_ne!(after.0, before.0);
+        assert_eq!(after.1, before.1);
+        fs::write(repo.join("Cargo.toml"), "[package]\nname = \"foo\"\n").unwrap();
+        assert_ne!(fingerprints().1, after.1);
+
+        let docs =
+            RepoConfig { cache_inputs: Some(vec!["*.md".to_string()]), ..Default::default() };
+        let docs_only = docs.inputs_fingerprint(repo).unwrap();
+        fs::write(repo.join("Cargo.toml"), "[package]\n").unwrap();
+        assert_eq!(docs.inputs_fingerprint(repo).unwrap(), docs_only);
+        let invalid =
+            RepoConfig { cache_inputs: Some(vec!["src/[".to_string()]), ..Default::default() };
+        assert!(invalid.inputs_fingerprint(repo).is_err());
+    }
+
+    #[test]
+    fn env_names_are_validated() {
+        let config = |key: &str| {
+            RepoConfig {
+                env: Some(BTreeMap::from([(key.to_string(), "/opt/z3".to_string())])),
+                ..Default::default()
+            }
+        };
+        assert_eq!(config("Z3_PATH").env().unwrap()["Z3_PATH"], "/opt/z3");
+        assert!(config("").env().is_err());
+        assert!(config("A=B").env().is_err());
+        assert!(config("FLUXFLAGS").env().is_err());
+    }
+
+    #[test]
+    fn overflow_checking_is_the_call_setting_or_the_last_flag() {
+        let flags = |flags: &[&str]| {
+            RepoConfig {
+                default_flux_flags: Some(flags.iter().map(|flag| flag.to_string()).collect()),
+                ..Default::default()
+            }
+        };
+        assert!(!RepoConfig::default().overflow_checked());
+        assert!(flags(&["-Fcheck-overflow=strict"]).overflow_checked());
+        assert!(!flags(&["-Fcheck-overflow=strict", "-Fcheck-overflow=none"]).overflow_checked());
+        assert!(!flags(&["-Fcheck-overflow-mode=strict"]).overflow_checked());
+
+        let off = flags(&["-Fcheck-overflow=strict"])
+            .merge(RepoConfig { check_overflow: Some(false), ..Default::default() });
+        assert!(!off.overflow_checked());
+        assert_eq!(off.check_overflow_flag().as_deref(), Some("-Fcheck-overflow=none"));
+        assert_eq!(RepoConfig::default().check_overflow_flag(), None);
+    }
+
+    #[test]
+    fn only_known_solvers_are_accepted() {
+        let config =
+            |solver: &str| RepoConfig { solver: Some(solver.to_string()), ..Default::default() };
+        assert_eq!(config("cvc5").solver_flag().unwrap().as_deref(), Some("-Fsolver=cvc5"));
+        assert_eq!(RepoConfig::default().solver_flag().unwrap(), None);
+        assert!(config("yices").solver_flag().is_err());
+    }
+}
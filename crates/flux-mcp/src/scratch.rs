@@ -0,0 +1,217 @@
+//! Scratch copies of a repository, for the tools that verify an edited version of it without
+//! touching the original.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use tempfile::TempDir;
+
+use crate::{
+    diagnostics,
+    flux_runner::{self, FluxRunner, FluxRunnerError},
+    repo_config::RepoConfig,
+};
+
+/// Files configuring the build of a repository that the copy needs to build the same way, even
+/// when they are ignored by git.
+const SHARED_CONFIG: &[&str] = &[
+    ".cargo/config.toml",
+    ".cargo/config",
+    "rust-toolchain",
+    "rust-toolchain.toml",
+    "flux.toml",
+    "flux-mcp.toml",
+];
+
+/// A copy of the cargo project containing a repository path in a temporary directory, which is
+/// removed when the copy is dropped. Files ignored by git and build output aren't copied, but
+/// the cargo, toolchain and flux configuration always is.
+pub(crate) struct ScratchRepo {
+    dir: TempDir,
+    original: PathBuf,
+    path: PathBuf,
+}
+
+impl ScratchRepo {
+    /// Copies the cargo project containing `repo_path`. Its [`Self::path`] is then where
+    /// `repo_path` is in the copy. The copy is made on the blocking pool.
+    pub(crate) async fn from_path(repo_path: &str) -> Result<Self, FluxRunnerError> {
+        let repo_path = repo_path.to_string();
+        flux_runner::blocking(move || Self::copy(&repo_path)).await
+    }
+
+    fn copy(repo_path: &str) -> Result<Self, FluxRunnerError> {
+        let (original, root, _) = FluxRunner::resolve_repository(repo_path, RepoConfig::default())?;
+        let dir = tempfile::tempdir().map_err(FluxRunnerError::TempDir)?;
+        copy_repository(&root, dir.path()).map_err(FluxRunnerError::Copy)?;
+        let scope = original.strip_prefix(&root).unwrap_or(Path::new(""));
+        let path = dir.path().join(scope);
+        Ok(ScratchRepo { dir, original, path })
+    }
+
+    /// The root of the copied cargo project.
+    pub(crate) fn root(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// The copy of the requested repository path.
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The requested repository path itself, canonicalized.
+    pub(crate) fn original(&self) -> &Path {
+        &self.original
+    }
+
+    /// The copy of `file`, which is relative to the requested repository path or an absolute
+    /// path inside it. Files that don't exist in the copy, or that `..` leads out of it, are
+    /// rejected so nothing outside of the copy is ever edited.
+    pub(crate) fn resolve(&self, file: &str) -> Result<PathBuf, FluxRunnerError> {
+        let relative = Path::new(file);
+        let relative = relative.strip_prefix(&self.original).unwrap_or(relative);
+        let path = diagnostics::without_parent_dirs(&self.path.join(relative));
+        if !path.starts_with(&self.path) || !path.is_file() {
+            return Err(FluxRunnerError::InvalidSource(PathBuf::from(file)));
+        }
+        Ok(path)
+    }
+}
+
+/// Copies the repository at `from` into the existing directory `to`. The files are those git
+/// lists as tracked or untracked but not ignored, or, outside of a git repository, every file
+/// outside of build output and hidden directories such as `.git`. The build configuration in
+/// [`SHARED_CONFIG`] is copied either way.
+fn copy_repository(from: &Path, to: &Path) -> io::Result<()> {
+    match git_files(from) {
+        Some(files) => {
+            for file in files {
+                if file.starts_with("target") {
+                    continue;
+                }
+                copy_file(&from.join(&file), &to.join(&file))?;
+            }
+        }
+        None => copy_tree(from, to)?,
+    }
+    for file in SHARED_CONFIG {
+        let source = from.join(file);
+        if source.is_file() {
+            copy_file(&source, &to.join(file))?;
+        }
+    }
+    Ok(())
+}
+
+/// The files of the working tree below `dir` that git doesn't ignore, relative to `dir`, or
+/// `None` if `dir` isn't in a git repository.
+fn git_files(dir: &Path) -> Option<Vec<PathBuf>> {
+    let output = Command::new("git")
+        .args(["ls-files", "-z", "--cached", "--others", "--exclude-standard"])
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let files = String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|file| !file.is_empty())
+        .map(PathBuf::from)
+        .collect();
+    Some(files)
+}
+
+/// Copies the file `from` to `to`, creating its directory. A tracked file deleted from the
+/// working tree is skipped.
+fn copy_file(from: &Path, to: &Path) -> io::Result<()> {
+    if !from.is_file() {
+        return Ok(());
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(from, to)?;
+    Ok(())
+}
+
+fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let target = to.join(&name);
+        if entry.file_type()?.is_dir() {
+            if name == "target" || name.to_string_lossy().starts_with('.') {
+                continue;
+            }
+            fs::create_dir(&target)?;
+            copy_tree(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn copies_leave_out_ignored_files_but_keep_the_build_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path();
+        fs::create_dir_all(repo.join("src")).unwrap();
+        fs::create_dir_all(repo.join("target/debug")).unwrap();
+        fs::create_dir_all(repo.join(".cargo")).unwrap();
+        fs::write(repo.join("Cargo.toml"), "[package]\nname = \"foo\"\n").unwrap();
+        fs::write(repo.join("src/lib.rs"), "fn f() {}\n").unwrap();
+        fs::write(repo.join("src/generated.rs"), "fn g() {}\n").unwrap();
+        fs::write(repo.join("target/debug/out"), "").unwrap();
+        fs::write(repo.join(".cargo/config.toml"), "[build]\n").unwrap();
+        fs::write(repo.join(".gitignore"), "/target\n/.cargo\nsrc/generated.rs\n").unwrap();
+
+        let copied = |scratch: &ScratchRepo| {
+            let mut files: Vec<_> = [
+                "Cargo.toml",
+                "src/lib.rs",
+                "src/generated.rs",
+                "target/debug/out",
+                ".cargo/config.toml",
+            ]
+            .into_iter()
+            .filter(|file| scratch.root().join(file).is_file())
+            .collect();
+            files.sort_unstable();
+            files
+        };
+        // Without git, only build output and hidden directories are left out.
+        let scratch = ScratchRepo::from_path(repo.to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            copied(&scratch),
+            [".cargo/config.toml", "Cargo.toml", "src/generated.rs", "src/lib.rs"]
+        );
+
+        let init = Command::new("git")
+            .args(["init", "-q"])
+            .current_dir(repo)
+            .status()
+            .unwrap();
+        assert!(init.success());
+        let scratch = ScratchRepo::from_path(repo.join("src").to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(copied(&scratch), [".cargo/config.toml", "Cargo.toml", "src/lib.rs"]);
+        assert_eq!(scratch.path(), scratch.root().join("src"));
+        assert_eq!(scratch.original(), repo.canonicalize().unwrap().join("src"));
+
+        let root = scratch.root().to_path_buf();
+        drop(scratch);
+        assert!(!root.exists());
+    }
+}
@@ -0,0 +1,204 @@
+//! Rendering flux signatures in a canonical layout, so that signatures written in different
+//! styles read the same.
+
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+
+use crate::source;
+
+/// Operators made of several punctuation characters, longest first.
+const MULTI_CHAR_OPS: &[&str] =
+    &["..=", "::", "->", "=>", "==", "!=", "<=", ">=", "&&", "||", "..", ">>", "<<"];
+
+/// Formats a flux signature, given either bare, e.g. `fn(i32[@n]) -> i32[n+1]`, or as the
+/// whole `#[flux::sig(...)]` attribute. Tokens are spaced consistently, every `requires` and
+/// `ensures` clause goes on its own line with the clauses aligned, and the refinement
+/// parameters the signature binds are listed in a trailing comment. Formatting is idempotent.
+/// Returns `None` if the text doesn't tokenize.
+pub fn format_sig(sig_text: &str) -> Option<String> {
+    let tokens = sig_tokens(sig_text)?;
+    let binders = source::refinement_params(tokens.clone());
+    let mut clauses: Vec<(Option<String>, Vec<TokenTree>)> = vec![(None, vec![])];
+    for tree in tokens {
+        match &tree {
+            TokenTree::Ident(ident) if ident == "requires" || ident == "ensures" => {
+                clauses.push((Some(ident.to_string()), vec![]));
+            }
+            _ => clauses.last_mut().unwrap().1.push(tree),
+        }
+    }
+    let mut res = String::new();
+    for (keyword, tokens) in clauses {
+        match keyword {
+            None => res.push_str(&render(tokens)),
+            Some(keyword) => {
+                if !res.is_empty() {
+                    res.push('\n');
+                }
+                res.push_str(&format!("    {keyword:<8} {}", render(tokens)));
+            }
+        }
+    }
+    if !binders.is_empty() {
+        res.push_str(&format!("\n// binds {}", binders.join(", ")));
+    }
+    Some(res)
+}
+
+/// The tokens of the signature in `sig_text`, unwrapping a `#[flux::sig(...)]` attribute.
+fn sig_tokens(sig_text: &str) -> Option<TokenStream> {
+    let text = sig_text.trim();
+    if let Some(inner) = text
+        .strip_prefix("#[")
+        .and_then(|text| text.strip_suffix(']'))
+        && let Ok(syn::Meta::List(list)) = syn::parse_str::<syn::Meta>(inner)
+        && list
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "sig")
+    {
+        return Some(list.tokens);
+    }
+    text.parse().ok()
+}
+
+/// A token as laid out by [`render`].
+enum Piece {
+    Word(String),
+    Op(String),
+    Group(Delimiter, String),
+}
+
+/// What the last piece rendered was, which decides the space before the next one.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Last {
+    Nothing,
+    Word,
+    /// A prefix operator or one binding tightly like `::`, never followed by a space
+    Tight,
+    /// A binary operator or separator, always followed by a space
+    Spaced,
+}
+
+fn render(tokens: impl IntoIterator<Item = TokenTree>) -> String {
+    let mut res = String::new();
+    let mut last = Last::Nothing;
+    // Whether the last word names a type, so that a `<` after it opens generic arguments
+    let mut after_type = false;
+    let mut open_generics = 0;
+    for piece in pieces(tokens) {
+        let starts_type =
+            matches!(&piece, Piece::Word(word) if word.starts_with(char::is_uppercase));
+        let (text, space_before, next) = match piece {
+            Piece::Word(word) => (word, last == Last::Word || last == Last::Spaced, Last::Word),
+            Piece::Group(delimiter, inner) => {
+                let (text, after_word) = match delimiter {
+                    Delimiter::Parenthesis => (format!("({inner})"), false),
+                    Delimiter::Bracket => (format!("[{inner}]"), false),
+                    Delimiter::Brace if inner.is_empty() => ("{}".to_string(), true),
+                    Delimiter::Brace => (format!("{{ {inner} }}"), true),
+                    Delimiter::None => (inner, true),
+                };
+                let space = last == Last::Spaced || (last == Last::Word && after_word);
+                (text, space, Last::Word)
+            }
+            Piece::Op(op) => {
+                let after_operand = last == Last::Word;
+                match op.as_str() {
+                    "," | ";" | ":" => (op, false, Last::Spaced),
+                    "::" | "." | ".." | "..=" | "'" => (op, false, Last::Tight),
+                    "@" | "#" | "$" => (op, after_operand || last == Last::Spaced, Last::Tight),
+                    "-" | "&" | "!" | "*" if !after_operand => {
+                        (op, last == Last::Spaced, Last::Tight)
+                    }
+                    // Generic brackets are written against their type, comparisons aren't.
+                    "<" if after_type => {
+                        open_generics += 1;
+                        (op, false, Last::Tight)
+                    }
+                    ">" if open_generics >= 1 => {
+                        open_generics -= 1;
+                        (op, false, Last::Word)
+                    }
+                    ">>" if open_generics >= 2 => {
+                        open_generics -= 2;
+                        (op, false, Last::Word)
+                    }
+                    _ => (op, last != Last::Nothing, Last::Spaced),
+                }
+            }
+        };
+        if space_before && !res.is_empty() {
+            res.push(' ');
+        }
+        res.push_str(&text);
+        last = next;
+        after_type = starts_type;
+    }
+    res
+}
+
+/// Splits `tokens` into words, operators and rendered groups, joining the punctuation of
+/// multi-character operators.
+fn pieces(tokens: impl IntoIterator<Item = TokenTree>) -> Vec<Piece> {
+    let mut res = Vec::new();
+    let mut run = String::new();
+    let flush = |run: &mut String, res: &mut Vec<Piece>| {
+        let mut rest = run.as_str();
+        while !rest.is_empty() {
+            let len = MULTI_CHAR_OPS
+                .iter()
+                .find(|op| rest.starts_with(**op))
+                .map_or(1, |op| op.len());
+            res.push(Piece::Op(rest[..len].to_string()));
+            rest = &rest[len..];
+        }
+        run.clear();
+    };
+    for tree in tokens {
+        if let TokenTree::Punct(punct) = &tree {
+            run.push(punct.as_char());
+            if punct.spacing() == proc_macro2::Spacing::Alone {
+                flush(&mut run, &mut res);
+            }
+            continue;
+        }
+        flush(&mut run, &mut res);
+        res.push(match tree {
+            TokenTree::Group(group) => Piece::Group(group.delimiter(), render(group.stream())),
+            tree => Piece::Word(tree.to_string()),
+        });
+    }
+    flush(&mut run, &mut res);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_are_laid_out_the_same_way() {
+        let formatted = format_sig(
+            "#[flux::sig(fn(self: &strg RVec<T>[@n],x:T)->()ensures self:RVec<T>[n+1] requires n<usize::MAX)]",
+        )
+        .unwrap();
+        assert_eq!(
+            formatted,
+            "fn(self: &strg RVec<T>[@n], x: T) -> ()\n    \
+             ensures  self: RVec<T>[n + 1]\n    \
+             requires n < usize::MAX\n\
+             // binds n"
+        );
+        for sig in [
+            formatted.as_str(),
+            "fn(&RVec<i32>[@v], {i32[@i] | 0 <= i && i < v}) -> i32[-1]",
+            "fn(x: i32) -> i32{v: v >= x}",
+            "fn(bool[true])",
+        ] {
+            let once = format_sig(sig).unwrap();
+            assert_eq!(format_sig(&once).unwrap(), once, "{sig}");
+        }
+        assert!(format_sig("fn(i32").is_none());
+    }
+}
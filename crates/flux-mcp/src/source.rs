@@ -0,0 +1,1807 @@
+//! Helpers for scanning the Rust sources of a repository without going through flux.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use globset::{GlobBuilder, GlobSetBuilder};
+use proc_macro2::{Delimiter, LineColumn, TokenStream, TokenTree};
+use syn::{
+    Token,
+    punctuated::Punctuated,
+    spanned::Spanned,
+    visit::{self, Visit},
+};
+
+use crate::diagnostics;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DefItem {
+    pub kind: DefKind,
+    pub name: String,
+    /// The parameters of a function, qualifier or property as written, e.g. `v: int`
+    #[serde(default)]
+    pub params: Vec<String>,
+    /// The sort a function returns, e.g. `ISeq`
+    #[serde(default)]
+    pub return_sort: Option<String>,
+    /// The source text of the definition
+    pub signature: String,
+    pub file_name: String,
+    pub line: usize,
+}
+
+/// What a `defs!` item declares, serialized as the keyword introducing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DefKind {
+    /// `opaque sort ISeq;`
+    Sort,
+    /// `fn cons(v: int, elems: ISeq) -> ISeq;`, uninterpreted unless it has a body
+    #[serde(rename = "fn")]
+    Func,
+    Qualifier,
+    Property,
+}
+
+/// Returns every `.rs` file under `repo_path`, skipping hidden directories and `target`.
+pub(crate) fn rust_files(repo_path: &Path) -> Vec<PathBuf> {
+    let mut res = repo_files(repo_path);
+    res.retain(|path| path.extension().is_some_and(|ext| ext == "rs"));
+    res
+}
+
+/// Returns every file under `repo_path`, skipping hidden directories and `target`.
+fn repo_files(repo_path: &Path) -> Vec<PathBuf> {
+    let mut res = Vec::new();
+    let mut pending = vec![repo_path.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else { continue };
+            if path.is_dir() {
+                if !name.starts_with('.') && name != "target" {
+                    pending.push(path);
+                }
+            } else {
+                res.push(path);
+            }
+        }
+    }
+    res.sort();
+    res
+}
+
+/// A hash of the paths and contents of the files under `repo_path` matching one of the
+/// `include` globs and none of the `exclude` ones, relative to `repo_path`. It changes whenever
+/// one of them is edited, added, removed or renamed and stays the same otherwise, whatever the
+/// order the files are listed in.
+pub(crate) fn fingerprint(
+    repo_path: &Path,
+    include: &[String],
+    exclude: &[String],
+) -> Result<String, globset::Error> {
+    let globs = |patterns: &[String]| {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+        }
+        builder.build()
+    };
+    let (include, exclude) = (globs(include)?, globs(exclude)?);
+    let mut hashes: Vec<u64> = repo_files(repo_path)
+        .iter()
+        .filter_map(|file| {
+            let relative = file.strip_prefix(repo_path).unwrap_or(file);
+            if !include.is_match(relative) || exclude.is_match(relative) {
+                return None;
+            }
+            let contents = fs::read(file).unwrap_or_default();
+            Some(hash_parts(&[relative.to_string_lossy().as_bytes(), &contents]))
+        })
+        .collect();
+    hashes.sort_unstable();
+    let hashes: Vec<u8> = hashes.iter().flat_map(|hash| hash.to_le_bytes()).collect();
+    Ok(format!("{:016x}", hash_parts(&[&hashes])))
+}
+
+/// A 64-bit FNV-1a hash of `parts`, which unlike `std`'s hasher is stable across releases and
+/// platforms, so hashes written to disk stay comparable.
+pub(crate) fn hash_parts(parts: &[&[u8]]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        // Separate the parts so that e.g. ("ab", "c") and ("a", "bc") differ.
+        for &byte in part.iter().chain(&[0xff]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// Parses a file, returning its contents alongside the syntax tree.
+pub(crate) fn parse_file(path: &Path) -> Option<(String, syn::File)> {
+    let contents = fs::read_to_string(path).ok()?;
+    match syn::parse_file(&contents) {
+        Ok(file) => Some((contents, file)),
+        Err(err) => {
+            tracing::debug!("Skipping {}: {err}", path.display());
+            None
+        }
+    }
+}
+
+/// Extracts the source text between two span locations. Lines are 1-based and columns are
+/// 0-based char offsets, as reported by `proc_macro2`.
+pub(crate) fn source_text(contents: &str, start: LineColumn, end: LineColumn) -> String {
+    let mut res = String::new();
+    for (i, line) in contents.lines().enumerate().skip(start.line - 1) {
+        let line_no = i + 1;
+        if line_no > end.line {
+            break;
+        }
+        let from = if line_no == start.line { start.column } else { 0 };
+        let to = if line_no == end.line { end.column } else { usize::MAX };
+        if line_no != start.line {
+            res.push('\n');
+        }
+        res.extend(line.chars().skip(from).take(to.saturating_sub(from)));
+    }
+    res
+}
+
+/// The path of `file` relative to `repo_path`, falling back to the full path.
+pub(crate) fn display_path(repo_path: &Path, file: &Path) -> String {
+    file.strip_prefix(repo_path)
+        .unwrap_or(file)
+        .display()
+        .to_string()
+}
+
+/// Finds the sorts and functions declared inside `flux_rs::defs! { ... }` blocks.
+pub(crate) fn find_definitions(repo_path: &Path) -> Vec<DefItem> {
+    let mut res = Vec::new();
+    for path in rust_files(repo_path) {
+        let Some((contents, file)) = parse_file(&path) else { continue };
+        let mut visitor = DefsVisitor { macros: vec![] };
+        visitor.visit_file(&file);
+        let file_name = display_path(repo_path, &path);
+        for tokens in visitor.macros {
+            for item in split_defs(tokens) {
+                let Some(def) = def_item(&contents, &file_name, &item) else { continue };
+                res.push(def);
+            }
+        }
+    }
+    res
+}
+
+/// An item flux takes on faith rather than checking.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TrustedItem {
+    /// e.g. `foo`, `Type::method` or `impl Type`
+    pub name: String,
+    /// `trusted` if the item's body isn't checked, `opaque` if its fields are hidden from the
+    /// refinement logic
+    pub attribute: String,
+    pub file_name: String,
+    pub line: usize,
+}
+
+/// Finds the items marked `#[flux::trusted]` or `#[flux::opaque]`, also spelled with
+/// `flux_rs::` or imported from `flux_rs::attrs`. Items explicitly opted out with
+/// `#[trusted(no)]` are skipped.
+pub(crate) fn find_trusted(repo_path: &Path) -> Vec<TrustedItem> {
+    let mut res = Vec::new();
+    for path in rust_files(repo_path) {
+        let Some((_, file)) = parse_file(&path) else { continue };
+        let mut visitor = TrustVisitor { current_owner: None, items: vec![] };
+        visitor.visit_file(&file);
+        let file_name = display_path(repo_path, &path);
+        for (name, attribute, line) in visitor.items {
+            res.push(TrustedItem {
+                name,
+                attribute: attribute.to_string(),
+                file_name: file_name.clone(),
+                line,
+            });
+        }
+    }
+    res
+}
+
+/// The flux trust attribute `attr` is, if any.
+fn trust_attribute(attr: &syn::Attribute) -> Option<&'static str> {
+    let segments: Vec<String> = attr
+        .path()
+        .segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let name = match segments[..] {
+        [name] | ["flux" | "flux_rs", name] | ["flux_rs", "attrs", name] => name,
+        _ => return None,
+    };
+    let attribute = match name {
+        "trusted" => "trusted",
+        "opaque" => "opaque",
+        _ => return None,
+    };
+    let opted_out = attr
+        .meta
+        .require_list()
+        .is_ok_and(|list| list.tokens.to_string() == "no");
+    (!opted_out).then_some(attribute)
+}
+
+struct TrustVisitor {
+    current_owner: Option<String>,
+    /// The name, attribute and first line of every trusted item
+    items: Vec<(String, &'static str, usize)>,
+}
+
+impl TrustVisitor {
+    fn check(&mut self, attrs: &[syn::Attribute], name: String) {
+        let attributes: Vec<_> = attrs.iter().filter_map(trust_attribute).collect();
+        if attributes.is_empty() {
+            return;
+        }
+        let line = attrs[0].span().start().line;
+        for attribute in attributes {
+            self.items.push((name.clone(), attribute, line));
+        }
+    }
+
+    fn qualified(&self, ident: &syn::Ident) -> String {
+        match &self.current_owner {
+            Some(owner) => format!("{owner}::{ident}"),
+            None => ident.to_string(),
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for TrustVisitor {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        let prev = self.current_owner.take();
+        self.check(&item.attrs, item.sig.ident.to_string());
+        visit::visit_item_fn(self, item);
+        self.current_owner = prev;
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        let owner = match &*item.self_ty {
+            syn::Type::Path(ty) => ty.path.segments.last().map(|seg| seg.ident.to_string()),
+            _ => None,
+        };
+        if let Some(owner) = &owner {
+            self.check(&item.attrs, format!("impl {owner}"));
+        }
+        let prev = std::mem::replace(&mut self.current_owner, owner);
+        visit::visit_item_impl(self, item);
+        self.current_owner = prev;
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast syn::ImplItemFn) {
+        self.check(&item.attrs, self.qualified(&item.sig.ident));
+        visit::visit_impl_item_fn(self, item);
+    }
+
+    fn visit_item_trait(&mut self, item: &'ast syn::ItemTrait) {
+        self.check(&item.attrs, item.ident.to_string());
+        let prev = self.current_owner.replace(item.ident.to_string());
+        visit::visit_item_trait(self, item);
+        self.current_owner = prev;
+    }
+
+    fn visit_trait_item_fn(&mut self, item: &'ast syn::TraitItemFn) {
+        self.check(&item.attrs, self.qualified(&item.sig.ident));
+        visit::visit_trait_item_fn(self, item);
+    }
+
+    fn visit_item_struct(&mut self, item: &'ast syn::ItemStruct) {
+        self.check(&item.attrs, item.ident.to_string());
+        visit::visit_item_struct(self, item);
+    }
+
+    fn visit_item_enum(&mut self, item: &'ast syn::ItemEnum) {
+        self.check(&item.attrs, item.ident.to_string());
+        visit::visit_item_enum(self, item);
+    }
+
+    fn visit_item_mod(&mut self, item: &'ast syn::ItemMod) {
+        self.check(&item.attrs, format!("mod {}", item.ident));
+        visit::visit_item_mod(self, item);
+    }
+}
+
+/// The refinement index of a type, declared with `#[flux::refined_by(...)]`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RefinedBy {
+    pub type_name: String,
+    pub file_name: String,
+    pub line: usize,
+    /// Each index field and its sort as written, e.g. `("elems", "ISeq")`
+    pub fields: Vec<(String, String)>,
+}
+
+/// Finds the `refined_by` attribute, also spelled with `flux::` or `flux_rs::`, of the struct or
+/// enum named by the last segment of `type_name`.
+pub(crate) fn find_refined_by(repo_path: &Path, type_name: &str) -> Option<RefinedBy> {
+    let type_name = type_name.rsplit("::").next().unwrap_or(type_name);
+    for path in rust_files(repo_path) {
+        let Some((contents, file)) = parse_file(&path) else { continue };
+        let mut visitor = RefinedByVisitor { type_name, attr: None };
+        visitor.visit_file(&file);
+        let Some(list) = visitor.attr else { continue };
+        let mut fields = vec![];
+        for field in split_commas(list.tokens) {
+            let colon = field
+                .iter()
+                .position(|tree| matches!(tree, TokenTree::Punct(p) if p.as_char() == ':'));
+            let (Some(colon), Some(last)) = (colon, field.last()) else { continue };
+            let Some(first_of_sort) = field.get(colon + 1) else { continue };
+            let name = field[..colon]
+                .iter()
+                .map(ToString::to_string)
+                .collect::<String>();
+            let sort = source_text(&contents, first_of_sort.span().start(), last.span().end());
+            fields.push((name, sort));
+        }
+        return Some(RefinedBy {
+            type_name: type_name.to_string(),
+            file_name: display_path(repo_path, &path),
+            line: list.path.span().start().line,
+            fields,
+        });
+    }
+    None
+}
+
+struct RefinedByVisitor<'a> {
+    type_name: &'a str,
+    attr: Option<syn::MetaList>,
+}
+
+impl RefinedByVisitor<'_> {
+    fn check(&mut self, attrs: &[syn::Attribute], ident: &syn::Ident) {
+        if self.attr.is_some() || ident != self.type_name {
+            return;
+        }
+        self.attr = attrs.iter().find_map(|attr| {
+            let segments: Vec<String> = attr
+                .path()
+                .segments
+                .iter()
+                .map(|segment| segment.ident.to_string())
+                .collect();
+            let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+            match segments[..] {
+                ["refined_by"] | ["flux" | "flux_rs", "refined_by"] => {
+                    attr.meta.require_list().ok().cloned()
+                }
+                _ => None,
+            }
+        });
+    }
+}
+
+impl<'ast> Visit<'ast> for RefinedByVisitor<'_> {
+    fn visit_item_struct(&mut self, item: &'ast syn::ItemStruct) {
+        self.check(&item.attrs, &item.ident);
+        visit::visit_item_struct(self, item);
+    }
+
+    fn visit_item_enum(&mut self, item: &'ast syn::ItemEnum) {
+        self.check(&item.attrs, &item.ident);
+        visit::visit_item_enum(self, item);
+    }
+}
+
+/// A call to a flux intrinsic that trusts or checks a fact in the middle of a body.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AssumptionSite {
+    /// `assume` if the fact is taken on faith, `assert` if flux has to prove it
+    pub intrinsic: String,
+    pub file_name: String,
+    pub line: usize,
+    /// The source text of the argument, e.g. `x > 0`
+    pub expr: String,
+}
+
+/// Finds the calls to `flux_rs::assume` and `flux_rs::assert`, also spelled with `flux::` or
+/// imported unqualified. Being a syntax walk, mentions in comments and strings don't count.
+pub(crate) fn find_assumptions(repo_path: &Path) -> Vec<AssumptionSite> {
+    let mut res = Vec::new();
+    for path in rust_files(repo_path) {
+        let Some((contents, file)) = parse_file(&path) else { continue };
+        let mut visitor = AssumptionVisitor { contents: &contents, sites: vec![] };
+        visitor.visit_file(&file);
+        let file_name = display_path(repo_path, &path);
+        for (intrinsic, line, expr) in visitor.sites {
+            res.push(AssumptionSite {
+                intrinsic: intrinsic.to_string(),
+                file_name: file_name.clone(),
+                line,
+                expr,
+            });
+        }
+    }
+    res
+}
+
+/// The flux intrinsic `path` names, if any.
+fn assumption_intrinsic(path: &syn::Path) -> Option<&'static str> {
+    let segments: Vec<String> = path
+        .segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    match segments[..] {
+        [name] | ["flux" | "flux_rs", name] => {
+            match name {
+                "assume" => Some("assume"),
+                "assert" => Some("assert"),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+struct AssumptionVisitor<'a> {
+    contents: &'a str,
+    /// The intrinsic, line and argument text of every call
+    sites: Vec<(&'static str, usize, String)>,
+}
+
+impl<'ast> Visit<'ast> for AssumptionVisitor<'_> {
+    fn visit_expr_call(&mut self, call: &'ast syn::ExprCall) {
+        if let syn::Expr::Path(func) = &*call.func
+            && let Some(intrinsic) = assumption_intrinsic(&func.path)
+        {
+            let span = call.args.span();
+            let expr = source_text(self.contents, span.start(), span.end());
+            let expr = expr.split_whitespace().collect::<Vec<_>>().join(" ");
+            self.sites.push((intrinsic, call.span().start().line, expr));
+        }
+        visit::visit_expr_call(self, call);
+    }
+}
+
+/// How many of the public functions of a package have a flux signature.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Coverage {
+    /// Public functions, methods and items of public traits
+    pub total_fns: usize,
+    /// Those with a `sig` attribute
+    pub specced_fns: usize,
+    /// Those marked `#[flux::trusted]`, with or without a signature
+    pub trusted_fns: usize,
+    /// `specced_fns / total_fns`, or 0 if there are no public functions
+    pub ratio: f64,
+}
+
+/// Counts the public functions under `dir` that have a flux signature or are trusted. Only items
+/// declared `pub` count, so restricted visibility like `pub(crate)` is left out, and so are the
+/// methods of trait impls, which take their signatures from the trait.
+pub(crate) fn spec_coverage(dir: &Path) -> Coverage {
+    let mut visitor = CoverageVisitor { coverage: Coverage::default(), in_trait_impl: false };
+    for path in rust_files(dir) {
+        let Some((_, file)) = parse_file(&path) else { continue };
+        visitor.visit_file(&file);
+    }
+    let mut coverage = visitor.coverage;
+    if coverage.total_fns > 0 {
+        coverage.ratio = coverage.specced_fns as f64 / coverage.total_fns as f64;
+    }
+    coverage
+}
+
+struct CoverageVisitor {
+    coverage: Coverage,
+    in_trait_impl: bool,
+}
+
+impl CoverageVisitor {
+    fn count(&mut self, public: bool, attrs: &[syn::Attribute]) {
+        if !public {
+            return;
+        }
+        self.coverage.total_fns += 1;
+        let has_sig = attrs.iter().any(|attr| {
+            attr.path()
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "sig")
+        });
+        if has_sig {
+            self.coverage.specced_fns += 1;
+        }
+        if attrs
+            .iter()
+            .any(|attr| trust_attribute(attr) == Some("trusted"))
+        {
+            self.coverage.trusted_fns += 1;
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for CoverageVisitor {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        self.count(matches!(item.vis, syn::Visibility::Public(_)), &item.attrs);
+        visit::visit_item_fn(self, item);
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        let prev = std::mem::replace(&mut self.in_trait_impl, item.trait_.is_some());
+        visit::visit_item_impl(self, item);
+        self.in_trait_impl = prev;
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast syn::ImplItemFn) {
+        let public = !self.in_trait_impl && matches!(item.vis, syn::Visibility::Public(_));
+        self.count(public, &item.attrs);
+        visit::visit_impl_item_fn(self, item);
+    }
+
+    fn visit_item_trait(&mut self, item: &'ast syn::ItemTrait) {
+        if matches!(item.vis, syn::Visibility::Public(_)) {
+            for trait_item in &item.items {
+                if let syn::TraitItem::Fn(function) = trait_item {
+                    self.count(true, &function.attrs);
+                }
+            }
+        }
+        visit::visit_item_trait(self, item);
+    }
+}
+
+/// A range of lines in a file, both ends inclusive and 1-based.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LineRange {
+    pub file_name: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+impl LineRange {
+    /// Whether the line `line` of `file_name` falls inside this range. Paths are compared by
+    /// suffix since flux reports them relative to the workspace root.
+    pub(crate) fn contains(&self, file_name: &str, line: usize) -> bool {
+        let same_file = Path::new(file_name).ends_with(&self.file_name)
+            || Path::new(&self.file_name).ends_with(file_name);
+        same_file && self.start_line <= line && line <= self.end_line
+    }
+}
+
+/// Finds the functions (free functions, methods and trait items) matching `fn_path`, e.g.
+/// `krate::module::foo` or `Type::method`. Functions are matched on their name and, when the
+/// preceding segment looks like a type, on the name of the enclosing `impl` or `trait`.
+pub(crate) fn find_fn_ranges(repo_path: &Path, fn_path: &str) -> Vec<LineRange> {
+    let (name, owner) = split_fn_path(fn_path);
+    let mut res = Vec::new();
+    for path in rust_files(repo_path) {
+        let Some((_, file)) = parse_file(&path) else { continue };
+        let mut visitor = FnVisitor { name, owner, current_owner: None, spans: vec![] };
+        visitor.visit_file(&file);
+        let file_name = display_path(repo_path, &path);
+        for span in visitor.spans {
+            res.push(LineRange {
+                file_name: file_name.clone(),
+                start_line: span.start().line,
+                end_line: span.end().line,
+            });
+        }
+    }
+    res
+}
+
+/// The name of the function `fn_path` points to, with its owning type or trait if the
+/// segment before the name looks like one.
+fn split_fn_path(fn_path: &str) -> (&str, Option<&str>) {
+    let mut segments = fn_path.rsplit("::");
+    let name = segments.next().unwrap_or_default();
+    let owner = segments
+        .next()
+        .filter(|segment| segment.starts_with(char::is_uppercase));
+    (name, owner)
+}
+
+/// A function of the repository and the functions its body calls.
+#[derive(Debug, Clone)]
+pub(crate) struct FnCalls {
+    /// The type or trait of a method
+    pub owner: Option<String>,
+    pub name: String,
+    /// The called paths as written, e.g. `helper`, `RVec::new` or `Self::len` with `Self`
+    /// replaced by the owner. Method calls are only known by the method's name, e.g. `push`.
+    pub callees: BTreeSet<String>,
+}
+
+impl FnCalls {
+    /// Whether `fn_path` names this function, matching it as [`find_fn_ranges`] does.
+    pub(crate) fn matches(&self, fn_path: &str) -> bool {
+        let (name, owner) = split_fn_path(fn_path);
+        self.name == name && owner.is_none_or(|owner| self.owner.as_deref() == Some(owner))
+    }
+}
+
+/// The direct calls made by every function, method and provided trait method with a body in
+/// the repository. Calls inside macro arguments that parse as expressions count, calls inside
+/// nested items don't.
+pub(crate) fn call_graph(repo_path: &Path) -> Vec<FnCalls> {
+    let mut res = Vec::new();
+    for path in rust_files(repo_path) {
+        let Some((_, file)) = parse_file(&path) else { continue };
+        let mut visitor = CallGraphVisitor { current_owner: None, fns: &mut res };
+        visitor.visit_file(&file);
+    }
+    res
+}
+
+/// A named item and the lines it spans, both ends inclusive and 1-based.
+#[derive(Debug, Clone)]
+pub(crate) struct ItemRange {
+    /// e.g. `foo`, `Type::method` or `impl Type`
+    pub name: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// Lists the functions, methods, `impl` blocks and type definitions in a file.
+pub(crate) fn item_ranges(path: &Path) -> Vec<ItemRange> {
+    let Some((_, file)) = parse_file(path) else { return vec![] };
+    let mut visitor = ItemVisitor { current_owner: None, items: vec![] };
+    visitor.visit_file(&file);
+    visitor.items
+}
+
+struct ItemVisitor {
+    current_owner: Option<String>,
+    items: Vec<ItemRange>,
+}
+
+impl ItemVisitor {
+    fn push(&mut self, name: String, span: proc_macro2::Span) {
+        self.items.push(ItemRange {
+            name,
+            start_line: span.start().line,
+            end_line: span.end().line,
+        });
+    }
+
+    fn push_fn(&mut self, ident: &syn::Ident, span: proc_macro2::Span) {
+        let name = match &self.current_owner {
+            Some(owner) => format!("{owner}::{ident}"),
+            None => ident.to_string(),
+        };
+        self.push(name, span);
+    }
+}
+
+impl<'ast> Visit<'ast> for ItemVisitor {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        // Nested functions are not qualified by the impl their parent belongs to.
+        let prev = self.current_owner.take();
+        self.push_fn(&item.sig.ident, item.span());
+        visit::visit_item_fn(self, item);
+        self.current_owner = prev;
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        let owner = match &*item.self_ty {
+            syn::Type::Path(ty) => ty.path.segments.last().map(|seg| seg.ident.to_string()),
+            _ => None,
+        };
+        if let Some(owner) = &owner {
+            self.push(format!("impl {owner}"), item.span());
+        }
+        let prev = std::mem::replace(&mut self.current_owner, owner);
+        visit::visit_item_impl(self, item);
+        self.current_owner = prev;
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast syn::ImplItemFn) {
+        self.push_fn(&item.sig.ident, item.span());
+        visit::visit_impl_item_fn(self, item);
+    }
+
+    fn visit_item_trait(&mut self, item: &'ast syn::ItemTrait) {
+        self.push(item.ident.to_string(), item.span());
+        let prev = self.current_owner.replace(item.ident.to_string());
+        visit::visit_item_trait(self, item);
+        self.current_owner = prev;
+    }
+
+    fn visit_trait_item_fn(&mut self, item: &'ast syn::TraitItemFn) {
+        self.push_fn(&item.sig.ident, item.span());
+        visit::visit_trait_item_fn(self, item);
+    }
+
+    fn visit_item_struct(&mut self, item: &'ast syn::ItemStruct) {
+        self.push(item.ident.to_string(), item.span());
+        visit::visit_item_struct(self, item);
+    }
+
+    fn visit_item_enum(&mut self, item: &'ast syn::ItemEnum) {
+        self.push(item.ident.to_string(), item.span());
+        visit::visit_item_enum(self, item);
+    }
+}
+
+/// Finds the item `item_path` points to, e.g. `crate::seq::RSeq::push`, `RSeq::push` or `len`:
+/// a free function, type, trait, constant, static or module, or an associated item of a type's
+/// `impl` blocks or of a trait. A path starting with `crate::` is resolved from the crate root,
+/// where files are placed by their path below `src`. Any other path can be a suffix of the
+/// item's full path, and the shortest such item wins, the first in file order on a tie. Items
+/// inside function bodies can't be named and aren't found.
+pub(crate) fn find_item(repo_path: &Path, item_path: &str) -> Option<LineRange> {
+    let (segments, absolute) = match item_path.strip_prefix("crate::") {
+        Some(rest) => (rest, true),
+        None => (item_path, false),
+    };
+    let segments: Vec<String> = segments.split("::").map(str::to_string).collect();
+    let mut best: Option<(usize, LineRange)> = None;
+    for path in rust_files(repo_path) {
+        let Some((_, file)) = parse_file(&path) else { continue };
+        let mut visitor = PathVisitor {
+            module: file_module(path.strip_prefix(repo_path).unwrap_or(&path)),
+            owner: None,
+            items: vec![],
+        };
+        visitor.visit_file(&file);
+        for (qualified, span) in visitor.items {
+            let matches =
+                if absolute { qualified == segments } else { qualified.ends_with(&segments) };
+            if matches && best.as_ref().is_none_or(|(len, _)| qualified.len() < *len) {
+                let range = LineRange {
+                    file_name: display_path(repo_path, &path),
+                    start_line: span.start().line,
+                    end_line: span.end().line,
+                };
+                best = Some((qualified.len(), range));
+            }
+        }
+    }
+    best.map(|(_, range)| range)
+}
+
+/// The module path of the file at `relative`, e.g. `["a", "b"]` for `src/a/b.rs` or
+/// `src/a/b/mod.rs`, and none for `src/lib.rs`, `src/main.rs` or a file outside of `src`
+/// such as an integration test, which is a crate root of its own.
+fn file_module(relative: &Path) -> Vec<String> {
+    let components: Vec<String> = relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    let Some(src) = components.iter().rposition(|component| component == "src") else {
+        return vec![];
+    };
+    let mut module: Vec<String> = components[src + 1..].to_vec();
+    match module
+        .pop()
+        .as_deref()
+        .and_then(|file| file.strip_suffix(".rs"))
+    {
+        Some("mod") => {}
+        Some("lib" | "main") if module.is_empty() => {}
+        Some(stem) => module.push(stem.to_string()),
+        None => {}
+    }
+    module
+}
+
+/// Collects the nameable items of a file with their full paths.
+struct PathVisitor {
+    module: Vec<String>,
+    /// The type of the `impl` or the trait being visited
+    owner: Option<String>,
+    items: Vec<(Vec<String>, proc_macro2::Span)>,
+}
+
+impl PathVisitor {
+    fn push(&mut self, ident: &syn::Ident, span: proc_macro2::Span) {
+        let mut path = self.module.clone();
+        path.extend(self.owner.clone());
+        path.push(ident.to_string());
+        self.items.push((path, span));
+    }
+}
+
+impl<'ast> Visit<'ast> for PathVisitor {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        self.push(&item.sig.ident, item.span());
+    }
+
+    fn visit_item_mod(&mut self, item: &'ast syn::ItemMod) {
+        self.push(&item.ident, item.span());
+        self.module.push(item.ident.to_string());
+        visit::visit_item_mod(self, item);
+        self.module.pop();
+    }
+
+    fn visit_item_struct(&mut self, item: &'ast syn::ItemStruct) {
+        self.push(&item.ident, item.span());
+    }
+
+    fn visit_item_enum(&mut self, item: &'ast syn::ItemEnum) {
+        self.push(&item.ident, item.span());
+    }
+
+    fn visit_item_union(&mut self, item: &'ast syn::ItemUnion) {
+        self.push(&item.ident, item.span());
+    }
+
+    fn visit_item_type(&mut self, item: &'ast syn::ItemType) {
+        self.push(&item.ident, item.span());
+    }
+
+    fn visit_item_const(&mut self, item: &'ast syn::ItemConst) {
+        self.push(&item.ident, item.span());
+    }
+
+    fn visit_item_static(&mut self, item: &'ast syn::ItemStatic) {
+        self.push(&item.ident, item.span());
+    }
+
+    fn visit_item_trait(&mut self, item: &'ast syn::ItemTrait) {
+        self.push(&item.ident, item.span());
+        self.owner = Some(item.ident.to_string());
+        visit::visit_item_trait(self, item);
+        self.owner = None;
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        let syn::Type::Path(ty) = &*item.self_ty else { return };
+        self.owner = ty.path.segments.last().map(|seg| seg.ident.to_string());
+        visit::visit_item_impl(self, item);
+        self.owner = None;
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast syn::ImplItemFn) {
+        self.push(&item.sig.ident, item.span());
+    }
+
+    fn visit_impl_item_const(&mut self, item: &'ast syn::ImplItemConst) {
+        self.push(&item.ident, item.span());
+    }
+
+    fn visit_impl_item_type(&mut self, item: &'ast syn::ImplItemType) {
+        self.push(&item.ident, item.span());
+    }
+
+    fn visit_trait_item_fn(&mut self, item: &'ast syn::TraitItemFn) {
+        self.push(&item.sig.ident, item.span());
+    }
+
+    fn visit_trait_item_const(&mut self, item: &'ast syn::TraitItemConst) {
+        self.push(&item.ident, item.span());
+    }
+
+    fn visit_trait_item_type(&mut self, item: &'ast syn::TraitItemType) {
+        self.push(&item.ident, item.span());
+    }
+}
+
+/// The source text of the innermost `impl` block in the file at `path` containing the 1-based
+/// `line`, attributes, generics and where-clauses included.
+pub(crate) fn enclosing_impl_text(path: &Path, line: usize) -> Option<String> {
+    let (contents, file) = parse_file(path)?;
+    let mut visitor = ImplVisitor { line, innermost: None };
+    visitor.visit_file(&file);
+    let span = visitor.innermost?;
+    Some(source_text(&contents, span.start(), span.end()))
+}
+
+struct ImplVisitor {
+    line: usize,
+    innermost: Option<proc_macro2::Span>,
+}
+
+impl<'ast> Visit<'ast> for ImplVisitor {
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        let span = item.span();
+        if span.start().line <= self.line && self.line <= span.end().line {
+            // Impls nested in a method of this one are visited after it, so the last is innermost.
+            self.innermost = Some(span);
+        }
+        visit::visit_item_impl(self, item);
+    }
+}
+
+/// Collects the identifiers mentioned in the `sig` attributes of the functions named `name`,
+/// e.g. `head` and `cons` for `#[flux::sig(fn(i32[@v], &Foo[@elems]) ensures head(cons(v, elems)) == v)]`.
+pub(crate) fn sig_idents(repo_path: &Path, name: &str) -> BTreeSet<String> {
+    sig_idents_of(repo_path, &BTreeSet::from([name]))
+        .remove(name)
+        .unwrap_or_default()
+}
+
+/// Like [`sig_idents`] for all of `names` at once, in a single pass over the sources. Names
+/// without a `sig` attribute are left out.
+pub(crate) fn sig_idents_of(
+    repo_path: &Path,
+    names: &BTreeSet<&str>,
+) -> BTreeMap<String, BTreeSet<String>> {
+    let mut visitor = SigVisitor { names, idents: BTreeMap::new() };
+    for path in rust_files(repo_path) {
+        let Some((_, file)) = parse_file(&path) else { continue };
+        visitor.visit_file(&file);
+    }
+    visitor.idents
+}
+
+/// The identifiers in a piece of source text, which needn't parse as an item or expression.
+/// Keywords are left out.
+pub(crate) fn text_idents(text: &str) -> BTreeSet<String> {
+    let mut idents = BTreeSet::new();
+    match text.parse::<TokenStream>() {
+        Ok(tokens) => collect_idents(tokens, &mut idents),
+        // An unbalanced snippet doesn't lex, so fall back to splitting on punctuation.
+        Err(_) => {
+            idents.extend(
+                text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .filter(|word| word.starts_with(|c: char| c.is_alphabetic() || c == '_'))
+                    .map(str::to_string),
+            );
+        }
+    }
+    idents.retain(|ident| syn::parse_str::<syn::Ident>(ident).is_ok());
+    idents
+}
+
+/// The flux signature of a function.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FnSig {
+    /// e.g. `foo`, `Type::method` or `Trait::method`
+    pub name: String,
+    /// The contents of the `sig` attribute as written, e.g. `fn(i32[@n]) -> i32[n + 1]`
+    pub sig_text: String,
+    /// The refinement parameters the signature binds, e.g. `n`, see [`refinement_params`]
+    #[serde(default)]
+    pub params: Vec<String>,
+    /// The line of the `sig` attribute
+    pub line: usize,
+}
+
+/// Collects the `sig` attributes of the functions, methods and trait items of the file at
+/// `path`, in source order, or `None` if it can't be read or parsed.
+pub(crate) fn file_sigs(path: &Path) -> Option<Vec<FnSig>> {
+    let (_, sites) = sig_sites(path)?;
+    Some(sites.into_iter().map(|site| site.sig).collect())
+}
+
+/// A signature found by [`sig_sites`] and where its text is in the file.
+pub(crate) struct SigSite {
+    pub sig: FnSig,
+    /// The UTF-8 byte range of [`FnSig::sig_text`] in the file
+    pub range: std::ops::Range<usize>,
+}
+
+/// Like [`file_sigs`], but also returns the contents of the file and where each signature is in
+/// it, so that it can be rewritten.
+pub(crate) fn sig_sites(path: &Path) -> Option<(String, Vec<SigSite>)> {
+    let (contents, file) = parse_file(path)?;
+    let mut visitor = FnSigVisitor { contents: &contents, current_owner: None, sites: vec![] };
+    visitor.visit_file(&file);
+    let sites = visitor.sites;
+    Some((contents, sites))
+}
+
+struct FnSigVisitor<'a> {
+    contents: &'a str,
+    current_owner: Option<String>,
+    sites: Vec<SigSite>,
+}
+
+impl FnSigVisitor<'_> {
+    fn check(&mut self, ident: &syn::Ident, attrs: &[syn::Attribute]) {
+        for attr in attrs {
+            let syn::Meta::List(list) = &attr.meta else { continue };
+            if list
+                .path
+                .segments
+                .last()
+                .is_none_or(|segment| segment.ident != "sig")
+            {
+                continue;
+            }
+            let tokens: Vec<TokenTree> = list.tokens.clone().into_iter().collect();
+            let (Some(first), Some(last)) = (tokens.first(), tokens.last()) else { continue };
+            let name = match &self.current_owner {
+                Some(owner) => format!("{owner}::{ident}"),
+                None => ident.to_string(),
+            };
+            let (start, end) = (first.span().start(), last.span().end());
+            let offset = |at: LineColumn| {
+                diagnostics::column_to_byte_offset(self.contents, at.line, at.column + 1)
+            };
+            self.sites.push(SigSite {
+                sig: FnSig {
+                    name,
+                    sig_text: source_text(self.contents, start, end),
+                    params: refinement_params(list.tokens.clone()),
+                    line: attr.span().start().line,
+                },
+                range: offset(start)..offset(end),
+            });
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for FnSigVisitor<'_> {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        let prev = self.current_owner.take();
+        self.check(&item.sig.ident, &item.attrs);
+        visit::visit_item_fn(self, item);
+        self.current_owner = prev;
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        let owner = match &*item.self_ty {
+            syn::Type::Path(ty) => ty.path.segments.last().map(|seg| seg.ident.to_string()),
+            _ => None,
+        };
+        let prev = std::mem::replace(&mut self.current_owner, owner);
+        visit::visit_item_impl(self, item);
+        self.current_owner = prev;
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast syn::ImplItemFn) {
+        self.check(&item.sig.ident, &item.attrs);
+        visit::visit_impl_item_fn(self, item);
+    }
+
+    fn visit_item_trait(&mut self, item: &'ast syn::ItemTrait) {
+        let prev = self.current_owner.replace(item.ident.to_string());
+        visit::visit_item_trait(self, item);
+        self.current_owner = prev;
+    }
+
+    fn visit_trait_item_fn(&mut self, item: &'ast syn::TraitItemFn) {
+        self.check(&item.sig.ident, &item.attrs);
+        visit::visit_trait_item_fn(self, item);
+    }
+}
+
+/// The `requires` and `ensures` clauses of a flux signature, and the parameters it binds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct SigClauses {
+    pub requires: Vec<String>,
+    pub ensures: Vec<String>,
+    pub params: Vec<String>,
+    /// Whether the function is `#[flux::trusted]`, so that its signature isn't checked
+    pub trusted: bool,
+}
+
+/// The refinement parameters a flux signature binds with `@`, e.g. `elems` and `v` for
+/// `fn(&Self[@elems], i32[@v]) -> Foo[cons(v, elems)]`, in order and without duplicates.
+/// Binders nested in brackets, e.g. `&RVec<i32[@n]>`, are found too.
+pub(crate) fn refinement_params(tokens: TokenStream) -> Vec<String> {
+    fn walk(tokens: TokenStream, params: &mut Vec<String>) {
+        let mut after_at = false;
+        for token in tokens {
+            match &token {
+                TokenTree::Ident(ident) if after_at => {
+                    let name = ident.to_string();
+                    if !params.contains(&name) {
+                        params.push(name);
+                    }
+                }
+                TokenTree::Group(group) => walk(group.stream(), params),
+                _ => {}
+            }
+            after_at = matches!(&token, TokenTree::Punct(punct) if punct.as_char() == '@');
+        }
+    }
+    let mut params = vec![];
+    walk(tokens, &mut params);
+    params
+}
+
+/// Extracts the clauses of the `sig` attributes of the functions named in `names`, e.g.
+/// `head(cons(v, elems)) == v` as an `ensures` for
+/// `#[flux::sig(fn(i32[@v], &Foo[@elems]) ensures head(cons(v, elems)) == v)]`. Each clause is
+/// taken from the source, with its whitespace collapsed. Only the first function of each name
+/// counts.
+pub(crate) fn sig_clauses(
+    repo_path: &Path,
+    names: &BTreeSet<&str>,
+) -> BTreeMap<String, SigClauses> {
+    let mut clauses = BTreeMap::new();
+    for path in rust_files(repo_path) {
+        let Some((contents, file)) = parse_file(&path) else { continue };
+        let mut visitor = ClauseVisitor { names, contents: &contents, clauses: &mut clauses };
+        visitor.visit_file(&file);
+    }
+    clauses
+}
+
+struct ClauseVisitor<'a> {
+    names: &'a BTreeSet<&'a str>,
+    contents: &'a str,
+    clauses: &'a mut BTreeMap<String, SigClauses>,
+}
+
+impl ClauseVisitor<'_> {
+    fn check(&mut self, ident: &syn::Ident, attrs: &[syn::Attribute]) {
+        let name = ident.to_string();
+        if !self.names.contains(name.as_str()) || self.clauses.contains_key(&name) {
+            return;
+        }
+        for attr in attrs {
+            let syn::Meta::List(list) = &attr.meta else { continue };
+            if list
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "sig")
+            {
+                let mut clauses = split_clauses(list.tokens.clone(), self.contents);
+                clauses.params = refinement_params(list.tokens.clone());
+                clauses.trusted = attrs
+                    .iter()
+                    .any(|attr| trust_attribute(attr) == Some("trusted"));
+                self.clauses.insert(name, clauses);
+                return;
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for ClauseVisitor<'_> {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        self.check(&item.sig.ident, &item.attrs);
+        visit::visit_item_fn(self, item);
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast syn::ImplItemFn) {
+        self.check(&item.sig.ident, &item.attrs);
+        visit::visit_impl_item_fn(self, item);
+    }
+}
+
+/// The parameters of a function, as Rust and its flux signature see them.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FnParams {
+    /// The Rust parameter names, `self` for a receiver and `_` for a pattern
+    pub names: Vec<String>,
+    /// The refinement parameters each argument of the signature binds with `@`, empty if the
+    /// function has no signature
+    pub binders: Vec<Vec<String>>,
+    /// The preconditions of the signature
+    pub requires: Vec<String>,
+}
+
+/// The parameters of the first function or method named `name` in the repository, or `None`
+/// if there is no such function.
+pub(crate) fn fn_params(repo_path: &Path, name: &str) -> Option<FnParams> {
+    for path in rust_files(repo_path) {
+        let Some((contents, file)) = parse_file(&path) else { continue };
+        let mut visitor = ParamsVisitor { name, contents: &contents, params: None };
+        visitor.visit_file(&file);
+        if visitor.params.is_some() {
+            return visitor.params;
+        }
+    }
+    None
+}
+
+struct ParamsVisitor<'a> {
+    name: &'a str,
+    contents: &'a str,
+    params: Option<FnParams>,
+}
+
+impl ParamsVisitor<'_> {
+    fn check(&mut self, sig: &syn::Signature, attrs: &[syn::Attribute]) {
+        if self.params.is_some() || sig.ident != self.name {
+            return;
+        }
+        let names = sig
+            .inputs
+            .iter()
+            .map(|input| {
+                match input {
+                    syn::FnArg::Receiver(_) => "self".to_string(),
+                    syn::FnArg::Typed(arg) => {
+                        match &*arg.pat {
+                            syn::Pat::Ident(pat) => pat.ident.to_string(),
+                            _ => "_".to_string(),
+                        }
+                    }
+                }
+            })
+            .collect();
+        let mut params = FnParams { names, ..Default::default() };
+        let sig_tokens = attrs.iter().find_map(|attr| {
+            let syn::Meta::List(list) = &attr.meta else { return None };
+            let segment = list.path.segments.last()?;
+            (segment.ident == "sig").then(|| list.tokens.clone())
+        });
+        if let Some(tokens) = sig_tokens {
+            params.requires = split_clauses(tokens.clone(), self.contents).requires;
+            let inputs = tokens.into_iter().find_map(|tree| {
+                match tree {
+                    TokenTree::Group(group) if group.delimiter() == Delimiter::Parenthesis => {
+                        Some(group.stream())
+                    }
+                    _ => None,
+                }
+            });
+            params.binders = inputs
+                .map(split_commas)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|arg| refinement_params(arg.into_iter().collect()))
+                .collect();
+        }
+        self.params = Some(params);
+    }
+}
+
+impl<'ast> Visit<'ast> for ParamsVisitor<'_> {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        self.check(&item.sig, &item.attrs);
+        visit::visit_item_fn(self, item);
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast syn::ImplItemFn) {
+        self.check(&item.sig, &item.attrs);
+        visit::visit_impl_item_fn(self, item);
+    }
+}
+
+/// A call as written in the source.
+#[derive(Debug, Clone)]
+pub(crate) struct CallSite {
+    /// The name of the function or method called, without its path
+    pub callee: String,
+    /// The source text of each argument, the receiver first for a method call
+    pub args: Vec<String>,
+}
+
+/// Parses `text`, e.g. the text of a diagnostic's span, as a single function or method call.
+pub(crate) fn parse_call(text: &str) -> Option<CallSite> {
+    let mut expr = syn::parse_str::<syn::Expr>(text).ok()?;
+    while let syn::Expr::Paren(inner) = expr {
+        expr = *inner.expr;
+    }
+    let arg_text = |arg: &syn::Expr| source_text(text, arg.span().start(), arg.span().end());
+    match &expr {
+        syn::Expr::Call(call) => {
+            let syn::Expr::Path(func) = &*call.func else { return None };
+            Some(CallSite {
+                callee: func.path.segments.last()?.ident.to_string(),
+                args: call.args.iter().map(arg_text).collect(),
+            })
+        }
+        syn::Expr::MethodCall(call) => {
+            Some(CallSite {
+                callee: call.method.to_string(),
+                args: std::iter::once(&*call.receiver)
+                    .chain(&call.args)
+                    .map(arg_text)
+                    .collect(),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Splits `tokens` at the commas outside of generic arguments like `Map<int, bool>`, dropping
+/// empty pieces.
+fn split_commas(tokens: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut args = vec![vec![]];
+    let mut depth = 0usize;
+    for tree in tokens {
+        if let TokenTree::Punct(punct) = &tree {
+            match punct.as_char() {
+                '<' => depth += 1,
+                '>' => depth = depth.saturating_sub(1),
+                ',' if depth == 0 => {
+                    args.push(vec![]);
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        args.last_mut().unwrap().push(tree);
+    }
+    args.retain(|arg| !arg.is_empty());
+    args
+}
+
+/// Splits the top-level tokens of a signature at the `requires` and `ensures` keywords, and the
+/// clauses after each keyword at top-level commas. Anything nested in brackets stays whole.
+fn split_clauses(tokens: TokenStream, contents: &str) -> SigClauses {
+    let mut clauses = SigClauses::default();
+    let mut target: Option<&mut Vec<String>> = None;
+    let mut clause: Vec<TokenTree> = vec![];
+    let flush = |clause: &mut Vec<TokenTree>, target: &mut Option<&mut Vec<String>>| {
+        if let (Some(first), Some(last), Some(target)) = (clause.first(), clause.last(), target) {
+            let text = source_text(contents, first.span().start(), last.span().end());
+            target.push(text.split_whitespace().collect::<Vec<_>>().join(" "));
+        }
+        clause.clear();
+    };
+    for token in tokens {
+        match &token {
+            TokenTree::Ident(ident) if ident == "requires" || ident == "ensures" => {
+                flush(&mut clause, &mut target);
+                target = Some(if ident == "requires" {
+                    &mut clauses.requires
+                } else {
+                    &mut clauses.ensures
+                });
+            }
+            TokenTree::Punct(punct) if punct.as_char() == ',' => flush(&mut clause, &mut target),
+            _ => clause.push(token),
+        }
+    }
+    flush(&mut clause, &mut target);
+    clauses
+}
+
+struct SigVisitor<'a> {
+    names: &'a BTreeSet<&'a str>,
+    idents: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl SigVisitor<'_> {
+    fn check(&mut self, ident: &syn::Ident, attrs: &[syn::Attribute]) {
+        let name = ident.to_string();
+        if !self.names.contains(name.as_str()) {
+            return;
+        }
+        for attr in attrs {
+            let syn::Meta::List(list) = &attr.meta else { continue };
+            if list
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "sig")
+            {
+                let idents = self.idents.entry(name.clone()).or_default();
+                collect_idents(list.tokens.clone(), idents);
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for SigVisitor<'_> {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        self.check(&item.sig.ident, &item.attrs);
+        visit::visit_item_fn(self, item);
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast syn::ImplItemFn) {
+        self.check(&item.sig.ident, &item.attrs);
+        visit::visit_impl_item_fn(self, item);
+    }
+}
+
+fn collect_idents(tokens: TokenStream, idents: &mut BTreeSet<String>) {
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => {
+                idents.insert(ident.to_string());
+            }
+            TokenTree::Group(group) => collect_idents(group.stream(), idents),
+            _ => {}
+        }
+    }
+}
+
+/// Counts the uses of each of the functions in `names` as call or path expressions, including
+/// those inside macro arguments that parse as expressions. Comments, strings and method calls
+/// of the same name don't count.
+/// Uses through a `use ... as alias` rename count towards the original name.
+pub(crate) fn use_counts(repo_path: &Path, names: &BTreeSet<&str>) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for path in rust_files(repo_path) {
+        let Some((_, file)) = parse_file(&path) else { continue };
+        let mut renames = RenameVisitor { renames: HashMap::new() };
+        renames.visit_file(&file);
+        let mut visitor = UseVisitor { names, renames: renames.renames, counts: &mut counts };
+        visitor.visit_file(&file);
+    }
+    counts
+}
+
+/// For every function named in `names`, the other functions in `names` that its body uses (as
+/// counted by [`use_counts`]) or that its `sig` attributes mention.
+pub(crate) fn fn_references(
+    repo_path: &Path,
+    names: &BTreeSet<&str>,
+) -> BTreeMap<String, BTreeSet<String>> {
+    let mut references = BTreeMap::new();
+    for path in rust_files(repo_path) {
+        let Some((_, file)) = parse_file(&path) else { continue };
+        let mut renames = RenameVisitor { renames: HashMap::new() };
+        renames.visit_file(&file);
+        let mut visitor =
+            RefsVisitor { names, renames: renames.renames, references: &mut references };
+        visitor.visit_file(&file);
+    }
+    references
+}
+
+struct RefsVisitor<'a> {
+    names: &'a BTreeSet<&'a str>,
+    renames: HashMap<String, String>,
+    references: &'a mut BTreeMap<String, BTreeSet<String>>,
+}
+
+impl RefsVisitor<'_> {
+    fn check(&mut self, ident: &syn::Ident, attrs: &[syn::Attribute], block: &syn::Block) {
+        let name = ident.to_string();
+        if !self.names.contains(name.as_str()) {
+            return;
+        }
+        let mut counts = BTreeMap::new();
+        let mut uses =
+            UseVisitor { names: self.names, renames: self.renames.clone(), counts: &mut counts };
+        uses.visit_block(block);
+        let mut sig = BTreeSet::new();
+        for attr in attrs {
+            if let syn::Meta::List(list) = &attr.meta {
+                collect_idents(list.tokens.clone(), &mut sig);
+            }
+        }
+        let references = self.references.entry(name.clone()).or_default();
+        references.extend(counts.into_keys());
+        references.extend(
+            sig.into_iter()
+                .filter(|ident| self.names.contains(ident.as_str())),
+        );
+        references.remove(&name);
+    }
+}
+
+impl<'ast> Visit<'ast> for RefsVisitor<'_> {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        self.check(&item.sig.ident, &item.attrs, &item.block);
+        visit::visit_item_fn(self, item);
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast syn::ImplItemFn) {
+        self.check(&item.sig.ident, &item.attrs, &item.block);
+        visit::visit_impl_item_fn(self, item);
+    }
+}
+
+struct RenameVisitor {
+    renames: HashMap<String, String>,
+}
+
+impl<'ast> Visit<'ast> for RenameVisitor {
+    fn visit_use_rename(&mut self, rename: &'ast syn::UseRename) {
+        self.renames
+            .insert(rename.rename.to_string(), rename.ident.to_string());
+    }
+}
+
+struct UseVisitor<'a> {
+    names: &'a BTreeSet<&'a str>,
+    renames: HashMap<String, String>,
+    counts: &'a mut BTreeMap<String, usize>,
+}
+
+impl UseVisitor<'_> {
+    fn count(&mut self, ident: &syn::Ident) {
+        let ident = ident.to_string();
+        let name = self.renames.get(&ident).unwrap_or(&ident);
+        if self.names.contains(name.as_str()) {
+            *self.counts.entry(name.clone()).or_insert(0) += 1;
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for UseVisitor<'_> {
+    fn visit_expr_path(&mut self, expr: &'ast syn::ExprPath) {
+        if let Some(segment) = expr.path.segments.last() {
+            self.count(&segment.ident);
+        }
+        visit::visit_expr_path(self, expr);
+    }
+
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        let args = mac.parse_body_with(Punctuated::<syn::Expr, Token![,]>::parse_terminated);
+        for arg in args.iter().flatten() {
+            self.visit_expr(arg);
+        }
+    }
+}
+
+struct FnVisitor<'a> {
+    name: &'a str,
+    owner: Option<&'a str>,
+    current_owner: Option<String>,
+    spans: Vec<proc_macro2::Span>,
+}
+
+impl FnVisitor<'_> {
+    fn check(&mut self, ident: &syn::Ident, span: proc_macro2::Span) {
+        let owner_matches = match self.owner {
+            Some(owner) => self.current_owner.as_deref() == Some(owner),
+            None => true,
+        };
+        if ident == self.name && owner_matches {
+            self.spans.push(span);
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for FnVisitor<'_> {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        self.check(&item.sig.ident, item.span());
+        visit::visit_item_fn(self, item);
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        let owner = match &*item.self_ty {
+            syn::Type::Path(ty) => ty.path.segments.last().map(|seg| seg.ident.to_string()),
+            _ => None,
+        };
+        let prev = std::mem::replace(&mut self.current_owner, owner);
+        visit::visit_item_impl(self, item);
+        self.current_owner = prev;
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast syn::ImplItemFn) {
+        self.check(&item.sig.ident, item.span());
+        visit::visit_impl_item_fn(self, item);
+    }
+
+    fn visit_item_trait(&mut self, item: &'ast syn::ItemTrait) {
+        let prev = self.current_owner.replace(item.ident.to_string());
+        visit::visit_item_trait(self, item);
+        self.current_owner = prev;
+    }
+
+    fn visit_trait_item_fn(&mut self, item: &'ast syn::TraitItemFn) {
+        self.check(&item.sig.ident, item.span());
+        visit::visit_trait_item_fn(self, item);
+    }
+}
+
+struct CallGraphVisitor<'a> {
+    current_owner: Option<String>,
+    fns: &'a mut Vec<FnCalls>,
+}
+
+impl CallGraphVisitor<'_> {
+    fn push(&mut self, ident: &syn::Ident, block: &syn::Block) {
+        let mut calls =
+            CallVisitor { owner: self.current_owner.as_deref(), callees: BTreeSet::new() };
+        calls.visit_block(block);
+        self.fns.push(FnCalls {
+            owner: self.current_owner.clone(),
+            name: ident.to_string(),
+            callees: calls.callees,
+        });
+    }
+}
+
+impl<'ast> Visit<'ast> for CallGraphVisitor<'_> {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        // Nested functions are not qualified by the impl their parent belongs to.
+        let prev = self.current_owner.take();
+        self.push(&item.sig.ident, &item.block);
+        visit::visit_item_fn(self, item);
+        self.current_owner = prev;
+    }
+
+    fn visit_item_impl(&mut self, item: &'ast syn::ItemImpl) {
+        let owner = match &*item.self_ty {
+            syn::Type::Path(ty) => ty.path.segments.last().map(|seg| seg.ident.to_string()),
+            _ => None,
+        };
+        let prev = std::mem::replace(&mut self.current_owner, owner);
+        visit::visit_item_impl(self, item);
+        self.current_owner = prev;
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast syn::ImplItemFn) {
+        self.push(&item.sig.ident, &item.block);
+        visit::visit_impl_item_fn(self, item);
+    }
+
+    fn visit_item_trait(&mut self, item: &'ast syn::ItemTrait) {
+        let prev = self.current_owner.replace(item.ident.to_string());
+        visit::visit_item_trait(self, item);
+        self.current_owner = prev;
+    }
+
+    fn visit_trait_item_fn(&mut self, item: &'ast syn::TraitItemFn) {
+        if let Some(block) = &item.default {
+            self.push(&item.sig.ident, block);
+        }
+        visit::visit_trait_item_fn(self, item);
+    }
+}
+
+struct CallVisitor<'a> {
+    owner: Option<&'a str>,
+    callees: BTreeSet<String>,
+}
+
+impl<'ast> Visit<'ast> for CallVisitor<'_> {
+    fn visit_expr_call(&mut self, expr: &'ast syn::ExprCall) {
+        if let syn::Expr::Path(func) = &*expr.func {
+            let segments: Vec<String> = func
+                .path
+                .segments
+                .iter()
+                .map(|segment| {
+                    match self.owner {
+                        Some(owner) if segment.ident == "Self" => owner.to_string(),
+                        _ => segment.ident.to_string(),
+                    }
+                })
+                .collect();
+            self.callees.insert(segments.join("::"));
+        }
+        visit::visit_expr_call(self, expr);
+    }
+
+    fn visit_expr_method_call(&mut self, expr: &'ast syn::ExprMethodCall) {
+        self.callees.insert(expr.method.to_string());
+        visit::visit_expr_method_call(self, expr);
+    }
+
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        let args = mac.parse_body_with(Punctuated::<syn::Expr, Token![,]>::parse_terminated);
+        for arg in args.iter().flatten() {
+            self.visit_expr(arg);
+        }
+    }
+
+    // The calls of nested functions are their own.
+    fn visit_item(&mut self, _: &'ast syn::Item) {}
+}
+
+struct DefsVisitor {
+    macros: Vec<TokenStream>,
+}
+
+impl<'ast> Visit<'ast> for DefsVisitor {
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        if mac
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "defs")
+        {
+            self.macros.push(mac.tokens.clone());
+        }
+        visit::visit_macro(self, mac);
+    }
+}
+
+/// Splits the body of a `defs!` block into its items. An item ends at a top-level `;` or at
+/// the braces delimiting a function body.
+fn split_defs(tokens: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut items = Vec::new();
+    let mut current = Vec::new();
+    for token in tokens {
+        match &token {
+            TokenTree::Punct(punct) if punct.as_char() == ';' => {
+                items.push(std::mem::take(&mut current));
+                continue;
+            }
+            TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => {
+                current.push(token);
+                items.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(token);
+    }
+    if !current.is_empty() {
+        items.push(current);
+    }
+    items
+}
+
+fn def_item(contents: &str, file_name: &str, item: &[TokenTree]) -> Option<DefItem> {
+    // Skip any attributes and visibility, e.g. `#[hide] pub fn ...`.
+    let tokens: Vec<&TokenTree> = item
+        .iter()
+        .skip_while(|token| {
+            match token {
+                TokenTree::Punct(punct) => punct.as_char() == '#',
+                TokenTree::Group(group) => group.delimiter() == Delimiter::Bracket,
+                TokenTree::Ident(ident) => ident == "pub",
+                TokenTree::Literal(_) => false,
+            }
+        })
+        .collect();
+    let mut idents = tokens.iter().enumerate().filter_map(|(i, token)| {
+        match token {
+            TokenTree::Ident(ident) => Some((i, ident.to_string())),
+            _ => None,
+        }
+    });
+    let (_, keyword) = idents.find(|(_, ident)| !matches!(ident.as_str(), "opaque" | "local"))?;
+    let kind = match keyword.as_str() {
+        "sort" => DefKind::Sort,
+        "fn" => DefKind::Func,
+        "qualifier" => DefKind::Qualifier,
+        "property" => DefKind::Property,
+        _ => return None,
+    };
+    let (name_at, name) = idents.next()?;
+    let rest = &tokens[name_at + 1..];
+    let params = rest
+        .iter()
+        .find_map(|token| {
+            match token {
+                TokenTree::Group(group) if group.delimiter() == Delimiter::Parenthesis => {
+                    Some(split_params(group.stream(), contents))
+                }
+                _ => None,
+            }
+        })
+        .unwrap_or_default();
+    let return_sort = (kind == DefKind::Func)
+        .then(|| return_sort(rest, contents))
+        .flatten();
+    let start = tokens.first()?.span().start();
+    let end = item.last()?.span().end();
+    let signature = source_text(contents, start, end);
+    Some(DefItem {
+        kind,
+        name,
+        params,
+        return_sort,
+        signature,
+        file_name: file_name.to_string(),
+        line: start.line,
+    })
+}
+
+/// Splits a parameter list at its top-level commas, with the whitespace of each parameter
+/// collapsed.
+fn split_params(tokens: TokenStream, contents: &str) -> Vec<String> {
+    let mut params = vec![];
+    let mut param: Vec<TokenTree> = vec![];
+    let mut flush = |param: &mut Vec<TokenTree>| {
+        if let (Some(first), Some(last)) = (param.first(), param.last()) {
+            let text = source_text(contents, first.span().start(), last.span().end());
+            params.push(text.split_whitespace().collect::<Vec<_>>().join(" "));
+        }
+        param.clear();
+    };
+    for token in tokens {
+        match &token {
+            TokenTree::Punct(punct) if punct.as_char() == ',' => flush(&mut param),
+            _ => param.push(token),
+        }
+    }
+    flush(&mut param);
+    params
+}
+
+/// The sort after the `->` of a function declaration, up to its body or the end.
+fn return_sort(tokens: &[&TokenTree], contents: &str) -> Option<String> {
+    let arrow = tokens.windows(2).position(|pair| {
+        matches!((pair[0], pair[1]), (TokenTree::Punct(dash), TokenTree::Punct(gt))
+            if dash.as_char() == '-' && gt.as_char() == '>')
+    })?;
+    let sort: Vec<_> = tokens[arrow + 2..]
+        .iter()
+        .take_while(|token| {
+            !matches!(token, TokenTree::Group(group) if group.delimiter() == Delimiter::Brace)
+        })
+        .collect();
+    let text = source_text(contents, sort.first()?.span().start(), sort.last()?.span().end());
+    Some(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
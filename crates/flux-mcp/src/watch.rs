@@ -0,0 +1,235 @@
+//! Re-verifying a repository whenever its sources change.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use rmcp::{
+    Peer, RoleServer,
+    model::{LoggingLevel, LoggingMessageNotificationParam},
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::flux_runner::FluxRunner;
+
+pub(crate) const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// A running watch on a single repository. Each watch owns its own file watcher and
+/// verification task, so watches on different repositories never see each other's events.
+pub(crate) struct Watch {
+    watcher: RecommendedWatcher,
+    task: JoinHandle<()>,
+}
+
+impl Watch {
+    /// Starts watching the `.rs` files under `repo_path`. Once a burst of changes has been quiet
+    /// for `debounce`, the repository is verified and the report is sent to `peer` as a logging
+    /// notification.
+    pub(crate) fn start(
+        repo_path: PathBuf,
+        debounce: Duration,
+        runner: Arc<FluxRunner>,
+        peer: Peer<RoleServer>,
+    ) -> notify::Result<Self> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let root = repo_path.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            match event {
+                Ok(event) if is_source_change(&root, &event) => {
+                    let _ = tx.send(());
+                }
+                Ok(_) => {}
+                Err(err) => tracing::warn!("Watch error on {}: {err}", root.display()),
+            }
+        })?;
+        watcher.watch(&repo_path, RecursiveMode::Recursive)?;
+
+        let task = tokio::spawn(async move {
+            while rx.recv().await.is_some() {
+                // Wait for the burst of events to settle; a closed channel ends the watch.
+                while let Ok(Some(())) = tokio::time::timeout(debounce, rx.recv()).await {}
+                let repo = repo_path.to_string_lossy();
+                let result = runner.verify_repository(&repo, false).await;
+                let (level, data) = match result {
+                    Ok(report) => {
+                        let level =
+                            if report.success { LoggingLevel::Info } else { LoggingLevel::Warning };
+                        (level, serde_json::json!({ "repo_path": repo, "report": report }))
+                    }
+                    Err(err) => {
+                        (
+                            LoggingLevel::Error,
+                            serde_json::json!({ "repo_path": repo, "error": err.to_string() }),
+                        )
+                    }
+                };
+                let notification = LoggingMessageNotificationParam {
+                    level,
+                    logger: Some("flux-watch".to_string()),
+                    data,
+                };
+                if let Err(err) = peer.notify_logging_message(notification).await {
+                    tracing::warn!("Stopping watch on {repo}: {err}");
+                    break;
+                }
+            }
+        });
+        Ok(Self { watcher, task })
+    }
+
+    /// Stops watching. The file watcher is dropped before the task is aborted so no further
+    /// events are queued.
+    pub(crate) fn stop(self) {
+        drop(self.watcher);
+        self.task.abort();
+    }
+}
+
+/// Whether `event` touches a Rust source file outside `target` or a hidden directory.
+fn is_source_change(root: &Path, event: &Event) -> bool {
+    if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+        return false;
+    }
+    event.paths.iter().any(|path| {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        path.extension().is_some_and(|ext| ext == "rs")
+            && !relative.components().any(|component| {
+                let name = component.as_os_str().to_string_lossy();
+                name == "target" || name.starts_with('.')
+            })
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+
+    use rmcp::service::{RunningService, serve_directly};
+    use tempfile::TempDir;
+    use tokio::io::{AsyncBufReadExt, BufReader, DuplexStream, Lines};
+
+    use super::*;
+    use crate::{
+        flux_mcp::FluxMcp,
+        flux_runner::{FluxRunnerConfig, tests::fake_cargo},
+    };
+
+    const DEBOUNCE: Duration = Duration::from_millis(100);
+
+    /// A runner whose `cargo` logs a line to `runs` on every call, and a repository to watch.
+    fn runner_and_repos(count: usize) -> (TempDir, Arc<FluxRunner>, Vec<TempDir>) {
+        let (dir, cargo_path) = fake_cargo("echo run >> \"$(dirname \"$0\")/runs\"");
+        let runner = Arc::new(FluxRunner::with_config(FluxRunnerConfig {
+            cargo_path,
+            ..Default::default()
+        }));
+        let repos = (0..count)
+            .map(|_| {
+                let repo = tempfile::tempdir().unwrap();
+                fs::create_dir(repo.path().join("src")).unwrap();
+                fs::write(repo.path().join("src/lib.rs"), "fn f() {}\n").unwrap();
+                repo
+            })
+            .collect();
+        (dir, runner, repos)
+    }
+
+    fn runs(cargo_dir: &TempDir) -> usize {
+        fs::read_to_string(cargo_dir.path().join("runs")).map_or(0, |runs| runs.lines().count())
+    }
+
+    /// A peer whose notifications are read back as JSON lines from the other end.
+    fn peer() -> (RunningService<RoleServer, FluxMcp>, Lines<BufReader<DuplexStream>>) {
+        let (server, client) = tokio::io::duplex(1 << 16);
+        (serve_directly(FluxMcp::new(), server, None), BufReader::new(client).lines())
+    }
+
+    /// The repository of the next watch report sent within `wait`, if any.
+    async fn next_report(
+        notifications: &mut Lines<BufReader<DuplexStream>>,
+        wait: Duration,
+    ) -> Option<String> {
+        let next = async {
+            while let Some(line) = notifications.next_line().await.unwrap() {
+                let message: serde_json::Value = serde_json::from_str(&line).unwrap();
+                if message["params"]["logger"] == "flux-watch" {
+                    return message["params"]["data"]["repo_path"]
+                        .as_str()
+                        .map(str::to_string);
+                }
+            }
+            None
+        };
+        tokio::time::timeout(wait, next).await.ok().flatten()
+    }
+
+    fn touch(repo: &TempDir) {
+        fs::write(repo.path().join("src/lib.rs"), "fn g() {}\n").unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_burst_of_changes_is_verified_once_it_settles() {
+        let (cargo_dir, runner, repos) = runner_and_repos(1);
+        let (service, mut notifications) = peer();
+        let watch =
+            Watch::start(repos[0].path().to_path_buf(), DEBOUNCE, runner, service.peer().clone())
+                .unwrap();
+
+        for _ in 0..3 {
+            touch(&repos[0]);
+        }
+        let repo = next_report(&mut notifications, Duration::from_secs(10)).await;
+        assert_eq!(repo.as_deref(), repos[0].path().to_str());
+        assert_eq!(runs(&cargo_dir), 1);
+        assert_eq!(next_report(&mut notifications, DEBOUNCE * 5).await, None);
+
+        // Changes outside the sources don't count.
+        fs::write(repos[0].path().join("notes.txt"), "").unwrap();
+        assert_eq!(next_report(&mut notifications, DEBOUNCE * 5).await, None);
+        watch.stop();
+    }
+
+    #[tokio::test]
+    async fn stopped_watches_verify_nothing() {
+        let (cargo_dir, runner, repos) = runner_and_repos(1);
+        let (service, mut notifications) = peer();
+        let watch =
+            Watch::start(repos[0].path().to_path_buf(), DEBOUNCE, runner, service.peer().clone())
+                .unwrap();
+
+        watch.stop();
+        touch(&repos[0]);
+        assert_eq!(next_report(&mut notifications, DEBOUNCE * 5).await, None);
+        assert_eq!(runs(&cargo_dir), 0);
+    }
+
+    #[tokio::test]
+    async fn watches_on_different_repos_do_not_interfere() {
+        let (cargo_dir, runner, repos) = runner_and_repos(2);
+        let (service, mut notifications) = peer();
+        let watches: Vec<_> = repos
+            .iter()
+            .map(|repo| {
+                let path = repo.path().to_path_buf();
+                Watch::start(path, DEBOUNCE, runner.clone(), service.peer().clone()).unwrap()
+            })
+            .collect();
+
+        touch(&repos[1]);
+        let repo = next_report(&mut notifications, Duration::from_secs(10)).await;
+        assert_eq!(repo.as_deref(), repos[1].path().to_str());
+        assert_eq!(next_report(&mut notifications, DEBOUNCE * 5).await, None);
+        assert_eq!(runs(&cargo_dir), 1);
+
+        let mut watches = watches.into_iter();
+        watches.next().unwrap().stop();
+        touch(&repos[0]);
+        touch(&repos[1]);
+        let repo = next_report(&mut notifications, Duration::from_secs(10)).await;
+        assert_eq!(repo.as_deref(), repos[1].path().to_str());
+        assert_eq!(runs(&cargo_dir), 2);
+    }
+}
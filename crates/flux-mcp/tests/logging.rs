@@ -0,0 +1,89 @@
+//! Logging defaults to high-level events on stderr, and `RUST_LOG` overrides it.
+
+#![cfg(unix)]
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::fs::PermissionsExt,
+    process::{Command, Stdio},
+};
+
+/// What the server logs while verifying a repository whose flux fails at once, with `RUST_LOG`
+/// set to `rust_log` if given.
+fn verification_log(rust_log: Option<&str>) -> String {
+    let dir = tempfile::tempdir().unwrap();
+    let cargo = dir.path().join("cargo");
+    fs::write(&cargo, "#!/bin/sh\nexit 1\n").unwrap();
+    fs::set_permissions(&cargo, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", dir.path().display(), std::env::var("PATH").unwrap_or_default());
+
+    let mut server = Command::new(env!("CARGO_BIN_EXE_flux-mcp"));
+    server
+        .env("PATH", path)
+        .env_remove("RUST_LOG")
+        .env_remove("FLUX_MCP_LOG_FILE");
+    if let Some(rust_log) = rust_log {
+        server.env("RUST_LOG", rust_log);
+    }
+    let mut server = server
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = server.stdin.take().unwrap();
+    let messages = [
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2024-11-05",
+                "capabilities": {},
+                "clientInfo": { "name": "test", "version": "0" },
+            },
+        }),
+        serde_json::json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "tools/call",
+            "params": { "name": "verify_repository", "arguments": { "repo_path": dir.path() } },
+        }),
+    ];
+    for message in messages {
+        writeln!(stdin, "{message}").unwrap();
+    }
+    // Hang up once the verification has answered, which ends the server.
+    let stdout = BufReader::new(server.stdout.take().unwrap());
+    for line in stdout.lines() {
+        let response: serde_json::Value = serde_json::from_str(&line.unwrap()).unwrap();
+        if response["id"] == 2 {
+            break;
+        }
+    }
+    drop(stdin);
+    let mut log = String::new();
+    server
+        .stderr
+        .take()
+        .unwrap()
+        .read_to_string(&mut log)
+        .unwrap();
+    server.wait().unwrap();
+    log
+}
+
+#[test]
+fn logs_are_quiet_by_default_and_follow_rust_log() {
+    let default = verification_log(None);
+    assert!(default.contains("Starting Flux MCP Server"), "{default}");
+    assert!(!default.contains("About to execute command"), "{default}");
+
+    let debug = verification_log(Some("flux_mcp=debug"));
+    assert!(debug.contains("About to execute command"), "{debug}");
+
+    let quiet = verification_log(Some("warn"));
+    assert!(!quiet.contains("Starting Flux MCP Server"), "{quiet}");
+}
@@ -0,0 +1,86 @@
+//! The server must not leave flux processes behind when it is interrupted.
+
+#![cfg(unix)]
+
+use std::{
+    fs,
+    io::Write,
+    os::unix::fs::PermissionsExt,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+fn is_running(pid: i32) -> bool {
+    unsafe { libc::kill(pid, 0) == 0 }
+}
+
+fn wait_for(timeout: Duration, mut done: impl FnMut() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if done() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    done()
+}
+
+#[test]
+fn sigterm_reaps_in_flight_verifications() {
+    let dir = tempfile::tempdir().unwrap();
+    let pid_file = dir.path().join("flux.pid");
+    let cargo = dir.path().join("cargo");
+    fs::write(&cargo, format!("#!/bin/sh\nsleep 60 &\necho $! > {}\nwait\n", pid_file.display()))
+        .unwrap();
+    fs::set_permissions(&cargo, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", dir.path().display(), std::env::var("PATH").unwrap_or_default());
+
+    let mut server = Command::new(env!("CARGO_BIN_EXE_flux-mcp"))
+        .env("PATH", path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = server.stdin.take().unwrap();
+    let call = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "tools/call",
+        "params": { "name": "verify_repository", "arguments": { "repo_path": dir.path() } },
+    });
+    let messages = [
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2024-11-05",
+                "capabilities": {},
+                "clientInfo": { "name": "test", "version": "0" },
+            },
+        }),
+        serde_json::json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+        call,
+    ];
+    for message in messages {
+        writeln!(stdin, "{message}").unwrap();
+    }
+
+    assert!(wait_for(Duration::from_secs(10), || pid_file.exists()), "flux never started");
+    thread::sleep(Duration::from_millis(100));
+    let flux: i32 = fs::read_to_string(&pid_file)
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+    assert!(is_running(flux));
+
+    unsafe { libc::kill(server.id() as i32, libc::SIGTERM) };
+    assert!(
+        wait_for(Duration::from_secs(15), || server.try_wait().unwrap().is_some()),
+        "the server didn't exit"
+    );
+    assert!(wait_for(Duration::from_secs(5), || !is_running(flux)), "flux was left running");
+}